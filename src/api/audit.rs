@@ -0,0 +1,106 @@
+use crate::{
+    api::response,
+    auth::audit::{AuditRecord, ChainStatus},
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use axum_macros::debug_handler;
+use opentelemetry::trace::Span;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+pub struct AuditQuery {
+    /// Only records after this sequence number.
+    #[serde(default)]
+    since: u64,
+    limit: Option<usize>,
+    principal: Option<String>,
+    key_prefix: Option<String>,
+    route: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct AuditPage {
+    records: Vec<AuditRecord>,
+    /// Pass as `since` to continue after the last record scanned.
+    next: u64,
+    /// Integrity of every record still on disk, not just this page.
+    chain: ChainStatus,
+}
+
+/// Returns matching audit records and whether the stored chain is intact.
+#[debug_handler]
+pub async fn query(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.audit");
+    span.set_attribute(opentelemetry::KeyValue::new("since", query.since as i64));
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let mut next = query.since;
+    let mut records = Vec::new();
+    let scanned = state.audit.scan(query.since, |record| {
+        if records.len() == limit {
+            return false;
+        }
+        next = record.seq;
+        let event = &record.event;
+        let matches = query
+            .principal
+            .as_ref()
+            .is_none_or(|principal| event.principal.as_ref() == Some(principal))
+            && query
+                .route
+                .as_ref()
+                .is_none_or(|route| &event.route == route)
+            && query.key_prefix.as_ref().is_none_or(|prefix| {
+                event
+                    .key
+                    .as_ref()
+                    .is_some_and(|key| key.starts_with(prefix.as_str()))
+            });
+        if matches {
+            records.push(record);
+        }
+        true
+    });
+    let chain = match scanned.and_then(|()| state.audit.chain_status()) {
+        Ok(chain) => chain,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let message = format!("audit log is not queryable: {}", e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::not_found(message);
+        }
+        Err(e) => {
+            let message = format!("cannot read audit log: {}", e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::internal_server_error(message);
+        }
+    };
+
+    span.set_attribute(opentelemetry::KeyValue::new(
+        "records",
+        records.len() as i64,
+    ));
+    if chain.valid {
+        span.set_status(opentelemetry::trace::Status::Ok);
+    } else {
+        span.set_status(opentelemetry::trace::Status::error("audit chain is broken"));
+    }
+    response::success(AuditPage {
+        records,
+        next,
+        chain,
+    })
+}
@@ -0,0 +1,341 @@
+use crate::{
+    api::{caching::Validators, handlers, middleware::throttled_response, response},
+    auth::audit::ValueDigest,
+    limits::ClientName,
+    storage::{
+        blob::{self, BlobError, BlobWriter, Manifest, Stored},
+        compression,
+        record::{self, Record},
+    },
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use axum_macros::debug_handler;
+use futures_util::{stream, StreamExt};
+use opentelemetry::trace::Span;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+/// Chunks read ahead of the response body.
+const CHUNK_BUFFER: usize = 4;
+
+#[derive(Deserialize, Debug)]
+pub struct BlobQuery {
+    key: String,
+}
+
+#[derive(Serialize, Debug)]
+struct Uploaded {
+    key: String,
+    size: u64,
+    chunks: u64,
+    sha256: String,
+}
+
+/// Stores the request body under a key, chunk by chunk as it arrives, so
+/// values of any size are written without buffering them whole.
+#[debug_handler]
+pub async fn put(
+    State(state): State<AppState>,
+    Query(query): Query<BlobQuery>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.blob.put");
+    span.set_attribute(opentelemetry::KeyValue::new("key", query.key.clone()));
+
+    if state.documents.is_document(&query.key) || state.schemas.covers(&query.key) {
+        let message = format!(
+            "cannot put key \"{}\": JSON documents cannot be chunked, write it through /put",
+            &query.key
+        );
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::bad_request(message);
+    }
+
+    let client = client.map_or_else(|| "anonymous".to_string(), |Extension(name)| name.0);
+    let mut writer = BlobWriter::new(
+        &state.rocksdb,
+        &state.encryption,
+        &state.history,
+        &state.indexes,
+        query.key.as_bytes(),
+    );
+    // Each frame is checked against the quota as it arrives, since chunked
+    // uploads declare no length up front. Returning early drops the writer,
+    // and with it the chunks written so far.
+    let mut frames = body.into_data_stream();
+    while let Some(frame) = frames.next().await {
+        let data = match frame {
+            Ok(data) => data,
+            Err(e) => {
+                let message = format!("cannot put key \"{}\": {}", &query.key, e);
+                span.set_status(opentelemetry::trace::Status::error(message.clone()));
+                return response::bad_request(message);
+            }
+        };
+        if let Err(throttled) = state.limits.check_quota(
+            &state.rocksdb,
+            &state.encryption,
+            &client,
+            "/blob",
+            query.key.as_bytes(),
+            data.len() as u64,
+        ) {
+            span.set_status(opentelemetry::trace::Status::error(
+                throttled.message.clone(),
+            ));
+            return throttled_response(throttled);
+        }
+        if let Err(e) = writer.write(&data) {
+            let message = format!("cannot put key \"{}\": {}", &query.key, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::internal_server_error(message);
+        }
+    }
+
+    let header = handlers::describe(&headers, &client, String::new());
+    match writer.commit(header) {
+        Ok(manifest) => {
+            span.set_attribute(opentelemetry::KeyValue::new("size", manifest.size as i64));
+            span.set_status(opentelemetry::trace::Status::Ok);
+            let digest = ValueDigest(manifest.sha256.clone());
+            let mut response = response::success(Uploaded {
+                key: query.key,
+                size: manifest.size,
+                chunks: manifest.chunks,
+                sha256: manifest.sha256,
+            });
+            response.extensions_mut().insert(digest);
+            response
+        }
+        Err(e) => {
+            let message = format!("cannot put key \"{}\": {}", &query.key, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            response::internal_server_error(message)
+        }
+    }
+}
+
+/// Streams a value back as raw bytes, honouring a single `Range`.
+#[debug_handler]
+pub async fn get(
+    State(state): State<AppState>,
+    Query(query): Query<BlobQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.blob.get");
+    span.set_attribute(opentelemetry::KeyValue::new("key", query.key.clone()));
+
+    let record = match blob::lookup(&state.rocksdb, &state.encryption, query.key.as_bytes()) {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            let message = format!("key \"{}\" not found", &query.key);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::not_found(message);
+        }
+        Err(e) => {
+            let message = format!("cannot get key \"{}\": {}", &query.key, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::internal_server_error(message);
+        }
+    };
+    let sha256 = match (&record.header, &record.body) {
+        (_, Stored::Blob(manifest)) => manifest.sha256.clone(),
+        (Some(header), _) => header.sha256.clone(),
+        (None, Stored::Value(value)) => record::digest(value),
+    };
+    let validators = Validators::of(&record, &sha256, state.cache.for_key(&query.key));
+    if validators.is_fresh(&headers) {
+        span.set_status(opentelemetry::trace::Status::Ok);
+        return validators.not_modified();
+    }
+    let content_type = record
+        .header
+        .as_ref()
+        .and_then(|header| header.content_type.as_deref())
+        .and_then(|content_type| HeaderValue::from_str(content_type).ok())
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+    let manifest = match record.body {
+        Stored::Blob(manifest) => manifest,
+        Stored::Value(value) => {
+            let value = match compression::unpack_value(&value) {
+                Ok(value) => value,
+                Err(e) => {
+                    let message = format!("cannot decompress key \"{}\": {}", &query.key, e);
+                    span.set_status(opentelemetry::trace::Status::error(message.clone()));
+                    return response::internal_server_error(message);
+                }
+            };
+            span.set_status(opentelemetry::trace::Status::Ok);
+            let mut response = bytes_response(&headers, content_type, value);
+            validators.apply(&mut response);
+            return response;
+        }
+    };
+
+    let (status, start, end) = match requested_range(&headers, manifest.size) {
+        Ok(Some((start, end))) => (StatusCode::PARTIAL_CONTENT, start, end),
+        Ok(None) => (StatusCode::OK, 0, manifest.size.saturating_sub(1)),
+        Err(()) => {
+            span.set_status(opentelemetry::trace::Status::error("range not satisfiable"));
+            return range_not_satisfiable(manifest.size);
+        }
+    };
+    span.set_status(opentelemetry::trace::Status::Ok);
+
+    let length = if manifest.size == 0 {
+        0
+    } else {
+        end - start + 1
+    };
+    let body = if length == 0 {
+        Body::empty()
+    } else {
+        stream_chunks(state, query.key.clone(), manifest.clone(), start, end)
+    };
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::CONTENT_TYPE, content_type);
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+    if status == StatusCode::PARTIAL_CONTENT {
+        response_headers.insert(
+            header::CONTENT_RANGE,
+            HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end, manifest.size)).unwrap(),
+        );
+    }
+    validators.apply(&mut response);
+    response
+}
+
+/// Reads the chunks covering bytes `start..=end` one at a time on a
+/// blocking thread, all from one snapshot so replacing the blob meanwhile
+/// does not cut the download short.
+fn stream_chunks(state: AppState, key: String, manifest: Manifest, start: u64, end: u64) -> Body {
+    let (sender, receiver) = mpsc::channel::<Result<Vec<u8>, BlobError>>(CHUNK_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        let snapshot = state.rocksdb.snapshot();
+        let current = blob::lookup_at(&snapshot, &state.encryption, key.as_bytes());
+        let unchanged = match current {
+            Ok(Some(Record {
+                body: Stored::Blob(current),
+                ..
+            })) => current.id == manifest.id,
+            _ => false,
+        };
+        if !unchanged {
+            let replaced = format!("{} was replaced before it was read", manifest.id);
+            let _ = sender.blocking_send(Err(BlobError::Corrupt(replaced)));
+            return;
+        }
+        for index in manifest.chunks_for(start, end) {
+            let chunk = blob::read_chunk(&snapshot, &state.encryption, &manifest, index)
+                .and_then(|chunk| slice_chunk(&manifest, index, chunk, start, end));
+            let failed = chunk.is_err();
+            if sender.blocking_send(chunk).is_err() || failed {
+                return;
+            }
+        }
+    });
+    let chunks = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    Body::from_stream(chunks)
+}
+
+/// The bytes of chunk `index` that fall within `start..=end`.
+fn slice_chunk(
+    manifest: &Manifest,
+    index: u64,
+    mut chunk: Vec<u8>,
+    start: u64,
+    end: u64,
+) -> Result<Vec<u8>, BlobError> {
+    let chunk_start = index * manifest.chunk_size;
+    let from = start.saturating_sub(chunk_start) as usize;
+    let to = ((end + 1 - chunk_start) as usize).min(chunk.len());
+    if from > to {
+        return Err(BlobError::Corrupt(format!(
+            "chunk {} of {} is shorter than expected",
+            index, manifest.id
+        )));
+    }
+    chunk.truncate(to);
+    chunk.drain(..from);
+    Ok(chunk)
+}
+
+/// A value stored whole, served with the same range handling as a blob.
+fn bytes_response(headers: &HeaderMap, content_type: HeaderValue, value: Vec<u8>) -> Response {
+    let size = value.len() as u64;
+    let content_headers = [
+        (header::CONTENT_TYPE, content_type),
+        (header::ACCEPT_RANGES, HeaderValue::from_static("bytes")),
+    ];
+    match requested_range(headers, size) {
+        Ok(Some((start, end))) => (
+            StatusCode::PARTIAL_CONTENT,
+            content_headers,
+            [(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, size),
+            )],
+            value[start as usize..=end as usize].to_vec(),
+        )
+            .into_response(),
+        Ok(None) => (StatusCode::OK, content_headers, value).into_response(),
+        Err(()) => range_not_satisfiable(size),
+    }
+}
+
+fn range_not_satisfiable(size: u64) -> Response {
+    (
+        StatusCode::RANGE_NOT_SATISFIABLE,
+        [(header::CONTENT_RANGE, format!("bytes */{}", size))],
+    )
+        .into_response()
+}
+
+/// Parses a `Range: bytes=...` header into inclusive bounds. Absent,
+/// malformed and multi-range headers select the whole value, as RFC 9110
+/// allows; a well-formed range outside the value is an error.
+fn requested_range(headers: &HeaderMap, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().strip_prefix("bytes="))
+    else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((first, last)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let (first, last) = (first.trim(), last.trim());
+    let bounds = match (first.parse::<u64>(), last.parse::<u64>()) {
+        (Ok(first), Ok(last)) if first <= last => (first, last.min(size.saturating_sub(1))),
+        (Ok(first), Err(_)) if last.is_empty() => (first, size.saturating_sub(1)),
+        (Err(_), Ok(suffix)) if first.is_empty() && suffix > 0 => {
+            (size.saturating_sub(suffix), size.saturating_sub(1))
+        }
+        _ => return Ok(None),
+    };
+    if size == 0 || bounds.0 >= size {
+        return Err(());
+    }
+    Ok(Some(bounds))
+}
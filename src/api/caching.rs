@@ -0,0 +1,149 @@
+use crate::storage::record::Record;
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CacheRule {
+    pub prefix: String,
+    pub cache_control: String,
+}
+
+/// Contents of the file named by `ROCKSDB_CACHE_CONFIG`.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct CacheConfig {
+    #[serde(default)]
+    pub rules: Vec<CacheRule>,
+    /// Used for keys no rule matches.
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+/// `Cache-Control` values by key prefix. Without a configuration no
+/// `Cache-Control` header is sent.
+#[derive(Debug, Default)]
+pub struct CachePolicy {
+    config: CacheConfig,
+}
+
+impl CachePolicy {
+    pub fn disabled() -> Self {
+        CachePolicy::default()
+    }
+
+    pub fn new(config: CacheConfig) -> Self {
+        CachePolicy { config }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {:?}: {}", path, e))?;
+        let config: CacheConfig =
+            serde_json::from_str(&contents).map_err(|e| format!("invalid {:?}: {}", path, e))?;
+        for rule in config.rules.iter().map(|rule| &rule.cache_control) {
+            HeaderValue::from_str(rule)
+                .map_err(|_| format!("invalid {:?}: bad Cache-Control \"{}\"", path, rule))?;
+        }
+        Ok(CachePolicy::new(config))
+    }
+
+    /// The `Cache-Control` of the longest matching prefix.
+    pub fn for_key(&self, key: &str) -> Option<&str> {
+        self.config
+            .rules
+            .iter()
+            .filter(|rule| key.starts_with(rule.prefix.as_str()))
+            .max_by_key(|rule| rule.prefix.len())
+            .map(|rule| rule.cache_control.as_str())
+            .or(self.config.default.as_deref())
+    }
+}
+
+/// What a client can revalidate a cached value against.
+#[derive(Debug, Clone)]
+pub struct Validators {
+    /// Weak, since the same value is sent with different encodings.
+    pub etag: String,
+    pub last_modified: Option<SystemTime>,
+    pub cache_control: Option<String>,
+}
+
+impl Validators {
+    pub fn new(sha256: &str, modified_ms: Option<u64>, cache_control: Option<&str>) -> Self {
+        let tag = sha256.get(..32).unwrap_or(sha256);
+        Validators {
+            etag: format!("W/\"{}\"", tag),
+            last_modified: modified_ms
+                .filter(|ms| *ms > 0)
+                .map(|ms| UNIX_EPOCH + Duration::from_millis(ms)),
+            cache_control: cache_control.map(str::to_string),
+        }
+    }
+
+    pub fn of<T>(record: &Record<T>, sha256: &str, cache_control: Option<&str>) -> Self {
+        let modified_ms = record.header.as_ref().map(|header| header.modified_ms);
+        Validators::new(sha256, modified_ms, cache_control)
+    }
+
+    /// Whether the request's conditions show the client's copy is current.
+    /// `If-None-Match` takes precedence over `If-Modified-Since`.
+    pub fn is_fresh(&self, request: &HeaderMap) -> bool {
+        if let Some(if_none_match) = request.get(header::IF_NONE_MATCH) {
+            let Ok(if_none_match) = if_none_match.to_str() else {
+                return false;
+            };
+            let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+            let ours = opaque(&self.etag);
+            return if_none_match
+                .split(',')
+                .any(|tag| tag.trim() == "*" || opaque(tag) == ours);
+        }
+        let (Some(last_modified), Some(since)) = (
+            self.last_modified,
+            request
+                .get(header::IF_MODIFIED_SINCE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| httpdate::parse_http_date(value).ok()),
+        ) else {
+            return false;
+        };
+        // HTTP dates have whole-second precision.
+        let last_modified = last_modified
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| UNIX_EPOCH + Duration::from_secs(elapsed.as_secs()))
+            .unwrap_or(UNIX_EPOCH);
+        last_modified <= since
+    }
+
+    pub fn apply(&self, response: &mut Response) {
+        let headers = response.headers_mut();
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        if let Some(last_modified) = self.last_modified {
+            let date = httpdate::fmt_http_date(last_modified);
+            if let Ok(date) = HeaderValue::from_str(&date) {
+                headers.insert(header::LAST_MODIFIED, date);
+            }
+        }
+        if let Some(cache_control) = &self.cache_control {
+            if let Ok(cache_control) = HeaderValue::from_str(cache_control) {
+                headers.insert(header::CACHE_CONTROL, cache_control);
+            }
+        }
+    }
+
+    /// 304 carrying the validators.
+    pub fn not_modified(&self) -> Response {
+        let mut response = StatusCode::NOT_MODIFIED.into_response();
+        self.apply(&mut response);
+        response
+    }
+}
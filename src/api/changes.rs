@@ -0,0 +1,163 @@
+use crate::{
+    api::response,
+    storage::{
+        compression,
+        lock::LOCK_COLUMN_FAMILY,
+        record,
+        wal::{self, ChangeBatch, ChangeFilter, ChangesError},
+    },
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+};
+use axum_macros::debug_handler;
+use futures_util::stream;
+use opentelemetry::trace::Span;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+const DEFAULT_LIMIT: usize = 1000;
+const MAX_WAIT_MS: u64 = 30000;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Deserialize, Debug)]
+pub struct ChangesQuery {
+    since: u64,
+    limit: Option<usize>,
+    /// Long-poll for up to this many milliseconds when nothing is pending.
+    wait_ms: Option<u64>,
+    /// Keep the response open and emit records as NDJSON.
+    #[serde(default)]
+    stream: bool,
+    /// Also emit the server's own entries: reserved keys and the records of
+    /// other column families.
+    #[serde(default)]
+    internal: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct PurgedBody {
+    error: String,
+    oldest_available: Option<u64>,
+}
+
+#[debug_handler]
+pub async fn changes(
+    State(state): State<AppState>,
+    Query(query): Query<ChangesQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.changes");
+    span.set_attribute(opentelemetry::KeyValue::new("since", query.since as i64));
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).max(1);
+    let wait = Duration::from_millis(query.wait_ms.unwrap_or(0).min(MAX_WAIT_MS));
+    // Lock records hold the lease tokens, which only their holders may see.
+    let filter = ChangeFilter {
+        internal: query.internal,
+        excluded: state
+            .rocksdb
+            .cf_handle(LOCK_COLUMN_FAMILY)
+            .map(wal::column_family_id)
+            .into_iter()
+            .collect(),
+    };
+
+    let first = match poll(&state, &filter, query.since, limit, wait).await {
+        Ok(batch) => batch,
+        Err(e) => {
+            let message = format!("cannot read changes since {}: {}", query.since, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return error_response(e, message);
+        }
+    };
+    span.set_attribute(opentelemetry::KeyValue::new(
+        "records",
+        first.records.len() as i64,
+    ));
+    span.set_status(opentelemetry::trace::Status::Ok);
+
+    if query.stream {
+        stream_changes(state, filter, first, limit)
+    } else {
+        response::success(first)
+    }
+}
+
+/// Reads changes, waiting up to `wait` for at least one record to appear.
+async fn poll(
+    state: &AppState,
+    filter: &ChangeFilter,
+    mut since: u64,
+    limit: usize,
+    wait: Duration,
+) -> Result<ChangeBatch, ChangesError> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let open = |key: &[u8], value: &[u8]| {
+            let opened = state.encryption.open_lossy(key, value);
+            let body = record::body(&opened);
+            compression::unpack_value(body).unwrap_or_else(|_| body.to_vec())
+        };
+        let batch = wal::changes_since_with(&state.rocksdb, since, limit, filter, &open)?;
+        if !batch.records.is_empty() || Instant::now() >= deadline {
+            return Ok(batch);
+        }
+        // Past batches whose records were all filtered out.
+        since = batch.next;
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+fn stream_changes(
+    state: AppState,
+    filter: ChangeFilter,
+    first: ChangeBatch,
+    limit: usize,
+) -> Response {
+    let lines = stream::unfold(Some((state, filter, first)), move |pending| async move {
+        let (state, filter, batch) = pending?;
+        let mut body = String::new();
+        for record in &batch.records {
+            body.push_str(&serde_json::to_string(record).unwrap());
+            body.push('\n');
+        }
+        let wait = Duration::from_millis(MAX_WAIT_MS);
+        let next = match poll(&state, &filter, batch.next, limit, wait).await {
+            Ok(batch) => Some((state, filter, batch)),
+            Err(e) => {
+                println!("Error streaming changes after {}: {:}", batch.next, e);
+                None
+            }
+        };
+        Some((Ok::<_, ChangesError>(body), next))
+    });
+
+    let mut response = Response::new(Body::from_stream(lines));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    response
+}
+
+fn error_response(e: ChangesError, message: String) -> Response {
+    match e {
+        ChangesError::Purged {
+            oldest_available, ..
+        } => response::gone(PurgedBody {
+            error: message,
+            oldest_available,
+        }),
+        ChangesError::Malformed(_) | ChangesError::Storage(_) => {
+            response::internal_server_error(message)
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use crate::{
+    api::{middleware::throttled_response, response},
+    limits::{ClientName, Throttled},
+    storage::{
+        self,
+        dump::{self, DumpError, DumpFormat, OnConflict, Scope},
+        rocksdb::Store,
+    },
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    body::Body,
+    extract::{Extension, Query, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+};
+use axum_macros::debug_handler;
+use futures_util::{stream, StreamExt};
+use opentelemetry::{global::BoxedSpan, trace::Span};
+use rocksdb::DEFAULT_COLUMN_FAMILY_NAME;
+use serde::Deserialize;
+use std::io;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::mpsc,
+};
+
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+/// NDJSON lines buffered between the snapshot reader and the response.
+const EXPORT_BUFFER: usize = 256;
+
+#[derive(Deserialize, Debug)]
+pub struct ExportQuery {
+    #[serde(default)]
+    format: DumpFormat,
+    #[serde(default)]
+    prefix: String,
+    column_family: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RestoreQuery {
+    #[serde(default)]
+    on_conflict: OnConflict,
+}
+
+/// Streams the keys in scope from a consistent snapshot, as NDJSON over
+/// every column family unless one is named, or as an SST file of a single
+/// column family. Values are exported as stored, so restoring encrypted
+/// ones needs the same master keys.
+#[debug_handler]
+pub async fn export(
+    State(state): State<AppState>,
+    Query(query): Query<ExportQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.dump.export");
+    span.set_attribute(opentelemetry::KeyValue::new(
+        "format",
+        format!("{:?}", query.format),
+    ));
+
+    if let Some(name) = &query.column_family {
+        if name != DEFAULT_COLUMN_FAMILY_NAME && state.rocksdb.cf_handle(name).is_none() {
+            let message = format!(
+                "cannot export: {}",
+                DumpError::UnknownColumnFamily(name.clone())
+            );
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::bad_request(message);
+        }
+    }
+    let scope = Scope {
+        column_family: query.column_family,
+        prefix: query.prefix.into_bytes(),
+    };
+    match query.format {
+        DumpFormat::Ndjson => export_ndjson(state, scope, &mut span),
+        DumpFormat::Sst => export_sst(state, scope, &mut span).await,
+    }
+}
+
+/// Replays an NDJSON dump into the column families it names, which must
+/// already exist, resolving keys the DB already has by `on_conflict`.
+/// Restored keys keep history and indexes up to date.
+#[debug_handler]
+pub async fn restore(
+    State(state): State<AppState>,
+    Query(query): Query<RestoreQuery>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.dump.restore");
+
+    let upload = match dump::staging_path(&state.rocksdb, "ndjson") {
+        Ok(upload) => upload,
+        Err(e) => return failed(&mut span, "cannot restore".to_string(), e.into()),
+    };
+    if let Err(e) = stage(body, &upload).await {
+        let _ = tokio::fs::remove_file(&upload).await;
+        let message = format!("cannot restore: {}", e);
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::bad_request(message);
+    }
+
+    let client = client.map_or_else(|| "anonymous".to_string(), |Extension(name)| name.0);
+    let checked = state.clone();
+    let staged = upload.clone();
+    let admitted = tokio::task::spawn_blocking(move || admit(&checked, &client, &staged))
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e).into()));
+    let throttled = match admitted {
+        Ok(throttled) => throttled,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&upload).await;
+            return failed(&mut span, "cannot restore".to_string(), e);
+        }
+    };
+    if let Some(throttled) = throttled {
+        let _ = tokio::fs::remove_file(&upload).await;
+        span.set_status(opentelemetry::trace::Status::error(
+            throttled.message.clone(),
+        ));
+        return throttled_response(throttled);
+    }
+
+    let restoring = state.clone();
+    let staged = upload.clone();
+    let restored = tokio::task::spawn_blocking(move || {
+        let store = Store {
+            db: &restoring.rocksdb,
+            encryption: &restoring.encryption,
+            history: &restoring.history,
+            indexes: &restoring.indexes,
+        };
+        let open = || Ok(io::BufReader::new(std::fs::File::open(&staged)?));
+        dump::restore(&store, open, query.on_conflict)
+    })
+    .await
+    .unwrap_or_else(|e| Err(io::Error::other(e).into()));
+    let _ = tokio::fs::remove_file(&upload).await;
+
+    let restored = match restored {
+        Ok(restored) => restored,
+        Err(e) => return failed(&mut span, "cannot restore".to_string(), e),
+    };
+    if let Err(e) = state.schemas.reload(&state.rocksdb, &state.encryption) {
+        let message = format!("restored, but cannot reload schemas: {}", e);
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::internal_server_error(message);
+    }
+    span.set_attribute(opentelemetry::KeyValue::new(
+        "restored",
+        restored.restored as i64,
+    ));
+    span.set_attribute(opentelemetry::KeyValue::new(
+        "skipped",
+        restored.skipped as i64,
+    ));
+    span.set_status(opentelemetry::trace::Status::Ok);
+    response::success(restored)
+}
+
+/// Reads the snapshot on a blocking thread, handing lines to the response
+/// as it goes. An error part way through aborts the response body.
+fn export_ndjson(state: AppState, scope: Scope, span: &mut BoxedSpan) -> Response {
+    let (sender, receiver) = mpsc::channel::<io::Result<Vec<u8>>>(EXPORT_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        let exported = dump::export(&state.rocksdb, &state.encryption, &scope, |entry| {
+            sender
+                .blocking_send(Ok(entry.to_line().into_bytes()))
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe).into())
+        });
+        if let Err(e) = exported {
+            let _ = sender.blocking_send(Err(io::Error::other(e.to_string())));
+        }
+    });
+    let lines = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|line| (line, receiver))
+    });
+    span.set_status(opentelemetry::trace::Status::Ok);
+
+    let mut response = Response::new(Body::from_stream(lines));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    response
+}
+
+/// Writes the SST file aside, then streams it back, unlinked so it goes
+/// away with the response.
+async fn export_sst(state: AppState, scope: Scope, span: &mut BoxedSpan) -> Response {
+    let path = match dump::staging_path(&state.rocksdb, "sst") {
+        Ok(path) => path,
+        Err(e) => return failed(span, "cannot export".to_string(), e.into()),
+    };
+    let written = path.clone();
+    let exported = tokio::task::spawn_blocking(move || {
+        dump::export_sst(&state.rocksdb, &state.encryption, &scope, &written)
+    })
+    .await
+    .unwrap_or_else(|e| Err(io::Error::other(e).into()));
+    let opened = match exported {
+        Ok(_) => tokio::fs::File::open(&path).await.map_err(DumpError::from),
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_file(&path).await;
+    let opened = match opened {
+        Ok(opened) => opened,
+        Err(e) => return failed(span, "cannot export".to_string(), e),
+    };
+
+    let chunks = stream::unfold(Some(opened), |opened| async move {
+        let mut opened = opened?;
+        let mut buffer = vec![0; FILE_CHUNK_SIZE];
+        match opened.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((Ok(buffer), Some(opened)))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+    span.set_status(opentelemetry::trace::Status::Ok);
+
+    let mut response = Response::new(Body::from_stream(chunks));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    response
+}
+
+/// Checks every key the dump restores into the default column family
+/// against its namespace's quota before anything is written. The first
+/// namespace found over quota, if any, refuses the whole restore.
+fn admit(
+    state: &AppState,
+    client: &str,
+    upload: &std::path::Path,
+) -> Result<Option<Throttled>, DumpError> {
+    let input = io::BufReader::new(std::fs::File::open(upload)?);
+    let mut throttled = None;
+    dump::read(input, |entry| {
+        let internal = entry.key.first() == Some(&(storage::RESERVED_KEY_PREFIX as u8));
+        if throttled.is_some() || entry.column_family != DEFAULT_COLUMN_FAMILY_NAME || internal {
+            return Ok(());
+        }
+        throttled = state
+            .limits
+            .check_quota(
+                &state.rocksdb,
+                &state.encryption,
+                client,
+                "/admin/restore",
+                &entry.key,
+                entry.value.len() as u64,
+            )
+            .err();
+        Ok(())
+    })?;
+    Ok(throttled)
+}
+
+/// Writes the request body to `upload` as it arrives.
+async fn stage(body: Body, upload: &std::path::Path) -> io::Result<()> {
+    let mut file = tokio::fs::File::create(upload).await?;
+    let mut frames = body.into_data_stream();
+    while let Some(frame) = frames.next().await {
+        let data = frame.map_err(io::Error::other)?;
+        file.write_all(&data).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+fn failed(span: &mut BoxedSpan, context: String, e: DumpError) -> Response {
+    let message = format!("{}: {}", context, e);
+    span.set_status(opentelemetry::trace::Status::error(message.clone()));
+    match e {
+        DumpError::Conflict { .. } => response::conflict(message),
+        DumpError::UnknownColumnFamily(_) | DumpError::Invalid { .. } => {
+            response::bad_request(message)
+        }
+        DumpError::Empty => response::not_found(message),
+        DumpError::Io(_) | DumpError::Storage(_) => response::internal_server_error(message),
+    }
+}
@@ -0,0 +1,59 @@
+use crate::{
+    api::response,
+    storage::encryption::ReencryptStatus,
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{extract::State, http::HeaderMap, response::Response};
+use axum_macros::debug_handler;
+use opentelemetry::trace::Span;
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+struct EncryptionStatus {
+    enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    active_key: Option<String>,
+    reencrypt: ReencryptStatus,
+}
+
+/// Reports whether values are encrypted and the progress of the last
+/// re-encryption run.
+#[debug_handler]
+pub async fn status(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.encryption");
+
+    span.set_status(opentelemetry::trace::Status::Ok);
+    response::success(EncryptionStatus {
+        enabled: state.encryption.is_enabled(),
+        active_key: state.encryption.active_key_id().map(str::to_string),
+        reencrypt: state.encryption.status(),
+    })
+}
+
+/// Starts rewriting, in the background, every value that is stored in
+/// plaintext or under a key other than the active one.
+#[debug_handler]
+pub async fn reencrypt(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.encryption.reencrypt");
+
+    if !state.encryption.is_enabled() {
+        let message = "encryption is not configured".to_string();
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::conflict(message);
+    }
+    if !state.encryption.start_reencrypt() {
+        let message = "re-encryption is already running".to_string();
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::conflict(message);
+    }
+
+    let encryption = state.encryption.clone();
+    let db = state.rocksdb.clone();
+    tokio::task::spawn_blocking(move || encryption.reencrypt(&db));
+
+    span.set_status(opentelemetry::trace::Status::Ok);
+    response::accepted(state.encryption.status())
+}
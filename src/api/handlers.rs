@@ -1,17 +1,30 @@
 use crate::{
-    api::response,
-    storage::rocksdb,
+    api::{caching::Validators, middleware::throttled_response, response, schemas},
+    auth::audit::ValueDigest,
+    limits::ClientName,
+    storage::{
+        blob::{self, Stored},
+        compression::{self, Codec},
+        document::{self, Patch, PatchError},
+        history,
+        record::{self, Header, Record},
+        rocksdb,
+    },
     telemetry::tracing::{current_span, extract_context_from_request},
     AppState,
 };
 use axum::{
-    extract::{Query, State},
-    http::HeaderMap,
-    response::Response,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
 };
 use axum_macros::debug_handler;
 use opentelemetry::trace::Span;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io;
 
 #[derive(Deserialize, Debug)]
 pub struct PutQuery {
@@ -21,61 +34,592 @@ pub struct PutQuery {
 #[derive(Deserialize, Debug)]
 pub struct GetQuery {
     key: String,
+    /// Reads the value the key held at this time, in milliseconds since the
+    /// epoch, from history.
+    as_of: Option<u64>,
+    /// Comma-separated JSON pointers selecting parts of a JSON document.
+    fields: Option<String>,
 }
 
+/// `GetQuery` of the resource-style routes, which name the key in the path.
+#[derive(Deserialize, Debug, Default)]
+pub struct ReadQuery {
+    as_of: Option<u64>,
+    fields: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MetaQuery {
+    key: String,
+}
+
+/// What `/meta` reports about a key.
+#[derive(Serialize, Debug)]
+struct Meta {
+    key: String,
+    size: u64,
+    chunked: bool,
+    sha256: String,
+    version: u64,
+    content_type: Option<String>,
+    /// Absent for values written before record headers were kept.
+    created_ms: Option<u64>,
+    modified_ms: Option<u64>,
+    modified_by: Option<String>,
+    tags: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct Deleted {
+    key: String,
+    deleted: bool,
+}
+
+/// Response header carrying the size of a value in answer to `HEAD`.
+pub const VALUE_SIZE_HEADER: &str = "x-value-size";
+
+/// Request header listing tags to store with a value, separated by commas.
+pub const TAGS_HEADER: &str = "x-value-tags";
+
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Route pattern of the resource-style key routes.
+pub const KV_ROUTE: &str = "/v1/kv/*key";
+
 #[debug_handler]
 pub async fn put(
     State(state): State<AppState>,
     Query(query): Query<PutQuery>,
+    client: Option<Extension<ClientName>>,
     headers: HeaderMap,
     value: String,
 ) -> Response {
-    let parent_cx = extract_context_from_request(&headers);
+    put_value(&state, query.key, "/put", client, &headers, value)
+}
+
+#[debug_handler]
+pub async fn put_key(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+    value: String,
+) -> Response {
+    put_value(&state, key, KV_ROUTE, client, &headers, value)
+}
+
+fn put_value(
+    state: &AppState,
+    key: String,
+    route: &str,
+    client: Option<Extension<ClientName>>,
+    headers: &HeaderMap,
+    value: String,
+) -> Response {
+    let parent_cx = extract_context_from_request(headers);
     let mut span = current_span(parent_cx, "rocksdb.http.put");
-    span.set_attribute(opentelemetry::KeyValue::new("key", query.key.clone()));
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    let is_document = state.documents.is_document(&key) || state.schemas.covers(&key);
+    if is_document {
+        let document = match serde_json::from_str::<Value>(&value) {
+            Ok(document) => document,
+            Err(e) => {
+                let message = format!("cannot put key \"{}\": not a JSON document: {}", &key, e);
+                span.set_status(opentelemetry::trace::Status::error(message.clone()));
+                return response::bad_request(message);
+            }
+        };
+        if let Err(violations) = state.schemas.validate(&key, &document) {
+            let message = format!(
+                "cannot put key \"{}\": value does not match its schema",
+                &key
+            );
+            return schemas::rejected(&mut span, message, violations);
+        }
+    }
+
+    let digest = ValueDigest::of(value.as_bytes());
+    let client = client.map_or_else(|| "anonymous".to_string(), |Extension(name)| name.0);
+    let mut header = describe(headers, &client, digest.0.clone());
+    if is_document && header.content_type.is_none() {
+        header.content_type = Some(JSON_CONTENT_TYPE.to_string());
+    }
+    let mut response = match state.limits.check_quota(
+        &state.rocksdb,
+        &state.encryption,
+        &client,
+        route,
+        key.as_bytes(),
+        value.len() as u64,
+    ) {
+        Err(throttled) => {
+            span.set_status(opentelemetry::trace::Status::error(
+                throttled.message.clone(),
+            ));
+            throttled_response(throttled)
+        }
+        Ok(()) => match store(state, &key, header, value) {
+            Ok(_) => {
+                let message = format!("put key \"{}\" successfully", &key);
+                span.set_status(opentelemetry::trace::Status::Ok);
+                response::success(message)
+            }
+            Err(e) => {
+                let message = format!("cannot put key \"{}\": {}", &key, e);
+                span.set_status(opentelemetry::trace::Status::error(message.clone()));
+                response::internal_server_error(message)
+            }
+        },
+    };
+    response.extensions_mut().insert(digest);
+    response
+}
+
+/// Writes a value, compressed first when stored compression is enabled.
+fn store(state: &AppState, key: &str, header: Header, value: String) -> Result<(), String> {
+    let stored = encode(state, value).map_err(|e| e.to_string())?;
+    rocksdb::put_encrypted(
+        &state.rocksdb,
+        &state.encryption,
+        &state.history,
+        &state.indexes,
+        key,
+        header,
+        stored,
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// A value as it is stored: compressed when stored compression is enabled.
+fn encode(state: &AppState, value: String) -> io::Result<Vec<u8>> {
+    match state.stored_compression {
+        Some(codec) => compression::pack(codec, &value),
+        None => Ok(value.into_bytes()),
+    }
+}
+
+#[debug_handler]
+pub async fn patch(
+    State(state): State<AppState>,
+    Query(query): Query<PutQuery>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    patch_value(&state, query.key, "/patch", client, &headers, body)
+}
 
-    let result = rocksdb::put(&state.rocksdb, &query.key, &value);
-    match result {
+#[debug_handler]
+pub async fn patch_key(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    patch_value(&state, key, KV_ROUTE, client, &headers, body)
+}
+
+/// Applies a JSON Patch or JSON Merge Patch, chosen by `Content-Type`, to
+/// the document under a key and answers with the updated document.
+fn patch_value(
+    state: &AppState,
+    key: String,
+    route: &str,
+    client: Option<Extension<ClientName>>,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Response {
+    let parent_cx = extract_context_from_request(headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.patch");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let patch = match Patch::parse(content_type, &body) {
+        Ok(Some(patch)) => patch,
+        Ok(None) => {
+            let message = format!(
+                "cannot patch key \"{}\": Content-Type must be {} or {}",
+                &key,
+                document::JSON_PATCH,
+                document::MERGE_PATCH
+            );
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return (StatusCode::UNSUPPORTED_MEDIA_TYPE, message).into_response();
+        }
+        Err(e) => {
+            let message = format!("cannot patch key \"{}\": invalid patch: {}", &key, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::bad_request(message);
+        }
+    };
+
+    let client = client.map_or_else(|| "anonymous".to_string(), |Extension(name)| name.0);
+    let mut patched = None;
+    let updated = rocksdb::update_encrypted(
+        &state.rocksdb,
+        &state.encryption,
+        &state.history,
+        &state.indexes,
+        &key,
+        |previous| {
+            let current = match previous.map(|previous| &previous.body) {
+                None => None,
+                Some(Stored::Blob(_)) => {
+                    return Err(PatchError::NotDocument("it is chunked".to_string()))
+                }
+                Some(Stored::Value(stored)) => {
+                    let value = compression::unpack_value(stored)
+                        .map_err(|e| PatchError::Storage(e.to_string()))?;
+                    Some(
+                        serde_json::from_slice(&value)
+                            .map_err(|e| PatchError::NotDocument(e.to_string()))?,
+                    )
+                }
+            };
+            let document = patch.apply(current)?;
+            state
+                .schemas
+                .validate(&key, &document)
+                .map_err(PatchError::Invalid)?;
+            let value = document.to_string();
+            // The quota counts the document the patch produces, not the patch.
+            state
+                .limits
+                .check_quota(
+                    &state.rocksdb,
+                    &state.encryption,
+                    &client,
+                    route,
+                    key.as_bytes(),
+                    value.len() as u64,
+                )
+                .map_err(PatchError::Throttled)?;
+            let digest = ValueDigest::of(value.as_bytes());
+            let mut header = describe(headers, &client, digest.0.clone());
+            header.content_type = Some(JSON_CONTENT_TYPE.to_string());
+            let stored = encode(state, value).map_err(|e| PatchError::Storage(e.to_string()))?;
+            patched = Some((document, digest));
+            Ok((header, stored))
+        },
+    );
+    match updated {
         Ok(_) => {
-            let message = format!("put key \"{}\" successfully", &query.key);
+            let (document, digest) = patched.expect("set by a successful update");
             span.set_status(opentelemetry::trace::Status::Ok);
-            response::success(message)
+            let mut response = response::success(document);
+            response.extensions_mut().insert(digest);
+            response
         }
         Err(e) => {
-            let message = format!("cannot put key \"{}\": {}", &query.key, e);
+            let message = format!("cannot patch key \"{}\": {}", &key, e);
             span.set_status(opentelemetry::trace::Status::error(message.clone()));
-            response::internal_server_error(message)
+            match e {
+                PatchError::Missing => response::not_found(message),
+                PatchError::NotDocument(_) | PatchError::Failed(_) => response::conflict(message),
+                PatchError::Invalid(violations) => {
+                    schemas::rejected(&mut span, message, violations)
+                }
+                PatchError::Throttled(throttled) => throttled_response(throttled),
+                PatchError::Storage(_) => response::internal_server_error(message),
+            }
         }
     }
 }
 
+/// Header for a value written by `client`, with the content type and tags
+/// the request declares.
+pub fn describe(headers: &HeaderMap, client: &str, sha256: String) -> Header {
+    let mut header = Header::new(sha256);
+    header.content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    header.modified_by = Some(client.to_string());
+    header.tags = headers
+        .get_all(TAGS_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect();
+    header
+}
+
 #[debug_handler]
 pub async fn get(
     State(state): State<AppState>,
     Query(query): Query<GetQuery>,
     headers: HeaderMap,
 ) -> Response {
-    let parent_cx = extract_context_from_request(&headers);
+    let read = ReadQuery {
+        as_of: query.as_of,
+        fields: query.fields,
+    };
+    get_value(&state, query.key, read, &headers)
+}
+
+#[debug_handler]
+pub async fn get_key(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(read): Query<ReadQuery>,
+    headers: HeaderMap,
+) -> Response {
+    get_value(&state, key, read, &headers)
+}
+
+fn get_value(state: &AppState, key: String, read: ReadQuery, headers: &HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(headers);
     let mut span = current_span(parent_cx, "rocksdb.http.get");
-    span.set_attribute(opentelemetry::KeyValue::new("key", query.key.clone()));
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
 
-    let result = rocksdb::get(&state.rocksdb, &query.key);
-    match result {
-        Ok(value) => match value {
-            Some(value) => {
-                span.set_status(opentelemetry::trace::Status::Ok);
-                response::success(value)
+    let as_of = read.as_of;
+    let found = match as_of {
+        Some(as_of_ms) => {
+            span.set_attribute(opentelemetry::KeyValue::new("as_of", as_of_ms as i64));
+            history::as_of(&state.rocksdb, &state.encryption, key.as_bytes(), as_of_ms)
+        }
+        None => rocksdb::get_decrypted(&state.rocksdb, &state.encryption, &key),
+    };
+    let record = match found {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            let message = match as_of {
+                Some(as_of_ms) => format!("key \"{}\" not found as of {}", &key, as_of_ms),
+                None => format!("key \"{}\" not found", &key),
+            };
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::not_found(message);
+        }
+        Err(e) => {
+            let message = format!("cannot get key \"{}\": {}", &key, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::internal_server_error(message);
+        }
+    };
+    if blob::is_manifest(&record.body) {
+        let message = format!("key \"{}\" holds a chunked value, read it from /blob", &key);
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::conflict(message);
+    }
+
+    let validators = Validators::of(&record, &record.sha256(), state.cache.for_key(&key));
+    if validators.is_fresh(headers) {
+        span.set_status(opentelemetry::trace::Status::Ok);
+        return validators.not_modified();
+    }
+    let mut response = match read.fields.as_deref() {
+        Some(fields) => {
+            let value = match compression::unpack_value(&record.body) {
+                Ok(value) => value,
+                Err(e) => {
+                    let message = format!("cannot decompress key \"{}\": {}", &key, e);
+                    span.set_status(opentelemetry::trace::Status::error(message.clone()));
+                    return response::internal_server_error(message);
+                }
+            };
+            match serde_json::from_slice::<Value>(&value) {
+                Ok(value) => {
+                    let fields = document::parse_fields(fields);
+                    response::success(document::project(&value, &fields))
+                }
+                Err(_) => {
+                    let message = format!("key \"{}\" holds no JSON document to select from", &key);
+                    span.set_status(opentelemetry::trace::Status::error(message.clone()));
+                    return response::conflict(message);
+                }
             }
-            None => {
-                let message = format!("key \"{}\" not found", &query.key);
-                span.set_status(opentelemetry::trace::Status::error(message.clone()));
-                response::not_found(message)
+        }
+        None => match compression::unpack(&record.body) {
+            Some((codec, body)) if accepts_encoding(headers, codec) => {
+                span.set_attribute(opentelemetry::KeyValue::new("encoding", codec.name()));
+                response::precompressed(codec.name(), body.to_vec())
             }
+            _ => match compression::unpack_value(&record.body) {
+                Ok(value) => response::success(String::from_utf8_lossy(&value)),
+                Err(e) => {
+                    let message = format!("cannot decompress key \"{}\": {}", &key, e);
+                    span.set_status(opentelemetry::trace::Status::error(message.clone()));
+                    return response::internal_server_error(message);
+                }
+            },
         },
+    };
+    span.set_status(opentelemetry::trace::Status::Ok);
+    validators.apply(&mut response);
+    response
+}
+
+/// Answers whether a key exists and, in `x-value-size`, how many bytes its
+/// value holds.
+#[debug_handler]
+pub async fn head_key(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.head");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    let record = match blob::lookup(&state.rocksdb, &state.encryption, key.as_bytes()) {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            span.set_status(opentelemetry::trace::Status::error(format!(
+                "key \"{}\" not found",
+                &key
+            )));
+            return StatusCode::NOT_FOUND.into_response();
+        }
+        Err(e) => {
+            let message = format!("cannot get key \"{}\": {}", &key, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::internal_server_error(message);
+        }
+    };
+    let (size, sha256) = match size_and_digest(&record) {
+        Ok(described) => described,
+        Err(e) => {
+            let message = format!("cannot decompress key \"{}\": {}", &key, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::internal_server_error(message);
+        }
+    };
+    span.set_status(opentelemetry::trace::Status::Ok);
+    let validators = Validators::of(&record, &sha256, state.cache.for_key(&key));
+    let mut response = if validators.is_fresh(&headers) {
+        validators.not_modified()
+    } else {
+        (StatusCode::OK, [(VALUE_SIZE_HEADER, size.to_string())]).into_response()
+    };
+    validators.apply(&mut response);
+    response
+}
+
+/// Returns a key's record header and size without its value.
+#[debug_handler]
+pub async fn meta(
+    State(state): State<AppState>,
+    Query(query): Query<MetaQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.meta");
+    span.set_attribute(opentelemetry::KeyValue::new("key", query.key.clone()));
+
+    let record = match blob::lookup(&state.rocksdb, &state.encryption, query.key.as_bytes()) {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            let message = format!("key \"{}\" not found", &query.key);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::not_found(message);
+        }
         Err(e) => {
             let message = format!("cannot get key \"{}\": {}", &query.key, e);
             span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::internal_server_error(message);
+        }
+    };
+    let (size, sha256) = match size_and_digest(&record) {
+        Ok(described) => described,
+        Err(e) => {
+            let message = format!("cannot decompress key \"{}\": {}", &query.key, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::internal_server_error(message);
+        }
+    };
+    let chunked = matches!(record.body, Stored::Blob(_));
+    let header = record.header.unwrap_or_default();
+    let known = |ms: u64| Some(ms).filter(|ms| *ms > 0);
+    span.set_status(opentelemetry::trace::Status::Ok);
+    response::success(Meta {
+        key: query.key,
+        size,
+        chunked,
+        sha256,
+        version: header.version.max(1),
+        content_type: header.content_type,
+        created_ms: known(header.created_ms),
+        modified_ms: known(header.modified_ms),
+        modified_by: header.modified_by,
+        tags: header.tags,
+    })
+}
+
+/// Size of a value as the client sent it and its SHA-256.
+fn size_and_digest(record: &Record<Stored>) -> io::Result<(u64, String)> {
+    match &record.body {
+        Stored::Blob(manifest) => Ok((manifest.size, manifest.sha256.clone())),
+        Stored::Value(stored) => {
+            let value = compression::unpack_value(stored)?;
+            let sha256 = record
+                .header
+                .as_ref()
+                .map_or_else(|| record::digest(&value), |header| header.sha256.clone());
+            Ok((value.len() as u64, sha256))
+        }
+    }
+}
+
+#[debug_handler]
+pub async fn delete_key(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.delete");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    match rocksdb::delete(
+        &state.rocksdb,
+        &state.encryption,
+        &state.history,
+        &state.indexes,
+        &key,
+    ) {
+        Ok(deleted) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Deleted { key, deleted })
+        }
+        Err(e) => {
+            let message = format!("cannot delete key \"{}\": {}", &key, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
             response::internal_server_error(message)
         }
     }
 }
+
+/// Whether `Accept-Encoding` allows `codec` with a non-zero quality, by
+/// name or, when it is not named, through `*`. A codec named with `q=0` is
+/// refused whatever `*` allows.
+pub fn accepts_encoding(headers: &HeaderMap, codec: Codec) -> bool {
+    let mut named = None;
+    let mut wildcard = None;
+    let items = headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','));
+    for item in items {
+        let mut parts = item.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let quality = parts
+            .find_map(|param| param.strip_prefix("q="))
+            .map_or(1.0, |q| q.parse::<f32>().unwrap_or(0.0));
+        if name.eq_ignore_ascii_case(codec.name()) {
+            named = Some(quality);
+        } else if name == "*" {
+            wildcard = Some(quality);
+        }
+    }
+    named.or(wildcard).is_some_and(|quality| quality > 0.0)
+}
@@ -0,0 +1,28 @@
+use crate::{
+    api::response,
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{extract::State, http::HeaderMap, response::Response};
+use axum_macros::debug_handler;
+use opentelemetry::trace::Span;
+
+/// Reports whether the opened view is fresh enough to serve reads.
+#[debug_handler]
+pub async fn ready(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.ready");
+
+    let readiness = state.view.readiness();
+    span.set_attribute(opentelemetry::KeyValue::new(
+        "staleness_ms",
+        readiness.staleness_ms as i64,
+    ));
+    if readiness.ready {
+        span.set_status(opentelemetry::trace::Status::Ok);
+        response::success(readiness)
+    } else {
+        span.set_status(opentelemetry::trace::Status::error("view is stale"));
+        response::service_unavailable(readiness)
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    api::response,
+    storage::{blob, compression, history, record::Record, rocksdb},
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use axum_macros::debug_handler;
+use opentelemetry::trace::Span;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+#[derive(Deserialize, Debug)]
+pub struct HistoryQuery {
+    key: String,
+}
+
+#[derive(Serialize, Debug)]
+struct KeyHistory {
+    key: String,
+    /// Newest first, starting with the current value if the key exists.
+    versions: Vec<Entry>,
+}
+
+#[derive(Serialize, Debug)]
+struct Entry {
+    version: u64,
+    modified_ms: Option<u64>,
+    /// When the next write replaced or deleted this version; absent for the
+    /// current value.
+    replaced_ms: Option<u64>,
+    sha256: String,
+    /// Chunked values are listed without their contents.
+    chunked: bool,
+    value: Option<String>,
+}
+
+impl Entry {
+    fn of(record: Record, version: u64, replaced_ms: Option<u64>) -> io::Result<Self> {
+        let sha256 = record.sha256();
+        let header = record.header.unwrap_or_default();
+        let chunked = blob::is_manifest(&record.body);
+        let value = if chunked {
+            None
+        } else {
+            let value = compression::unpack_value(&record.body)?;
+            Some(String::from_utf8_lossy(&value).into_owned())
+        };
+        Ok(Entry {
+            version,
+            modified_ms: Some(header.modified_ms).filter(|ms| *ms > 0),
+            replaced_ms,
+            sha256,
+            chunked,
+            value,
+        })
+    }
+}
+
+/// Lists the current value of a key and the versions history retains.
+#[debug_handler]
+pub async fn history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.history");
+    span.set_attribute(opentelemetry::KeyValue::new("key", query.key.clone()));
+
+    let read =
+        rocksdb::get_decrypted(&state.rocksdb, &state.encryption, &query.key).and_then(|current| {
+            let replaced =
+                history::versions(&state.rocksdb, &state.encryption, query.key.as_bytes())?;
+            Ok((current, replaced))
+        });
+    let (current, replaced) = match read {
+        Ok(read) => read,
+        Err(e) => {
+            let message = format!("cannot read history of key \"{}\": {}", &query.key, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::internal_server_error(message);
+        }
+    };
+    if current.is_none() && replaced.is_empty() {
+        let message = format!("key \"{}\" not found", &query.key);
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::not_found(message);
+    }
+
+    let current = current.map(|record| {
+        let version = record
+            .header
+            .as_ref()
+            .map_or(1, |header| header.version.max(1));
+        Entry::of(record, version, None)
+    });
+    let replaced = replaced
+        .into_iter()
+        .rev()
+        .map(|kept| Entry::of(kept.record, kept.version, Some(kept.replaced_ms)));
+    match current.into_iter().chain(replaced).collect() {
+        Ok(versions) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(KeyHistory {
+                key: query.key,
+                versions,
+            })
+        }
+        Err(e) => {
+            let message = format!("cannot decompress history of key \"{}\": {}", &query.key, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            response::internal_server_error(message)
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{
+    api::response,
+    limits::ClientName,
+    storage::import::{self, Format, ImportError, Target},
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+    Extension,
+};
+use axum_macros::debug_handler;
+use futures_util::StreamExt;
+use opentelemetry::trace::Span;
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Deserialize, Debug)]
+pub struct ImportQuery {
+    format: Format,
+}
+
+/// Stages an NDJSON, CSV or SST upload and imports it in the background,
+/// answering at once with the progress to poll under `/admin/import/{id}`.
+#[debug_handler]
+pub async fn start(
+    State(state): State<AppState>,
+    Query(query): Query<ImportQuery>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.import.start");
+
+    let (progress, upload) = match state.imports.start(&state.rocksdb, query.format) {
+        Ok(started) => started,
+        Err(e) => {
+            let message = format!("cannot import: {}", e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::internal_server_error(message);
+        }
+    };
+    let id = progress.lock().unwrap().id.clone();
+    span.set_attribute(opentelemetry::KeyValue::new("import", id.clone()));
+
+    if let Err(e) = stage(body, &upload).await {
+        let _ = tokio::fs::remove_file(&upload).await;
+        let message = format!("cannot import: {}", e);
+        let mut failed = progress.lock().unwrap();
+        failed.error = Some(message.clone());
+        failed.phase = import::Phase::Failed;
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::bad_request(message);
+    }
+
+    let client = client.map_or_else(|| "anonymous".to_string(), |Extension(name)| name.0);
+    let job = progress.clone();
+    tokio::task::spawn_blocking(move || {
+        let target = Target {
+            db: &state.rocksdb,
+            encryption: &state.encryption,
+            history: &state.history,
+            indexes: &state.indexes,
+            stored_compression: state.stored_compression,
+            documents: &state.documents,
+            schemas: &state.schemas,
+            limits: &state.limits,
+            client: &client,
+        };
+        import::run(&target, &upload, &job);
+    });
+    span.set_status(opentelemetry::trace::Status::Ok);
+    let started = progress.lock().unwrap().clone();
+    response::accepted(started)
+}
+
+/// Reports how far an import has got and the rows it rejected.
+#[debug_handler]
+pub async fn status(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.import.status");
+    span.set_attribute(opentelemetry::KeyValue::new("import", id.clone()));
+
+    match state.imports.get(&id) {
+        Some(progress) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(progress)
+        }
+        None => {
+            let message = format!("no import \"{}\"", &id);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            response::not_found(message)
+        }
+    }
+}
+
+/// Lists the imports since the server started.
+#[debug_handler]
+pub async fn list(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.import.list");
+    span.set_status(opentelemetry::trace::Status::Ok);
+    response::success(state.imports.list())
+}
+
+/// Writes the request body to `upload` as it arrives.
+async fn stage(body: Body, upload: &std::path::Path) -> Result<(), ImportError> {
+    let mut file = tokio::fs::File::create(upload).await?;
+    let mut frames = body.into_data_stream();
+    while let Some(frame) = frames.next().await {
+        let data = frame.map_err(std::io::Error::other)?;
+        file.write_all(&data).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
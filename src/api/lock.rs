@@ -0,0 +1,178 @@
+use crate::{
+    api::response,
+    limits::ClientName,
+    storage::lock::{Lease, LockError},
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+    Extension,
+};
+use axum_macros::debug_handler;
+use opentelemetry::{global::BoxedSpan, trace::Span};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_TTL_SECONDS: u64 = 30;
+
+#[derive(Deserialize, Debug)]
+pub struct AcquireQuery {
+    /// Defaults to the authenticated client.
+    owner: Option<String>,
+    /// Seconds the lease lasts unless renewed.
+    ttl: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LeaseQuery {
+    token: String,
+    ttl: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+struct Leased {
+    name: String,
+    #[serde(flatten)]
+    lease: Lease,
+}
+
+#[derive(Serialize, Debug)]
+struct LockStatus {
+    name: String,
+    fence: u64,
+    held: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_ms: Option<u64>,
+}
+
+/// Takes a lease on a lock if no one else holds it, answering with the
+/// owner token and a fencing token larger than any handed out before.
+#[debug_handler]
+pub async fn acquire(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<AcquireQuery>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.lock.acquire");
+    span.set_attribute(opentelemetry::KeyValue::new("lock", name.clone()));
+
+    let owner = query.owner.unwrap_or_else(|| {
+        client.map_or_else(|| "anonymous".to_string(), |Extension(name)| name.0)
+    });
+    match state.locks.acquire(
+        &state.rocksdb,
+        &state.encryption,
+        &name,
+        &owner,
+        ttl_ms(query.ttl),
+    ) {
+        Ok(lease) => {
+            span.set_attribute(opentelemetry::KeyValue::new("fence", lease.fence as i64));
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Leased { name, lease })
+        }
+        Err(e) => failed(&mut span, &name, "acquire", e),
+    }
+}
+
+/// Extends a lease that has not yet expired.
+#[debug_handler]
+pub async fn renew(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<LeaseQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.lock.renew");
+    span.set_attribute(opentelemetry::KeyValue::new("lock", name.clone()));
+
+    match state.locks.renew(
+        &state.rocksdb,
+        &state.encryption,
+        &name,
+        &query.token,
+        ttl_ms(query.ttl),
+    ) {
+        Ok(lease) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Leased { name, lease })
+        }
+        Err(e) => failed(&mut span, &name, "renew", e),
+    }
+}
+
+/// Ends a lease early, freeing the lock for the next owner.
+#[debug_handler]
+pub async fn release(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<LeaseQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.lock.release");
+    span.set_attribute(opentelemetry::KeyValue::new("lock", name.clone()));
+
+    match state
+        .locks
+        .release(&state.rocksdb, &state.encryption, &name, &query.token)
+    {
+        Ok(()) => {
+            let message = format!("released lock \"{}\" successfully", &name);
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(message)
+        }
+        Err(e) => failed(&mut span, &name, "release", e),
+    }
+}
+
+/// Reports who holds a lock, if anyone, and its latest fencing token.
+#[debug_handler]
+pub async fn status(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.lock.status");
+    span.set_attribute(opentelemetry::KeyValue::new("lock", name.clone()));
+
+    match state.locks.status(&state.rocksdb, &state.encryption, &name) {
+        Ok(lock) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(LockStatus {
+                name,
+                fence: lock.fence,
+                held: lock.lease.is_some(),
+                owner: lock.lease.as_ref().map(|lease| lease.owner.clone()),
+                expires_ms: lock.lease.as_ref().map(|lease| lease.expires_ms),
+            })
+        }
+        Err(e) => failed(&mut span, &name, "read", e),
+    }
+}
+
+fn ttl_ms(ttl: Option<u64>) -> u64 {
+    ttl.unwrap_or(DEFAULT_TTL_SECONDS).saturating_mul(1000)
+}
+
+fn failed(span: &mut BoxedSpan, name: &str, action: &str, e: LockError) -> Response {
+    let message = format!("cannot {} lock \"{}\": {}", action, name, e);
+    span.set_status(opentelemetry::trace::Status::error(message.clone()));
+    match e {
+        LockError::InvalidName(_) | LockError::InvalidTtl => response::bad_request(message),
+        LockError::Held { .. } | LockError::NotHeld => response::conflict(message),
+        LockError::Unavailable => response::service_unavailable(message),
+        LockError::Corrupt(_) | LockError::Encryption(_) | LockError::Storage(_) => {
+            response::internal_server_error(message)
+        }
+    }
+}
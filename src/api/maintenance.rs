@@ -0,0 +1,313 @@
+use crate::{
+    api::response,
+    storage::maintenance::{self, MaintenanceError},
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use axum_macros::debug_handler;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use opentelemetry::{global::BoxedSpan, trace::Span};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+#[derive(Deserialize, Debug)]
+pub struct ColumnFamilyQuery {
+    /// Column family to act on; all of them, or the default one for
+    /// options and properties, when absent.
+    column_family: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CompactQuery {
+    column_family: Option<String>,
+    /// First key to compact; from the start when absent.
+    from: Option<String>,
+    /// Last key to compact; to the end when absent.
+    to: Option<String>,
+    #[serde(default)]
+    key_encoding: KeyEncoding,
+}
+
+/// How `from` and `to` are written, so that binary keys can be given.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyEncoding {
+    #[default]
+    Utf8,
+    Hex,
+    Base64,
+}
+
+impl KeyEncoding {
+    fn decode(self, key: &str) -> Result<Vec<u8>, String> {
+        match self {
+            KeyEncoding::Utf8 => Ok(key.as_bytes().to_vec()),
+            KeyEncoding::Hex => {
+                if !key.is_ascii() || !key.len().is_multiple_of(2) {
+                    return Err(format!("\"{}\" is not hex", key));
+                }
+                (0..key.len())
+                    .step_by(2)
+                    .map(|at| u8::from_str_radix(&key[at..at + 2], 16))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("\"{}\" is not hex", key))
+            }
+            KeyEncoding::Base64 => STANDARD
+                .decode(key)
+                .map_err(|e| format!("\"{}\" is not base64: {}", key, e)),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Done {
+    column_families: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct Property {
+    name: String,
+    value: String,
+}
+
+/// Compacts a key range of one column family, or the whole DB. Returns
+/// once compaction has finished. Bounds are UTF-8 unless `key_encoding`
+/// is `hex` or `base64`.
+#[debug_handler]
+pub async fn compact(
+    State(state): State<AppState>,
+    Query(query): Query<CompactQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.compact");
+    if let Some(name) = &query.column_family {
+        span.set_attribute(opentelemetry::KeyValue::new("column_family", name.clone()));
+    }
+
+    if let Err(message) = check_writable(&state) {
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::method_not_allowed(message);
+    }
+    let decode = |key: &Option<String>| {
+        key.as_deref()
+            .map(|key| query.key_encoding.decode(key))
+            .transpose()
+    };
+    let (from, to) = match (decode(&query.from), decode(&query.to)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => {
+            let message = format!("cannot compact: {}", e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::bad_request(message);
+        }
+    };
+    let db = state.rocksdb.clone();
+    let compacted = tokio::task::spawn_blocking(move || {
+        maintenance::compact(
+            &db,
+            query.column_family.as_deref(),
+            from.as_deref(),
+            to.as_deref(),
+        )
+    })
+    .await;
+    match compacted {
+        Ok(Ok(column_families)) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Done { column_families })
+        }
+        Ok(Err(e)) => failed(&mut span, "cannot compact".to_string(), e),
+        Err(e) => {
+            let message = format!("cannot compact: {}", e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            response::internal_server_error(message)
+        }
+    }
+}
+
+/// Flushes the memtables of one column family, or of all of them, to SST
+/// files.
+#[debug_handler]
+pub async fn flush(
+    State(state): State<AppState>,
+    Query(query): Query<ColumnFamilyQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.flush");
+
+    if let Err(message) = check_writable(&state) {
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::method_not_allowed(message);
+    }
+    let db = state.rocksdb.clone();
+    let flushed = tokio::task::spawn_blocking(move || {
+        maintenance::flush(&db, query.column_family.as_deref())
+    })
+    .await;
+    match flushed {
+        Ok(Ok(column_families)) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Done { column_families })
+        }
+        Ok(Err(e)) => failed(&mut span, "cannot flush".to_string(), e),
+        Err(e) => {
+            let message = format!("cannot flush: {}", e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            response::internal_server_error(message)
+        }
+    }
+}
+
+/// Syncs the WAL to disk.
+#[debug_handler]
+pub async fn sync_wal(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.wal.sync");
+
+    if let Err(message) = check_writable(&state) {
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::method_not_allowed(message);
+    }
+    let db = state.rocksdb.clone();
+    match tokio::task::spawn_blocking(move || maintenance::sync_wal(&db)).await {
+        Ok(Ok(())) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success("synced the WAL successfully")
+        }
+        Ok(Err(e)) => failed(&mut span, "cannot sync the WAL".to_string(), e),
+        Err(e) => {
+            let message = format!("cannot sync the WAL: {}", e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            response::internal_server_error(message)
+        }
+    }
+}
+
+/// Changes the mutable options in the body, a JSON object of option names
+/// to values, until the server restarts.
+#[debug_handler]
+pub async fn set_options(
+    State(state): State<AppState>,
+    Query(query): Query<ColumnFamilyQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.options");
+
+    if let Err(message) = check_writable(&state) {
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::method_not_allowed(message);
+    }
+    let options = match parse_options(&body) {
+        Ok(options) => options,
+        Err(message) => {
+            let message = format!("cannot set options: {}", message);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::bad_request(message);
+        }
+    };
+    let pairs: Vec<(&str, &str)> = options
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    match maintenance::set_options(&state.rocksdb, query.column_family.as_deref(), &pairs) {
+        Ok(()) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(options)
+        }
+        Err(e) => failed(&mut span, "cannot set options".to_string(), e),
+    }
+}
+
+/// Reads a `rocksdb.*` property, such as `rocksdb.stats` or
+/// `rocksdb.estimate-num-keys`.
+#[debug_handler]
+pub async fn property(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<ColumnFamilyQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.property");
+    span.set_attribute(opentelemetry::KeyValue::new("property", name.clone()));
+
+    match maintenance::property(&state.rocksdb, query.column_family.as_deref(), &name) {
+        Ok(value) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Property { name, value })
+        }
+        Err(e) => failed(&mut span, "cannot read property".to_string(), e),
+    }
+}
+
+/// Lists the SST files the DB currently uses, with their levels and key
+/// ranges.
+#[debug_handler]
+pub async fn live_files(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.live_files");
+
+    match maintenance::live_files(&state.rocksdb) {
+        Ok(files) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(files)
+        }
+        Err(e) => failed(&mut span, "cannot list live files".to_string(), e),
+    }
+}
+
+/// Refuses maintenance that writes files when the DB was opened without
+/// write access. Followers keep their own files, so they may.
+fn check_writable(state: &AppState) -> Result<(), String> {
+    let mode = state.view.mode();
+    match mode.is_writable() {
+        true => Ok(()),
+        false => Err(format!(
+            "maintenance is disabled: the database is opened in {} mode",
+            mode.name()
+        )),
+    }
+}
+
+/// Reads a JSON object of option names to strings, numbers or booleans.
+fn parse_options(body: &[u8]) -> Result<BTreeMap<String, String>, String> {
+    let options: BTreeMap<String, Value> =
+        serde_json::from_slice(body).map_err(|e| format!("invalid JSON: {}", e))?;
+    if options.is_empty() {
+        return Err("no options given".to_string());
+    }
+    options
+        .into_iter()
+        .map(|(name, value)| match value {
+            Value::String(value) => Ok((name, value)),
+            Value::Number(_) | Value::Bool(_) => Ok((name, value.to_string())),
+            _ => Err(format!(
+                "option \"{}\" must be a string, number or boolean",
+                name
+            )),
+        })
+        .collect()
+}
+
+fn failed(span: &mut BoxedSpan, context: String, e: MaintenanceError) -> Response {
+    let message = format!("{}: {}", context, e);
+    span.set_status(opentelemetry::trace::Status::error(message.clone()));
+    match e {
+        MaintenanceError::UnknownColumnFamily(_) | MaintenanceError::InvalidOptions(_) => {
+            response::bad_request(message)
+        }
+        MaintenanceError::UnknownProperty(_) => response::not_found(message),
+        MaintenanceError::Storage(_) => response::internal_server_error(message),
+    }
+}
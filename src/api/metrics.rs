@@ -0,0 +1,85 @@
+use crate::{
+    limits::escape_label,
+    replication::ReplicationStatus,
+    storage::queue::Depth,
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use axum_macros::debug_handler;
+use opentelemetry::trace::Span;
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Serves throttling counters, queue depths and, on a follower, how far
+/// it trails the primary in the Prometheus text format.
+#[debug_handler]
+pub async fn metrics(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.metrics");
+
+    let mut body = state.limits.metrics();
+    match state.queues.depths(&state.rocksdb) {
+        Ok(depths) => body.push_str(&queue_metrics(&depths)),
+        Err(e) => println!("Error reading queue depths: {}", e),
+    }
+    if state.replication.is_follower() {
+        let status = state
+            .replication
+            .status(state.rocksdb.latest_sequence_number());
+        body.push_str(&replication_metrics(&status));
+    }
+    span.set_status(opentelemetry::trace::Status::Ok);
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+fn queue_metrics(depths: &BTreeMap<String, Depth>) -> String {
+    let mut out = String::new();
+    out.push_str("# HELP rocksdb_queue_messages Messages in each queue by state.\n");
+    out.push_str("# TYPE rocksdb_queue_messages gauge\n");
+    for (name, depth) in depths {
+        let states = [
+            ("waiting", depth.waiting),
+            ("in_flight", depth.in_flight),
+            ("dead_letter", depth.dead_letters),
+        ];
+        for (state, count) in states {
+            let _ = writeln!(
+                out,
+                "rocksdb_queue_messages{{queue=\"{}\",state=\"{}\"}} {}",
+                escape_label(name),
+                state,
+                count
+            );
+        }
+    }
+    out
+}
+
+/// Lag gauges of a follower; only those it has values for, so a follower
+/// that has not reached the primary yet reports none rather than zero.
+fn replication_metrics(status: &ReplicationStatus) -> String {
+    let mut out = String::new();
+    if let Some(lag) = status.lag_sequences {
+        out.push_str(
+            "# HELP rocksdb_replication_lag_sequences Sequence numbers the follower is behind the primary.\n",
+        );
+        out.push_str("# TYPE rocksdb_replication_lag_sequences gauge\n");
+        let _ = writeln!(out, "rocksdb_replication_lag_sequences {}", lag);
+    }
+    if let Some(ms_ago) = status.last_contact_ms_ago {
+        out.push_str(
+            "# HELP rocksdb_replication_last_contact_seconds Seconds since the follower last heard from the primary.\n",
+        );
+        out.push_str("# TYPE rocksdb_replication_last_contact_seconds gauge\n");
+        let _ = writeln!(
+            out,
+            "rocksdb_replication_last_contact_seconds {}",
+            ms_ago as f64 / 1000.0
+        );
+    }
+    out
+}
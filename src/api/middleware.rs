@@ -0,0 +1,362 @@
+use crate::{
+    api::response,
+    auth::{
+        acl::{Access, Permission},
+        audit::{self, AuditEvent, Outcome, ValueDigest},
+        Principal,
+    },
+    limits::{ClientName, Throttled},
+    server::tls::ClientIdentity,
+    storage::{
+        self, lock::LOCK_COLUMN_FAMILY, queue::QUEUE_COLUMN_FAMILY,
+        timeseries::TIMESERIES_COLUMN_FAMILY,
+    },
+    telemetry::tracing::{
+        current_span, extract_context_from_request, inject_context_into_request, trace_id,
+    },
+    AppState,
+};
+use axum::{
+    extract::{MatchedPath, Query, Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::{
+    trace::{Span, Status, TraceContextExt},
+    KeyValue,
+};
+use percent_encoding::percent_decode_str;
+use rocksdb::DEFAULT_COLUMN_FAMILY_NAME;
+use serde::{Deserialize, Serialize};
+
+/// Prefixes of the resource-style key routes; the rest of the path is the
+/// key.
+const KEY_PREFIXES: &[&str] = &["/v1/kv/", "/v1/hash/", "/v1/set/", "/v1/zset/"];
+/// Prefixes of the routes over a column family of their own, with that
+/// column family; the next path segment names the queue, lock or series.
+const NAMED_ROUTES: &[(&str, &str)] = &[
+    ("/queue/", QUEUE_COLUMN_FAMILY),
+    ("/lock/", LOCK_COLUMN_FAMILY),
+    ("/ts/", TIMESERIES_COLUMN_FAMILY),
+];
+
+/// Routes naming their key in the `key` query parameter.
+const KEY_QUERY_ROUTES: &[&str] = &["/get", "/put", "/patch", "/meta", "/history", "/blob"];
+
+/// Paths that load balancers and orchestrators probe without credentials.
+const PUBLIC_PATHS: &[&str] = &["/ready"];
+
+#[derive(Serialize, Debug)]
+struct UnauthorizedBody {
+    error: &'static str,
+    message: String,
+}
+
+#[derive(Serialize, Debug)]
+struct ForbiddenBody {
+    error: &'static str,
+    message: String,
+}
+
+#[derive(Serialize, Debug)]
+struct ThrottledBody {
+    error: &'static str,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct KeyQuery {
+    key: Option<String>,
+}
+
+/// Wraps the whole request in a span that records the caller's verified TLS
+/// identity and authenticated principal, and makes it the parent of the span
+/// the handler opens.
+pub async fn trace_request(mut request: Request, next: Next) -> Response {
+    let parent_cx = extract_context_from_request(request.headers());
+    let mut span = current_span(parent_cx.clone(), "rocksdb.http.request");
+    span.set_attribute(KeyValue::new(
+        "http.request.method",
+        request.method().to_string(),
+    ));
+    span.set_attribute(KeyValue::new("url.path", request.uri().path().to_string()));
+    if let Some(identity) = request.extensions().get::<ClientIdentity>() {
+        span.set_attribute(KeyValue::new(
+            "tls.client.subject",
+            identity.subject.clone(),
+        ));
+        span.set_attribute(KeyValue::new(
+            "tls.client.serial_number",
+            identity.serial.clone(),
+        ));
+    }
+
+    let cx = parent_cx.with_span(span);
+    inject_context_into_request(&cx, request.headers_mut());
+    let response = next.run(request).await;
+
+    let span = cx.span();
+    if let Some(principal) = response.extensions().get::<Principal>() {
+        span.set_attribute(KeyValue::new("enduser.id", principal.name.clone()));
+        span.set_attribute(KeyValue::new(
+            "enduser.auth_method",
+            principal.method.name(),
+        ));
+    }
+    let status = response.status();
+    span.set_attribute(KeyValue::new(
+        "http.response.status_code",
+        i64::from(status.as_u16()),
+    ));
+    if status.is_server_error() {
+        span.set_status(Status::error(status.to_string()));
+    } else {
+        span.set_status(Status::Ok);
+    }
+    span.end();
+    response
+}
+
+/// Rejects requests without valid credentials and attaches the caller's
+/// `Principal` to the request for handlers, and to the response so the
+/// request span can record it.
+pub async fn authenticate(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if PUBLIC_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+    match state.auth.authenticate(request.headers()) {
+        Ok(Some(principal)) => {
+            request.extensions_mut().insert(principal.clone());
+            let mut response = next.run(request).await;
+            response.extensions_mut().insert(principal);
+            response
+        }
+        Ok(None) => next.run(request).await,
+        Err(e) => response::unauthorized(UnauthorizedBody {
+            error: e.code(),
+            message: e.to_string(),
+        }),
+    }
+}
+
+/// Applies the token bucket of the calling client on the matched route and
+/// tags the request with a `ClientName` for later quota checks.
+pub async fn limit_rate(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if PUBLIC_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+    let client = match (
+        request.extensions().get::<Principal>(),
+        request.extensions().get::<ClientIdentity>(),
+    ) {
+        (Some(principal), _) => principal.name.clone(),
+        (None, Some(identity)) => identity.subject.clone(),
+        (None, None) => "anonymous".to_string(),
+    };
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    if let Err(throttled) = state.limits.check_rate(&client, &route) {
+        return throttled_response(throttled);
+    }
+    request.extensions_mut().insert(ClientName(client));
+    next.run(request).await
+}
+
+/// 429 for a request rejected by a rate limit or quota.
+pub fn throttled_response(throttled: Throttled) -> Response {
+    response::too_many_requests(
+        ThrottledBody {
+            error: throttled.reason.name(),
+            message: throttled.message,
+        },
+        throttled.retry_after,
+    )
+}
+
+/// Checks the authenticated principal against the access rules and
+/// audit-logs every denial.
+pub async fn authorize(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if PUBLIC_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+    let access = required_access(&request);
+    let principal = request.extensions().get::<Principal>();
+    if state.acl.check(principal, &access) {
+        return next.run(request).await;
+    }
+
+    let name = principal.map(|principal| principal.name.clone());
+    let message = format!(
+        "{} is not allowed {}",
+        name.as_deref().unwrap_or("anonymous caller"),
+        access
+    );
+    let mut event = AuditEvent::denied(
+        name,
+        request.method().to_string(),
+        request.uri().path().to_string(),
+        access,
+    );
+    event.trace_id = trace_id(&extract_context_from_request(request.headers()));
+    audit::record(state.audit.clone(), event).await;
+    response::forbidden(ForbiddenBody {
+        error: "forbidden",
+        message,
+    })
+}
+
+/// Writes an audit record for every call to a mutating or admin route,
+/// including the hash of any value the handler stored.
+pub async fn audit_mutation(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let principal = request
+        .extensions()
+        .get::<Principal>()
+        .map(|principal| principal.name.clone());
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| request.uri().path(), |path| path.as_str())
+        .to_string();
+    let method = request.method().to_string();
+    let key = request_key(&request);
+    let trace_id = trace_id(&extract_context_from_request(request.headers()));
+
+    let response = next.run(request).await;
+
+    let status = response.status();
+    let outcome = if status.is_success() {
+        Outcome::Succeeded
+    } else {
+        Outcome::Failed
+    };
+    let mut event = AuditEvent::new(method, route, outcome);
+    event.principal = principal;
+    event.key = key;
+    event.value_sha256 = response
+        .extensions()
+        .get::<ValueDigest>()
+        .map(|digest| digest.0.clone());
+    event.status = Some(status.as_u16());
+    event.trace_id = trace_id;
+    audit::record(state.audit.clone(), event).await;
+    response
+}
+
+/// Maps a request to the access it needs. Routes without a finer-grained
+/// mapping here need admin access to the whole database.
+fn required_access(request: &Request) -> Access {
+    let path = request.uri().path();
+    let key_access = |permission| Access {
+        permission,
+        column_family: Some(DEFAULT_COLUMN_FAMILY_NAME.to_string()),
+        key: request_key(request),
+    };
+    if KEY_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+        return match *request.method() {
+            Method::GET | Method::HEAD => key_access(Permission::Read),
+            _ => key_access(Permission::Write),
+        };
+    }
+    for (prefix, column_family) in NAMED_ROUTES {
+        let Some(rest) = path.strip_prefix(prefix) else {
+            continue;
+        };
+        let name = rest.split('/').next().unwrap_or_default();
+        let permission = match *request.method() {
+            Method::GET | Method::HEAD => Permission::Read,
+            _ => Permission::Write,
+        };
+        return Access {
+            permission,
+            column_family: Some(column_family.to_string()),
+            key: Some(percent_decode_str(name).decode_utf8_lossy().into_owned()),
+        };
+    }
+    match path {
+        "/get" | "/meta" | "/history" => key_access(Permission::Read),
+        "/put" | "/patch" => key_access(Permission::Write),
+        "/blob" if request.method() == Method::GET || request.method() == Method::HEAD => {
+            key_access(Permission::Read)
+        }
+        "/blob" => key_access(Permission::Write),
+        "/changes" | "/query" => Access::everything(Permission::Read),
+        _ => Access::everything(Permission::Admin),
+    }
+}
+
+/// The key a request names, either in a `/v1/` key path or the `key` query
+/// parameter.
+fn request_key(request: &Request) -> Option<String> {
+    let path = request.uri().path();
+    match KEY_PREFIXES
+        .iter()
+        .find_map(|prefix| path.strip_prefix(prefix))
+    {
+        Some(encoded) => Some(percent_decode_str(encoded).decode_utf8_lossy().into_owned()),
+        None => Query::<KeyQuery>::try_from_uri(request.uri())
+            .ok()
+            .and_then(|Query(query)| query.key),
+    }
+}
+
+/// Rejects keys in the reserved namespace on every key route, so clients
+/// cannot read or overwrite the entries the server keeps for itself.
+pub async fn reject_reserved_keys(request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    let names_key = KEY_PREFIXES.iter().any(|prefix| path.starts_with(prefix))
+        || KEY_QUERY_ROUTES.contains(&path);
+    if names_key {
+        if let Some(key) = request_key(&request) {
+            if storage::is_reserved_key(&key) {
+                return response::bad_request(format!(
+                    "invalid key {:?}: keys starting with {:?} are reserved",
+                    key,
+                    storage::RESERVED_KEY_PREFIX
+                ));
+            }
+        }
+    }
+    next.run(request).await
+}
+
+/// Rejects writes when the DB was opened without write access or while this
+/// server follows another primary.
+pub async fn require_writable(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mode = state.view.mode();
+    if !mode.is_writable() {
+        let message = format!(
+            "writes are disabled: the database is opened in {} mode",
+            mode.name()
+        );
+        return response::method_not_allowed(message);
+    }
+    if state.replication.is_follower() {
+        let message = format!(
+            "this server is a read-only follower of {}; send writes to the primary",
+            state.replication.primary_url().unwrap_or("another server")
+        );
+        return response::service_unavailable(message);
+    }
+    next.run(request).await
+}
@@ -2,8 +2,47 @@
 //!
 //! This module contains all HTTP-related functionality including:
 //! - Request handlers
+//! - Streaming uploads and ranged downloads
+//! - Audit log queries
+//! - Cache validators and conditional requests
+//! - Change data capture feed
+//! - Encryption status and key rotation
+//! - Key history
+//! - Bulk imports
+//! - Exports and restores of logical dumps
+//! - Secondary index queries
+//! - Durable queues
+//! - Distributed locks
+//! - Hashes, sets and sorted sets
+//! - Time series
+//! - Replication endpoints
+//! - Schema administration
+//! - Compaction, flushes, mutable options and DB properties
+//! - Readiness reporting
+//! - Throttling metrics
 //! - Response formatting
-//! - Middleware (future)
+//! - Middleware
+//! - Routing
 
+pub mod audit;
+pub mod blob;
+pub mod caching;
+pub mod changes;
+pub mod dump;
+pub mod encryption;
 pub mod handlers;
+pub mod health;
+pub mod history;
+pub mod import;
+pub mod lock;
+pub mod maintenance;
+pub mod metrics;
+pub mod middleware;
+pub mod query;
+pub mod queue;
+pub mod replication;
 pub mod response;
+pub mod routes;
+pub mod schemas;
+pub mod structures;
+pub mod timeseries;
@@ -0,0 +1,144 @@
+use crate::{
+    api::response,
+    storage::{
+        blob, compression,
+        index::{IndexError, Lookup},
+        rocksdb,
+    },
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use axum_macros::debug_handler;
+use opentelemetry::trace::Span;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const DEFAULT_LIMIT: usize = 1000;
+
+/// Terms are read as JSON when they parse as a string, number, boolean or
+/// null, so `eq=42` matches the number and `eq="42"` the string; anything
+/// else is taken as a string.
+#[derive(Deserialize, Debug)]
+pub struct IndexQuery {
+    index: String,
+    eq: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    /// Returns the values along with their keys.
+    #[serde(default)]
+    documents: bool,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+struct Matches {
+    index: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keys: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    documents: Option<Vec<Document>>,
+}
+
+#[derive(Serialize, Debug)]
+struct Document {
+    key: String,
+    /// The value as JSON, or as a string if it does not parse.
+    value: Value,
+}
+
+/// Looks up keys, or their values, through a secondary index.
+#[debug_handler]
+pub async fn query(
+    State(state): State<AppState>,
+    Query(query): Query<IndexQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.query");
+    span.set_attribute(opentelemetry::KeyValue::new("index", query.index.clone()));
+
+    let lookup = match (query.eq.as_deref(), &query.from, &query.to) {
+        (Some(eq), None, None) => Lookup::Eq(term(eq)),
+        (None, from, to) => Lookup::Range {
+            from: from.as_deref().map(term),
+            to: to.as_deref().map(term),
+        },
+        _ => {
+            let message = "eq cannot be combined with from or to".to_string();
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::bad_request(message);
+        }
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    let keys = match state
+        .indexes
+        .query(&state.rocksdb, &query.index, &lookup, limit)
+    {
+        Ok(keys) => keys,
+        Err(e) => {
+            let message = format!("cannot query index \"{}\": {}", &query.index, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return match e {
+                IndexError::UnknownIndex(_) => response::not_found(message),
+                IndexError::Unindexable(_) => response::bad_request(message),
+                IndexError::Storage(_) => response::internal_server_error(message),
+            };
+        }
+    };
+    span.set_attribute(opentelemetry::KeyValue::new("matches", keys.len() as i64));
+    let keys: Vec<String> = keys
+        .iter()
+        .map(|key| String::from_utf8_lossy(key).into_owned())
+        .collect();
+
+    if !query.documents {
+        span.set_status(opentelemetry::trace::Status::Ok);
+        return response::success(Matches {
+            index: query.index,
+            keys: Some(keys),
+            documents: None,
+        });
+    }
+    let mut documents = Vec::with_capacity(keys.len());
+    for key in keys {
+        let value = match rocksdb::get_decrypted(&state.rocksdb, &state.encryption, &key) {
+            Ok(Some(record)) if !blob::is_manifest(&record.body) => {
+                compression::unpack_value(&record.body).map_err(|e| e.to_string())
+            }
+            // Deleted or replaced by a chunked value since the lookup.
+            Ok(_) => continue,
+            Err(e) => Err(e.to_string()),
+        };
+        match value {
+            Ok(value) => documents.push(Document {
+                value: serde_json::from_slice(&value).unwrap_or_else(|_| {
+                    Value::String(String::from_utf8_lossy(&value).into_owned())
+                }),
+                key,
+            }),
+            Err(e) => {
+                let message = format!("cannot get key \"{}\": {}", &key, e);
+                span.set_status(opentelemetry::trace::Status::error(message.clone()));
+                return response::internal_server_error(message);
+            }
+        }
+    }
+    span.set_status(opentelemetry::trace::Status::Ok);
+    response::success(Matches {
+        index: query.index,
+        keys: None,
+        documents: Some(documents),
+    })
+}
+
+fn term(raw: &str) -> Value {
+    match serde_json::from_str(raw) {
+        Ok(value @ (Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_))) => value,
+        _ => Value::String(raw.to_string()),
+    }
+}
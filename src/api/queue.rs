@@ -0,0 +1,295 @@
+use crate::{
+    api::{middleware::throttled_response, response, schemas},
+    limits::ClientName,
+    storage::queue::{Depth, QueueError, QUEUE_COLUMN_FAMILY},
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use axum_macros::debug_handler;
+use opentelemetry::{global::BoxedSpan, trace::Span};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const DEFAULT_VISIBILITY_TIMEOUT_SECONDS: u64 = 30;
+const DEFAULT_LIMIT: usize = 100;
+/// Most messages delivered, or dead letters listed, per request.
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+pub struct EnqueueQuery {
+    /// Seconds before the message is first delivered.
+    #[serde(default)]
+    delay: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DequeueQuery {
+    /// Seconds a delivered message stays hidden from other consumers.
+    visibility_timeout: Option<u64>,
+    max: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReceiptQuery {
+    receipt: String,
+    /// Seconds before a nacked message is delivered again.
+    #[serde(default)]
+    delay: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeadLetterQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+struct QueueStatus {
+    name: String,
+    #[serde(flatten)]
+    depth: Depth,
+}
+
+#[derive(Serialize, Debug)]
+struct Answered {
+    id: u64,
+    dead_lettered: bool,
+}
+
+/// Appends the request body to a queue. With a schema attached to the
+/// queues column family the body must be a JSON document valid against it.
+#[debug_handler]
+pub async fn enqueue(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<EnqueueQuery>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.queue.enqueue");
+    span.set_attribute(opentelemetry::KeyValue::new("queue", name.clone()));
+
+    if state.schemas.covers_column_family(QUEUE_COLUMN_FAMILY) {
+        let document = match serde_json::from_str::<Value>(&body) {
+            Ok(document) => document,
+            Err(e) => {
+                let message = format!(
+                    "cannot enqueue to \"{}\": not a JSON document: {}",
+                    &name, e
+                );
+                span.set_status(opentelemetry::trace::Status::error(message.clone()));
+                return response::bad_request(message);
+            }
+        };
+        if let Err(violations) = state
+            .schemas
+            .validate_column_family(QUEUE_COLUMN_FAMILY, &document)
+        {
+            let message = format!(
+                "cannot enqueue to \"{}\": message does not match its schema",
+                &name
+            );
+            return schemas::rejected(&mut span, message, violations);
+        }
+    }
+
+    let client = client.map_or_else(|| "anonymous".to_string(), |Extension(name)| name.0);
+    if let Err(throttled) = state.limits.check_quota(
+        &state.rocksdb,
+        &state.encryption,
+        &client,
+        "/queue/:name/messages",
+        name.as_bytes(),
+        body.len() as u64,
+    ) {
+        span.set_status(opentelemetry::trace::Status::error(
+            throttled.message.clone(),
+        ));
+        return throttled_response(throttled);
+    }
+    match state.queues.enqueue(
+        &state.rocksdb,
+        &state.encryption,
+        &name,
+        body,
+        query.delay.saturating_mul(1000),
+    ) {
+        Ok(message) => {
+            span.set_attribute(opentelemetry::KeyValue::new("id", message.id as i64));
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(message)
+        }
+        Err(e) => failed(&mut span, format!("cannot enqueue to \"{}\"", &name), e),
+    }
+}
+
+/// Delivers the visible messages of a queue that have been due longest; an
+/// empty list if there are none.
+#[debug_handler]
+pub async fn dequeue(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<DequeueQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.queue.dequeue");
+    span.set_attribute(opentelemetry::KeyValue::new("queue", name.clone()));
+
+    let visibility = query
+        .visibility_timeout
+        .unwrap_or(DEFAULT_VISIBILITY_TIMEOUT_SECONDS);
+    match state.queues.dequeue(
+        &state.rocksdb,
+        &state.encryption,
+        &name,
+        visibility.saturating_mul(1000),
+        query.max.unwrap_or(1).min(MAX_LIMIT),
+    ) {
+        Ok(messages) => {
+            span.set_attribute(opentelemetry::KeyValue::new(
+                "delivered",
+                messages.len() as i64,
+            ));
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(messages)
+        }
+        Err(e) => failed(&mut span, format!("cannot dequeue from \"{}\"", &name), e),
+    }
+}
+
+/// Acknowledges a delivered message, removing it from the queue.
+#[debug_handler]
+pub async fn ack(
+    State(state): State<AppState>,
+    Path((name, id)): Path<(String, u64)>,
+    Query(query): Query<ReceiptQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.queue.ack");
+    span.set_attribute(opentelemetry::KeyValue::new("queue", name.clone()));
+    span.set_attribute(opentelemetry::KeyValue::new("id", id as i64));
+
+    match state
+        .queues
+        .ack(&state.rocksdb, &state.encryption, &name, id, &query.receipt)
+    {
+        Ok(()) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Answered {
+                id,
+                dead_lettered: false,
+            })
+        }
+        Err(e) => failed(
+            &mut span,
+            format!("cannot ack message {} of \"{}\"", id, &name),
+            e,
+        ),
+    }
+}
+
+/// Hands a delivered message back for redelivery, or to the dead letters
+/// once it has used up its attempts.
+#[debug_handler]
+pub async fn nack(
+    State(state): State<AppState>,
+    Path((name, id)): Path<(String, u64)>,
+    Query(query): Query<ReceiptQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.queue.nack");
+    span.set_attribute(opentelemetry::KeyValue::new("queue", name.clone()));
+    span.set_attribute(opentelemetry::KeyValue::new("id", id as i64));
+
+    match state.queues.nack(
+        &state.rocksdb,
+        &state.encryption,
+        &name,
+        id,
+        &query.receipt,
+        query.delay.saturating_mul(1000),
+    ) {
+        Ok(dead_lettered) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Answered { id, dead_lettered })
+        }
+        Err(e) => failed(
+            &mut span,
+            format!("cannot nack message {} of \"{}\"", id, &name),
+            e,
+        ),
+    }
+}
+
+/// Reports how many messages a queue holds in each state.
+#[debug_handler]
+pub async fn status(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.queue.status");
+    span.set_attribute(opentelemetry::KeyValue::new("queue", name.clone()));
+
+    match state.queues.depth(&state.rocksdb, &name) {
+        Ok(depth) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(QueueStatus { name, depth })
+        }
+        Err(e) => failed(&mut span, format!("cannot read queue \"{}\"", &name), e),
+    }
+}
+
+/// Lists the messages of a queue that were dead-lettered, oldest first.
+#[debug_handler]
+pub async fn dead_letters(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<DeadLetterQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.queue.dead_letters");
+    span.set_attribute(opentelemetry::KeyValue::new("queue", name.clone()));
+
+    match state.queues.dead_letters(
+        &state.rocksdb,
+        &state.encryption,
+        &name,
+        query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT),
+    ) {
+        Ok(messages) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(messages)
+        }
+        Err(e) => failed(
+            &mut span,
+            format!("cannot read dead letters of \"{}\"", &name),
+            e,
+        ),
+    }
+}
+
+fn failed(span: &mut BoxedSpan, context: String, e: QueueError) -> Response {
+    let message = format!("{}: {}", context, e);
+    span.set_status(opentelemetry::trace::Status::error(message.clone()));
+    match e {
+        QueueError::InvalidName(_) => response::bad_request(message),
+        QueueError::UnknownMessage(_) => response::not_found(message),
+        QueueError::StaleReceipt(_) => response::conflict(message),
+        QueueError::Unavailable => response::service_unavailable(message),
+        QueueError::Corrupt(_) | QueueError::Encryption(_) | QueueError::Storage(_) => {
+            response::internal_server_error(message)
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{
+    api::response,
+    replication::{WalBatch, WalFrame},
+    storage::{
+        checkpoint, dump,
+        wal::{self, ChangesError},
+    },
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+};
+use axum_macros::debug_handler;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::stream;
+use opentelemetry::trace::Span;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::{
+    io::AsyncReadExt,
+    time::{sleep, Instant},
+};
+
+const WAL_BATCH_LIMIT: usize = 1000;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Deserialize, Debug)]
+pub struct WalQuery {
+    since: u64,
+}
+
+#[debug_handler]
+pub async fn create_checkpoint(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.replication.checkpoint");
+
+    match checkpoint::create(&state.rocksdb) {
+        Ok(info) => {
+            span.set_attribute(opentelemetry::KeyValue::new("checkpoint", info.id.clone()));
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(info)
+        }
+        Err(e) => {
+            let message = format!("cannot create checkpoint: {}", e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            response::internal_server_error(message)
+        }
+    }
+}
+
+#[debug_handler]
+pub async fn checkpoint_file(
+    State(state): State<AppState>,
+    Path((id, file)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.replication.checkpoint_file");
+    span.set_attribute(opentelemetry::KeyValue::new("checkpoint", id.clone()));
+    span.set_attribute(opentelemetry::KeyValue::new("file", file.clone()));
+
+    let opened = match checkpoint::checkpoint_file_path(&state.rocksdb, &id, &file) {
+        Some(path) => tokio::fs::File::open(path).await.ok(),
+        None => None,
+    };
+    let Some(opened) = opened else {
+        let message = format!("checkpoint file \"{}/{}\" not found", id, file);
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return response::not_found(message);
+    };
+
+    let chunks = stream::unfold(Some(opened), |opened| async move {
+        let mut opened = opened?;
+        let mut buffer = vec![0; FILE_CHUNK_SIZE];
+        match opened.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((Ok(buffer), Some(opened)))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+    span.set_status(opentelemetry::trace::Status::Ok);
+
+    let mut response = Response::new(Body::from_stream(chunks));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    response
+}
+
+#[debug_handler]
+pub async fn remove_checkpoint(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.replication.remove_checkpoint");
+    span.set_attribute(opentelemetry::KeyValue::new("checkpoint", id.clone()));
+
+    match checkpoint::remove(&state.rocksdb, &id) {
+        Ok(_) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(format!("removed checkpoint \"{}\" successfully", id))
+        }
+        Err(e) => {
+            let message = format!("cannot remove checkpoint \"{}\": {}", id, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            response::not_found(message)
+        }
+    }
+}
+
+/// Streams raw write batches after `since` as NDJSON `WalFrame`s.
+#[debug_handler]
+pub async fn wal(
+    State(state): State<AppState>,
+    Query(query): Query<WalQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.replication.wal");
+    span.set_attribute(opentelemetry::KeyValue::new("since", query.since as i64));
+
+    // Surface a purged WAL as a status code before the stream starts.
+    if let Err(e) = wal::batches_since(&state.rocksdb, query.since, 1) {
+        let message = format!("cannot stream WAL since {}: {}", query.since, e);
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        return match e {
+            ChangesError::Purged { .. } => response::gone(message),
+            _ => response::internal_server_error(message),
+        };
+    }
+    span.set_status(opentelemetry::trace::Status::Ok);
+
+    let frames = stream::unfold(Some((state, query.since)), |pending| async move {
+        let (state, since) = pending?;
+        match next_frame(&state, since).await {
+            Ok((frame, next)) => {
+                let mut line = serde_json::to_string(&frame).unwrap();
+                line.push('\n');
+                Some((Ok(line), Some((state, next))))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+
+    let mut response = Response::new(Body::from_stream(frames));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    response
+}
+
+/// Waits for batches after `since`, or returns a heartbeat frame.
+async fn next_frame(state: &AppState, since: u64) -> Result<(WalFrame, u64), ChangesError> {
+    let deadline = Instant::now() + HEARTBEAT_INTERVAL;
+    loop {
+        let (mut frame, next) = read_frame(state, since)?;
+        if !frame.batches.is_empty() || Instant::now() >= deadline {
+            frame.column_families = dump::column_families(&state.rocksdb);
+            return Ok((frame, next));
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+fn read_frame(state: &AppState, since: u64) -> Result<(WalFrame, u64), ChangesError> {
+    let mut next = since;
+    let mut batches = Vec::new();
+    for (seq, write_batch) in wal::batches_since(&state.rocksdb, since, WAL_BATCH_LIMIT)? {
+        next = wal::last_sequence(seq, &write_batch);
+        batches.push(WalBatch {
+            seq,
+            data: STANDARD.encode(write_batch.data()),
+        });
+    }
+    let frame = WalFrame {
+        primary_sequence: state.rocksdb.latest_sequence_number(),
+        batches,
+        column_families: Vec::new(),
+    };
+    Ok((frame, next))
+}
+
+#[debug_handler]
+pub async fn status(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.replication.status");
+
+    let status = state
+        .replication
+        .status(state.rocksdb.latest_sequence_number());
+    if let Some(lag) = status.lag_sequences {
+        span.set_attribute(opentelemetry::KeyValue::new("lag_sequences", lag as i64));
+    }
+    span.set_status(opentelemetry::trace::Status::Ok);
+    response::success(status)
+}
+
+#[debug_handler]
+pub async fn promote(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.promote");
+
+    let message = if state.replication.promote() {
+        // Schemas replicated since start-up apply to writes from now on.
+        if let Err(e) = state.schemas.reload(&state.rocksdb, &state.encryption) {
+            println!("Error reloading schemas: {}", e);
+        }
+        "promoted to primary successfully"
+    } else {
+        "already a primary"
+    };
+    span.set_status(opentelemetry::trace::Status::Ok);
+    response::success(message)
+}
@@ -1,17 +1,86 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, time::Duration};
 
 use axum::response::{IntoResponse, Response};
-use axum::{http::StatusCode, Json};
+use axum::{
+    http::{header, StatusCode},
+    Json,
+};
 use serde::Serialize;
 
 pub fn success<T: Serialize>(body: T) -> Response {
     (StatusCode::OK, Json(body)).into_response()
 }
 
+pub fn accepted<T: Serialize>(body: T) -> Response {
+    (StatusCode::ACCEPTED, Json(body)).into_response()
+}
+
+/// 200 with a JSON body that is already compressed with `encoding`.
+pub fn precompressed(encoding: &'static str, body: Vec<u8>) -> Response {
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::CONTENT_ENCODING, encoding),
+            (header::VARY, "accept-encoding"),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+pub fn bad_request<T: Serialize>(body: T) -> Response {
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+pub fn unauthorized<T: Serialize>(body: T) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        Json(body),
+    )
+        .into_response()
+}
+
+pub fn forbidden<T: Serialize>(body: T) -> Response {
+    (StatusCode::FORBIDDEN, Json(body)).into_response()
+}
+
 pub fn not_found<T: Serialize>(body: T) -> Response {
     (StatusCode::NOT_FOUND, Json(body)).into_response()
 }
 
+pub fn method_not_allowed<T: Serialize>(body: T) -> Response {
+    (StatusCode::METHOD_NOT_ALLOWED, Json(body)).into_response()
+}
+
+pub fn conflict<T: Serialize>(body: T) -> Response {
+    (StatusCode::CONFLICT, Json(body)).into_response()
+}
+
+pub fn unprocessable_entity<T: Serialize>(body: T) -> Response {
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response()
+}
+
+pub fn gone<T: Serialize>(body: T) -> Response {
+    (StatusCode::GONE, Json(body)).into_response()
+}
+
+/// 429 with `Retry-After` rounded up to whole seconds.
+pub fn too_many_requests<T: Serialize>(body: T, retry_after: Duration) -> Response {
+    let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, seconds.to_string())],
+        Json(body),
+    )
+        .into_response()
+}
+
+pub fn service_unavailable<T: Serialize>(body: T) -> Response {
+    (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response()
+}
+
 pub fn internal_server_error<T: Serialize + Debug>(body: T) -> Response {
     println!("{:#?}", body);
     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -0,0 +1,136 @@
+use crate::{
+    api::{
+        audit, blob, changes, dump, encryption, handlers, health, history, import, lock,
+        maintenance, metrics,
+        middleware::{
+            audit_mutation, authenticate, authorize, limit_rate, reject_reserved_keys,
+            require_writable, trace_request,
+        },
+        query, queue, replication, schemas, structures, timeseries,
+    },
+    AppState,
+};
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware::{from_fn, from_fn_with_state},
+    routing::{delete, get, post, put},
+    Router,
+};
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate},
+        CompressionLayer, DefaultPredicate,
+    },
+    decompression::RequestDecompressionLayer,
+};
+
+pub fn router(state: AppState) -> Router {
+    let writes = Router::new()
+        .route("/put", post(handlers::put))
+        .route("/patch", post(handlers::patch))
+        .route("/blob", put(blob::put))
+        .route(
+            handlers::KV_ROUTE,
+            put(handlers::put_key)
+                .patch(handlers::patch_key)
+                .delete(handlers::delete_key),
+        )
+        .route("/queue/:name/messages", post(queue::enqueue))
+        .route("/queue/:name/dequeue", post(queue::dequeue))
+        .route("/queue/:name/messages/:id/ack", post(queue::ack))
+        .route("/queue/:name/messages/:id/nack", post(queue::nack))
+        .route(
+            structures::HASH_ROUTE,
+            post(structures::hset).delete(structures::hdel),
+        )
+        .route(
+            structures::SET_ROUTE,
+            post(structures::sadd).delete(structures::srem),
+        )
+        .route(
+            structures::ZSET_ROUTE,
+            post(structures::zadd).delete(structures::zrem),
+        )
+        .route("/ts/:series/points", post(timeseries::append))
+        .route("/lock/:name/acquire", post(lock::acquire))
+        .route("/lock/:name/renew", post(lock::renew))
+        .route("/lock/:name/release", post(lock::release))
+        .route("/admin/encryption/reencrypt", post(encryption::reencrypt))
+        .route("/admin/import", post(import::start))
+        .route("/admin/restore", post(dump::restore))
+        .route(
+            "/admin/schemas",
+            put(schemas::attach).delete(schemas::detach),
+        )
+        .route_layer(from_fn_with_state(state.clone(), require_writable));
+
+    let admin = Router::new()
+        .route(
+            "/replication/checkpoints",
+            post(replication::create_checkpoint),
+        )
+        .route(
+            "/replication/checkpoints/:id",
+            delete(replication::remove_checkpoint),
+        )
+        .route("/admin/promote", post(replication::promote))
+        .route("/admin/audit", get(audit::query))
+        .route("/admin/encryption", get(encryption::status))
+        .route("/admin/schemas", get(schemas::list))
+        .route("/admin/compact", post(maintenance::compact))
+        .route("/admin/flush", post(maintenance::flush))
+        .route("/admin/wal/sync", post(maintenance::sync_wal))
+        .route("/admin/options", put(maintenance::set_options))
+        .route("/admin/properties/:name", get(maintenance::property))
+        .route("/admin/live-files", get(maintenance::live_files))
+        .route("/admin/export", get(dump::export))
+        .route("/admin/import", get(import::list))
+        .route("/admin/import/:id", get(import::status));
+
+    let audited = writes
+        .merge(admin)
+        .route_layer(from_fn_with_state(state.clone(), audit_mutation));
+
+    Router::new()
+        .route("/get", post(handlers::get))
+        .route(
+            handlers::KV_ROUTE,
+            get(handlers::get_key).head(handlers::head_key),
+        )
+        .route(structures::HASH_ROUTE, get(structures::hget))
+        .route(structures::SET_ROUTE, get(structures::smembers))
+        .route(structures::ZSET_ROUTE, get(structures::zrangebyscore))
+        .route("/blob", get(blob::get))
+        .route("/meta", get(handlers::meta))
+        .route("/history", get(history::history))
+        .route("/query", get(query::query))
+        .route("/queue/:name", get(queue::status))
+        .route("/lock/:name", get(lock::status))
+        .route("/ts/:series", get(timeseries::range))
+        .route("/queue/:name/dead-letters", get(queue::dead_letters))
+        .route("/changes", get(changes::changes))
+        .route("/ready", get(health::ready))
+        .route("/metrics", get(metrics::metrics))
+        .route(
+            "/replication/checkpoints/:id/:file",
+            get(replication::checkpoint_file),
+        )
+        .route("/replication/wal", get(replication::wal))
+        .route("/replication/status", get(replication::status))
+        .merge(audited)
+        .layer(from_fn(reject_reserved_keys))
+        .layer(DefaultBodyLimit::max(200000000))
+        .layer(RequestDecompressionLayer::new())
+        .layer(CompressionLayer::new().compress_when(compressible()))
+        .layer(from_fn_with_state(state.clone(), authorize))
+        .layer(from_fn_with_state(state.clone(), limit_rate))
+        .layer(from_fn_with_state(state.clone(), authenticate))
+        .layer(from_fn(trace_request))
+        .with_state(state)
+}
+
+/// Streamed NDJSON feeds are left alone so records are not held back in the
+/// encoder's buffer.
+fn compressible() -> impl Predicate {
+    DefaultPredicate::new().and(NotForContentType::const_new("application/x-ndjson"))
+}
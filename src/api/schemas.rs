@@ -0,0 +1,177 @@
+use crate::{
+    api::response,
+    storage::schema::{SchemaError, Scope, Violation},
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use axum_macros::debug_handler;
+use opentelemetry::{global::BoxedSpan, trace::Span};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Deserialize, Debug)]
+pub struct SchemaQuery {
+    /// Key prefix the schema applies to; empty for every key.
+    #[serde(default)]
+    prefix: String,
+    /// Column family the schema applies to instead of a prefix.
+    column_family: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct Attached {
+    #[serde(flatten)]
+    scope: Scope,
+    schema: Value,
+}
+
+#[derive(Serialize, Debug)]
+struct Detached {
+    #[serde(flatten)]
+    scope: Scope,
+    deleted: bool,
+}
+
+/// Body of a 422 for a write that fails its schema.
+#[derive(Serialize, Debug)]
+struct Rejected {
+    message: String,
+    violations: Vec<Violation>,
+}
+
+/// Lists the schemas attached to key prefixes and column families.
+#[debug_handler]
+pub async fn list(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.schemas");
+
+    let schemas: Vec<Attached> = state
+        .schemas
+        .list()
+        .into_iter()
+        .map(|(scope, schema)| Attached { scope, schema })
+        .collect();
+    span.set_status(opentelemetry::trace::Status::Ok);
+    response::success(schemas)
+}
+
+/// Attaches the JSON Schema in the body to a key prefix or column family.
+/// Later writes under the prefix or to the column family are validated
+/// against it; stored values are not.
+#[debug_handler]
+pub async fn attach(
+    State(state): State<AppState>,
+    Query(query): Query<SchemaQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.schemas.attach");
+    let scope = match scope_of(&mut span, query, "attach") {
+        Ok(scope) => scope,
+        Err(message) => return response::bad_request(message),
+    };
+
+    let schema = match serde_json::from_slice::<Value>(&body) {
+        Ok(schema) => schema,
+        Err(e) => {
+            let message = format!("cannot attach schema to {}: invalid JSON: {}", &scope, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::bad_request(message);
+        }
+    };
+    match state
+        .schemas
+        .attach(&state.rocksdb, &state.encryption, &scope, schema.clone())
+    {
+        Ok(()) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Attached { scope, schema })
+        }
+        Err(e) => {
+            let message = format!("cannot attach schema to {}: {}", &scope, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            match e {
+                SchemaError::Invalid(_) | SchemaError::UnknownColumnFamily(_) => {
+                    response::bad_request(message)
+                }
+                SchemaError::Encryption(_) | SchemaError::Storage(_) => {
+                    response::internal_server_error(message)
+                }
+            }
+        }
+    }
+}
+
+/// Detaches the schema of a key prefix or column family.
+#[debug_handler]
+pub async fn detach(
+    State(state): State<AppState>,
+    Query(query): Query<SchemaQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.admin.schemas.detach");
+    let scope = match scope_of(&mut span, query, "detach") {
+        Ok(scope) => scope,
+        Err(message) => return response::bad_request(message),
+    };
+
+    match state.schemas.detach(&state.rocksdb, &scope) {
+        Ok(true) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Detached {
+                scope,
+                deleted: true,
+            })
+        }
+        Ok(false) => {
+            let message = format!("no schema is attached to {}", &scope);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            response::not_found(message)
+        }
+        Err(e) => {
+            let message = format!("cannot detach schema from {}: {}", &scope, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            response::internal_server_error(message)
+        }
+    }
+}
+
+/// 422 for a write failing the schemas listed in `violations`.
+pub fn rejected(span: &mut BoxedSpan, message: String, violations: Vec<Violation>) -> Response {
+    span.set_status(opentelemetry::trace::Status::error(message.clone()));
+    response::unprocessable_entity(Rejected {
+        message,
+        violations,
+    })
+}
+
+/// The prefix or column family named by the query, or the message to
+/// reject it with when it names both.
+fn scope_of(span: &mut BoxedSpan, query: SchemaQuery, operation: &str) -> Result<Scope, String> {
+    match query.column_family {
+        None => {
+            span.set_attribute(opentelemetry::KeyValue::new("prefix", query.prefix.clone()));
+            Ok(Scope::Prefix(query.prefix))
+        }
+        Some(name) if query.prefix.is_empty() => {
+            span.set_attribute(opentelemetry::KeyValue::new("column_family", name.clone()));
+            Ok(Scope::ColumnFamily(name))
+        }
+        Some(_) => {
+            let message = format!(
+                "cannot {} schema: give either a prefix or a column family",
+                operation
+            );
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            Err(message)
+        }
+    }
+}
@@ -0,0 +1,448 @@
+use crate::{
+    api::{middleware::throttled_response, response, schemas},
+    limits::ClientName,
+    storage::{
+        encryption::Encryption,
+        schema::Schemas,
+        structures::{self, Kind, StructureError},
+    },
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use axum_macros::debug_handler;
+use opentelemetry::{global::BoxedSpan, trace::Span};
+use rocksdb::DB;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const HASH_ROUTE: &str = "/v1/hash/*key";
+pub const SET_ROUTE: &str = "/v1/set/*key";
+pub const ZSET_ROUTE: &str = "/v1/zset/*key";
+
+const DEFAULT_LIMIT: usize = 1000;
+
+/// Without `field`, a page of fields: at most `limit` of them, starting
+/// after the field `after`.
+#[derive(Deserialize, Debug)]
+pub struct FieldQuery {
+    field: Option<String>,
+    after: Option<String>,
+    limit: Option<usize>,
+}
+
+/// A page of members: at most `limit` of them, starting after the member
+/// `after`.
+#[derive(Deserialize, Debug)]
+pub struct PageQuery {
+    after: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Bounds are inclusive and default to the lowest and highest scores.
+#[derive(Deserialize, Debug)]
+pub struct ScoreQuery {
+    min: Option<f64>,
+    max: Option<f64>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+struct Added {
+    key: String,
+    added: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct Removed {
+    key: String,
+    removed: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct Scored {
+    member: String,
+    score: f64,
+}
+
+/// HSET: sets the fields of a hash from a JSON object of strings.
+#[debug_handler]
+pub async fn hset(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.hset");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    let fields: BTreeMap<String, String> = match parse(&mut span, &key, "hset", &body) {
+        Ok(fields) => fields,
+        Err(message) => return response::bad_request(message),
+    };
+    let client = client.map_or_else(|| "anonymous".to_string(), |Extension(name)| name.0);
+    if let Err(throttled) = state.limits.check_quota(
+        &state.rocksdb,
+        &state.encryption,
+        &client,
+        HASH_ROUTE,
+        key.as_bytes(),
+        body.len() as u64,
+    ) {
+        span.set_status(opentelemetry::trace::Status::error(
+            throttled.message.clone(),
+        ));
+        return throttled_response(throttled);
+    }
+    match structures::hset(
+        &state.rocksdb,
+        &state.encryption,
+        &state.schemas,
+        &key,
+        &fields,
+    ) {
+        Ok(added) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Added { key, added })
+        }
+        Err(e) => failed(&mut span, &key, "hset", e),
+    }
+}
+
+/// HGETALL, or HGET with `field`: a page of the fields of a hash as a JSON
+/// object in field order, or the value of one field. A page with fewer than
+/// `limit` fields is the last.
+#[debug_handler]
+pub async fn hget(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(query): Query<FieldQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.hget");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    let Some(field) = query.field else {
+        return match structures::hgetall(
+            &state.rocksdb,
+            &state.encryption,
+            &key,
+            query.after.as_deref(),
+            query.limit.unwrap_or(DEFAULT_LIMIT),
+        ) {
+            Ok(fields) => {
+                span.set_status(opentelemetry::trace::Status::Ok);
+                response::success(fields)
+            }
+            Err(e) => failed(&mut span, &key, "hgetall", e),
+        };
+    };
+    match structures::hget(&state.rocksdb, &state.encryption, &key, &field) {
+        Ok(Some(value)) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(value)
+        }
+        Ok(None) => {
+            let message = format!("hash \"{}\" has no field \"{}\"", &key, &field);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            response::not_found(message)
+        }
+        Err(e) => failed(&mut span, &key, "hget", e),
+    }
+}
+
+/// SADD: adds the members of a JSON array of strings to a set.
+#[debug_handler]
+pub async fn sadd(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.sadd");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    let members: Vec<String> = match parse(&mut span, &key, "sadd", &body) {
+        Ok(members) => members,
+        Err(message) => return response::bad_request(message),
+    };
+    let client = client.map_or_else(|| "anonymous".to_string(), |Extension(name)| name.0);
+    if let Err(throttled) = state.limits.check_quota(
+        &state.rocksdb,
+        &state.encryption,
+        &client,
+        SET_ROUTE,
+        key.as_bytes(),
+        body.len() as u64,
+    ) {
+        span.set_status(opentelemetry::trace::Status::error(
+            throttled.message.clone(),
+        ));
+        return throttled_response(throttled);
+    }
+    match structures::sadd(
+        &state.rocksdb,
+        &state.encryption,
+        &state.schemas,
+        &key,
+        &members,
+    ) {
+        Ok(added) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Added { key, added })
+        }
+        Err(e) => failed(&mut span, &key, "sadd", e),
+    }
+}
+
+/// SMEMBERS: a page of the members of a set in byte order. A page with
+/// fewer than `limit` members is the last.
+#[debug_handler]
+pub async fn smembers(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(query): Query<PageQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.smembers");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    match structures::smembers(
+        &state.rocksdb,
+        &state.encryption,
+        &key,
+        query.after.as_deref(),
+        query.limit.unwrap_or(DEFAULT_LIMIT),
+    ) {
+        Ok(members) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(members)
+        }
+        Err(e) => failed(&mut span, &key, "smembers", e),
+    }
+}
+
+/// ZADD: adds members to a sorted set from a JSON object of member to
+/// score.
+#[debug_handler]
+pub async fn zadd(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.zadd");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    let members: BTreeMap<String, f64> = match parse(&mut span, &key, "zadd", &body) {
+        Ok(members) => members,
+        Err(message) => return response::bad_request(message),
+    };
+    let client = client.map_or_else(|| "anonymous".to_string(), |Extension(name)| name.0);
+    if let Err(throttled) = state.limits.check_quota(
+        &state.rocksdb,
+        &state.encryption,
+        &client,
+        ZSET_ROUTE,
+        key.as_bytes(),
+        body.len() as u64,
+    ) {
+        span.set_status(opentelemetry::trace::Status::error(
+            throttled.message.clone(),
+        ));
+        return throttled_response(throttled);
+    }
+    match structures::zadd(
+        &state.rocksdb,
+        &state.encryption,
+        &state.schemas,
+        &key,
+        &members,
+    ) {
+        Ok(added) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Added { key, added })
+        }
+        Err(e) => failed(&mut span, &key, "zadd", e),
+    }
+}
+
+/// ZRANGEBYSCORE: the members of a sorted set within a score range, lowest
+/// first.
+#[debug_handler]
+pub async fn zrangebyscore(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(query): Query<ScoreQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.zrangebyscore");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    match structures::zrangebyscore(
+        &state.rocksdb,
+        &state.encryption,
+        &key,
+        query.min.unwrap_or(f64::NEG_INFINITY),
+        query.max.unwrap_or(f64::INFINITY),
+        query.limit.unwrap_or(DEFAULT_LIMIT),
+    ) {
+        Ok(members) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            let members: Vec<Scored> = members
+                .into_iter()
+                .map(|(member, score)| Scored { member, score })
+                .collect();
+            response::success(members)
+        }
+        Err(e) => failed(&mut span, &key, "zrangebyscore", e),
+    }
+}
+
+/// HDEL: removes the fields in a JSON array of strings from a hash, or the
+/// whole hash without a body.
+#[debug_handler]
+pub async fn hdel(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.hdel");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    let removal = Removal {
+        kind: Kind::Hash,
+        operation: "hdel",
+        remove: structures::hdel,
+    };
+    remove(&state, &mut span, key, &body, removal)
+}
+
+/// SREM: removes the members in a JSON array of strings from a set, or the
+/// whole set without a body.
+#[debug_handler]
+pub async fn srem(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.srem");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    let removal = Removal {
+        kind: Kind::Set,
+        operation: "srem",
+        remove: structures::srem,
+    };
+    remove(&state, &mut span, key, &body, removal)
+}
+
+/// ZREM: removes the members in a JSON array of strings from a sorted set,
+/// or the whole sorted set without a body.
+#[debug_handler]
+pub async fn zrem(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.zrem");
+    span.set_attribute(opentelemetry::KeyValue::new("key", key.clone()));
+
+    let removal = Removal {
+        kind: Kind::SortedSet,
+        operation: "zrem",
+        remove: structures::zrem,
+    };
+    remove(&state, &mut span, key, &body, removal)
+}
+
+/// Removes the named fields or members from a structure.
+type Remove = fn(&DB, &Encryption, &Schemas, &str, &[String]) -> Result<u64, StructureError>;
+
+/// How to remove entries from one type of structure.
+struct Removal {
+    kind: Kind,
+    operation: &'static str,
+    remove: Remove,
+}
+
+/// Removes the entries named in `body` from the structure at `key`, or the
+/// whole structure when the body is empty.
+fn remove(
+    state: &AppState,
+    span: &mut BoxedSpan,
+    key: String,
+    body: &[u8],
+    removal: Removal,
+) -> Response {
+    let removed = if body.is_empty() {
+        structures::delete(&state.rocksdb, &state.encryption, removal.kind, &key)
+    } else {
+        let names: Vec<String> = match parse(span, &key, removal.operation, body) {
+            Ok(names) => names,
+            Err(message) => return response::bad_request(message),
+        };
+        (removal.remove)(
+            &state.rocksdb,
+            &state.encryption,
+            &state.schemas,
+            &key,
+            &names,
+        )
+    };
+    match removed {
+        Ok(removed) => {
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(Removed { key, removed })
+        }
+        Err(e) => failed(span, &key, removal.operation, e),
+    }
+}
+
+/// The body as JSON, or the message to reject it with.
+fn parse<T: DeserializeOwned>(
+    span: &mut BoxedSpan,
+    key: &str,
+    operation: &str,
+    body: &[u8],
+) -> Result<T, String> {
+    serde_json::from_slice(body).map_err(|e| {
+        let message = format!("cannot {} key \"{}\": invalid body: {}", operation, key, e);
+        span.set_status(opentelemetry::trace::Status::error(message.clone()));
+        message
+    })
+}
+
+fn failed(span: &mut BoxedSpan, key: &str, operation: &str, e: StructureError) -> Response {
+    let message = format!("cannot {} key \"{}\": {}", operation, key, e);
+    span.set_status(opentelemetry::trace::Status::error(message.clone()));
+    match e {
+        StructureError::Invalid(violations) => schemas::rejected(span, message, violations),
+        StructureError::InvalidKey(_) | StructureError::InvalidScore(_) => {
+            response::bad_request(message)
+        }
+        StructureError::Corrupt(_) | StructureError::Encryption(_) | StructureError::Storage(_) => {
+            response::internal_server_error(message)
+        }
+    }
+}
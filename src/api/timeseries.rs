@@ -0,0 +1,184 @@
+use crate::{
+    api::{middleware::throttled_response, response, schemas},
+    limits::ClientName,
+    storage::{
+        record,
+        schema::Violation,
+        timeseries::{
+            Aggregation, Appended, Point, Selection, TimeSeriesError, TIMESERIES_COLUMN_FAMILY,
+        },
+    },
+    telemetry::tracing::{current_span, extract_context_from_request},
+    AppState,
+};
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use axum_macros::debug_handler;
+use opentelemetry::{global::BoxedSpan, trace::Span};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LIMIT: usize = 10_000;
+
+/// A point to append; without a timestamp it is taken at arrival.
+#[derive(Deserialize, Debug)]
+struct Sample {
+    timestamp: Option<u64>,
+    value: f64,
+}
+
+/// Bounds are inclusive Unix milliseconds and default to the whole series.
+#[derive(Deserialize, Debug)]
+pub struct RangeQuery {
+    from: Option<u64>,
+    to: Option<u64>,
+    /// Milliseconds per aggregated bucket; raw points without it.
+    step: Option<u64>,
+    #[serde(default)]
+    agg: Aggregation,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+struct AppendedTo {
+    series: String,
+    #[serde(flatten)]
+    appended: Appended,
+}
+
+/// Appends a JSON array of `{"timestamp", "value"}` points to a series.
+/// With a schema attached to the time series column family every point must
+/// be valid against it, timestamps taken at arrival included.
+#[debug_handler]
+pub async fn append(
+    State(state): State<AppState>,
+    Path(series): Path<String>,
+    client: Option<Extension<ClientName>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.timeseries.append");
+    span.set_attribute(opentelemetry::KeyValue::new("series", series.clone()));
+
+    let samples: Vec<Sample> = match serde_json::from_slice(&body) {
+        Ok(samples) => samples,
+        Err(e) => {
+            let message = format!("cannot append to \"{}\": invalid body: {}", &series, e);
+            span.set_status(opentelemetry::trace::Status::error(message.clone()));
+            return response::bad_request(message);
+        }
+    };
+    let client = client.map_or_else(|| "anonymous".to_string(), |Extension(name)| name.0);
+    if let Err(throttled) = state.limits.check_quota(
+        &state.rocksdb,
+        &state.encryption,
+        &client,
+        "/ts/:series/points",
+        series.as_bytes(),
+        body.len() as u64,
+    ) {
+        span.set_status(opentelemetry::trace::Status::error(
+            throttled.message.clone(),
+        ));
+        return throttled_response(throttled);
+    }
+    let now = record::unix_millis();
+    let points: Vec<Point> = samples
+        .into_iter()
+        .map(|sample| Point {
+            timestamp: sample.timestamp.unwrap_or(now),
+            value: sample.value,
+        })
+        .collect();
+    if state.schemas.covers_column_family(TIMESERIES_COLUMN_FAMILY) {
+        let violations = check_points(&state, &points);
+        if !violations.is_empty() {
+            let message = format!(
+                "cannot append to \"{}\": points do not match their schema",
+                &series
+            );
+            return schemas::rejected(&mut span, message, violations);
+        }
+    }
+    match state
+        .timeseries
+        .append(&state.rocksdb, &state.encryption, &series, &points)
+    {
+        Ok(appended) => {
+            span.set_attribute(opentelemetry::KeyValue::new("points", points.len() as i64));
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(AppendedTo { series, appended })
+        }
+        Err(e) => failed(&mut span, format!("cannot append to \"{}\"", &series), e),
+    }
+}
+
+/// Reads the points of a series within a time range, raw or aggregated
+/// into buckets of `step` milliseconds.
+#[debug_handler]
+pub async fn range(
+    State(state): State<AppState>,
+    Path(series): Path<String>,
+    Query(query): Query<RangeQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let parent_cx = extract_context_from_request(&headers);
+    let mut span = current_span(parent_cx, "rocksdb.http.timeseries.range");
+    span.set_attribute(opentelemetry::KeyValue::new("series", series.clone()));
+
+    let selection = Selection {
+        from: query.from.unwrap_or(0),
+        to: query.to.unwrap_or(u64::MAX),
+        step: query.step,
+        aggregation: query.agg,
+        limit: query.limit.unwrap_or(DEFAULT_LIMIT),
+    };
+    match state
+        .timeseries
+        .range(&state.rocksdb, &state.encryption, &series, selection)
+    {
+        Ok(points) => {
+            span.set_attribute(opentelemetry::KeyValue::new("points", points.len() as i64));
+            span.set_status(opentelemetry::trace::Status::Ok);
+            response::success(points)
+        }
+        Err(e) => failed(&mut span, format!("cannot read \"{}\"", &series), e),
+    }
+}
+
+/// How `points` fail the time series schema, with paths into the array
+/// of points.
+fn check_points(state: &AppState, points: &[Point]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for (index, point) in points.iter().enumerate() {
+        let document = serde_json::to_value(point).unwrap_or_default();
+        if let Err(failed) = state
+            .schemas
+            .validate_column_family(TIMESERIES_COLUMN_FAMILY, &document)
+        {
+            violations.extend(failed.into_iter().map(|mut violation| {
+                violation.path = format!("/{}{}", index, violation.path);
+                violation
+            }));
+        }
+    }
+    violations
+}
+
+fn failed(span: &mut BoxedSpan, context: String, e: TimeSeriesError) -> Response {
+    let message = format!("{}: {}", context, e);
+    span.set_status(opentelemetry::trace::Status::error(message.clone()));
+    match e {
+        TimeSeriesError::InvalidName(_)
+        | TimeSeriesError::InvalidValue(_)
+        | TimeSeriesError::InvalidStep => response::bad_request(message),
+        TimeSeriesError::Unavailable => response::service_unavailable(message),
+        TimeSeriesError::Corrupt(_)
+        | TimeSeriesError::Encryption(_)
+        | TimeSeriesError::Storage(_) => response::internal_server_error(message),
+    }
+}
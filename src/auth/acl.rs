@@ -0,0 +1,195 @@
+use crate::auth::Principal;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+/// Rules naming this principal apply to every caller.
+pub const ANY_PRINCIPAL: &str = "*";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Read,
+    Write,
+    /// Replication, promotion and other server-wide operations. Implies read
+    /// and write.
+    Admin,
+}
+
+impl Permission {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Permission::Read => "read",
+            Permission::Write => "write",
+            Permission::Admin => "admin",
+        }
+    }
+
+    fn grants(&self, requested: Permission) -> bool {
+        *self == Permission::Admin || *self == requested
+    }
+}
+
+/// What a request needs. `None` for the column family or key means the
+/// request touches all of them, which only unrestricted rules cover.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Access {
+    pub permission: Permission,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column_family: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl Access {
+    pub fn everything(permission: Permission) -> Self {
+        Access {
+            permission,
+            column_family: None,
+            key: None,
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} access to ", self.permission.name())?;
+        match (&self.column_family, &self.key) {
+            (Some(cf), Some(key)) => write!(f, "key \"{}\" in column family \"{}\"", key, cf),
+            (Some(cf), None) => write!(f, "column family \"{}\"", cf),
+            (None, Some(key)) => write!(f, "key \"{}\"", key),
+            (None, None) => write!(f, "the whole database"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub principal: String,
+    #[serde(default)]
+    pub column_family: Option<String>,
+    #[serde(default)]
+    pub prefix: Option<String>,
+    pub permissions: Vec<Permission>,
+}
+
+impl Rule {
+    fn allows(&self, principal: &Principal, access: &Access) -> bool {
+        let principal_matches = self.principal == ANY_PRINCIPAL || self.principal == principal.name;
+        let column_family_matches = match &self.column_family {
+            None => true,
+            Some(cf) => access.column_family.as_ref() == Some(cf),
+        };
+        let key_matches = match &self.prefix {
+            None => true,
+            Some(prefix) => access
+                .key
+                .as_ref()
+                .is_some_and(|key| key.starts_with(prefix.as_str())),
+        };
+        principal_matches
+            && column_family_matches
+            && key_matches
+            && self
+                .permissions
+                .iter()
+                .any(|permission| permission.grants(access.permission))
+    }
+}
+
+/// Contents of the file named by `ROCKSDB_ACL_CONFIG`.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct AclConfig {
+    pub rules: Vec<Rule>,
+}
+
+impl AclConfig {
+    /// Anything not granted by a rule is denied.
+    pub fn allows(&self, principal: &Principal, access: &Access) -> bool {
+        self.rules.iter().any(|rule| rule.allows(principal, access))
+    }
+}
+
+/// Access rules that are re-read whenever their file changes. Without a file
+/// every request is allowed.
+#[derive(Debug)]
+pub struct AccessControl {
+    path: Option<PathBuf>,
+    current: RwLock<Arc<AclConfig>>,
+    loaded_at: RwLock<Option<SystemTime>>,
+}
+
+impl AccessControl {
+    pub fn disabled() -> Self {
+        AccessControl {
+            path: None,
+            current: RwLock::new(Arc::new(AclConfig::default())),
+            loaded_at: RwLock::new(None),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let config = load_config(path)?;
+        Ok(AccessControl {
+            path: Some(path.to_path_buf()),
+            current: RwLock::new(Arc::new(config)),
+            loaded_at: RwLock::new(modification_time(path)),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn current(&self) -> Arc<AclConfig> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn check(&self, principal: Option<&Principal>, access: &Access) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+        principal.is_some_and(|principal| self.current().allows(principal, access))
+    }
+
+    /// Reloads the rules if the file changed since the last load. A broken
+    /// update is logged and the previous rules kept.
+    pub fn reload_if_changed(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let modified = modification_time(path);
+        if *self.loaded_at.read().unwrap() == modified {
+            return;
+        }
+        match load_config(path) {
+            Ok(config) => {
+                *self.current.write().unwrap() = Arc::new(config);
+                println!("Reloaded access rules from {:?}", path);
+            }
+            Err(e) => println!("Error reloading access rules, keeping previous: {}", e),
+        }
+        *self.loaded_at.write().unwrap() = modified;
+    }
+
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            self.reload_if_changed();
+        }
+    }
+}
+
+fn load_config(path: &Path) -> Result<AclConfig, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {:?}: {}", path, e))?;
+    serde_json::from_str(&contents).map_err(|e| format!("invalid {:?}: {}", path, e))
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,537 @@
+use crate::auth::acl::Access;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// `prev_hash` of the first record in a log.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Size at which the current file is rotated when no limit is configured.
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Succeeded,
+    Failed,
+    Denied,
+}
+
+/// What happened, before it is placed in the chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    pub timestamp_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub principal: Option<String>,
+    pub method: String,
+    pub route: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<Access>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    pub outcome: Outcome,
+}
+
+impl AuditEvent {
+    pub fn new(method: String, route: String, outcome: Outcome) -> Self {
+        AuditEvent {
+            timestamp_ms: unix_millis(),
+            principal: None,
+            method,
+            route,
+            key: None,
+            value_sha256: None,
+            access: None,
+            status: None,
+            trace_id: None,
+            outcome,
+        }
+    }
+
+    pub fn denied(principal: Option<String>, method: String, path: String, access: Access) -> Self {
+        AuditEvent {
+            principal,
+            access: Some(access),
+            ..AuditEvent::new(method, path, Outcome::Denied)
+        }
+    }
+}
+
+/// SHA-256 of a written value, attached to the response by handlers so the
+/// audit middleware can record it without buffering the body itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueDigest(pub String);
+
+impl ValueDigest {
+    pub fn of(value: &[u8]) -> Self {
+        ValueDigest(hex(&Sha256::digest(value)))
+    }
+}
+
+/// One line of the audit log. `hash` covers every other field, including
+/// `prev_hash`, so editing or dropping a record breaks the chain after it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub seq: u64,
+    pub prev_hash: String,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hash: String,
+}
+
+impl AuditRecord {
+    fn chained(seq: u64, prev_hash: String, event: AuditEvent) -> Self {
+        let mut record = AuditRecord {
+            seq,
+            prev_hash,
+            event,
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash();
+        record
+    }
+
+    fn compute_hash(&self) -> String {
+        let unsigned = AuditRecord {
+            hash: String::new(),
+            ..self.clone()
+        };
+        let body = serde_json::to_vec(&unsigned).unwrap_or_default();
+        hex(&Sha256::digest(body))
+    }
+}
+
+/// Result of walking a run of records.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChainStatus {
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub broken_at: Option<u64>,
+}
+
+/// Checks that each record's hash is intact and links to the one before it.
+/// `prev_hash` is the hash expected before the first record, if known.
+pub fn verify_chain(records: &[AuditRecord], prev_hash: Option<&str>) -> ChainStatus {
+    let mut expected = prev_hash.map(str::to_string);
+    for record in records {
+        let linked = expected
+            .as_deref()
+            .is_none_or(|hash| hash == record.prev_hash);
+        if !linked || record.hash != record.compute_hash() {
+            return ChainStatus {
+                valid: false,
+                broken_at: Some(record.seq),
+            };
+        }
+        expected = Some(record.hash.clone());
+    }
+    ChainStatus {
+        valid: true,
+        broken_at: None,
+    }
+}
+
+#[derive(Debug)]
+struct FileSink {
+    path: PathBuf,
+    file: Arc<File>,
+    size: u64,
+    max_bytes: u64,
+}
+
+#[derive(Debug)]
+struct Chain {
+    next_seq: u64,
+    last_hash: String,
+    sink: Option<FileSink>,
+}
+
+/// What is known of one log file, from verifying it or from writing it,
+/// and the size and modification time that knowledge holds for.
+#[derive(Debug, Clone, PartialEq)]
+struct Verified {
+    len: u64,
+    modified: Option<SystemTime>,
+    /// `seq` and `prev_hash` of the first record.
+    first: Option<(u64, String)>,
+    last_hash: Option<String>,
+    broken_at: Option<u64>,
+}
+
+/// Append-only, hash-chained audit log. Records go to a file that is rotated
+/// by size, or to stdout when no file is configured. Writers append under
+/// one lock and sync outside it, one sync covering every record appended
+/// before it. Files are verified once and again only when they change
+/// behind the log's back.
+#[derive(Debug)]
+pub struct AuditLog {
+    chain: Mutex<Chain>,
+    /// Highest `seq` known to be on disk.
+    synced: Mutex<u64>,
+    verified: Mutex<HashMap<PathBuf, Verified>>,
+}
+
+impl AuditLog {
+    pub fn stdout() -> Self {
+        AuditLog {
+            chain: Mutex::new(Chain {
+                next_seq: 1,
+                last_hash: GENESIS_HASH.to_string(),
+                sink: None,
+            }),
+            synced: Mutex::new(0),
+            verified: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn open(path: &Path) -> io::Result<Self> {
+        AuditLog::open_with_rotation(path, DEFAULT_MAX_FILE_BYTES)
+    }
+
+    /// Opens `path` for appending and resumes the chain from its last record.
+    /// Once the file grows past `max_bytes` it is renamed to
+    /// `<path>.<first seq of the next file>` and a new one started.
+    pub fn open_with_rotation(path: &Path, max_bytes: u64) -> io::Result<Self> {
+        let last = log_files(path)?
+            .iter()
+            .rev()
+            .find_map(|file| read_records(file).ok()?.pop());
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        let (next_seq, last_hash) = match last {
+            Some(record) => (record.seq + 1, record.hash),
+            None => (1, GENESIS_HASH.to_string()),
+        };
+        Ok(AuditLog {
+            chain: Mutex::new(Chain {
+                next_seq,
+                last_hash,
+                sink: Some(FileSink {
+                    path: path.to_path_buf(),
+                    file: Arc::new(file),
+                    size,
+                    max_bytes,
+                }),
+            }),
+            synced: Mutex::new(next_seq - 1),
+            verified: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Path of the current file, when records are kept on disk.
+    pub fn path(&self) -> Option<PathBuf> {
+        let chain = self.chain.lock().unwrap();
+        chain.sink.as_ref().map(|sink| sink.path.clone())
+    }
+
+    /// Appends a record for `event` and returns once it is on disk.
+    pub fn record(&self, event: AuditEvent) {
+        let seq = {
+            let mut chain = self.chain.lock().unwrap();
+            let record = AuditRecord::chained(chain.next_seq, chain.last_hash.clone(), event);
+            let line = match serde_json::to_string(&record) {
+                Ok(line) => line,
+                Err(e) => {
+                    println!("Error serializing audit record {:?}: {}", record, e);
+                    return;
+                }
+            };
+
+            match &mut chain.sink {
+                Some(sink) => {
+                    if let Err(e) = sink.append(&line, &record, &self.verified) {
+                        println!("Error writing audit record {}: {}", line, e);
+                        return;
+                    }
+                }
+                None => println!("audit: {}", line),
+            }
+            chain.next_seq = record.seq + 1;
+            chain.last_hash = record.hash;
+            record.seq
+        };
+        if let Err(e) = self.sync(seq) {
+            println!("Error syncing audit record {}: {}", seq, e);
+        }
+    }
+
+    /// Makes sure records up to `seq` are on disk. Whoever syncs covers the
+    /// records appended so far, so writers waiting here usually find theirs
+    /// already synced.
+    fn sync(&self, seq: u64) -> io::Result<()> {
+        let mut synced = self.synced.lock().unwrap();
+        if *synced >= seq {
+            return Ok(());
+        }
+        let (file, written) = {
+            let chain = self.chain.lock().unwrap();
+            let file = chain.sink.as_ref().map(|sink| sink.file.clone());
+            (file, chain.next_seq - 1)
+        };
+        if let Some(file) = file {
+            file.sync_data()?;
+        }
+        *synced = written;
+        Ok(())
+    }
+
+    /// Every stored record, oldest first, across rotated files.
+    pub fn read_all(&self) -> io::Result<Vec<AuditRecord>> {
+        let mut records = Vec::new();
+        self.scan(0, |record| {
+            records.push(record);
+            true
+        })?;
+        Ok(records)
+    }
+
+    /// Hands the stored records after `since` to `visit`, oldest first,
+    /// until it returns false. Rotated files holding only earlier records
+    /// are not read.
+    pub fn scan(&self, since: u64, mut visit: impl FnMut(AuditRecord) -> bool) -> io::Result<()> {
+        let path = self.queryable_path()?;
+        for file in log_files(&path)? {
+            // A rotated file holds the records before its suffix.
+            if file != path && rotated_seq(&file).is_some_and(|next| next <= since + 1) {
+                continue;
+            }
+            for record in read_records(&file)? {
+                if record.seq > since && !visit(record) {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether every record still on disk is intact and linked to the one
+    /// before it. Older files may have been pruned, in which case the first
+    /// remaining record's link cannot be checked.
+    pub fn chain_status(&self) -> io::Result<ChainStatus> {
+        let files = log_files(&self.queryable_path()?)?;
+        let mut expected: Option<String> = None;
+        for file in &files {
+            let verified = self.verify(file)?;
+            if let Some(broken_at) = verified.broken_at {
+                return Ok(broken(broken_at));
+            }
+            let Some((first_seq, prev_hash)) = verified.first else {
+                continue;
+            };
+            let linked = match &expected {
+                Some(hash) => *hash == prev_hash,
+                None => first_seq != 1 || prev_hash == GENESIS_HASH,
+            };
+            if !linked {
+                return Ok(broken(first_seq));
+            }
+            expected = verified.last_hash;
+        }
+        Ok(ChainStatus {
+            valid: true,
+            broken_at: None,
+        })
+    }
+
+    /// What is known of `file`, read and verified again unless it is
+    /// unchanged since it was last verified or written.
+    fn verify(&self, file: &Path) -> io::Result<Verified> {
+        let metadata = fs::metadata(file)?;
+        let (len, modified) = (metadata.len(), metadata.modified().ok());
+        if let Some(verified) = self.verified.lock().unwrap().get(file) {
+            if verified.len == len && verified.modified == modified {
+                return Ok(verified.clone());
+            }
+        }
+        let records = read_records(file)?;
+        let verified = Verified {
+            len,
+            modified,
+            first: records
+                .first()
+                .map(|record| (record.seq, record.prev_hash.clone())),
+            last_hash: records.last().map(|record| record.hash.clone()),
+            broken_at: verify_chain(&records, None).broken_at,
+        };
+        self.verified
+            .lock()
+            .unwrap()
+            .insert(file.to_path_buf(), verified.clone());
+        Ok(verified)
+    }
+
+    fn queryable_path(&self) -> io::Result<PathBuf> {
+        self.path().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "audit records are only written to stdout",
+            )
+        })
+    }
+}
+
+/// Appends a record for `event` from async code and returns once it is on
+/// disk. The write and sync block, so they run on a blocking thread rather
+/// than holding up a runtime worker.
+pub async fn record(log: Arc<AuditLog>, event: AuditEvent) {
+    if let Err(e) = tokio::task::spawn_blocking(move || log.record(event)).await {
+        println!("Error writing audit record: {}", e);
+    }
+}
+
+impl FileSink {
+    /// Appends `line` for `record` without syncing it, keeping what is
+    /// known of the file in step.
+    fn append(
+        &mut self,
+        line: &str,
+        record: &AuditRecord,
+        verified: &Mutex<HashMap<PathBuf, Verified>>,
+    ) -> io::Result<()> {
+        if self.size >= self.max_bytes {
+            self.rotate(record.seq, verified)?;
+        }
+        let mut bytes = line.as_bytes().to_vec();
+        bytes.push(b'\n');
+        let before = self.size;
+        (&*self.file).write_all(&bytes)?;
+        self.size += bytes.len() as u64;
+
+        let mut verified = verified.lock().unwrap();
+        let known = verified
+            .get_mut(&self.path)
+            .filter(|known| known.len == before);
+        match (known, self.file.metadata()) {
+            (Some(known), Ok(metadata)) => {
+                known.len = metadata.len();
+                known.modified = metadata.modified().ok();
+                known
+                    .first
+                    .get_or_insert((record.seq, record.prev_hash.clone()));
+                known.last_hash = Some(record.hash.clone());
+            }
+            _ => {
+                verified.remove(&self.path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Syncs the full file before moving it aside, so rotated files are
+    /// always on disk.
+    fn rotate(
+        &mut self,
+        next_seq: u64,
+        verified: &Mutex<HashMap<PathBuf, Verified>>,
+    ) -> io::Result<()> {
+        self.file.sync_data()?;
+        let rotated = rotated_path(&self.path, next_seq);
+        fs::rename(&self.path, &rotated)?;
+        let mut verified = verified.lock().unwrap();
+        if let Some(known) = verified.remove(&self.path) {
+            verified.insert(rotated, known);
+        }
+        self.file = Arc::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?,
+        );
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn broken(seq: u64) -> ChainStatus {
+    ChainStatus {
+        valid: false,
+        broken_at: Some(seq),
+    }
+}
+
+/// The suffix of a rotated file: the `seq` the file after it starts at.
+fn rotated_seq(file: &Path) -> Option<u64> {
+    file.extension()?.to_str()?.parse().ok()
+}
+
+fn rotated_path(path: &Path, next_seq: u64) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{:020}", next_seq));
+    path.with_file_name(name)
+}
+
+/// Rotated files in order, followed by the current file if it exists.
+fn log_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let rotated_prefix = format!("{}.", name);
+
+    let mut files: Vec<PathBuf> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|file| {
+                file.file_name()
+                    .and_then(|file_name| file_name.to_str())
+                    .and_then(|file_name| file_name.strip_prefix(&rotated_prefix))
+                    .is_some_and(|suffix| suffix.bytes().all(|b| b.is_ascii_digit()))
+            })
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    files.sort();
+    if path.exists() {
+        files.push(path.to_path_buf());
+    }
+    Ok(files)
+}
+
+fn read_records(path: &Path) -> io::Result<Vec<AuditRecord>> {
+    let mut records = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid audit record in {:?}: {}", path, e),
+            )
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,133 @@
+use crate::auth::AuthError;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+#[derive(Deserialize, Debug)]
+pub struct JwtConfig {
+    pub keys: Vec<JwtKey>,
+    /// Required `iss` claim, when set.
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// Required entry of the `aud` claim, when set.
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// Clock skew tolerated when checking `exp` and `nbf`.
+    #[serde(default = "default_leeway_seconds")]
+    pub leeway_seconds: u64,
+}
+
+fn default_leeway_seconds() -> u64 {
+    60
+}
+
+/// A shared HMAC secret. Tokens whose header names a `kid` are only checked
+/// against the key with that id, which lets secrets rotate without downtime.
+#[derive(Deserialize, Debug)]
+pub struct JwtKey {
+    #[serde(default)]
+    pub kid: Option<String>,
+    pub secret: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: u64,
+    #[serde(default)]
+    pub nbf: Option<u64>,
+    #[serde(default)]
+    iss: Option<String>,
+    #[serde(default)]
+    aud: Option<Audience>,
+}
+
+/// Verifies an HS256 token signed by one of the configured keys and returns
+/// its claims. `now` is in seconds since the Unix epoch.
+pub fn verify(config: &JwtConfig, token: &str, now: u64) -> Result<Claims, AuthError> {
+    let invalid = |reason: &str| AuthError::InvalidToken(reason.to_string());
+
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid("expected three dot-separated parts"));
+    };
+
+    let header: Header = decode_json(header).ok_or_else(|| invalid("malformed header"))?;
+    if header.alg != "HS256" {
+        return Err(AuthError::InvalidToken(format!(
+            "unsupported algorithm \"{}\"",
+            header.alg
+        )));
+    }
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| invalid("malformed signature"))?;
+    let signed = &token[..header_and_payload_len(token)];
+    let verified = config
+        .keys
+        .iter()
+        .filter(|key| header.kid.is_none() || key.kid == header.kid)
+        .any(|key| {
+            let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(key.secret.as_bytes()) else {
+                return false;
+            };
+            mac.update(signed.as_bytes());
+            mac.verify_slice(&signature).is_ok()
+        });
+    if !verified {
+        return Err(invalid("signature does not match any configured key"));
+    }
+
+    let claims: Claims = decode_json(payload).ok_or_else(|| invalid("malformed claims"))?;
+    if claims.exp.saturating_add(config.leeway_seconds) <= now {
+        return Err(AuthError::TokenExpired);
+    }
+    if claims
+        .nbf
+        .is_some_and(|nbf| nbf > now.saturating_add(config.leeway_seconds))
+    {
+        return Err(invalid("token is not valid yet"));
+    }
+    if let Some(issuer) = &config.issuer {
+        if claims.iss.as_ref() != Some(issuer) {
+            return Err(invalid("unexpected issuer"));
+        }
+    }
+    if let Some(audience) = &config.audience {
+        let accepted = match &claims.aud {
+            Some(Audience::One(aud)) => aud == audience,
+            Some(Audience::Many(auds)) => auds.contains(audience),
+            None => false,
+        };
+        if !accepted {
+            return Err(invalid("unexpected audience"));
+        }
+    }
+    Ok(claims)
+}
+
+fn header_and_payload_len(token: &str) -> usize {
+    token.rfind('.').unwrap_or(token.len())
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(part: &str) -> Option<T> {
+    let bytes = URL_SAFE_NO_PAD.decode(part).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
@@ -0,0 +1,218 @@
+//! Auth Layer
+//!
+//! This module decides who is calling and what they may do:
+//! - API key authentication
+//! - HS256 JWT bearer token authentication
+//! - Per-namespace access rules that reload without a restart
+//! - Audit logging of denied requests
+
+pub mod acl;
+pub mod audit;
+pub mod jwt;
+
+use axum::http::{header::AUTHORIZATION, HeaderMap};
+use jwt::JwtConfig;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt, fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    ApiKey,
+    Jwt,
+}
+
+impl AuthMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuthMethod::ApiKey => "api_key",
+            AuthMethod::Jwt => "jwt",
+        }
+    }
+}
+
+/// The authenticated caller. The auth middleware inserts it into the request
+/// extensions, so handlers can take `Extension<Principal>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    pub name: String,
+    pub method: AuthMethod,
+}
+
+/// Contents of the file named by `ROCKSDB_AUTH_CONFIG`.
+#[derive(Deserialize, Debug, Default)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    #[serde(default)]
+    pub jwt: Option<JwtConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ApiKey {
+    pub principal: String,
+    pub key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    MissingCredentials,
+    InvalidApiKey,
+    InvalidToken(String),
+    TokenExpired,
+}
+
+impl AuthError {
+    /// Stable identifier returned to clients in the 401 body.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::MissingCredentials => "missing_credentials",
+            AuthError::InvalidApiKey => "invalid_api_key",
+            AuthError::InvalidToken(_) => "invalid_token",
+            AuthError::TokenExpired => "token_expired",
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingCredentials => write!(
+                f,
+                "send an API key in {} or a bearer token in Authorization",
+                API_KEY_HEADER
+            ),
+            AuthError::InvalidApiKey => write!(f, "API key is not recognized"),
+            AuthError::InvalidToken(reason) => write!(f, "bearer token is invalid: {}", reason),
+            AuthError::TokenExpired => write!(f, "bearer token has expired"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Checks request credentials against the configured key set. A disabled
+/// authenticator lets every request through without a principal.
+pub struct Authenticator {
+    enabled: bool,
+    /// SHA-256 of each API key, so lookups compare fixed-length digests.
+    api_keys: Vec<([u8; 32], String)>,
+    jwt: Option<JwtConfig>,
+}
+
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Authenticator")
+            .field("enabled", &self.enabled)
+            .field("api_keys", &self.api_keys.len())
+            .field("jwt", &self.jwt.is_some())
+            .finish()
+    }
+}
+
+impl Authenticator {
+    pub fn disabled() -> Self {
+        Authenticator {
+            enabled: false,
+            api_keys: Vec::new(),
+            jwt: None,
+        }
+    }
+
+    pub fn new(config: AuthConfig) -> Self {
+        Authenticator {
+            enabled: true,
+            api_keys: config
+                .api_keys
+                .into_iter()
+                .map(|key| (digest(&key.key), key.principal))
+                .collect(),
+            jwt: config.jwt,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {:?}: {}", path, e))?;
+        let config: AuthConfig =
+            serde_json::from_str(&contents).map_err(|e| format!("invalid {:?}: {}", path, e))?;
+        Ok(Authenticator::new(config))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the caller, or `None` when authentication is disabled.
+    ///
+    /// Credentials are read from `X-API-Key` or `Authorization: Bearer`. A
+    /// bearer value shaped like a JWT is verified as one when JWT keys are
+    /// configured; anything else is treated as an API key.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Option<Principal>, AuthError> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        if let Some(key) = headers.get(API_KEY_HEADER) {
+            let key = key.to_str().map_err(|_| AuthError::InvalidApiKey)?;
+            return self.authenticate_api_key(key).map(Some);
+        }
+        let Some(authorization) = headers.get(AUTHORIZATION) else {
+            return Err(AuthError::MissingCredentials);
+        };
+        let token = authorization
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .ok_or_else(|| AuthError::InvalidToken("expected a Bearer token".to_string()))?;
+        match &self.jwt {
+            Some(config) if token.matches('.').count() == 2 => {
+                let claims = jwt::verify(config, token, unix_now())?;
+                Ok(Some(Principal {
+                    name: claims.sub,
+                    method: AuthMethod::Jwt,
+                }))
+            }
+            _ => self.authenticate_api_key(token).map(Some),
+        }
+    }
+
+    fn authenticate_api_key(&self, key: &str) -> Result<Principal, AuthError> {
+        let presented = digest(key);
+        // Compare against every key without short-circuiting.
+        let mut matched = None;
+        for (expected, principal) in &self.api_keys {
+            let difference = expected
+                .iter()
+                .zip(presented.iter())
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+            if difference == 0 {
+                matched = Some(principal);
+            }
+        }
+        matched
+            .map(|name| Principal {
+                name: name.clone(),
+                method: AuthMethod::ApiKey,
+            })
+            .ok_or(AuthError::InvalidApiKey)
+    }
+}
+
+fn digest(key: &str) -> [u8; 32] {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
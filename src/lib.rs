@@ -1,14 +1,81 @@
+use api::caching::CachePolicy;
+use auth::{acl::AccessControl, audit::AuditLog, Authenticator};
+use limits::Limits;
+use replication::Replication;
 use rocksdb::DB;
 use std::sync::Arc;
+use storage::{
+    compression::Codec, document::Documents, encryption::Encryption, history::History,
+    import::Imports, index::Indexes, lock::Locks, mode::DbView, queue::Queues, schema::Schemas,
+    timeseries::TimeSeries,
+};
 
 #[derive(Clone, Debug)]
 pub struct AppState {
     pub rocksdb: Arc<DB>,
+    pub replication: Arc<Replication>,
+    pub view: Arc<DbView>,
+    pub auth: Arc<Authenticator>,
+    pub acl: Arc<AccessControl>,
+    pub audit: Arc<AuditLog>,
+    pub limits: Arc<Limits>,
+    pub encryption: Arc<Encryption>,
+    /// Codec values are compressed with before they are stored, if any.
+    pub stored_compression: Option<Codec>,
+    pub cache: Arc<CachePolicy>,
+    pub history: Arc<History>,
+    pub indexes: Arc<Indexes>,
+    pub documents: Arc<Documents>,
+    pub schemas: Arc<Schemas>,
+    pub queues: Arc<Queues>,
+    pub locks: Arc<Locks>,
+    pub timeseries: Arc<TimeSeries>,
+    pub imports: Arc<Imports>,
+}
+
+impl AppState {
+    /// State for a standalone, writable primary without authentication,
+    /// access rules, limits, encryption, history, indexes, document keys,
+    /// schemas or time-series retention.
+    pub fn new(rocksdb: Arc<DB>) -> Self {
+        AppState {
+            rocksdb,
+            replication: Arc::new(Replication::primary()),
+            view: Arc::new(DbView::read_write()),
+            auth: Arc::new(Authenticator::disabled()),
+            acl: Arc::new(AccessControl::disabled()),
+            audit: Arc::new(AuditLog::stdout()),
+            limits: Arc::new(Limits::disabled()),
+            encryption: Arc::new(Encryption::disabled()),
+            stored_compression: None,
+            cache: Arc::new(CachePolicy::disabled()),
+            history: Arc::new(History::disabled()),
+            indexes: Arc::new(Indexes::disabled()),
+            documents: Arc::new(Documents::disabled()),
+            schemas: Arc::new(Schemas::default()),
+            queues: Arc::new(Queues::default()),
+            locks: Arc::new(Locks::default()),
+            timeseries: Arc::new(TimeSeries::default()),
+            imports: Arc::new(Imports::default()),
+        }
+    }
 }
 
 /// API layer - HTTP handlers and response utilities
 pub mod api;
 
+/// Auth layer - Caller authentication and authorization
+pub mod auth;
+
+/// Limits layer - Rate limiting and storage quotas
+pub mod limits;
+
+/// Replication layer - Primary/follower WAL shipping
+pub mod replication;
+
+/// Server layer - Listener and TLS termination
+pub mod server;
+
 /// Storage layer - Database operations
 pub mod storage;
 
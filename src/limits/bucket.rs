@@ -0,0 +1,45 @@
+use std::time::{Duration, Instant};
+
+/// A token bucket that refills continuously at `rate` tokens per second up
+/// to `capacity`.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn full(capacity: f64, now: Instant) -> Self {
+        TokenBucket {
+            tokens: capacity,
+            refilled_at: now,
+        }
+    }
+
+    /// Whether the bucket has refilled to `capacity` by `now`, so it is no
+    /// different from a fresh one.
+    pub fn is_full(&self, rate: f64, capacity: f64, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens + elapsed * rate >= capacity
+    }
+
+    /// Takes one token, or returns how long until one is available.
+    pub fn try_take(&mut self, rate: f64, capacity: f64, now: Instant) -> Result<(), Duration> {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(capacity);
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        if rate <= 0.0 {
+            return Err(Duration::MAX);
+        }
+        Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+    }
+}
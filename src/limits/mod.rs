@@ -0,0 +1,358 @@
+//! Limits Layer
+//!
+//! This module protects the server from noisy clients:
+//! - Token-bucket rate limiting per client identity and route
+//! - Per-namespace storage quotas on measured sizes
+//! - Throttling counters exported as metrics
+
+pub mod bucket;
+
+use crate::storage::{encryption::Encryption, usage};
+use bucket::TokenBucket;
+use rocksdb::DB;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Matches every client or every route in a rate limit.
+pub const ANY: &str = "*";
+
+/// How often namespace sizes are re-measured.
+pub const QUOTA_REFRESH: Duration = Duration::from_secs(10);
+/// Buckets kept before full ones, which a fresh bucket would replace
+/// exactly, are dropped.
+const MAX_BUCKETS: usize = 10_000;
+/// Clients with throttling counters of their own; the rest are counted
+/// together under `OTHER_CLIENTS`.
+const MAX_COUNTED_CLIENTS: usize = 1_000;
+const OTHER_CLIENTS: &str = "other";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RateLimit {
+    /// Principal name or TLS subject, or `*`.
+    #[serde(default = "any")]
+    pub client: String,
+    /// Route pattern such as `/put`, or `*`.
+    #[serde(default = "any")]
+    pub route: String,
+    pub requests_per_second: f64,
+    /// Requests allowed in a burst. Defaults to one second's worth.
+    #[serde(default)]
+    pub burst: Option<f64>,
+}
+
+impl RateLimit {
+    fn capacity(&self) -> f64 {
+        self.burst.unwrap_or(self.requests_per_second).max(1.0)
+    }
+
+    /// Higher is more specific; `None` when the limit does not apply.
+    fn specificity(&self, client: &str, route: &str) -> Option<u8> {
+        let client_score = match self.client.as_str() {
+            ANY => 0,
+            name if name == client => 2,
+            _ => return None,
+        };
+        let route_score = match self.route.as_str() {
+            ANY => 0,
+            pattern if pattern == route => 1,
+            _ => return None,
+        };
+        Some(client_score + route_score)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Quota {
+    pub prefix: String,
+    pub max_bytes: u64,
+}
+
+/// Contents of the file named by `ROCKSDB_LIMITS_CONFIG`.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct LimitsConfig {
+    #[serde(default)]
+    pub rate_limits: Vec<RateLimit>,
+    #[serde(default)]
+    pub quotas: Vec<Quota>,
+}
+
+fn any() -> String {
+    ANY.to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThrottleReason {
+    RateLimit,
+    Quota,
+}
+
+impl ThrottleReason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThrottleReason::RateLimit => "rate_limit",
+            ThrottleReason::Quota => "quota",
+        }
+    }
+}
+
+/// A request that must wait before it is retried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Throttled {
+    pub reason: ThrottleReason,
+    pub retry_after: Duration,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    /// Bytes last measured plus those admitted since.
+    bytes: u64,
+    /// Bytes ever admitted, so a measurement can add back those admitted
+    /// while it ran.
+    admitted: u64,
+    measured: bool,
+}
+
+/// Name used for rate limits and metrics, attached to each request by the
+/// rate limiting middleware: the principal, else the TLS client subject.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientName(pub String);
+
+#[derive(Debug, Default)]
+pub struct Limits {
+    config: LimitsConfig,
+    buckets: Mutex<HashMap<(String, String), TokenBucket>>,
+    usage: Mutex<HashMap<String, Usage>>,
+    throttled: Mutex<BTreeMap<(String, String, ThrottleReason), u64>>,
+}
+
+impl Limits {
+    /// Limits with no rate limits or quotas; only the metrics are kept.
+    pub fn disabled() -> Self {
+        Limits::default()
+    }
+
+    pub fn new(config: LimitsConfig) -> Self {
+        Limits {
+            config,
+            ..Limits::default()
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {:?}: {}", path, e))?;
+        let config: LimitsConfig =
+            serde_json::from_str(&contents).map_err(|e| format!("invalid {:?}: {}", path, e))?;
+        Ok(Limits::new(config))
+    }
+
+    /// Takes a token from the bucket of `client` on `route`, using the most
+    /// specific configured limit.
+    pub fn check_rate(&self, client: &str, route: &str) -> Result<(), Throttled> {
+        let Some(limit) = self.rate_limit(client, route) else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        let capacity = limit.capacity();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS {
+            buckets.retain(
+                |(client, route), bucket| match self.rate_limit(client, route) {
+                    Some(limit) => {
+                        !bucket.is_full(limit.requests_per_second, limit.capacity(), now)
+                    }
+                    None => false,
+                },
+            );
+        }
+        let bucket = buckets
+            .entry((client.to_string(), route.to_string()))
+            .or_insert_with(|| TokenBucket::full(capacity, now));
+        match bucket.try_take(limit.requests_per_second, capacity, now) {
+            Ok(()) => Ok(()),
+            Err(retry_after) => {
+                drop(buckets);
+                self.record(client, route, ThrottleReason::RateLimit);
+                Err(Throttled {
+                    reason: ThrottleReason::RateLimit,
+                    retry_after,
+                    message: format!(
+                        "{} exceeded {} requests per second on {}",
+                        client, limit.requests_per_second, route
+                    ),
+                })
+            }
+        }
+    }
+
+    /// The most specific rate limit for `client` on `route`.
+    fn rate_limit(&self, client: &str, route: &str) -> Option<&RateLimit> {
+        self.config
+            .rate_limits
+            .iter()
+            .filter_map(|limit| Some((limit.specificity(client, route)?, limit)))
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, limit)| limit)
+    }
+
+    /// Checks that writing `incoming` more bytes under `key` keeps its
+    /// namespace within quota. Queues, time series and structures are
+    /// namespaced by their names. Usage is the size last measured, plus
+    /// whatever was admitted since; a namespace is measured on its first
+    /// check and then by `measure_periodically`.
+    pub fn check_quota(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        client: &str,
+        route: &str,
+        key: &[u8],
+        incoming: u64,
+    ) -> Result<(), Throttled> {
+        let Some(quota) = self
+            .config
+            .quotas
+            .iter()
+            .filter(|quota| key.starts_with(quota.prefix.as_bytes()))
+            .max_by_key(|quota| quota.prefix.len())
+        else {
+            return Ok(());
+        };
+
+        let measured = self
+            .usage
+            .lock()
+            .unwrap()
+            .get(&quota.prefix)
+            .is_some_and(|usage| usage.measured);
+        if !measured {
+            self.measure(db, encryption, &quota.prefix);
+        }
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(quota.prefix.clone()).or_default();
+        if entry.bytes.saturating_add(incoming) <= quota.max_bytes {
+            entry.bytes += incoming;
+            entry.admitted += incoming;
+            return Ok(());
+        }
+        let used = entry.bytes;
+        drop(usage);
+
+        self.record(client, route, ThrottleReason::Quota);
+        Err(Throttled {
+            reason: ThrottleReason::Quota,
+            retry_after: QUOTA_REFRESH,
+            message: format!(
+                "namespace \"{}\" is over its quota: {} of {} bytes used",
+                quota.prefix, used, quota.max_bytes
+            ),
+        })
+    }
+
+    /// Re-measures every namespace with a quota, which also takes back the
+    /// bytes of values since deleted or overwritten.
+    pub fn measure_all(&self, db: &DB, encryption: &Encryption) {
+        for quota in &self.config.quotas {
+            self.measure(db, encryption, &quota.prefix);
+        }
+    }
+
+    /// Replaces the usage of the namespace of `prefix` with its measured
+    /// size, keeping the bytes admitted while it was measured.
+    fn measure(&self, db: &DB, encryption: &Encryption, prefix: &str) {
+        let admitted = self
+            .usage
+            .lock()
+            .unwrap()
+            .get(prefix)
+            .map_or(0, |usage| usage.admitted);
+        let size = match usage::namespace_size(db, encryption, prefix.as_bytes()) {
+            Ok(size) => size,
+            Err(e) => {
+                println!("Error measuring namespace \"{}\": {}", prefix, e);
+                return;
+            }
+        };
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(prefix.to_string()).or_default();
+        entry.bytes = size + (entry.admitted - admitted);
+        entry.measured = true;
+    }
+
+    pub fn has_quotas(&self) -> bool {
+        !self.config.quotas.is_empty()
+    }
+
+    fn record(&self, client: &str, route: &str, reason: ThrottleReason) {
+        let mut throttled = self.throttled.lock().unwrap();
+        let counted = throttled.keys().any(|(name, _, _)| name == client);
+        let client = match counted || clients(&throttled) < MAX_COUNTED_CLIENTS {
+            true => client,
+            false => OTHER_CLIENTS,
+        };
+        *throttled
+            .entry((client.to_string(), route.to_string(), reason))
+            .or_insert(0) += 1;
+    }
+
+    /// Throttling counters in the Prometheus text format.
+    pub fn metrics(&self) -> String {
+        let mut out = String::new();
+        out.push_str(
+            "# HELP rocksdb_http_throttled_total Requests rejected by rate limits or quotas.\n",
+        );
+        out.push_str("# TYPE rocksdb_http_throttled_total counter\n");
+        for ((client, route, reason), count) in self.throttled.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "rocksdb_http_throttled_total{{client=\"{}\",route=\"{}\",reason=\"{}\"}} {}",
+                escape_label(client),
+                escape_label(route),
+                reason.name(),
+                count
+            );
+        }
+        out
+    }
+}
+
+/// Distinct clients with throttling counters.
+fn clients(throttled: &BTreeMap<(String, String, ThrottleReason), u64>) -> usize {
+    let mut clients: Vec<&str> = throttled.keys().map(|(name, _, _)| name.as_str()).collect();
+    clients.dedup();
+    clients.len()
+}
+
+/// Re-measures the namespaces with quotas every `interval`, off the async
+/// runtime since each is read in full.
+pub async fn measure_periodically(
+    limits: Arc<Limits>,
+    db: Arc<DB>,
+    encryption: Arc<Encryption>,
+    interval: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let (limits, db, encryption) = (limits.clone(), db.clone(), encryption.clone());
+        let measure = move || limits.measure_all(&db, &encryption);
+        if let Err(e) = tokio::task::spawn_blocking(measure).await {
+            println!("Error measuring namespaces: {}", e);
+        }
+    }
+}
+
+pub(crate) fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
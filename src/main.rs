@@ -1,10 +1,45 @@
-use axum::{extract::DefaultBodyLimit, routing::post, Router};
-use h_rocksdb::{api::handlers, AppState};
+use h_rocksdb::{
+    api::{caching::CachePolicy, routes},
+    auth::{
+        acl::AccessControl,
+        audit::{AuditLog, DEFAULT_MAX_FILE_BYTES},
+        Authenticator,
+    },
+    limits::{self, Limits, QUOTA_REFRESH},
+    replication::{follower, Replication},
+    server::tls::{
+        self, ClientAuth, ReloadingConfig, TlsSettings, DEFAULT_HANDSHAKE_TIMEOUT_SECONDS,
+    },
+    storage::{
+        blob, checkpoint,
+        compression::Codec,
+        document::Documents,
+        dump::{self, DumpFormat, OnConflict, Scope},
+        encryption::Encryption,
+        history::{History, Retention},
+        index::Indexes,
+        lock::Locks,
+        mode::{self, AccessMode, DbView},
+        queue::{Queues, DEFAULT_MAX_ATTEMPTS},
+        rocksdb::Store,
+        schema::Schemas,
+        timeseries::{self, TimeSeries, DEFAULT_RESOLUTION_MS},
+    },
+    AppState,
+};
 use opentelemetry::{global, KeyValue};
 use opentelemetry_otlp::{Protocol, WithExportConfig};
 use opentelemetry_sdk::{propagation::TraceContextPropagator, trace as sdktrace, Resource};
-use rocksdb::DB;
-use std::{env, process, sync::Arc};
+use rocksdb::Options;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, BufReader, Write},
+    path::Path,
+    process,
+    sync::Arc,
+    time::Duration,
+};
 use tokio::runtime::Builder;
 
 fn init_tracer() -> Result<sdktrace::SdkTracerProvider, sdktrace::TraceError> {
@@ -46,7 +81,7 @@ fn get_db_path() -> String {
     match env::current_dir() {
         Ok(path) => {
             if let Some(str_path) = path.join("rocks.db").to_str() {
-                return str_path.to_string();
+                str_path.to_string()
             } else {
                 eprintln!("Failed to convert path to string.");
                 process::exit(1);
@@ -59,9 +94,510 @@ fn get_db_path() -> String {
     }
 }
 
+/// Keeps WAL files around long enough for `/changes` consumers to catch up,
+/// and installs history pruning.
+fn get_db_options(history: &History) -> Options {
+    let wal_ttl_seconds = env::var("ROCKSDB_WAL_TTL_SECONDS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(86400);
+
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.set_wal_ttl_seconds(wal_ttl_seconds);
+    history.configure(&mut opts);
+    opts
+}
+
+/// Reads `ROCKSDB_MODE`: `read_write` (default), `read_only` or `secondary`.
+fn get_access_mode(rocksdb_path: &str) -> AccessMode {
+    match env::var("ROCKSDB_MODE").as_deref() {
+        Err(_) | Ok("read_write") => AccessMode::ReadWrite,
+        Ok("read_only") => AccessMode::ReadOnly,
+        Ok("secondary") => {
+            let secondary_path = env::var("ROCKSDB_SECONDARY_PATH")
+                .unwrap_or_else(|_| format!("{}.secondary", rocksdb_path));
+            AccessMode::Secondary {
+                secondary_path: secondary_path.into(),
+            }
+        }
+        Ok(other) => {
+            eprintln!("Unknown ROCKSDB_MODE \"{}\"", other);
+            process::exit(1);
+        }
+    }
+}
+
+fn get_catch_up_interval() -> Duration {
+    let millis = env::var("ROCKSDB_CATCH_UP_INTERVAL_MS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(1000);
+    Duration::from_millis(millis)
+}
+
+/// TLS is enabled when both `ROCKSDB_TLS_CERT` and `ROCKSDB_TLS_KEY` are set.
+/// `ROCKSDB_TLS_CLIENT_CA` turns on client certificate verification, which is
+/// required unless `ROCKSDB_TLS_CLIENT_AUTH=optional`. Clients that have not
+/// completed the handshake after `ROCKSDB_TLS_HANDSHAKE_TIMEOUT_SECONDS`, ten
+/// by default, are disconnected.
+fn get_tls_settings() -> Option<TlsSettings> {
+    let cert_path = env::var("ROCKSDB_TLS_CERT").ok()?;
+    let Ok(key_path) = env::var("ROCKSDB_TLS_KEY") else {
+        eprintln!("ROCKSDB_TLS_CERT is set but ROCKSDB_TLS_KEY is not");
+        process::exit(1);
+    };
+    let client_auth = match env::var("ROCKSDB_TLS_CLIENT_AUTH").as_deref() {
+        Err(_) | Ok("required") => ClientAuth::Required,
+        Ok("optional") => ClientAuth::Optional,
+        Ok(other) => {
+            eprintln!("Unknown ROCKSDB_TLS_CLIENT_AUTH \"{}\"", other);
+            process::exit(1);
+        }
+    };
+    Some(TlsSettings {
+        cert_path: cert_path.into(),
+        key_path: key_path.into(),
+        client_ca_path: env::var("ROCKSDB_TLS_CLIENT_CA").ok().map(Into::into),
+        client_auth,
+        handshake_timeout: Duration::from_secs(
+            env::var("ROCKSDB_TLS_HANDSHAKE_TIMEOUT_SECONDS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_HANDSHAKE_TIMEOUT_SECONDS),
+        ),
+    })
+}
+
+/// Authentication is enabled when `ROCKSDB_AUTH_CONFIG` names a JSON file
+/// with `api_keys` and/or `jwt` signing keys.
+fn get_authenticator() -> Authenticator {
+    let Ok(path) = env::var("ROCKSDB_AUTH_CONFIG") else {
+        return Authenticator::disabled();
+    };
+    match Authenticator::load(Path::new(&path)) {
+        Ok(authenticator) => authenticator,
+        Err(err) => {
+            eprintln!("Failed to load auth configuration: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Access rules are enforced when `ROCKSDB_ACL_CONFIG` names a JSON file of
+/// `rules`; the file is re-read when it changes.
+fn get_access_control() -> AccessControl {
+    let Ok(path) = env::var("ROCKSDB_ACL_CONFIG") else {
+        return AccessControl::disabled();
+    };
+    match AccessControl::load(Path::new(&path)) {
+        Ok(access_control) => access_control,
+        Err(err) => {
+            eprintln!("Failed to load access rules: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Audit records go to `ROCKSDB_AUDIT_LOG` when set, rotated once a file
+/// reaches `ROCKSDB_AUDIT_LOG_MAX_BYTES`, and to stdout otherwise.
+fn get_audit_log() -> AuditLog {
+    let Ok(path) = env::var("ROCKSDB_AUDIT_LOG") else {
+        return AuditLog::stdout();
+    };
+    let max_bytes = env::var("ROCKSDB_AUDIT_LOG_MAX_BYTES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_FILE_BYTES);
+    match AuditLog::open_with_rotation(Path::new(&path), max_bytes) {
+        Ok(audit_log) => audit_log,
+        Err(err) => {
+            eprintln!("Failed to open audit log {path}: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Rate limits and quotas are read from `ROCKSDB_LIMITS_CONFIG` when set.
+fn get_limits() -> Limits {
+    let Ok(path) = env::var("ROCKSDB_LIMITS_CONFIG") else {
+        return Limits::disabled();
+    };
+    match Limits::load(Path::new(&path)) {
+        Ok(limits) => limits,
+        Err(err) => {
+            eprintln!("Failed to load limits: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Values are encrypted with keys from `ROCKSDB_ENCRYPTION_KEYFILE` when set.
+fn get_encryption() -> Encryption {
+    let Ok(path) = env::var("ROCKSDB_ENCRYPTION_KEYFILE") else {
+        return Encryption::disabled();
+    };
+    match Encryption::load(Path::new(&path)) {
+        Ok(encryption) => encryption,
+        Err(err) => {
+            eprintln!("Failed to load encryption keys: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Values are stored compressed with the codec named by
+/// `ROCKSDB_STORE_COMPRESSED` (`gzip`, `zstd` or `br`) when set.
+fn get_stored_compression() -> Option<Codec> {
+    let name = env::var("ROCKSDB_STORE_COMPRESSED").ok()?;
+    match Codec::from_name(&name) {
+        Some(codec) => Some(codec),
+        None => {
+            eprintln!(
+                "Unknown ROCKSDB_STORE_COMPRESSED codec \"{name}\", expected gzip, zstd or br"
+            );
+            process::exit(1);
+        }
+    }
+}
+
+/// Per-namespace `Cache-Control` is read from `ROCKSDB_CACHE_CONFIG` when set.
+fn get_cache_policy() -> CachePolicy {
+    let Ok(path) = env::var("ROCKSDB_CACHE_CONFIG") else {
+        return CachePolicy::disabled();
+    };
+    match CachePolicy::load(Path::new(&path)) {
+        Ok(policy) => policy,
+        Err(err) => {
+            eprintln!("Failed to load cache policy: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Replaced values are kept when `ROCKSDB_HISTORY_VERSIONS` (versions per
+/// key) or `ROCKSDB_HISTORY_SECONDS` (age since replaced) is set.
+fn get_history() -> History {
+    let limit = |name: &str| match env::var(name) {
+        Err(_) => None,
+        Ok(value) => match value.parse::<u64>() {
+            Ok(limit) => Some(limit),
+            Err(_) => {
+                eprintln!("Invalid {name} \"{value}\", expected a whole number");
+                process::exit(1);
+            }
+        },
+    };
+    let max_versions = limit("ROCKSDB_HISTORY_VERSIONS");
+    let max_age_ms = limit("ROCKSDB_HISTORY_SECONDS").map(|secs| secs.saturating_mul(1000));
+    if max_versions.is_none() && max_age_ms.is_none() {
+        return History::disabled();
+    }
+    History::new(Retention {
+        max_versions,
+        max_age_ms,
+    })
+}
+
+/// Secondary indexes are maintained as declared in `ROCKSDB_INDEX_CONFIG`
+/// when set.
+fn get_indexes(encryption: &Encryption) -> Indexes {
+    let Ok(path) = env::var("ROCKSDB_INDEX_CONFIG") else {
+        return Indexes::disabled();
+    };
+    let indexes = match Indexes::load(Path::new(&path)) {
+        Ok(indexes) => indexes,
+        Err(err) => {
+            eprintln!("Failed to load indexes: {err}");
+            process::exit(1);
+        }
+    };
+    if let Err(err) = indexes.check_encryption(encryption) {
+        eprintln!("Invalid {path}: {err}");
+        process::exit(1);
+    }
+    indexes
+}
+
+/// Keys starting with one of the comma-separated `ROCKSDB_DOCUMENT_PREFIXES`
+/// must hold JSON documents. Set but empty, every key must; empty entries
+/// in a list, as left by a trailing comma, are ignored rather than taken
+/// to cover every key.
+fn get_documents() -> Documents {
+    let Ok(prefixes) = env::var("ROCKSDB_DOCUMENT_PREFIXES") else {
+        return Documents::disabled();
+    };
+    if prefixes.trim().is_empty() {
+        return Documents::new(vec![String::new()]);
+    }
+    Documents::new(
+        prefixes
+            .split(',')
+            .map(str::trim)
+            .filter(|prefix| !prefix.is_empty())
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+/// Queue messages are dead-lettered after `ROCKSDB_QUEUE_MAX_ATTEMPTS`
+/// deliveries, five by default.
+fn get_queues() -> Queues {
+    let Ok(value) = env::var("ROCKSDB_QUEUE_MAX_ATTEMPTS") else {
+        return Queues::new(DEFAULT_MAX_ATTEMPTS);
+    };
+    match value.parse::<u32>() {
+        Ok(max_attempts) if max_attempts > 0 => Queues::new(max_attempts),
+        _ => {
+            eprintln!("Invalid ROCKSDB_QUEUE_MAX_ATTEMPTS \"{value}\", expected a positive number");
+            process::exit(1);
+        }
+    }
+}
+
+/// Time-series points older than `ROCKSDB_TIMESERIES_RAW_SECONDS` are
+/// downsampled into buckets of `ROCKSDB_TIMESERIES_RESOLUTION_SECONDS`, a
+/// minute by default, which are dropped after
+/// `ROCKSDB_TIMESERIES_BUCKET_SECONDS`. Nothing is downsampled or dropped
+/// while the first is unset.
+fn get_timeseries() -> TimeSeries {
+    let seconds = |name: &str| match env::var(name) {
+        Err(_) => None,
+        Ok(value) => match value.parse::<u64>() {
+            Ok(seconds) if seconds > 0 => Some(seconds.saturating_mul(1000)),
+            _ => {
+                eprintln!("Invalid {name} \"{value}\", expected a positive number");
+                process::exit(1);
+            }
+        },
+    };
+    let raw_retention_ms = seconds("ROCKSDB_TIMESERIES_RAW_SECONDS");
+    let bucket_retention_ms = seconds("ROCKSDB_TIMESERIES_BUCKET_SECONDS");
+    if raw_retention_ms.is_none() && bucket_retention_ms.is_some() {
+        eprintln!("ROCKSDB_TIMESERIES_BUCKET_SECONDS requires ROCKSDB_TIMESERIES_RAW_SECONDS");
+        process::exit(1);
+    }
+    let resolution_ms =
+        seconds("ROCKSDB_TIMESERIES_RESOLUTION_SECONDS").unwrap_or(DEFAULT_RESOLUTION_MS);
+    TimeSeries::new(resolution_ms, raw_retention_ms, bucket_retention_ms)
+}
+
+/// Reads `--name value` pairs, exiting on anything not in `allowed`.
+fn get_flags(command: &str, allowed: &[&str]) -> BTreeMap<String, String> {
+    let mut flags = BTreeMap::new();
+    let mut args = env::args().skip(2);
+    while let Some(arg) = args.next() {
+        let name = arg.strip_prefix("--").unwrap_or_default();
+        match args.next() {
+            Some(value) if allowed.contains(&name) => {
+                flags.insert(name.to_string(), value);
+            }
+            _ => {
+                eprintln!(
+                    "Usage: {} {}",
+                    command,
+                    allowed
+                        .iter()
+                        .map(|name| format!("[--{} VALUE]", name))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+                process::exit(1);
+            }
+        }
+    }
+    flags
+}
+
+/// `export [--format ndjson|sst] [--prefix P] [--column-family CF]
+/// [--output PATH]` dumps `rocks.db` from a read-only snapshot, to stdout
+/// unless an output is given. SST files need one.
+fn export(rocksdb_path: &str) {
+    let flags = get_flags("export", &["format", "prefix", "column-family", "output"]);
+    let format = match flags.get("format").map(|format| format.parse()) {
+        None => DumpFormat::Ndjson,
+        Some(Ok(format)) => format,
+        Some(Err(err)) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    };
+    let scope = Scope {
+        column_family: flags.get("column-family").cloned(),
+        prefix: flags
+            .get("prefix")
+            .cloned()
+            .unwrap_or_default()
+            .into_bytes(),
+    };
+    let db = match mode::open(
+        &Options::default(),
+        Path::new(rocksdb_path),
+        &AccessMode::ReadOnly,
+    ) {
+        Ok(db) => db,
+        Err(err) => {
+            eprintln!("Failed to open {rocksdb_path}: {err}");
+            process::exit(1);
+        }
+    };
+
+    let encryption = get_encryption();
+    let exported = match (format, flags.get("output")) {
+        (DumpFormat::Sst, Some(output)) => {
+            dump::export_sst(&db, &encryption, &scope, Path::new(output))
+        }
+        (DumpFormat::Sst, None) => {
+            eprintln!("SST exports need --output");
+            process::exit(1);
+        }
+        (DumpFormat::Ndjson, output) => {
+            let mut out: Box<dyn Write> = match output {
+                Some(output) => match fs::File::create(output) {
+                    Ok(file) => Box::new(io::BufWriter::new(file)),
+                    Err(err) => {
+                        eprintln!("Failed to create {output}: {err}");
+                        process::exit(1);
+                    }
+                },
+                None => Box::new(io::BufWriter::new(io::stdout().lock())),
+            };
+            dump::export(&db, &encryption, &scope, |entry| {
+                out.write_all(entry.to_line().as_bytes())?;
+                Ok(())
+            })
+            .and_then(|exported| {
+                out.flush()?;
+                Ok(exported)
+            })
+        }
+    };
+    match exported {
+        Ok(exported) => eprintln!("Exported {exported} keys"),
+        Err(err) => {
+            eprintln!("Failed to export: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+/// `restore --input PATH [--on-conflict overwrite|skip|fail]` replays an
+/// NDJSON dump into `rocks.db`, creating the column families it names and
+/// keeping history and the indexes in `ROCKSDB_INDEX_CONFIG` up to date.
+/// The server must be stopped.
+fn restore(rocksdb_path: &str) {
+    let flags = get_flags("restore", &["input", "on-conflict"]);
+    let Some(input) = flags.get("input") else {
+        eprintln!("Usage: restore --input PATH [--on-conflict overwrite|skip|fail]");
+        process::exit(1);
+    };
+    let on_conflict = match flags.get("on-conflict").map(|policy| policy.parse()) {
+        None => OnConflict::Overwrite,
+        Some(Ok(policy)) => policy,
+        Some(Err(err)) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    };
+    let open_input = || match fs::File::open(input) {
+        Ok(file) => BufReader::new(file),
+        Err(err) => {
+            eprintln!("Failed to open {input}: {err}");
+            process::exit(1);
+        }
+    };
+    let column_families = match dump::dumped_column_families(open_input()) {
+        Ok(column_families) => column_families,
+        Err(err) => {
+            eprintln!("Failed to read {input}: {err}");
+            process::exit(1);
+        }
+    };
+
+    let history = get_history();
+    let mut db = match mode::open(
+        &get_db_options(&history),
+        Path::new(rocksdb_path),
+        &AccessMode::ReadWrite,
+    ) {
+        Ok(db) => db,
+        Err(err) => {
+            eprintln!("Failed to open {rocksdb_path}: {err}");
+            process::exit(1);
+        }
+    };
+    let existing = dump::column_families(&db);
+    for name in column_families {
+        if existing.contains(&name) {
+            continue;
+        }
+        if let Err(err) = db.create_cf(&name, &Options::default()) {
+            eprintln!("Failed to create column family \"{name}\": {err}");
+            process::exit(1);
+        }
+    }
+    let encryption = get_encryption();
+    let store = Store {
+        db: &db,
+        encryption: &encryption,
+        history: &history,
+        indexes: &get_indexes(&encryption),
+    };
+    match dump::restore(&store, || Ok(open_input()), on_conflict) {
+        Ok(restored) => eprintln!(
+            "Restored {} keys, skipped {}",
+            restored.restored, restored.skipped
+        ),
+        Err(err) => {
+            eprintln!("Failed to restore: {err}");
+            process::exit(1);
+        }
+    }
+}
+
 fn main() {
     let rocksdb_path = get_db_path();
-    let db = DB::open_default(rocksdb_path).unwrap();
+    match env::args().nth(1).as_deref() {
+        None => {}
+        Some("export") => return export(&rocksdb_path),
+        Some("restore") => return restore(&rocksdb_path),
+        Some(other) => {
+            eprintln!("Unknown command \"{other}\", expected export or restore");
+            process::exit(1);
+        }
+    }
+    let primary_url = env::var("ROCKSDB_REPLICATE_FROM").ok();
+    let listen_addr =
+        env::var("ROCKSDB_LISTEN_ADDR").unwrap_or_else(|_| "127.0.0.1:4000".to_string());
+    let tls_config = get_tls_settings().map(|settings| match ReloadingConfig::load(settings) {
+        Ok(config) => Arc::new(config),
+        Err(err) => {
+            eprintln!("Failed to load TLS configuration: {err}");
+            process::exit(1);
+        }
+    });
+    let authenticator = get_authenticator();
+    let access_control = Arc::new(get_access_control());
+    if access_control.is_enabled() && !authenticator.is_enabled() {
+        eprintln!("ROCKSDB_ACL_CONFIG requires ROCKSDB_AUTH_CONFIG");
+        process::exit(1);
+    }
+    let audit_log = get_audit_log();
+    let limits = get_limits();
+    let encryption = get_encryption();
+    let stored_compression = get_stored_compression();
+    let cache_policy = get_cache_policy();
+    let history = get_history();
+    let indexes = get_indexes(&encryption);
+    let documents = get_documents();
+    let queues = get_queues();
+    let locks = Locks::default();
+    let timeseries = Arc::new(get_timeseries());
+    let access_mode = get_access_mode(&rocksdb_path);
+    if primary_url.is_some() && !access_mode.is_writable() {
+        eprintln!("ROCKSDB_REPLICATE_FROM requires ROCKSDB_MODE=read_write");
+        process::exit(1);
+    }
 
     let runtime = Builder::new_multi_thread()
         .worker_threads(4)
@@ -69,9 +605,129 @@ fn main() {
         .build()
         .unwrap();
 
-    let state = AppState {
-        rocksdb: Arc::new(db),
+    let replication_client =
+        match follower::client(env::var("ROCKSDB_REPLICATE_TOKEN").ok().as_deref()) {
+            Ok(client) => client,
+            Err(err) => {
+                eprintln!("Failed to build replication client: {err}");
+                process::exit(1);
+            }
+        };
+    if let Some(primary_url) = &primary_url {
+        if !Path::new(&rocksdb_path).exists() {
+            let bootstrap =
+                follower::bootstrap(&replication_client, primary_url, Path::new(&rocksdb_path));
+            match runtime.block_on(bootstrap) {
+                Ok(checkpoint) => println!(
+                    "Bootstrapped from checkpoint \"{}\" of {}",
+                    checkpoint.id, primary_url
+                ),
+                Err(err) => {
+                    eprintln!("Failed to bootstrap from {primary_url}: {err}");
+                    process::exit(1);
+                }
+            }
+        }
+    }
+
+    let mut db = mode::open(
+        &get_db_options(&history),
+        Path::new(&rocksdb_path),
+        &access_mode,
+    )
+    .unwrap();
+    if access_mode.is_writable() {
+        match checkpoint::sweep(&db) {
+            Ok(0) => {}
+            Ok(swept) => println!("Removed {swept} checkpoints left by unfinished bootstraps"),
+            Err(err) => eprintln!("Failed to remove stale checkpoints: {err}"),
+        }
+    }
+    // A follower only changes through the primary's WAL: a write of its own
+    // would take the sequence number of the next replicated batch, and a
+    // column family of its own would not match the primary's.
+    if access_mode.is_writable() && primary_url.is_none() {
+        if let Err(err) = indexes.prepare(&mut db, &encryption) {
+            eprintln!("Failed to build indexes: {err}");
+            process::exit(1);
+        }
+        match blob::sweep_uploads(&db) {
+            Ok(0) => {}
+            Ok(swept) => println!("Dropped the chunks of {swept} interrupted uploads"),
+            Err(err) => {
+                eprintln!("Failed to drop the chunks of interrupted uploads: {err}");
+                process::exit(1);
+            }
+        }
+        if let Err(err) = queues.prepare(&mut db, &encryption) {
+            eprintln!("Failed to prepare the queues: {err}");
+            process::exit(1);
+        }
+        if let Err(err) = locks.prepare(&mut db) {
+            eprintln!("Failed to create the locks column family: {err}");
+            process::exit(1);
+        }
+        if let Err(err) = timeseries.prepare(&mut db) {
+            eprintln!("Failed to create the time series column family: {err}");
+            process::exit(1);
+        }
+    }
+    let schemas = match Schemas::load(&db, &encryption) {
+        Ok(schemas) => schemas,
+        Err(err) => {
+            eprintln!("Failed to load schemas: {err}");
+            process::exit(1);
+        }
     };
+    let writable = access_mode.is_writable();
+    let mut state = AppState::new(Arc::new(db));
+    state.auth = Arc::new(authenticator);
+    state.acl = access_control.clone();
+    state.audit = Arc::new(audit_log);
+    state.limits = Arc::new(limits);
+    state.encryption = Arc::new(encryption);
+    state.stored_compression = stored_compression;
+    state.cache = Arc::new(cache_policy);
+    state.history = Arc::new(history);
+    state.indexes = Arc::new(indexes);
+    state.documents = Arc::new(documents);
+    state.schemas = Arc::new(schemas);
+    state.queues = Arc::new(queues);
+    state.locks = Arc::new(locks);
+    state.timeseries = timeseries.clone();
+    if let AccessMode::Secondary { .. } = access_mode {
+        let interval = get_catch_up_interval();
+        let view = Arc::new(DbView::new(access_mode, interval * 3));
+        runtime.spawn(mode::catch_up_with_primary(
+            state.rocksdb.clone(),
+            view.clone(),
+            interval,
+        ));
+        state.view = view;
+    } else if access_mode == AccessMode::ReadOnly {
+        state.view = Arc::new(DbView::new(access_mode, Duration::MAX));
+    }
+    if let Some(primary_url) = primary_url {
+        state.replication = Arc::new(Replication::follower(primary_url));
+        runtime.spawn(follower::run(state.clone(), replication_client));
+    }
+    if writable && state.limits.has_quotas() {
+        runtime.spawn(limits::measure_periodically(
+            state.limits.clone(),
+            state.rocksdb.clone(),
+            state.encryption.clone(),
+            QUOTA_REFRESH,
+        ));
+    }
+    if writable && timeseries.is_downsampling() {
+        runtime.spawn(timeseries::downsample_periodically(
+            timeseries,
+            state.rocksdb.clone(),
+            state.encryption.clone(),
+            state.replication.clone(),
+            Duration::from_secs(60),
+        ));
+    }
 
     runtime.block_on(async {
         let tracer_provider = match init_tracer() {
@@ -82,19 +738,25 @@ fn main() {
             }
         };
 
-        let app = Router::new()
-            .route("/put", post(handlers::put))
-            .route("/get", post(handlers::get))
-            .layer(DefaultBodyLimit::max(200000000))
-            .with_state(state);
+        if access_control.is_enabled() {
+            tokio::spawn(access_control.watch(Duration::from_secs(10)));
+        }
+        let app = routes::router(state);
 
-        let listener = tokio::net::TcpListener::bind("127.0.0.1:4000")
-            .await
-            .unwrap();
+        let listener = tokio::net::TcpListener::bind(&listen_addr).await.unwrap();
 
-        println!("Server running",);
-        if let Err(err) = axum::serve(listener, app).await {
-            eprintln!("Server error: {err}");
+        match tls_config {
+            Some(tls_config) => {
+                tokio::spawn(tls_config.clone().watch(Duration::from_secs(10)));
+                println!("Server running with TLS on {listen_addr}");
+                tls::serve(listener, app, tls_config).await;
+            }
+            None => {
+                println!("Server running on {listen_addr}");
+                if let Err(err) = axum::serve(listener, app).await {
+                    eprintln!("Server error: {err}");
+                }
+            }
         }
         if let Some(provider) = tracer_provider {
             if let Err(err) = provider.shutdown() {
@@ -0,0 +1,184 @@
+use crate::{
+    replication::{Replication, WalFrame},
+    storage::{checkpoint::CheckpointInfo, dump},
+    AppState,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use rocksdb::{WriteBatch, DB};
+use std::{fs, io::Write, path::Path, time::Duration};
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// HTTP client for talking to the primary, sending `token` as a bearer
+/// credential when the primary requires authentication.
+pub fn client(token: Option<&str>) -> Result<reqwest::Client, String> {
+    let mut headers = HeaderMap::new();
+    if let Some(token) = token {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|e| format!("invalid replication token: {}", e))?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Copies a fresh checkpoint of the primary into `db_path`.
+///
+/// Files are downloaded into a sibling directory that is renamed into place
+/// once complete, so an interrupted bootstrap never leaves a partial DB.
+pub async fn bootstrap(
+    client: &reqwest::Client,
+    primary_url: &str,
+    db_path: &Path,
+) -> Result<CheckpointInfo, String> {
+    let primary_url = primary_url.trim_end_matches('/');
+
+    let checkpoint: CheckpointInfo = client
+        .post(format!("{}/replication/checkpoints", primary_url))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("cannot create checkpoint on primary: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("invalid checkpoint response: {}", e))?;
+
+    let staging = db_path.with_extension("bootstrap");
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(|e| e.to_string())?;
+    }
+    fs::create_dir_all(&staging).map_err(|e| e.to_string())?;
+
+    for file in &checkpoint.files {
+        let url = format!(
+            "{}/replication/checkpoints/{}/{}",
+            primary_url, checkpoint.id, file.name
+        );
+        let mut response = client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("cannot download \"{}\": {}", file.name, e))?;
+        let mut out = fs::File::create(staging.join(&file.name)).map_err(|e| e.to_string())?;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("cannot download \"{}\": {}", file.name, e))?
+        {
+            out.write_all(&chunk).map_err(|e| e.to_string())?;
+        }
+        out.sync_all().map_err(|e| e.to_string())?;
+    }
+    fs::rename(&staging, db_path).map_err(|e| e.to_string())?;
+
+    let _ = client
+        .delete(format!(
+            "{}/replication/checkpoints/{}",
+            primary_url, checkpoint.id
+        ))
+        .send()
+        .await;
+
+    Ok(checkpoint)
+}
+
+/// Tails the primary's WAL and applies it until this server is promoted.
+pub async fn run(state: AppState, client: reqwest::Client) {
+    let replication = state.replication.clone();
+    let primary_url = match replication.primary_url() {
+        Some(url) => url.to_string(),
+        None => return,
+    };
+
+    while replication.is_follower() {
+        let since = state.rocksdb.latest_sequence_number();
+        let url = format!("{}/replication/wal?since={}", primary_url, since);
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match response {
+            Ok(response) => {
+                if let Err(message) = tail(&state.rocksdb, &replication, response).await {
+                    replication.record_error(message);
+                }
+            }
+            Err(e) => replication.record_error(format!("cannot reach primary: {}", e)),
+        }
+        tokio::time::sleep(RETRY_INTERVAL).await;
+    }
+}
+
+async fn tail(
+    db: &DB,
+    replication: &Replication,
+    mut response: reqwest::Response,
+) -> Result<(), String> {
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("WAL stream interrupted: {}", e))?
+    {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let frame: WalFrame =
+                serde_json::from_slice(&line).map_err(|e| format!("invalid WAL frame: {}", e))?;
+            if !apply(db, replication, &frame)? {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies one frame. Returns `false` once the server has been promoted.
+///
+/// A follower cannot create column families while serving, and one created
+/// on its own would not get the id the primary's batches refer to. Batches
+/// are refused as soon as the primary has a column family the follower
+/// lacks, such as that of an index added after the bootstrap, until the
+/// follower bootstraps again from a fresh checkpoint.
+pub fn apply(db: &DB, replication: &Replication, frame: &WalFrame) -> Result<bool, String> {
+    let _guard = replication.lock_apply();
+    if !replication.is_follower() {
+        return Ok(false);
+    }
+    if !frame.batches.is_empty() {
+        let local = dump::column_families(db);
+        if let Some(missing) = frame
+            .column_families
+            .iter()
+            .find(|name| !local.contains(name))
+        {
+            return Err(format!(
+                "primary has column family \"{}\" which this follower lacks; remove {} and restart to bootstrap again",
+                missing,
+                db.path().display()
+            ));
+        }
+    }
+    for batch in &frame.batches {
+        let expected = db.latest_sequence_number() + 1;
+        if batch.seq != expected {
+            return Err(format!(
+                "expected batch at sequence {} but primary sent {}",
+                expected, batch.seq
+            ));
+        }
+        let data = STANDARD
+            .decode(&batch.data)
+            .map_err(|e| format!("invalid batch at sequence {}: {}", batch.seq, e))?;
+        db.write(WriteBatch::from_data(&data))
+            .map_err(|e| format!("cannot apply batch at sequence {}: {}", batch.seq, e))?;
+    }
+    replication.record_contact(frame.primary_sequence);
+    Ok(true)
+}
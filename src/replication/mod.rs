@@ -0,0 +1,150 @@
+//! Replication Layer
+//!
+//! This module ships the primary's WAL to followers:
+//! - Replication role and lag tracking
+//! - Follower bootstrap from a primary checkpoint and WAL tailing
+
+pub mod follower;
+
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::Instant,
+};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Primary,
+    Follower,
+}
+
+/// One line of the `/replication/wal` NDJSON stream. Frames without batches
+/// are heartbeats that keep the follower's lag current.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WalFrame {
+    pub primary_sequence: u64,
+    pub batches: Vec<WalBatch>,
+    /// The primary's column families, so a follower can tell when the
+    /// batches refer to one it lacks.
+    #[serde(default)]
+    pub column_families: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WalBatch {
+    pub seq: u64,
+    /// Base64 of the serialized `WriteBatch`.
+    pub data: String,
+}
+
+#[derive(Debug)]
+pub struct Replication {
+    primary_url: Option<String>,
+    follower: AtomicBool,
+    primary_sequence: AtomicU64,
+    last_contact: Mutex<Option<Instant>>,
+    last_error: Mutex<Option<String>>,
+    /// Held while a follower applies a batch so promotion cannot interleave.
+    apply_lock: Mutex<()>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReplicationStatus {
+    pub role: Role,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_url: Option<String>,
+    pub applied_sequence: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_sequence: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lag_sequences: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_contact_ms_ago: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl Replication {
+    pub fn primary() -> Self {
+        Replication {
+            primary_url: None,
+            follower: AtomicBool::new(false),
+            primary_sequence: AtomicU64::new(0),
+            last_contact: Mutex::new(None),
+            last_error: Mutex::new(None),
+            apply_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn follower(primary_url: String) -> Self {
+        Replication {
+            primary_url: Some(primary_url.trim_end_matches('/').to_string()),
+            follower: AtomicBool::new(true),
+            ..Replication::primary()
+        }
+    }
+
+    pub fn role(&self) -> Role {
+        if self.follower.load(Ordering::SeqCst) {
+            Role::Follower
+        } else {
+            Role::Primary
+        }
+    }
+
+    pub fn is_follower(&self) -> bool {
+        self.role() == Role::Follower
+    }
+
+    pub fn primary_url(&self) -> Option<&str> {
+        self.primary_url.as_deref()
+    }
+
+    /// Stops applying the primary's WAL and starts accepting writes.
+    ///
+    /// Returns `false` if this server already was a primary.
+    pub fn promote(&self) -> bool {
+        let _guard = self.lock_apply();
+        self.follower.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn lock_apply(&self) -> MutexGuard<'_, ()> {
+        self.apply_lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn record_contact(&self, primary_sequence: u64) {
+        self.primary_sequence
+            .store(primary_sequence, Ordering::SeqCst);
+        *self.last_contact.lock().unwrap() = Some(Instant::now());
+        *self.last_error.lock().unwrap() = None;
+    }
+
+    pub fn record_error(&self, message: String) {
+        println!("Replication error: {}", message);
+        *self.last_error.lock().unwrap() = Some(message);
+    }
+
+    pub fn status(&self, applied_sequence: u64) -> ReplicationStatus {
+        let role = self.role();
+        let last_contact = *self.last_contact.lock().unwrap();
+        let primary_sequence = match role {
+            Role::Follower if last_contact.is_some() => {
+                Some(self.primary_sequence.load(Ordering::SeqCst))
+            }
+            _ => None,
+        };
+        ReplicationStatus {
+            role,
+            primary_url: self.primary_url.clone(),
+            applied_sequence,
+            primary_sequence,
+            lag_sequences: primary_sequence.map(|seq| seq.saturating_sub(applied_sequence)),
+            last_contact_ms_ago: last_contact.map(|at| at.elapsed().as_millis() as u64),
+            last_error: self.last_error.lock().unwrap().clone(),
+        }
+    }
+}
@@ -0,0 +1,6 @@
+//! Server Layer
+//!
+//! This module owns the network listener:
+//! - TLS and mutual-TLS termination with certificate hot reload
+
+pub mod tls;
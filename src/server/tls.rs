@@ -0,0 +1,221 @@
+use axum::{extract::Request, Router};
+use hyper::{body::Incoming, service::service_fn};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+};
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig, ServerConnection,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+pub const DEFAULT_HANDSHAKE_TIMEOUT_SECONDS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// Handshakes without a certificate signed by the client CA fail.
+    Required,
+    /// Clients may connect without a certificate; presented ones are verified.
+    Optional,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    pub client_ca_path: Option<PathBuf>,
+    pub client_auth: ClientAuth,
+    /// How long a client may take to complete the handshake before its
+    /// connection is dropped, so stalled clients do not hold sockets open.
+    pub handshake_timeout: Duration,
+}
+
+/// Subject of a client certificate that passed verification, attached to
+/// each request made over that connection.
+#[derive(Debug, Clone)]
+pub struct ClientIdentity {
+    pub subject: String,
+    pub serial: String,
+}
+
+/// Server configuration that is rebuilt whenever the files behind it change.
+pub struct ReloadingConfig {
+    settings: TlsSettings,
+    current: RwLock<Arc<ServerConfig>>,
+    loaded_at: RwLock<Vec<Option<SystemTime>>>,
+}
+
+impl ReloadingConfig {
+    pub fn load(settings: TlsSettings) -> Result<Self, String> {
+        let config = load_server_config(&settings)?;
+        let loaded_at = modification_times(&settings);
+        Ok(ReloadingConfig {
+            settings,
+            current: RwLock::new(Arc::new(config)),
+            loaded_at: RwLock::new(loaded_at),
+        })
+    }
+
+    pub fn current(&self) -> Arc<ServerConfig> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn handshake_timeout(&self) -> Duration {
+        self.settings.handshake_timeout
+    }
+
+    /// Reloads the certificates if any file changed since the last load.
+    /// A broken update is logged and the previous configuration kept.
+    pub fn reload_if_changed(&self) {
+        let modified = modification_times(&self.settings);
+        if *self.loaded_at.read().unwrap() == modified {
+            return;
+        }
+        match load_server_config(&self.settings) {
+            Ok(config) => {
+                *self.current.write().unwrap() = Arc::new(config);
+                println!("Reloaded TLS certificates");
+            }
+            Err(e) => println!("Error reloading TLS certificates, keeping previous: {}", e),
+        }
+        *self.loaded_at.write().unwrap() = modified;
+    }
+
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            self.reload_if_changed();
+        }
+    }
+}
+
+/// Accepts TLS connections and serves `app` over HTTP/1.1 or HTTP/2.
+pub async fn serve(listener: TcpListener, app: Router, tls: Arc<ReloadingConfig>) {
+    loop {
+        let (tcp, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("Error accepting connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let acceptor = TlsAcceptor::from(tls.current());
+        let handshake_timeout = tls.handshake_timeout();
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            let stream = match tokio::time::timeout(handshake_timeout, acceptor.accept(tcp)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    println!("TLS handshake with {} failed: {}", remote, e);
+                    return;
+                }
+                Err(_) => {
+                    println!("TLS handshake with {} timed out", remote);
+                    return;
+                }
+            };
+            let identity = client_identity(stream.get_ref().1);
+
+            let service = service_fn(move |mut request: Request<Incoming>| {
+                if let Some(identity) = &identity {
+                    request.extensions_mut().insert(identity.clone());
+                }
+                app.clone().oneshot(request)
+            });
+            if let Err(e) = Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                println!("Error serving connection from {}: {}", remote, e);
+            }
+        });
+    }
+}
+
+fn client_identity(connection: &ServerConnection) -> Option<ClientIdentity> {
+    let certificate = connection.peer_certificates()?.first()?;
+    let (_, parsed) = x509_parser::parse_x509_certificate(certificate.as_ref()).ok()?;
+    Some(ClientIdentity {
+        subject: parsed.subject().to_string(),
+        serial: parsed.raw_serial_as_string(),
+    })
+}
+
+fn load_server_config(settings: &TlsSettings) -> Result<ServerConfig, String> {
+    let certs = load_certs(&settings.cert_path)?;
+    let key = load_key(&settings.key_path)?;
+
+    let builder = ServerConfig::builder();
+    let builder = match &settings.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| format!("invalid client CA {:?}: {}", ca_path, e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+            let verifier = match settings.client_auth {
+                ClientAuth::Required => verifier,
+                ClientAuth::Optional => verifier.allow_unauthenticated(),
+            };
+            let verifier = verifier
+                .build()
+                .map_err(|e| format!("invalid client CA {:?}: {}", ca_path, e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("invalid certificate or key: {}", e))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let mut reader = io::BufReader::new(
+        fs::File::open(path).map_err(|e| format!("cannot open {:?}: {}", path, e))?,
+    );
+    let certs = rustls_pemfile::certs(&mut reader)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("cannot read certificates from {:?}: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {:?}", path));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let mut reader = io::BufReader::new(
+        fs::File::open(path).map_err(|e| format!("cannot open {:?}: {}", path, e))?,
+    );
+    rustls_pemfile::private_key(&mut reader)
+        .map_err(|e| format!("cannot read private key from {:?}: {}", path, e))?
+        .ok_or_else(|| format!("no private key found in {:?}", path))
+}
+
+fn modification_times(settings: &TlsSettings) -> Vec<Option<SystemTime>> {
+    [
+        Some(&settings.cert_path),
+        Some(&settings.key_path),
+        settings.client_ca_path.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+    .collect()
+}
@@ -0,0 +1,380 @@
+use crate::storage::{
+    encryption::{Encryption, EncryptionError},
+    history::History,
+    index::Indexes,
+    record::{self, Header, Record},
+};
+use rocksdb::{Direction, Error, IteratorMode, Snapshot, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Bytes per chunk key.
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+/// Prefix of a manifest stored under the blob's own key.
+const MANIFEST_MAGIC: &[u8] = b"\0BLB\x01";
+/// Chunks live under `CHUNK_PREFIX <upload id> / <index>`, so an upload never
+/// touches the chunks of the blob it replaces until it commits.
+const CHUNK_PREFIX: &[u8] = b"\0blob\0";
+/// `UPLOAD_PREFIX <upload id>` marks an upload that has written chunks but
+/// not yet committed or aborted, so its chunks can be dropped after a crash.
+const UPLOAD_PREFIX: &[u8] = b"\0upload\0";
+
+static UPLOADS: AtomicU64 = AtomicU64::new(0);
+
+/// Describes a value split across chunk keys.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub id: String,
+    pub size: u64,
+    pub chunk_size: u64,
+    pub chunks: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    pub fn chunk_key(&self, index: u64) -> Vec<u8> {
+        chunk_key(&self.id, index)
+    }
+
+    /// Bounds covering every chunk key of the blob.
+    pub fn chunk_range(&self) -> (Vec<u8>, Vec<u8>) {
+        chunk_range(&self.id)
+    }
+
+    /// Chunk indices holding bytes `start..=end`.
+    pub fn chunks_for(&self, start: u64, end: u64) -> std::ops::RangeInclusive<u64> {
+        start / self.chunk_size..=end / self.chunk_size
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut encoded = MANIFEST_MAGIC.to_vec();
+        encoded.extend_from_slice(&serde_json::to_vec(self).unwrap_or_default());
+        encoded
+    }
+}
+
+/// What a key holds.
+#[derive(Debug)]
+pub enum Stored {
+    Blob(Manifest),
+    Value(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub enum BlobError {
+    Storage(Error),
+    Unreadable(EncryptionError),
+    /// A manifest or chunk does not match what the manifest promises.
+    Corrupt(String),
+}
+
+impl fmt::Display for BlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlobError::Storage(e) => write!(f, "{}", e),
+            BlobError::Unreadable(e) => write!(f, "{}", e),
+            BlobError::Corrupt(reason) => write!(f, "corrupt chunked value: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for BlobError {}
+
+impl From<Error> for BlobError {
+    fn from(e: Error) -> Self {
+        BlobError::Storage(e)
+    }
+}
+
+impl From<EncryptionError> for BlobError {
+    fn from(e: EncryptionError) -> Self {
+        match e {
+            EncryptionError::Storage(e) => BlobError::Storage(e),
+            e => BlobError::Unreadable(e),
+        }
+    }
+}
+
+/// Whether the body of a decrypted value is a manifest rather than a plain
+/// value.
+pub fn is_manifest(value: &[u8]) -> bool {
+    value.starts_with(MANIFEST_MAGIC)
+}
+
+/// Reads `key`, telling chunked values apart from plain ones.
+pub fn lookup(
+    db: &DB,
+    encryption: &Encryption,
+    key: &[u8],
+) -> Result<Option<Record<Stored>>, BlobError> {
+    decode(encryption, key, db.get(key)?)
+}
+
+/// Reads `key` as of `snapshot`, telling chunked values apart from plain
+/// ones.
+pub fn lookup_at(
+    snapshot: &Snapshot,
+    encryption: &Encryption,
+    key: &[u8],
+) -> Result<Option<Record<Stored>>, BlobError> {
+    decode(encryption, key, snapshot.get(key)?)
+}
+
+/// The manifest a value holds as stored under `key`, `None` for plain
+/// values and ones that cannot be read.
+pub fn manifest_of(encryption: &Encryption, key: &[u8], stored: &[u8]) -> Option<Manifest> {
+    match decode(encryption, key, Some(stored.to_vec())) {
+        Ok(Some(Record {
+            body: Stored::Blob(manifest),
+            ..
+        })) => Some(manifest),
+        _ => None,
+    }
+}
+
+fn decode(
+    encryption: &Encryption,
+    key: &[u8],
+    stored: Option<Vec<u8>>,
+) -> Result<Option<Record<Stored>>, BlobError> {
+    let Some(stored) = stored else {
+        return Ok(None);
+    };
+    let record = record::unwrap(encryption.open(key, &stored)?);
+    let manifest = match record.body.strip_prefix(MANIFEST_MAGIC) {
+        Some(json) => Some(
+            serde_json::from_slice(json)
+                .map_err(|e| BlobError::Corrupt(format!("invalid manifest: {}", e)))?,
+        ),
+        None => None,
+    };
+    Ok(Some(record.map(|value| match manifest {
+        Some(manifest) => Stored::Blob(manifest),
+        None => Stored::Value(value),
+    })))
+}
+
+/// Reads one chunk of a blob as of `snapshot`, so a blob replaced part way
+/// through a download still reads whole.
+pub fn read_chunk(
+    snapshot: &Snapshot,
+    encryption: &Encryption,
+    manifest: &Manifest,
+    index: u64,
+) -> Result<Vec<u8>, BlobError> {
+    let key = manifest.chunk_key(index);
+    let stored = snapshot.get(&key)?.ok_or_else(|| {
+        BlobError::Corrupt(format!("chunk {} of {} is missing", index, manifest.id))
+    })?;
+    Ok(encryption.open(&key, &stored)?)
+}
+
+/// Drops the chunks of uploads that were neither committed nor aborted,
+/// left behind by a crash. Returns how many uploads there were.
+pub fn sweep_uploads(db: &DB) -> Result<u64, Error> {
+    let mut batch = WriteBatch::default();
+    let mut swept = 0;
+    for item in db.iterator(IteratorMode::From(UPLOAD_PREFIX, Direction::Forward)) {
+        let (key, _) = item?;
+        let Some(id) = key.strip_prefix(UPLOAD_PREFIX) else {
+            break;
+        };
+        let (start, end) = chunk_range(&String::from_utf8_lossy(id));
+        batch.delete_range(start, end);
+        batch.delete(&key);
+        swept += 1;
+    }
+    db.write(batch)?;
+    Ok(swept)
+}
+
+/// Bytes of the chunks written so far by uploads of keys starting with
+/// `prefix` that have not committed yet.
+pub fn uploading_under(db: &DB, prefix: &[u8]) -> Result<u64, Error> {
+    let mut size = 0;
+    for item in db.iterator(IteratorMode::From(UPLOAD_PREFIX, Direction::Forward)) {
+        let (marker, key) = item?;
+        let Some(id) = marker.strip_prefix(UPLOAD_PREFIX) else {
+            break;
+        };
+        if !key.starts_with(prefix) {
+            continue;
+        }
+        let (start, end) = chunk_range(&String::from_utf8_lossy(id));
+        for chunk in db.iterator(IteratorMode::From(&start, Direction::Forward)) {
+            let (chunk, stored) = chunk?;
+            if *chunk >= *end {
+                break;
+            }
+            size += stored.len() as u64;
+        }
+    }
+    Ok(size)
+}
+
+/// Adds deletion of a blob's chunks to `batch`.
+pub fn delete_chunks(batch: &mut WriteBatch, manifest: &Manifest) {
+    let (start, end) = manifest.chunk_range();
+    batch.delete_range(start, end);
+}
+
+/// Writes a value chunk by chunk as it arrives, then commits it by storing
+/// its manifest under the key. A writer dropped before it commits, with its
+/// request aborted or refused, drops the chunks it wrote. Until then the
+/// upload is marked with the key, so quotas count its chunks.
+pub struct BlobWriter<'a> {
+    db: &'a DB,
+    encryption: &'a Encryption,
+    history: &'a History,
+    indexes: &'a Indexes,
+    key: Vec<u8>,
+    id: String,
+    buffer: Vec<u8>,
+    chunks: u64,
+    size: u64,
+    hasher: Sha256,
+    committed: bool,
+}
+
+impl<'a> BlobWriter<'a> {
+    pub fn new(
+        db: &'a DB,
+        encryption: &'a Encryption,
+        history: &'a History,
+        indexes: &'a Indexes,
+        key: &[u8],
+    ) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos())
+            .unwrap_or(0);
+        let upload = UPLOADS.fetch_add(1, Ordering::Relaxed);
+        BlobWriter {
+            db,
+            encryption,
+            history,
+            indexes,
+            key: key.to_vec(),
+            id: format!("{:032x}{:08x}", nanos, upload),
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+            chunks: 0,
+            size: 0,
+            hasher: Sha256::new(),
+            committed: false,
+        }
+    }
+
+    pub fn write(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        self.hasher.update(data);
+        self.size += data.len() as u64;
+        while !data.is_empty() {
+            let take = (CHUNK_SIZE - self.buffer.len()).min(data.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.buffer.len() == CHUNK_SIZE {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the manifest under its key, replacing whatever was there, and
+    /// drops the chunks of a blob it replaces, which history therefore does
+    /// not keep. The header's digest is taken
+    /// from the bytes written and its version follows the replaced value's.
+    pub fn commit(mut self, mut header: Header) -> Result<Manifest, BlobError> {
+        if !self.buffer.is_empty() {
+            self.flush()?;
+        }
+        let manifest = Manifest {
+            id: self.id.clone(),
+            size: self.size,
+            chunk_size: CHUNK_SIZE as u64,
+            chunks: self.chunks,
+            sha256: record::hex(&self.hasher.clone().finalize()),
+        };
+
+        let key = self.key.as_slice();
+        let _writes = self.encryption.hold_writes();
+        let _versions = record::hold_versions(key);
+        let mut batch = WriteBatch::default();
+        let previous = lookup(self.db, self.encryption, key)?;
+        header.succeed(previous.as_ref());
+        let replaced = previous.as_ref().map(|previous| &previous.body);
+        self.indexes
+            .update(self.db, &mut batch, key, replaced, None);
+        if let Some(Record {
+            body: Stored::Blob(previous),
+            ..
+        }) = previous
+        {
+            delete_chunks(&mut batch, &previous);
+        }
+        self.history
+            .keep(self.db, self.encryption, &mut batch, key, Some(&mut header))?;
+        header.sha256 = manifest.sha256.clone();
+        let wrapped = record::wrap(&header, &manifest.encode());
+        batch.put(key, self.encryption.seal(key, &wrapped));
+        batch.delete(upload_key(&self.id));
+        self.db.write(batch)?;
+        self.committed = true;
+        Ok(manifest)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        let key = chunk_key(&self.id, self.chunks);
+        let sealed = self.encryption.seal(&key, &self.buffer);
+        let mut batch = WriteBatch::default();
+        if self.chunks == 0 {
+            batch.put(upload_key(&self.id), &self.key);
+        }
+        batch.put(&key, sealed);
+        self.db.write(batch)?;
+        self.buffer.clear();
+        self.chunks += 1;
+        Ok(())
+    }
+}
+
+impl Drop for BlobWriter<'_> {
+    /// Drops the chunks written so far unless the upload committed.
+    fn drop(&mut self) {
+        if self.committed || self.chunks == 0 {
+            return;
+        }
+        let (start, end) = chunk_range(&self.id);
+        let mut batch = WriteBatch::default();
+        batch.delete_range(start, end);
+        batch.delete(upload_key(&self.id));
+        if let Err(e) = self.db.write(batch) {
+            println!("Error dropping chunks of upload {}: {:}", self.id, e);
+        }
+    }
+}
+
+fn chunk_key(id: &str, index: u64) -> Vec<u8> {
+    let mut key = CHUNK_PREFIX.to_vec();
+    key.extend_from_slice(format!("{}/{:010}", id, index).as_bytes());
+    key
+}
+
+fn upload_key(id: &str) -> Vec<u8> {
+    let mut key = UPLOAD_PREFIX.to_vec();
+    key.extend_from_slice(id.as_bytes());
+    key
+}
+
+/// Bounds covering every chunk key of one blob.
+fn chunk_range(id: &str) -> (Vec<u8>, Vec<u8>) {
+    let mut start = CHUNK_PREFIX.to_vec();
+    start.extend_from_slice(id.as_bytes());
+    let mut end = start.clone();
+    start.push(b'/');
+    end.push(b'/' + 1);
+    (start, end)
+}
@@ -0,0 +1,120 @@
+use rocksdb::{checkpoint::Checkpoint, DB};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CheckpointFile {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CheckpointInfo {
+    pub id: String,
+    /// Sequence number the checkpoint contains at least.
+    pub sequence: u64,
+    pub files: Vec<CheckpointFile>,
+}
+
+/// Directory holding checkpoints of `db`, next to its data directory.
+pub fn checkpoints_dir(db: &DB) -> PathBuf {
+    let mut dir = OsString::from(db.path().as_os_str());
+    dir.push(".checkpoints");
+    PathBuf::from(dir)
+}
+
+/// Returns the directory of checkpoint `id`, or `None` if the id could
+/// escape the checkpoints directory.
+pub fn checkpoint_path(db: &DB, id: &str) -> Option<PathBuf> {
+    if !is_plain_name(id) {
+        return None;
+    }
+    Some(checkpoints_dir(db).join(id))
+}
+
+/// Returns the path of `file` inside checkpoint `id`.
+pub fn checkpoint_file_path(db: &DB, id: &str, file: &str) -> Option<PathBuf> {
+    if !is_plain_name(file) {
+        return None;
+    }
+    checkpoint_path(db, id).map(|dir| dir.join(file))
+}
+
+/// Creates a new checkpoint and lists the files a follower has to copy.
+pub fn create(db: &DB) -> io::Result<CheckpointInfo> {
+    let sequence = db.latest_sequence_number();
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let id = format!("{}-{}", sequence, millis);
+
+    let root = checkpoints_dir(db);
+    fs::create_dir_all(&root)?;
+    let dir = root.join(&id);
+    let result = Checkpoint::new(db).and_then(|checkpoint| checkpoint.create_checkpoint(&dir));
+    if let Err(e) = result {
+        println!("Error creating checkpoint \"{:?}\": {:}", dir, e);
+        return Err(io::Error::other(e));
+    }
+
+    Ok(CheckpointInfo {
+        id,
+        sequence,
+        files: list_files(&dir)?,
+    })
+}
+
+pub fn remove(db: &DB, id: &str) -> io::Result<()> {
+    match checkpoint_path(db, id) {
+        Some(dir) => fs::remove_dir_all(dir),
+        None => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+    }
+}
+
+/// Removes every checkpoint of `db`. Checkpoints only serve followers
+/// bootstrapping from this process, so any found at startup were left by
+/// bootstraps that never finished and would otherwise keep their SST files
+/// on disk for good. Returns how many there were.
+pub fn sweep(db: &DB) -> io::Result<usize> {
+    let entries = match fs::read_dir(checkpoints_dir(db)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut swept = 0;
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+            swept += 1;
+        }
+    }
+    Ok(swept)
+}
+
+fn list_files(dir: &Path) -> io::Result<Vec<CheckpointFile>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        files.push(CheckpointFile {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            size: metadata.len(),
+        });
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
@@ -0,0 +1,122 @@
+use serde::Deserialize;
+use std::io::{self, Read, Write};
+
+/// Prefix of values stored compressed, followed by a codec byte. Values
+/// without it are stored as written.
+const MAGIC: &[u8] = b"\0CMP\x01";
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    Gzip,
+    Zstd,
+    Br,
+}
+
+impl Codec {
+    /// Name of the codec as a `Content-Encoding` token.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+            Codec::Zstd => "zstd",
+            Codec::Br => "br",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "gzip" => Some(Codec::Gzip),
+            "zstd" => Some(Codec::Zstd),
+            "br" => Some(Codec::Br),
+            _ => None,
+        }
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            Codec::Gzip => 1,
+            Codec::Zstd => 2,
+            Codec::Br => 3,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(Codec::Gzip),
+            2 => Some(Codec::Zstd),
+            3 => Some(Codec::Br),
+            _ => None,
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Codec::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Codec::Zstd => zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL),
+            Codec::Br => {
+                let mut compressed = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(
+                        &mut compressed,
+                        BROTLI_BUFFER_SIZE,
+                        BROTLI_QUALITY,
+                        BROTLI_WINDOW,
+                    );
+                    encoder.write_all(data)?;
+                }
+                Ok(compressed)
+            }
+        }
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        match self {
+            Codec::Gzip => {
+                flate2::read::GzDecoder::new(data).read_to_end(&mut decompressed)?;
+            }
+            Codec::Zstd => decompressed = zstd::decode_all(data)?,
+            Codec::Br => {
+                brotli::Decompressor::new(data, BROTLI_BUFFER_SIZE)
+                    .read_to_end(&mut decompressed)?;
+            }
+        }
+        Ok(decompressed)
+    }
+}
+
+/// A value as `/get` returns it: the JSON-encoded string, compressed with
+/// `codec`, so it can be sent to clients accepting that encoding as stored.
+pub fn pack(codec: Codec, value: &str) -> io::Result<Vec<u8>> {
+    let body = serde_json::to_vec(value)?;
+    let mut packed = MAGIC.to_vec();
+    packed.push(codec.tag());
+    packed.extend_from_slice(&codec.compress(&body)?);
+    Ok(packed)
+}
+
+/// The codec and compressed JSON body of a packed value, or `None` for
+/// values stored as written.
+pub fn unpack(stored: &[u8]) -> Option<(Codec, &[u8])> {
+    let rest = stored.strip_prefix(MAGIC)?;
+    let (&tag, body) = rest.split_first()?;
+    Some((Codec::from_tag(tag)?, body))
+}
+
+/// Restores the value from a packed one. Other values are returned as they
+/// are.
+pub fn unpack_value(stored: &[u8]) -> io::Result<Vec<u8>> {
+    let Some((codec, body)) = unpack(stored) else {
+        return Ok(stored.to_vec());
+    };
+    let value: String = serde_json::from_slice(&codec.decompress(body)?)?;
+    Ok(value.into_bytes())
+}
@@ -0,0 +1,153 @@
+use crate::{limits::Throttled, storage::schema::Violation};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Content type of an RFC 6902 JSON Patch.
+pub const JSON_PATCH: &str = "application/json-patch+json";
+/// Content type of an RFC 7396 JSON Merge Patch.
+pub const MERGE_PATCH: &str = "application/merge-patch+json";
+
+/// Key prefixes whose values must be JSON documents. Disabled, no key is a
+/// document; an empty prefix makes every key one.
+#[derive(Debug, Default)]
+pub struct Documents {
+    prefixes: Vec<String>,
+}
+
+impl Documents {
+    pub fn disabled() -> Self {
+        Documents::default()
+    }
+
+    pub fn new(prefixes: Vec<String>) -> Self {
+        Documents { prefixes }
+    }
+
+    pub fn is_document(&self, key: &str) -> bool {
+        self.prefixes
+            .iter()
+            .any(|prefix| key.starts_with(prefix.as_str()))
+    }
+}
+
+/// A partial update of a JSON document.
+#[derive(Debug)]
+pub enum Patch {
+    Json(json_patch::Patch),
+    Merge(Value),
+}
+
+impl Patch {
+    /// Reads a patch of the given content type. `Ok(None)` for content types
+    /// that are not patches.
+    pub fn parse(content_type: &str, body: &[u8]) -> Result<Option<Self>, serde_json::Error> {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        if media_type.eq_ignore_ascii_case(JSON_PATCH) {
+            serde_json::from_slice(body).map(|patch| Some(Patch::Json(patch)))
+        } else if media_type.eq_ignore_ascii_case(MERGE_PATCH) {
+            serde_json::from_slice(body).map(|patch| Some(Patch::Merge(patch)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Applies the patch to `document`, `None` if the key has no value. A
+    /// merge patch creates the document; a JSON Patch needs one to exist.
+    /// Either applies whole or not at all.
+    pub fn apply(&self, document: Option<Value>) -> Result<Value, PatchError> {
+        match self {
+            Patch::Json(patch) => {
+                let mut document = document.ok_or(PatchError::Missing)?;
+                json_patch::patch(&mut document, &patch.0).map_err(PatchError::Failed)?;
+                Ok(document)
+            }
+            Patch::Merge(patch) => {
+                let mut document = document.unwrap_or(Value::Null);
+                json_patch::merge(&mut document, patch);
+                Ok(document)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PatchError {
+    /// JSON Patch of a key that has no value.
+    Missing,
+    /// The stored value is chunked or not JSON.
+    NotDocument(String),
+    Failed(json_patch::PatchError),
+    /// The patched document does not match its schema.
+    Invalid(Vec<Violation>),
+    /// The patched document would take its namespace over quota.
+    Throttled(Throttled),
+    Storage(String),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::Missing => write!(f, "no document to patch"),
+            PatchError::NotDocument(reason) => write!(f, "not a JSON document: {}", reason),
+            PatchError::Failed(e) => write!(f, "{}", e),
+            PatchError::Invalid(_) => write!(f, "patched document does not match its schema"),
+            PatchError::Throttled(throttled) => write!(f, "{}", throttled.message),
+            PatchError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl From<rocksdb::Error> for PatchError {
+    fn from(e: rocksdb::Error) -> Self {
+        PatchError::Storage(e.to_string())
+    }
+}
+
+/// Reads a comma-separated list of JSON pointers. A leading `/` may be left
+/// out, so `name,address/city` selects `/name` and `/address/city`.
+pub fn parse_fields(fields: &str) -> Vec<String> {
+    fields
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| match field.starts_with('/') {
+            true => field.to_string(),
+            false => format!("/{}", field),
+        })
+        .collect()
+}
+
+/// The parts of `document` at `fields`, nested as in the document. Missing
+/// fields are left out; fields inside arrays are keyed by their index.
+pub fn project(document: &Value, fields: &[String]) -> Value {
+    let mut projected = Value::Object(Map::new());
+    'fields: for field in fields {
+        let Some(value) = document.pointer(field) else {
+            continue;
+        };
+        let mut tokens: Vec<String> = field
+            .split('/')
+            .skip(1)
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect();
+        let Some(last) = tokens.pop() else {
+            continue;
+        };
+        let mut target = &mut projected;
+        for token in tokens {
+            // A parent selected whole already holds this field.
+            let Value::Object(map) = target else {
+                continue 'fields;
+            };
+            target = map
+                .entry(token)
+                .or_insert_with(|| Value::Object(Map::new()));
+        }
+        if let Value::Object(map) = target {
+            map.insert(last, value.clone());
+        }
+    }
+    projected
+}
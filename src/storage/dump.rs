@@ -0,0 +1,514 @@
+use crate::storage::{
+    self,
+    blob::{self, BlobError, Stored},
+    encryption::Encryption,
+    history,
+    record::{self, Record},
+    rocksdb::Store,
+    structures,
+};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use rocksdb::{
+    Direction, Error, IteratorMode, Options, Snapshot, SstFileWriter, WriteBatch, DB,
+    DEFAULT_COLUMN_FAMILY_NAME,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fmt, fs, io,
+    io::BufRead,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Entries restored per write batch.
+const RESTORE_BATCH_SIZE: usize = 1000;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DumpFormat {
+    /// One `Line` per entry, over every column family in scope.
+    #[default]
+    Ndjson,
+    /// An SST file of one column family, ready for `ingest_external_file`.
+    Sst,
+}
+
+/// What a restore does with a key the DB already has.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    #[default]
+    Overwrite,
+    Skip,
+    /// Refuses the restore, writing nothing, when the dump holds such a
+    /// key.
+    Fail,
+}
+
+/// Entries to export: every column family unless one is named, and only
+/// keys starting with `prefix`, along with the internal entries of the
+/// default column family that belong to them: chunks of chunked values,
+/// history and the entries of structures.
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    pub column_family: Option<String>,
+    pub prefix: Vec<u8>,
+}
+
+/// An entry as it is stored, sealed and with its record header, so a
+/// restore brings back exactly what was dumped. Restoring encrypted values
+/// needs the master keys they were sealed with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub column_family: String,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+/// An entry in an NDJSON dump, with key and value in base64.
+#[derive(Serialize, Deserialize, Debug)]
+struct Line {
+    column_family: String,
+    key: String,
+    value: String,
+}
+
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Restored {
+    pub restored: u64,
+    pub skipped: u64,
+}
+
+#[derive(Debug)]
+pub enum DumpError {
+    UnknownColumnFamily(String),
+    /// Nothing in scope, and an SST file cannot be empty.
+    Empty,
+    Invalid {
+        line: u64,
+        message: String,
+    },
+    Conflict {
+        column_family: String,
+        key: String,
+    },
+    Io(io::Error),
+    Storage(Error),
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DumpError::UnknownColumnFamily(name) => {
+                write!(f, "unknown column family \"{}\"", name)
+            }
+            DumpError::Empty => write!(f, "no keys in scope"),
+            DumpError::Invalid { line, message } => write!(f, "line {}: {}", line, message),
+            DumpError::Conflict { column_family, key } => write!(
+                f,
+                "key \"{}\" already exists in column family \"{}\"",
+                key, column_family
+            ),
+            DumpError::Io(e) => write!(f, "{}", e),
+            DumpError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for DumpError {}
+
+impl From<io::Error> for DumpError {
+    fn from(e: io::Error) -> Self {
+        DumpError::Io(e)
+    }
+}
+
+impl From<Error> for DumpError {
+    fn from(e: Error) -> Self {
+        DumpError::Storage(e)
+    }
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "ndjson" => Ok(DumpFormat::Ndjson),
+            "sst" => Ok(DumpFormat::Sst),
+            _ => Err(format!(
+                "unknown format \"{}\", expected ndjson or sst",
+                value
+            )),
+        }
+    }
+}
+
+impl FromStr for OnConflict {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "overwrite" => Ok(OnConflict::Overwrite),
+            "skip" => Ok(OnConflict::Skip),
+            "fail" => Ok(OnConflict::Fail),
+            _ => Err(format!(
+                "unknown conflict policy \"{}\", expected overwrite, skip or fail",
+                value
+            )),
+        }
+    }
+}
+
+impl Entry {
+    /// The entry as a line of an NDJSON dump, newline included.
+    pub fn to_line(&self) -> String {
+        let line = Line {
+            column_family: self.column_family.clone(),
+            key: STANDARD.encode(&self.key),
+            value: STANDARD.encode(&self.value),
+        };
+        let mut text = serde_json::to_string(&line).expect("dump lines serialize");
+        text.push('\n');
+        text
+    }
+
+    fn from_line(text: &str) -> Result<Self, String> {
+        let line: Line = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let decode = |field: &str, encoded: &str| {
+            STANDARD
+                .decode(encoded)
+                .map_err(|e| format!("{} is not valid base64: {}", field, e))
+        };
+        Ok(Entry {
+            key: decode("key", &line.key)?,
+            value: decode("value", &line.value)?,
+            column_family: line.column_family,
+        })
+    }
+}
+
+/// Names of the column families of `db`, the default one first.
+pub fn column_families(db: &DB) -> Vec<String> {
+    let mut names = DB::list_cf(&Options::default(), db.path()).unwrap_or_default();
+    names.retain(|name| name != DEFAULT_COLUMN_FAMILY_NAME);
+    names.insert(0, DEFAULT_COLUMN_FAMILY_NAME.to_string());
+    names
+}
+
+/// Hands every entry in `scope` to `emit`, in key order per column family,
+/// all read from one snapshot so the export is consistent across column
+/// families. Returns how many there were.
+pub fn export(
+    db: &DB,
+    encryption: &Encryption,
+    scope: &Scope,
+    mut emit: impl FnMut(Entry) -> Result<(), DumpError>,
+) -> Result<u64, DumpError> {
+    let names = match &scope.column_family {
+        Some(name) => vec![name.clone()],
+        None => column_families(db),
+    };
+    let snapshot = db.snapshot();
+    let mut exported = 0;
+    for name in names {
+        let column_family = match name == DEFAULT_COLUMN_FAMILY_NAME {
+            true => None,
+            false => Some(
+                db.cf_handle(&name)
+                    .ok_or_else(|| DumpError::UnknownColumnFamily(name.clone()))?,
+            ),
+        };
+        let mut ranges = match column_family {
+            None => dependents(&snapshot, encryption, &scope.prefix)?,
+            Some(_) => Vec::new(),
+        };
+        ranges.push(KeyRange::Prefix(scope.prefix.clone()));
+        for range in ranges {
+            let from = IteratorMode::From(range.start(), Direction::Forward);
+            let entries = match column_family {
+                None => snapshot.iterator(from),
+                Some(column_family) => snapshot.iterator_cf(column_family, from),
+            };
+            for item in entries {
+                let (key, value) = item?;
+                if !range.holds(&key) {
+                    break;
+                }
+                emit(Entry {
+                    column_family: name.clone(),
+                    key: key.into_vec(),
+                    value: value.into_vec(),
+                })?;
+                exported += 1;
+            }
+        }
+    }
+    Ok(exported)
+}
+
+/// Keys an export reads in one pass.
+enum KeyRange {
+    Prefix(Vec<u8>),
+    /// `start..end`.
+    Between(Vec<u8>, Vec<u8>),
+}
+
+impl KeyRange {
+    fn start(&self) -> &[u8] {
+        match self {
+            KeyRange::Prefix(start) | KeyRange::Between(start, _) => start,
+        }
+    }
+
+    fn holds(&self, key: &[u8]) -> bool {
+        match self {
+            KeyRange::Prefix(prefix) => key.starts_with(prefix),
+            KeyRange::Between(_, end) => key < end.as_slice(),
+        }
+    }
+}
+
+/// Ranges of the internal entries belonging to the keys starting with
+/// `prefix`, in key order. They all sort before the keys themselves, so an
+/// SST export can write them first. Empty when the prefix already covers
+/// internal keys.
+fn dependents(
+    snapshot: &Snapshot,
+    encryption: &Encryption,
+    prefix: &[u8],
+) -> Result<Vec<KeyRange>, DumpError> {
+    if prefix.is_empty() || is_reserved(prefix) {
+        return Ok(Vec::new());
+    }
+    let mut ranges = Vec::new();
+    for item in snapshot.iterator(IteratorMode::From(prefix, Direction::Forward)) {
+        let (key, value) = item?;
+        if !key.starts_with(prefix) {
+            break;
+        }
+        if let Some(manifest) = blob::manifest_of(encryption, &key, &value) {
+            let (start, end) = manifest.chunk_range();
+            ranges.push(KeyRange::Between(start, end));
+        }
+    }
+    ranges.push(KeyRange::Prefix(history::entries_under(prefix)));
+    ranges.extend(
+        structures::entries_under(prefix)
+            .into_iter()
+            .map(KeyRange::Prefix),
+    );
+    ranges.sort_by(|a, b| a.start().cmp(b.start()));
+    Ok(ranges)
+}
+
+/// Writes the entries of one column family in `scope`, the default one
+/// unless another is named, to an SST file at `path`.
+pub fn export_sst(
+    db: &DB,
+    encryption: &Encryption,
+    scope: &Scope,
+    path: &Path,
+) -> Result<u64, DumpError> {
+    let scope = Scope {
+        column_family: Some(
+            scope
+                .column_family
+                .clone()
+                .unwrap_or_else(|| DEFAULT_COLUMN_FAMILY_NAME.to_string()),
+        ),
+        prefix: scope.prefix.clone(),
+    };
+    let options = Options::default();
+    let mut writer = SstFileWriter::create(&options);
+    writer.open(path)?;
+    let exported = export(db, encryption, &scope, |entry| {
+        writer.put(&entry.key, &entry.value)?;
+        Ok(())
+    })?;
+    if exported == 0 {
+        return Err(DumpError::Empty);
+    }
+    writer.finish()?;
+    Ok(exported)
+}
+
+/// A fresh path with `extension` to stage an SST export or a restore
+/// upload at, in a directory next to the data directory of `db`.
+pub fn staging_path(db: &DB, extension: &str) -> io::Result<PathBuf> {
+    let mut dir = OsString::from(db.path().as_os_str());
+    dir.push(".dumps");
+    let dir = PathBuf::from(dir);
+    fs::create_dir_all(&dir)?;
+    let mut suffix = [0u8; 4];
+    OsRng.fill_bytes(&mut suffix);
+    let name = format!("{}-{}", record::unix_millis(), record::hex(&suffix));
+    Ok(dir.join(name).with_extension(extension))
+}
+
+/// Hands every entry of an NDJSON dump to `visit`, in order.
+pub fn read(
+    input: impl BufRead,
+    mut visit: impl FnMut(Entry) -> Result<(), DumpError>,
+) -> Result<(), DumpError> {
+    for (index, text) in input.lines().enumerate() {
+        let text = text?;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let entry = Entry::from_line(&text).map_err(|message| DumpError::Invalid {
+            line: index as u64 + 1,
+            message,
+        })?;
+        visit(entry)?;
+    }
+    Ok(())
+}
+
+/// Column families named in an NDJSON dump, so they can be created before
+/// it is restored.
+pub fn dumped_column_families(input: impl BufRead) -> Result<Vec<String>, DumpError> {
+    let mut names = Vec::new();
+    read(input, |entry| {
+        if !names.contains(&entry.column_family) {
+            names.push(entry.column_family);
+        }
+        Ok(())
+    })?;
+    Ok(names)
+}
+
+/// Replays an NDJSON dump into the DB of `store`, whose column families
+/// must all exist, reading it from `open` once more to look for conflicts
+/// first when `on_conflict` fails on them. Entries are written as they were
+/// dumped, headers included, while the keys they replace go to history and
+/// indexes follow the restored values. A key written while the restore runs
+/// can still make it fail part way.
+pub fn restore<R: BufRead>(
+    store: &Store,
+    open: impl Fn() -> io::Result<R>,
+    on_conflict: OnConflict,
+) -> Result<Restored, DumpError> {
+    if on_conflict == OnConflict::Fail {
+        read(open()?, |entry| match exists(store.db, &entry)? {
+            true => Err(conflict(entry)),
+            false => Ok(()),
+        })?;
+    }
+    let mut restored = Restored::default();
+    let mut pending = Vec::with_capacity(RESTORE_BATCH_SIZE);
+    read(open()?, |entry| {
+        pending.push(entry);
+        if pending.len() == RESTORE_BATCH_SIZE {
+            write(store, &mut pending, on_conflict, &mut restored)?;
+        }
+        Ok(())
+    })?;
+    write(store, &mut pending, on_conflict, &mut restored)?;
+    Ok(restored)
+}
+
+fn write(
+    store: &Store,
+    pending: &mut Vec<Entry>,
+    on_conflict: OnConflict,
+    restored: &mut Restored,
+) -> Result<(), DumpError> {
+    let db = store.db;
+    let _writes = store.encryption.hold_writes();
+    let _versions = record::hold_versions_of(pending.iter().map(|entry| entry.key.as_slice()));
+    let mut batch = WriteBatch::default();
+    for entry in pending.drain(..) {
+        if on_conflict != OnConflict::Overwrite && exists(db, &entry)? {
+            if on_conflict == OnConflict::Fail {
+                return Err(conflict(entry));
+            }
+            restored.skipped += 1;
+            continue;
+        }
+        if entry.column_family != DEFAULT_COLUMN_FAMILY_NAME {
+            let column_family = db
+                .cf_handle(&entry.column_family)
+                .ok_or_else(|| DumpError::UnknownColumnFamily(entry.column_family.clone()))?;
+            batch.put_cf(column_family, &entry.key, &entry.value);
+        } else {
+            if !is_reserved(&entry.key) {
+                maintain(store, &mut batch, &entry)?;
+            }
+            batch.put(&entry.key, &entry.value);
+        }
+        restored.restored += 1;
+    }
+    db.write(batch)?;
+    Ok(())
+}
+
+/// Adds to `batch` what replacing a user key with a restored entry changes
+/// besides the key itself: the replaced value goes to history, the chunks
+/// of a replaced chunked value are dropped and indexes follow.
+fn maintain(store: &Store, batch: &mut WriteBatch, entry: &Entry) -> Result<(), DumpError> {
+    let previous = match blob::lookup(store.db, store.encryption, &entry.key) {
+        Ok(previous) => previous,
+        Err(BlobError::Storage(e)) => return Err(e.into()),
+        // An unreadable value is simply overwritten.
+        Err(_) => None,
+    };
+    let next = store
+        .encryption
+        .open(&entry.key, &entry.value)
+        .ok()
+        .map(record::unwrap);
+    store.indexes.update(
+        store.db,
+        batch,
+        &entry.key,
+        previous.as_ref().map(|previous| &previous.body),
+        next.as_ref().map(|next| next.body.as_slice()),
+    );
+    if let Some(Record {
+        body: Stored::Blob(previous),
+        ..
+    }) = &previous
+    {
+        let kept = blob::manifest_of(store.encryption, &entry.key, &entry.value)
+            .is_some_and(|manifest| manifest.id == previous.id);
+        if !kept {
+            blob::delete_chunks(batch, previous);
+        }
+    }
+    let mut header = next.and_then(|next| next.header);
+    store.history.keep(
+        store.db,
+        store.encryption,
+        batch,
+        &entry.key,
+        header.as_mut(),
+    )?;
+    Ok(())
+}
+
+fn exists(db: &DB, entry: &Entry) -> Result<bool, DumpError> {
+    let existing = match entry.column_family == DEFAULT_COLUMN_FAMILY_NAME {
+        true => db.get(&entry.key)?,
+        false => {
+            let column_family = db
+                .cf_handle(&entry.column_family)
+                .ok_or_else(|| DumpError::UnknownColumnFamily(entry.column_family.clone()))?;
+            db.get_cf(column_family, &entry.key)?
+        }
+    };
+    Ok(existing.is_some())
+}
+
+fn conflict(entry: Entry) -> DumpError {
+    DumpError::Conflict {
+        column_family: entry.column_family,
+        key: String::from_utf8_lossy(&entry.key).into_owned(),
+    }
+}
+
+fn is_reserved(key: &[u8]) -> bool {
+    key.first() == Some(&(storage::RESERVED_KEY_PREFIX as u8))
+}
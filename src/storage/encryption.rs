@@ -0,0 +1,437 @@
+use crate::storage::{
+    lock::LOCK_COLUMN_FAMILY, queue::QUEUE_COLUMN_FAMILY, timeseries::TIMESERIES_COLUMN_FAMILY,
+};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use rocksdb::{ColumnFamily, Direction, Error, IteratorMode, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::Path,
+    sync::{Mutex, RwLock, RwLockReadGuard},
+};
+
+/// Prefix of every encrypted value. Values without it were written before
+/// encryption was enabled and are returned as stored.
+const MAGIC: &[u8] = b"\0ENC\x01";
+const NONCE_SIZE: usize = 12;
+/// A wrapped 256-bit data key plus its 16-byte tag.
+const WRAPPED_KEY_SIZE: usize = 48;
+/// Keys examined per write-locked step of a re-encryption run.
+const REENCRYPT_CHUNK: usize = 1000;
+/// Column families besides the default one holding sealed values.
+const SEALED_COLUMN_FAMILIES: [&str; 3] = [
+    QUEUE_COLUMN_FAMILY,
+    LOCK_COLUMN_FAMILY,
+    TIMESERIES_COLUMN_FAMILY,
+];
+
+type KeyValue = (Box<[u8]>, Box<[u8]>);
+
+/// Contents of the file named by `ROCKSDB_ENCRYPTION_KEYFILE`: base64-encoded
+/// 256-bit master keys by id, and the id new values are encrypted with.
+#[derive(Deserialize, Debug)]
+pub struct KeyFile {
+    pub active_key: String,
+    pub keys: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    /// The value names a master key that is not in the keyfile.
+    UnknownKey(String),
+    /// The envelope is truncated or fails authentication.
+    Corrupt(String),
+    Storage(Error),
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::UnknownKey(id) => write!(f, "unknown encryption key \"{}\"", id),
+            EncryptionError::Corrupt(reason) => write!(f, "corrupt encrypted value: {}", reason),
+            EncryptionError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+impl From<Error> for EncryptionError {
+    fn from(e: Error) -> Self {
+        EncryptionError::Storage(e)
+    }
+}
+
+/// Progress of the most recent re-encryption run.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ReencryptStatus {
+    pub running: bool,
+    pub scanned: u64,
+    pub rewritten: u64,
+    /// Values that could not be opened with any known key.
+    pub failed: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct Keyring {
+    active: String,
+    keys: HashMap<String, Aes256Gcm>,
+}
+
+/// Envelope encryption of values with AES-256-GCM.
+///
+/// Each value gets a fresh data key that encrypts it, with the RocksDB key as
+/// associated data so values cannot be moved between keys. The data key is
+/// wrapped by the active master key, whose id is stored in the envelope so
+/// older values stay readable after the active key changes:
+///
+/// `MAGIC | id length | id | wrap nonce | wrapped data key | nonce | ciphertext`
+pub struct Encryption {
+    keyring: Option<Keyring>,
+    /// Writers hold this shared; re-encryption takes it exclusively while it
+    /// rewrites a chunk so it never overwrites a newer value.
+    writes: RwLock<()>,
+    status: Mutex<ReencryptStatus>,
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encryption")
+            .field("active_key", &self.active_key_id())
+            .finish()
+    }
+}
+
+impl Encryption {
+    pub fn disabled() -> Self {
+        Encryption {
+            keyring: None,
+            writes: RwLock::new(()),
+            status: Mutex::new(ReencryptStatus::default()),
+        }
+    }
+
+    pub fn new(key_file: KeyFile) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for (id, encoded) in key_file.keys {
+            if id.len() > u8::MAX as usize {
+                return Err(format!("key id \"{}\" is longer than 255 bytes", id));
+            }
+            let bytes = STANDARD
+                .decode(encoded.trim())
+                .map_err(|e| format!("key \"{}\" is not valid base64: {}", id, e))?;
+            let cipher = Aes256Gcm::new_from_slice(&bytes)
+                .map_err(|_| format!("key \"{}\" must be 32 bytes, got {}", id, bytes.len()))?;
+            keys.insert(id, cipher);
+        }
+        if !keys.contains_key(&key_file.active_key) {
+            return Err(format!(
+                "active key \"{}\" is not in the keyfile",
+                key_file.active_key
+            ));
+        }
+        Ok(Encryption {
+            keyring: Some(Keyring {
+                active: key_file.active_key,
+                keys,
+            }),
+            ..Encryption::disabled()
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {:?}: {}", path, e))?;
+        let key_file: KeyFile =
+            serde_json::from_str(&contents).map_err(|e| format!("invalid {:?}: {}", path, e))?;
+        Encryption::new(key_file).map_err(|e| format!("invalid {:?}: {}", path, e))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.keyring.is_some()
+    }
+
+    pub fn active_key_id(&self) -> Option<&str> {
+        self.keyring.as_ref().map(|keyring| keyring.active.as_str())
+    }
+
+    /// Held by anything that writes values, so a re-encryption run cannot
+    /// interleave with it.
+    pub fn hold_writes(&self) -> RwLockReadGuard<'_, ()> {
+        self.writes.read().unwrap()
+    }
+
+    /// Encrypts `value` for storage under `key`. Returns it unchanged when
+    /// encryption is disabled.
+    pub fn seal(&self, key: &[u8], value: &[u8]) -> Vec<u8> {
+        let Some(keyring) = &self.keyring else {
+            return value.to_vec();
+        };
+        let master = &keyring.keys[&keyring.active];
+        let data_key = Aes256Gcm::generate_key(&mut OsRng);
+        let data_cipher = Aes256Gcm::new(&data_key);
+
+        let wrap_nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let wrapped = master
+            .encrypt(
+                &wrap_nonce,
+                Payload {
+                    msg: data_key.as_slice(),
+                    aad: keyring.active.as_bytes(),
+                },
+            )
+            .expect("AES-GCM encryption of a data key cannot fail");
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = data_cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: value,
+                    aad: key,
+                },
+            )
+            .expect("AES-GCM encryption of a value cannot fail");
+
+        let mut sealed = Vec::with_capacity(
+            MAGIC.len() + 1 + keyring.active.len() + 2 * NONCE_SIZE + WRAPPED_KEY_SIZE,
+        );
+        sealed.extend_from_slice(MAGIC);
+        sealed.push(keyring.active.len() as u8);
+        sealed.extend_from_slice(keyring.active.as_bytes());
+        sealed.extend_from_slice(&wrap_nonce);
+        sealed.extend_from_slice(&wrapped);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// Decrypts a value stored under `key`. Values written without
+    /// encryption are returned as they are.
+    pub fn open(&self, key: &[u8], stored: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let Some(envelope) = Envelope::parse(stored)? else {
+            return Ok(stored.to_vec());
+        };
+        let master = self
+            .keyring
+            .as_ref()
+            .and_then(|keyring| keyring.keys.get(envelope.key_id))
+            .ok_or_else(|| EncryptionError::UnknownKey(envelope.key_id.to_string()))?;
+
+        let data_key = master
+            .decrypt(
+                Nonce::from_slice(envelope.wrap_nonce),
+                Payload {
+                    msg: envelope.wrapped_key,
+                    aad: envelope.key_id.as_bytes(),
+                },
+            )
+            .map_err(|_| EncryptionError::Corrupt("data key fails authentication".to_string()))?;
+        let data_cipher = Aes256Gcm::new_from_slice(&data_key)
+            .map_err(|_| EncryptionError::Corrupt("data key has the wrong size".to_string()))?;
+        data_cipher
+            .decrypt(
+                Nonce::from_slice(envelope.nonce),
+                Payload {
+                    msg: envelope.ciphertext,
+                    aad: key,
+                },
+            )
+            .map_err(|_| EncryptionError::Corrupt("value fails authentication".to_string()))
+    }
+
+    /// Like `open`, but logs failures and falls back to the stored bytes.
+    /// Used where one bad value must not hide the others, such as the
+    /// change feed.
+    pub fn open_lossy(&self, key: &[u8], stored: &[u8]) -> Vec<u8> {
+        self.open(key, stored).unwrap_or_else(|e| {
+            println!("Error decrypting key \"{:?}\": {:}", key, e);
+            stored.to_vec()
+        })
+    }
+
+    /// Id of the master key a stored value is encrypted with.
+    pub fn key_id(stored: &[u8]) -> Option<String> {
+        Envelope::parse(stored)
+            .ok()
+            .flatten()
+            .map(|envelope| envelope.key_id.to_string())
+    }
+
+    pub fn status(&self) -> ReencryptStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Marks a re-encryption run as started. Returns `false` if one is
+    /// already running.
+    pub fn start_reencrypt(&self) -> bool {
+        let mut status = self.status.lock().unwrap();
+        if status.running {
+            return false;
+        }
+        *status = ReencryptStatus {
+            running: true,
+            ..ReencryptStatus::default()
+        };
+        true
+    }
+
+    /// Rewrites every value in the default column family that is not
+    /// encrypted with the active key, and those of the queues, locks and
+    /// time series sealed with another key. Call after `start_reencrypt`;
+    /// blocks until done.
+    pub fn reencrypt(&self, db: &DB) {
+        let result = self.reencrypt_column_families(db);
+        let mut status = self.status.lock().unwrap();
+        status.running = false;
+        if let Err(e) = result {
+            println!("Error re-encrypting values: {}", e);
+            status.error = Some(e.to_string());
+        }
+    }
+
+    fn reencrypt_column_families(&self, db: &DB) -> Result<(), Error> {
+        let Some(active) = self.active_key_id() else {
+            return Ok(());
+        };
+        self.reencrypt_chunks(db, None, active)?;
+        for name in SEALED_COLUMN_FAMILIES {
+            if let Some(column_family) = db.cf_handle(name) {
+                self.reencrypt_chunks(db, Some(column_family), active)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-encrypts `column_family`, the default one when `None`. Outside
+    /// the default column family, values stored in plaintext are counters
+    /// and index entries read as they are, so only sealed ones are
+    /// rewritten.
+    fn reencrypt_chunks(
+        &self,
+        db: &DB,
+        column_family: Option<&ColumnFamily>,
+        active: &str,
+    ) -> Result<(), Error> {
+        let mut resume: Option<Vec<u8>> = None;
+        loop {
+            let mode = match &resume {
+                Some(last) => IteratorMode::From(last, Direction::Forward),
+                None => IteratorMode::Start,
+            };
+            let entries = match column_family {
+                Some(column_family) => db.iterator_cf(column_family, mode),
+                None => db.iterator(mode),
+            };
+            let mut stale = Vec::new();
+            let mut scanned = 0;
+            for item in entries {
+                let (key, value) = item?;
+                if resume.as_deref() == Some(&key[..]) {
+                    continue;
+                }
+                scanned += 1;
+                resume = Some(key.to_vec());
+                let is_stale = match Encryption::key_id(&value) {
+                    Some(key_id) => key_id != active,
+                    None => column_family.is_none(),
+                };
+                if is_stale {
+                    stale.push((key, value));
+                }
+                if scanned == REENCRYPT_CHUNK {
+                    break;
+                }
+            }
+
+            let (rewritten, failed) = self.rewrite(db, column_family, stale)?;
+            let mut status = self.status.lock().unwrap();
+            status.scanned += scanned as u64;
+            status.rewritten += rewritten;
+            status.failed += failed;
+            drop(status);
+
+            if scanned < REENCRYPT_CHUNK {
+                return Ok(());
+            }
+        }
+    }
+
+    fn rewrite(
+        &self,
+        db: &DB,
+        column_family: Option<&ColumnFamily>,
+        stale: Vec<KeyValue>,
+    ) -> Result<(u64, u64), Error> {
+        let _exclusive = self.writes.write().unwrap();
+        let mut batch = WriteBatch::default();
+        let (mut rewritten, mut failed) = (0, 0);
+        for (key, value) in stale {
+            let current = match column_family {
+                Some(column_family) => db.get_cf(column_family, &key)?,
+                None => db.get(&key)?,
+            };
+            // Skip values overwritten or deleted since the scan.
+            if current.as_deref() != Some(&value[..]) {
+                continue;
+            }
+            match self.open(&key, &value) {
+                Ok(plaintext) => {
+                    let sealed = self.seal(&key, &plaintext);
+                    match column_family {
+                        Some(column_family) => batch.put_cf(column_family, &key, sealed),
+                        None => batch.put(&key, sealed),
+                    }
+                    rewritten += 1;
+                }
+                Err(e) => {
+                    println!("Error re-encrypting key \"{:?}\": {:}", key, e);
+                    failed += 1;
+                }
+            }
+        }
+        db.write(batch)?;
+        Ok((rewritten, failed))
+    }
+}
+
+struct Envelope<'a> {
+    key_id: &'a str,
+    wrap_nonce: &'a [u8],
+    wrapped_key: &'a [u8],
+    nonce: &'a [u8],
+    ciphertext: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    /// `None` for values that are not encrypted.
+    fn parse(stored: &'a [u8]) -> Result<Option<Self>, EncryptionError> {
+        let Some(rest) = stored.strip_prefix(MAGIC) else {
+            return Ok(None);
+        };
+        let truncated = || EncryptionError::Corrupt("envelope is truncated".to_string());
+        let (&id_len, rest) = rest.split_first().ok_or_else(truncated)?;
+        let id_len = id_len as usize;
+        if rest.len() < id_len + 2 * NONCE_SIZE + WRAPPED_KEY_SIZE {
+            return Err(truncated());
+        }
+        let (key_id, rest) = rest.split_at(id_len);
+        let key_id = std::str::from_utf8(key_id)
+            .map_err(|_| EncryptionError::Corrupt("key id is not UTF-8".to_string()))?;
+        let (wrap_nonce, rest) = rest.split_at(NONCE_SIZE);
+        let (wrapped_key, rest) = rest.split_at(WRAPPED_KEY_SIZE);
+        let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+        Ok(Some(Envelope {
+            key_id,
+            wrap_nonce,
+            wrapped_key,
+            nonce,
+            ciphertext,
+        }))
+    }
+}
@@ -0,0 +1,258 @@
+use crate::storage::{
+    blob,
+    encryption::{Encryption, EncryptionError},
+    record::{self, Header, Record},
+};
+use rocksdb::{
+    compaction_filter::Decision, Direction, Error, IteratorMode, Options, WriteBatch, DB,
+};
+
+/// Replaced versions live under
+/// `HISTORY_PREFIX <key> \0 <version> <replaced at>`, both numbers as
+/// big-endian `u64`s so a key's versions sort oldest first and the
+/// compaction filter can age them out without decrypting anything.
+const HISTORY_PREFIX: &[u8] = b"\0hist\0";
+const SUFFIX_SIZE: usize = 16;
+
+/// How much history to keep. Unset limits keep versions forever.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    /// Replaced versions kept per key.
+    pub max_versions: Option<u64>,
+    /// How long a version is kept after it was replaced, in milliseconds.
+    pub max_age_ms: Option<u64>,
+}
+
+/// Keeps the values that writes replace. Disabled unless a retention is
+/// configured, in which case writes copy the value they replace into
+/// history first. Chunked values are not kept: their chunks are dropped as
+/// soon as they are replaced, and a kept manifest would point at nothing.
+/// They still take their version numbers.
+#[derive(Debug, Default)]
+pub struct History {
+    retention: Option<Retention>,
+}
+
+/// A replaced value read back from history.
+#[derive(Debug)]
+pub struct Version {
+    pub version: u64,
+    /// When the next write replaced or deleted this version.
+    pub replaced_ms: u64,
+    pub record: Record,
+}
+
+impl History {
+    pub fn disabled() -> Self {
+        History::default()
+    }
+
+    pub fn new(retention: Retention) -> Self {
+        History {
+            retention: Some(retention),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.retention.is_some()
+    }
+
+    /// Installs the compaction filter dropping versions older than the
+    /// maximum age. Must be applied to the options the DB is opened with.
+    pub fn configure(&self, opts: &mut Options) {
+        let Some(max_age_ms) = self.retention.and_then(|retention| retention.max_age_ms) else {
+            return;
+        };
+        opts.set_compaction_filter("history_retention", move |_level, key, _value| {
+            if is_expired(key, max_age_ms, record::unix_millis()) {
+                Decision::Remove
+            } else {
+                Decision::Keep
+            }
+        });
+    }
+
+    /// Adds to `batch` a copy of the value `key` holds before `next`
+    /// replaces it, or before it is deleted when `next` is `None`, and drops
+    /// versions beyond the retained count. A key recreated after a delete
+    /// continues numbering from its history. Call with writes to the key
+    /// held.
+    pub fn keep(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        batch: &mut WriteBatch,
+        key: &[u8],
+        next: Option<&mut Header>,
+    ) -> Result<(), Error> {
+        let Some(retention) = self.retention else {
+            return Ok(());
+        };
+        let replaced_ms = next
+            .as_ref()
+            .map_or_else(record::unix_millis, |header| header.modified_ms);
+        let current = match db.get(key)? {
+            Some(stored) => match encryption.open(key, &stored) {
+                Ok(plaintext) => Some(plaintext),
+                Err(EncryptionError::Storage(e)) => return Err(e),
+                // An unreadable value cannot be kept; it is simply replaced.
+                Err(_) => None,
+            },
+            None => None,
+        };
+
+        let kept = match current {
+            Some(plaintext) => {
+                let replaced = record::unwrap(plaintext.clone());
+                let version = replaced.header.map_or(1, |header| header.version.max(1));
+                if !blob::is_manifest(&replaced.body) {
+                    let entry = entry_key(key, version, replaced_ms);
+                    batch.put(&entry, encryption.seal(&entry, &plaintext));
+                }
+                version
+            }
+            None => {
+                if let (Some(next), Some(latest)) = (next, latest_version(db, key)?) {
+                    next.version = next.version.max(latest + 1);
+                }
+                return Ok(());
+            }
+        };
+        if let Some(max_versions) = retention.max_versions {
+            let oldest_kept = (kept + 1).saturating_sub(max_versions);
+            batch.delete_range(entry_key(key, 0, 0), entry_key(key, oldest_kept, 0));
+        }
+        Ok(())
+    }
+}
+
+/// Whether a key is a history entry replaced more than `max_age_ms` before
+/// `now_ms`.
+pub fn is_expired(key: &[u8], max_age_ms: u64, now_ms: u64) -> bool {
+    match parse_entry(key) {
+        Some((_, _, replaced_ms)) => now_ms.saturating_sub(replaced_ms) > max_age_ms,
+        None => false,
+    }
+}
+
+/// Replaced versions of `key`, oldest first.
+pub fn versions(
+    db: &DB,
+    encryption: &Encryption,
+    key: &[u8],
+) -> Result<Vec<Version>, EncryptionError> {
+    let prefix = entry_prefix(key);
+    let mut versions = Vec::new();
+    for item in db.iterator(IteratorMode::From(&prefix, Direction::Forward)) {
+        let (entry, stored) = item?;
+        if !entry.starts_with(&prefix) {
+            break;
+        }
+        let Some((name, version, replaced_ms)) = parse_entry(&entry) else {
+            continue;
+        };
+        if name != key {
+            continue;
+        }
+        versions.push(Version {
+            version,
+            replaced_ms,
+            record: record::unwrap(encryption.open(&entry, &stored)?),
+        });
+    }
+    Ok(versions)
+}
+
+/// The value `key` held at `as_of_ms`, from history or, if it has not
+/// been replaced since, the current value. `None` when the key did not
+/// exist then or that version is no longer retained, as with chunked
+/// values and manifests kept before they were left out of history.
+pub fn as_of(
+    db: &DB,
+    encryption: &Encryption,
+    key: &[u8],
+    as_of_ms: u64,
+) -> Result<Option<Record>, EncryptionError> {
+    let prefix = entry_prefix(key);
+    let mut found = None;
+    for item in db.iterator(IteratorMode::From(&prefix, Direction::Forward)) {
+        let (entry, stored) = item?;
+        if !entry.starts_with(&prefix) {
+            break;
+        }
+        match parse_entry(&entry) {
+            Some((name, _, replaced_ms)) if name == key && replaced_ms > as_of_ms => {
+                let record = record::unwrap(encryption.open(&entry, &stored)?);
+                if blob::is_manifest(&record.body) {
+                    return Ok(None);
+                }
+                found = Some(record);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let record = match found {
+        Some(record) => record,
+        None => match db.get(key)? {
+            Some(stored) => record::unwrap(encryption.open(key, &stored)?),
+            None => return Ok(None),
+        },
+    };
+    let written_ms = record
+        .header
+        .as_ref()
+        .map_or(0, |header| header.modified_ms);
+    Ok(Some(record).filter(|_| written_ms <= as_of_ms))
+}
+
+fn latest_version(db: &DB, key: &[u8]) -> Result<Option<u64>, Error> {
+    let prefix = entry_prefix(key);
+    let mut end = prefix.clone();
+    end.extend_from_slice(&[0xff; SUFFIX_SIZE + 1]);
+    for item in db.iterator(IteratorMode::From(&end, Direction::Reverse)) {
+        let (entry, _) = item?;
+        if !entry.starts_with(&prefix) {
+            break;
+        }
+        if let Some((name, version, _)) = parse_entry(&entry) {
+            if name == key {
+                return Ok(Some(version));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn entry_prefix(key: &[u8]) -> Vec<u8> {
+    let mut prefix = HISTORY_PREFIX.to_vec();
+    prefix.extend_from_slice(key);
+    prefix.push(0);
+    prefix
+}
+
+/// Prefix of the history entries of every key starting with `prefix`.
+pub fn entries_under(prefix: &[u8]) -> Vec<u8> {
+    let mut entries = HISTORY_PREFIX.to_vec();
+    entries.extend_from_slice(prefix);
+    entries
+}
+
+fn entry_key(key: &[u8], version: u64, replaced_ms: u64) -> Vec<u8> {
+    let mut entry = entry_prefix(key);
+    entry.extend_from_slice(&version.to_be_bytes());
+    entry.extend_from_slice(&replaced_ms.to_be_bytes());
+    entry
+}
+
+/// Splits a history entry key into the key it belongs to, its version and
+/// when it was replaced.
+fn parse_entry(entry: &[u8]) -> Option<(&[u8], u64, u64)> {
+    let rest = entry.strip_prefix(HISTORY_PREFIX)?;
+    let split = rest.len().checked_sub(SUFFIX_SIZE + 1)?;
+    let (name, suffix) = rest.split_at(split);
+    let suffix = suffix.strip_prefix(&[0])?;
+    let version = u64::from_be_bytes(suffix[..8].try_into().ok()?);
+    let replaced_ms = u64::from_be_bytes(suffix[8..].try_into().ok()?);
+    Some((name, version, replaced_ms))
+}
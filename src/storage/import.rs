@@ -0,0 +1,450 @@
+use crate::{
+    limits::Limits,
+    storage::{
+        self,
+        blob::{self, BlobError},
+        compression::{self, Codec},
+        document::Documents,
+        encryption::Encryption,
+        history::History,
+        index::Indexes,
+        record::{self, Header},
+        rocksdb::{stage_upkeep, Store},
+        schema::Schemas,
+    },
+};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use rocksdb::{Error, IteratorMode, Options, SstFileWriter, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt,
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Row errors kept per import; the rest are only counted.
+const MAX_ROW_ERRORS: usize = 100;
+/// Rows ingested per SST file, and the most held in memory at once.
+const IMPORT_BATCH_SIZE: usize = 1000;
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// One `{"key": ..., "value": ...}` object per line. Values that are
+    /// not strings are stored as their JSON text.
+    Ndjson,
+    /// `key,value` records without a header row.
+    Csv,
+    /// A file built with `SstFileWriter` holding UTF-8 keys and bare values,
+    /// read back in key order.
+    Sst,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Reading and checking rows, ingesting them in batches as they pass.
+    Reading,
+    Done,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    /// Line of an NDJSON upload, record of a CSV upload or entry of an SST
+    /// file, from 1.
+    pub row: u64,
+    pub message: String,
+}
+
+/// Where an import stands, reported while it runs and kept once it ends.
+#[derive(Serialize, Debug, Clone)]
+pub struct Progress {
+    pub id: String,
+    pub format: Format,
+    pub phase: Phase,
+    pub rows_read: u64,
+    /// Values ingested so far. A key given more than once within a batch is
+    /// ingested once, with its last value.
+    pub keys_imported: u64,
+    pub rows_rejected: u64,
+    /// The first rejected rows.
+    pub row_errors: Vec<RowError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub started_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_ms: Option<u64>,
+}
+
+/// A row that passed its checks.
+struct Row {
+    key: String,
+    value: String,
+    is_document: bool,
+}
+
+/// What an import needs to write keys the way `/put` would.
+pub struct Target<'a> {
+    pub db: &'a DB,
+    pub encryption: &'a Encryption,
+    pub history: &'a History,
+    pub indexes: &'a Indexes,
+    pub stored_compression: Option<Codec>,
+    pub documents: &'a Documents,
+    pub schemas: &'a Schemas,
+    /// Quotas every row is checked against, as if it were put on its own.
+    pub limits: &'a Limits,
+    /// Client recorded as the writer of every key.
+    pub client: &'a str,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    Storage(Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "{}", e),
+            ImportError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<Error> for ImportError {
+    fn from(e: Error) -> Self {
+        ImportError::Storage(e)
+    }
+}
+
+/// Bulk imports by id. Uploads are staged next to the data directory, and
+/// the rows that pass their checks are sorted into SST files written with
+/// `SstFileWriter` and ingested with `ingest_external_file`. Ingested values
+/// skip the WAL, so the change feed does not list them and followers must
+/// bootstrap again to see them; the history and index entries of each batch
+/// are written through the WAL once it is ingested. Progress lives in memory
+/// and is lost on restart.
+#[derive(Debug, Default)]
+pub struct Imports {
+    jobs: Mutex<BTreeMap<String, Arc<Mutex<Progress>>>>,
+}
+
+impl Imports {
+    /// Registers an import whose upload is staged at the returned path.
+    pub fn start(&self, db: &DB, format: Format) -> io::Result<(Arc<Mutex<Progress>>, PathBuf)> {
+        let started_ms = record::unix_millis();
+        let id = format!("{}-{}", started_ms, record::hex(&random_suffix()));
+        let dir = imports_dir(db);
+        fs::create_dir_all(&dir)?;
+        let progress = Arc::new(Mutex::new(Progress {
+            id: id.clone(),
+            format,
+            phase: Phase::Reading,
+            rows_read: 0,
+            keys_imported: 0,
+            rows_rejected: 0,
+            row_errors: Vec::new(),
+            error: None,
+            started_ms,
+            finished_ms: None,
+        }));
+        self.jobs
+            .lock()
+            .unwrap()
+            .insert(id.clone(), progress.clone());
+        Ok((progress, dir.join(id)))
+    }
+
+    pub fn get(&self, id: &str) -> Option<Progress> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.get(id)
+            .map(|progress| progress.lock().unwrap().clone())
+    }
+
+    /// Every import since the server started, oldest first.
+    pub fn list(&self) -> Vec<Progress> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.values()
+            .map(|progress| progress.lock().unwrap().clone())
+            .collect()
+    }
+}
+
+/// Imports the upload staged at `upload` and records how it went in
+/// `progress`. The upload, the SST file being ingested and the scratch DB an
+/// SST upload is read through are removed either way; batches ingested
+/// before a failure stay.
+pub fn run(target: &Target, upload: &Path, progress: &Mutex<Progress>) {
+    let result = import(target, upload, progress);
+    let _ = fs::remove_file(upload);
+    let _ = fs::remove_file(ingest_path(upload));
+    let scratch = scratch_path(upload);
+    if scratch.exists() {
+        let _ = DB::destroy(&Options::default(), &scratch);
+        let _ = fs::remove_dir_all(&scratch);
+    }
+    let mut progress = progress.lock().unwrap();
+    match result {
+        Ok(()) => progress.phase = Phase::Done,
+        Err(e) => {
+            println!("Error importing \"{}\": {:}", progress.id, e);
+            progress.error = Some(e.to_string());
+            progress.phase = Phase::Failed;
+        }
+    }
+    progress.finished_ms = Some(record::unix_millis());
+}
+
+fn import(target: &Target, upload: &Path, progress: &Mutex<Progress>) -> Result<(), ImportError> {
+    let format = progress.lock().unwrap().format;
+    let mut batches = Batches {
+        target,
+        progress,
+        sst: ingest_path(upload),
+        rows: BTreeMap::new(),
+    };
+    match format {
+        Format::Ndjson => {
+            let file = File::open(upload)?;
+            for (line, text) in BufReader::new(file).lines().enumerate() {
+                let text = text?;
+                if !text.trim().is_empty() {
+                    batches.accept(line as u64 + 1, parse_json_row(&text))?;
+                }
+            }
+        }
+        Format::Csv => {
+            let file = File::open(upload)?;
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(file);
+            for (index, record) in reader.records().enumerate() {
+                batches.accept(index as u64 + 1, parse_csv_row(record))?;
+            }
+        }
+        Format::Sst => {
+            // Ingesting into a DB of its own is the way to read an SST file
+            // back, entry by entry, without trusting what it holds.
+            let mut options = Options::default();
+            options.create_if_missing(true);
+            let scratch = DB::open(&options, scratch_path(upload))?;
+            scratch.ingest_external_file(vec![upload])?;
+            for (index, entry) in scratch.iterator(IteratorMode::Start).enumerate() {
+                let (key, value) = entry?;
+                batches.accept(index as u64 + 1, parse_sst_row(&key, &value))?;
+            }
+        }
+    }
+    batches.write()
+}
+
+/// Rows waiting to be ingested, the last of each key.
+struct Batches<'a> {
+    target: &'a Target<'a>,
+    progress: &'a Mutex<Progress>,
+    /// Where each batch is written to be ingested.
+    sst: PathBuf,
+    rows: BTreeMap<String, Row>,
+}
+
+impl Batches<'_> {
+    fn accept(
+        &mut self,
+        number: u64,
+        parsed: Result<(String, String), String>,
+    ) -> Result<(), ImportError> {
+        self.progress.lock().unwrap().rows_read = number;
+        match parsed.and_then(|(key, value)| check(self.target, key, value)) {
+            Ok(row) => {
+                self.rows.insert(row.key.clone(), row);
+            }
+            Err(message) => reject(self.progress, number, message),
+        }
+        if self.rows.len() >= IMPORT_BATCH_SIZE {
+            self.write()?;
+        }
+        Ok(())
+    }
+
+    /// Ingests the pending rows as one SST file, in the form `/put` stores
+    /// a value, then writes the history, index and chunk changes of the
+    /// replaced values in a batch. Other writes of its keys wait until both
+    /// are done.
+    fn write(&mut self) -> Result<(), ImportError> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let target = self.target;
+        let store = Store {
+            db: target.db,
+            encryption: target.encryption,
+            history: target.history,
+            indexes: target.indexes,
+        };
+        let _writes = target.encryption.hold_writes();
+        let _versions = record::hold_versions_of(self.rows.keys().map(|key| key.as_bytes()));
+        let mut upkeep = WriteBatch::default();
+        let options = Options::default();
+        let mut writer = SstFileWriter::create(&options);
+        writer.open(&self.sst)?;
+        let mut keys = 0;
+        // The rows are sorted by key, as an SST file must be.
+        for (key, row) in std::mem::take(&mut self.rows) {
+            let previous = match blob::lookup(target.db, target.encryption, key.as_bytes()) {
+                Ok(previous) => previous,
+                Err(BlobError::Storage(e)) => return Err(e.into()),
+                // An unreadable previous value is simply overwritten.
+                Err(_) => None,
+            };
+            let mut header = Header::new(record::digest(row.value.as_bytes()));
+            header.modified_by = Some(target.client.to_string());
+            if row.is_document {
+                header.content_type = Some(JSON_CONTENT_TYPE.to_string());
+            }
+            let body = match target.stored_compression {
+                Some(codec) => compression::pack(codec, &row.value)?,
+                None => row.value.into_bytes(),
+            };
+            let header = stage_upkeep(&store, &mut upkeep, &key, previous.as_ref(), header, &body)?;
+            let sealed = target
+                .encryption
+                .seal(key.as_bytes(), &record::wrap(&header, &body));
+            writer.put(key.as_bytes(), sealed)?;
+            keys += 1;
+        }
+        writer.finish()?;
+        target.db.ingest_external_file(vec![&self.sst])?;
+        fs::remove_file(&self.sst)?;
+        if !upkeep.is_empty() {
+            target.db.write(upkeep)?;
+        }
+        self.progress.lock().unwrap().keys_imported += keys;
+        Ok(())
+    }
+}
+
+/// Checks a row the way `/put` checks a value: internal keys are off
+/// limits, values cannot pass for the server's own encodings and document
+/// keys must hold JSON matching their schemas.
+fn check(target: &Target, key: String, value: String) -> Result<Row, String> {
+    if key.is_empty() || storage::is_reserved_key(&key) {
+        return Err(format!("invalid key {:?}", key));
+    }
+    if blob::is_manifest(value.as_bytes()) || compression::unpack(value.as_bytes()).is_some() {
+        return Err(format!(
+            "key \"{}\": value starts like a chunked or compressed value",
+            key
+        ));
+    }
+    let is_document = target.documents.is_document(&key) || target.schemas.covers(&key);
+    if is_document {
+        let document: Value = serde_json::from_str(&value)
+            .map_err(|e| format!("key \"{}\": not a JSON document: {}", key, e))?;
+        if let Err(violations) = target.schemas.validate(&key, &document) {
+            let details: Vec<String> = violations
+                .iter()
+                .map(|violation| format!("{}: {}", violation.path, violation.message))
+                .collect();
+            return Err(format!(
+                "key \"{}\": value does not match its schema: {}",
+                key,
+                details.join("; ")
+            ));
+        }
+    }
+    target
+        .limits
+        .check_quota(
+            target.db,
+            target.encryption,
+            target.client,
+            "/admin/import",
+            key.as_bytes(),
+            value.len() as u64,
+        )
+        .map_err(|throttled| format!("key \"{}\": {}", key, throttled.message))?;
+    Ok(Row {
+        key,
+        value,
+        is_document,
+    })
+}
+
+fn parse_json_row(text: &str) -> Result<(String, String), String> {
+    #[derive(Deserialize)]
+    struct Row {
+        key: String,
+        value: Value,
+    }
+    let row: Row = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let value = match row.value {
+        Value::String(value) => value,
+        value => value.to_string(),
+    };
+    Ok((row.key, value))
+}
+
+fn parse_csv_row(record: csv::Result<csv::StringRecord>) -> Result<(String, String), String> {
+    let record = record.map_err(|e| e.to_string())?;
+    match (record.get(0), record.get(1), record.len()) {
+        (Some(key), Some(value), 2) => Ok((key.to_string(), value.to_string())),
+        (_, _, fields) => Err(format!("expected 2 fields, found {}", fields)),
+    }
+}
+
+fn parse_sst_row(key: &[u8], value: &[u8]) -> Result<(String, String), String> {
+    let key = String::from_utf8(key.to_vec()).map_err(|_| "key is not UTF-8".to_string())?;
+    match String::from_utf8(value.to_vec()) {
+        Ok(value) => Ok((key, value)),
+        Err(_) => Err(format!("key \"{}\": value is not UTF-8", key)),
+    }
+}
+
+fn reject(progress: &Mutex<Progress>, row: u64, message: String) {
+    let mut progress = progress.lock().unwrap();
+    progress.rows_rejected += 1;
+    if progress.row_errors.len() < MAX_ROW_ERRORS {
+        progress.row_errors.push(RowError { row, message });
+    }
+}
+
+/// Directory staging uploads of `db`, next to its data directory.
+fn imports_dir(db: &DB) -> PathBuf {
+    let mut dir = OsString::from(db.path().as_os_str());
+    dir.push(".imports");
+    PathBuf::from(dir)
+}
+
+/// Where each batch of an import is written to be ingested.
+fn ingest_path(upload: &Path) -> PathBuf {
+    upload.with_extension("sst")
+}
+
+/// Where an SST upload is ingested to be read back.
+fn scratch_path(upload: &Path) -> PathBuf {
+    upload.with_extension("scratch")
+}
+
+fn random_suffix() -> [u8; 4] {
+    let mut bytes = [0u8; 4];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
@@ -0,0 +1,411 @@
+use crate::storage::{
+    blob::{self, Stored},
+    compression,
+    encryption::Encryption,
+    record,
+};
+use rocksdb::{Direction, Error, IteratorMode, Options, WriteBatch, DB};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::BTreeSet, fmt, fs, path::Path};
+
+/// Index entries are keyed `<encoded term> \0 <primary key>` and hold the
+/// primary key, so a key containing `\0` cannot be mistaken for another.
+const SEPARATOR: u8 = 0;
+/// Holds the pointer an index was last built for, once it is built. Terms
+/// never start with a NUL byte, so it cannot be mistaken for an entry.
+const BUILT: &[u8] = b"\0built";
+const BACKFILL_BATCH: usize = 1000;
+
+/// Leading byte of an encoded term. Terms sort by type first, then by value.
+const NULL: u8 = 1;
+const FALSE: u8 = 2;
+const TRUE: u8 = 3;
+const NUMBER: u8 = 4;
+const STRING: u8 = 5;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct IndexDefinition {
+    pub name: String,
+    /// JSON pointer to the indexed field, e.g. `/user/email`. Arrays of
+    /// scalars index each element; objects are not indexed.
+    pub pointer: String,
+    /// Defaults to `index.<name>`.
+    #[serde(default)]
+    pub column_family: Option<String>,
+}
+
+impl IndexDefinition {
+    pub fn column_family(&self) -> String {
+        self.column_family
+            .clone()
+            .unwrap_or_else(|| format!("index.{}", self.name))
+    }
+
+    fn terms(&self, document: &[u8]) -> BTreeSet<Vec<u8>> {
+        let Ok(document) = serde_json::from_slice::<Value>(document) else {
+            return BTreeSet::new();
+        };
+        match document.pointer(&self.pointer) {
+            Some(Value::Array(items)) => items.iter().filter_map(encode).collect(),
+            Some(value) => encode(value).into_iter().collect(),
+            None => BTreeSet::new(),
+        }
+    }
+}
+
+/// Contents of the file named by `ROCKSDB_INDEX_CONFIG`.
+#[derive(Deserialize, Debug, Default)]
+pub struct IndexConfig {
+    #[serde(default)]
+    pub indexes: Vec<IndexDefinition>,
+    /// Accepts that index terms are stored unencrypted, readable in the
+    /// index column families, dumps and internal changes. Required to use
+    /// indexes with encryption.
+    #[serde(default)]
+    pub plaintext_terms: bool,
+}
+
+/// Which entries of an index a query selects. Bounds are inclusive; a range
+/// with one bound stays within that bound's type.
+#[derive(Debug, Clone)]
+pub enum Lookup {
+    Eq(Value),
+    Range {
+        from: Option<Value>,
+        to: Option<Value>,
+    },
+}
+
+#[derive(Debug)]
+pub enum IndexError {
+    UnknownIndex(String),
+    /// Only strings, numbers, booleans and null can be looked up.
+    Unindexable(Value),
+    Storage(Error),
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::UnknownIndex(name) => write!(f, "unknown index \"{}\"", name),
+            IndexError::Unindexable(value) => write!(f, "{} cannot be looked up", value),
+            IndexError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {}
+
+impl From<Error> for IndexError {
+    fn from(e: Error) -> Self {
+        IndexError::Storage(e)
+    }
+}
+
+/// Secondary indexes on fields of JSON values, each kept in its own column
+/// family and updated in the same batch as the value. Index entries are
+/// stored unencrypted, which the configuration must accept when values are
+/// encrypted.
+#[derive(Debug, Default)]
+pub struct Indexes {
+    definitions: Vec<IndexDefinition>,
+    plaintext_terms: bool,
+}
+
+impl Indexes {
+    pub fn disabled() -> Self {
+        Indexes::default()
+    }
+
+    pub fn new(config: IndexConfig) -> Self {
+        Indexes {
+            definitions: config.indexes,
+            plaintext_terms: config.plaintext_terms,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {:?}: {}", path, e))?;
+        let config: IndexConfig =
+            serde_json::from_str(&contents).map_err(|e| format!("invalid {:?}: {}", path, e))?;
+        let mut names = BTreeSet::new();
+        for definition in &config.indexes {
+            if !names.insert(definition.name.as_str()) {
+                return Err(format!(
+                    "invalid {:?}: index \"{}\" is defined twice",
+                    path, definition.name
+                ));
+            }
+            if !definition.pointer.is_empty() && !definition.pointer.starts_with('/') {
+                return Err(format!(
+                    "invalid {:?}: pointer \"{}\" of index \"{}\" must start with \"/\"",
+                    path, definition.pointer, definition.name
+                ));
+            }
+            if definition.column_family() == rocksdb::DEFAULT_COLUMN_FAMILY_NAME {
+                return Err(format!(
+                    "invalid {:?}: index \"{}\" cannot use the default column family",
+                    path, definition.name
+                ));
+            }
+        }
+        Ok(Indexes::new(config))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.definitions.is_empty()
+    }
+
+    /// Refuses to index encrypted values unless `plaintext_terms` accepts
+    /// that the indexed fields are stored in the clear.
+    pub fn check_encryption(&self, encryption: &Encryption) -> Result<(), String> {
+        if self.is_enabled() && encryption.is_enabled() && !self.plaintext_terms {
+            return Err(
+                "index terms are stored unencrypted; set \"plaintext_terms\": true to index encrypted values"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&IndexDefinition> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.name == name)
+    }
+
+    /// Creates the column families of new indexes and fills them from the
+    /// values already stored. An index is rebuilt from scratch when it was
+    /// never finished or its definition has changed since.
+    pub fn prepare(&self, db: &mut DB, encryption: &Encryption) -> Result<(), Error> {
+        for definition in &self.definitions {
+            let column_family = definition.column_family();
+            if db.cf_handle(&column_family).is_none() {
+                db.create_cf(&column_family, &Options::default())?;
+            }
+        }
+        let db: &DB = db;
+        for definition in &self.definitions {
+            let Some(column_family) = db.cf_handle(&definition.column_family()) else {
+                continue;
+            };
+            let built = db.get_cf(column_family, BUILT)?;
+            if built.as_deref() == Some(definition.pointer.as_bytes()) {
+                continue;
+            }
+            db.delete_range_cf(column_family, [NULL], [STRING + 1])?;
+            let indexed = backfill(db, encryption, definition)?;
+            db.put_cf(column_family, BUILT, definition.pointer.as_bytes())?;
+            println!(
+                "Built index \"{}\" over {} values",
+                definition.name, indexed
+            );
+        }
+        Ok(())
+    }
+
+    /// Adds to `batch` the index changes for `key` going from `previous` to
+    /// the stored value `next`, `None` meaning absent.
+    pub fn update(
+        &self,
+        db: &DB,
+        batch: &mut WriteBatch,
+        key: &[u8],
+        previous: Option<&Stored>,
+        next: Option<&[u8]>,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+        let previous = match previous {
+            Some(Stored::Value(stored)) => document(stored),
+            _ => None,
+        };
+        let next = next.and_then(document);
+        for definition in &self.definitions {
+            let Some(column_family) = db.cf_handle(&definition.column_family()) else {
+                continue;
+            };
+            let before = previous
+                .as_deref()
+                .map(|document| definition.terms(document))
+                .unwrap_or_default();
+            let after = next
+                .as_deref()
+                .map(|document| definition.terms(document))
+                .unwrap_or_default();
+            for term in before.difference(&after) {
+                batch.delete_cf(column_family, entry_key(term, key));
+            }
+            for term in after.difference(&before) {
+                batch.put_cf(column_family, entry_key(term, key), key);
+            }
+        }
+    }
+
+    /// Keys of the values matching `lookup`, in index order.
+    pub fn query(
+        &self,
+        db: &DB,
+        name: &str,
+        lookup: &Lookup,
+        limit: usize,
+    ) -> Result<Vec<Vec<u8>>, IndexError> {
+        let definition = self
+            .definition(name)
+            .ok_or_else(|| IndexError::UnknownIndex(name.to_string()))?;
+        let Some(column_family) = db.cf_handle(&definition.column_family()) else {
+            return Ok(Vec::new());
+        };
+        let encoded =
+            |value: &Value| encode(value).ok_or_else(|| IndexError::Unindexable(value.clone()));
+        let (start, end, exact) = match lookup {
+            Lookup::Eq(value) => {
+                let mut start = encoded(value)?;
+                start.push(SEPARATOR);
+                let mut end = start.clone();
+                end.push(u8::MAX);
+                (start, Some(end), true)
+            }
+            Lookup::Range { from, to } => {
+                let from = from.as_ref().map(encoded).transpose()?;
+                let to = to.as_ref().map(encoded).transpose()?;
+                let start = match (&from, &to) {
+                    (Some(from), _) => from.clone(),
+                    (None, Some(to)) => vec![to[0]],
+                    (None, None) => Vec::new(),
+                };
+                let end = match (&from, to) {
+                    (_, Some(mut to)) => {
+                        to.push(SEPARATOR + 1);
+                        Some(to)
+                    }
+                    (Some(from), None) => Some(vec![from[0] + 1]),
+                    (None, None) => None,
+                };
+                (start, end, false)
+            }
+        };
+
+        let mut keys = Vec::new();
+        let entries = db.iterator_cf(
+            column_family,
+            IteratorMode::From(&start, Direction::Forward),
+        );
+        for item in entries {
+            let (entry, key) = item?;
+            if end.as_deref().is_some_and(|end| entry.as_ref() >= end) {
+                break;
+            }
+            // An equality prefix also matches longer strings that contain
+            // the separator; only entries of exactly the term qualify.
+            if exact && entry.len() != start.len() + key.len() {
+                continue;
+            }
+            keys.push(key.to_vec());
+            if keys.len() == limit {
+                break;
+            }
+        }
+        Ok(keys)
+    }
+}
+
+fn backfill(db: &DB, encryption: &Encryption, definition: &IndexDefinition) -> Result<u64, Error> {
+    let Some(column_family) = db.cf_handle(&definition.column_family()) else {
+        return Ok(0);
+    };
+    let mut batch = WriteBatch::default();
+    let (mut pending, mut indexed) = (0, 0);
+    for item in db.iterator(IteratorMode::Start) {
+        let (key, stored) = item?;
+        // Chunks, history and other internal keys start with a NUL byte.
+        if key.first() == Some(&0) {
+            continue;
+        }
+        let Ok(plaintext) = encryption.open(&key, &stored) else {
+            println!("Error indexing key \"{:?}\": cannot decrypt", key);
+            continue;
+        };
+        let Some(document) = document(record::body(&plaintext)) else {
+            continue;
+        };
+        let terms = definition.terms(&document);
+        if terms.is_empty() {
+            continue;
+        }
+        for term in terms {
+            batch.put_cf(column_family, entry_key(&term, &key), &key);
+        }
+        indexed += 1;
+        pending += 1;
+        if pending == BACKFILL_BATCH {
+            db.write(std::mem::take(&mut batch))?;
+            pending = 0;
+        }
+    }
+    db.write(batch)?;
+    Ok(indexed)
+}
+
+/// The JSON text of a stored body, if it is a plain value.
+fn document(stored: &[u8]) -> Option<Vec<u8>> {
+    if blob::is_manifest(stored) {
+        return None;
+    }
+    compression::unpack_value(stored).ok()
+}
+
+fn entry_key(term: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(term.len() + 1 + key.len());
+    entry.extend_from_slice(term);
+    entry.push(SEPARATOR);
+    entry.extend_from_slice(key);
+    entry
+}
+
+/// Big-endian bytes of `number` transformed so that byte order matches
+/// numeric order.
+pub fn order_number(number: f64) -> [u8; 8] {
+    let bits = number.to_bits();
+    let ordered = if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | 1 << 63
+    };
+    ordered.to_be_bytes()
+}
+
+/// Reverses `order_number`.
+pub fn unorder_number(ordered: [u8; 8]) -> f64 {
+    let ordered = u64::from_be_bytes(ordered);
+    let bits = if ordered >> 63 == 1 {
+        ordered & !(1 << 63)
+    } else {
+        !ordered
+    };
+    f64::from_bits(bits)
+}
+
+/// Encodes a scalar so that byte order matches value order: numbers by
+/// `order_number`, strings by their UTF-8 bytes.
+fn encode(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Null => Some(vec![NULL]),
+        Value::Bool(false) => Some(vec![FALSE]),
+        Value::Bool(true) => Some(vec![TRUE]),
+        Value::Number(number) => {
+            let mut encoded = vec![NUMBER];
+            encoded.extend_from_slice(&order_number(number.as_f64()?));
+            Some(encoded)
+        }
+        Value::String(string) => {
+            let mut encoded = vec![STRING];
+            encoded.extend_from_slice(string.as_bytes());
+            Some(encoded)
+        }
+        Value::Array(_) | Value::Object(_) => None,
+    }
+}
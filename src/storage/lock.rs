@@ -0,0 +1,266 @@
+use crate::storage::{
+    encryption::{Encryption, EncryptionError},
+    record,
+};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use rocksdb::{ColumnFamily, Error, Options, WriteBatch, WriteOptions, DB};
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Mutex};
+
+pub const LOCK_COLUMN_FAMILY: &str = "locks";
+
+/// A lock as stored under its name. It outlives its leases so the fencing
+/// token keeps counting up across releases and restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Lock {
+    /// Fencing token of the latest lease.
+    pub fence: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease: Option<Lease>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Lease {
+    pub owner: String,
+    /// Secret the holder renews and releases the lease with.
+    pub token: String,
+    pub fence: u64,
+    pub acquired_ms: u64,
+    pub expires_ms: u64,
+}
+
+impl Lock {
+    /// The lease, unless it has expired.
+    fn holder(&self, now: u64) -> Option<&Lease> {
+        self.lease.as_ref().filter(|lease| lease.expires_ms > now)
+    }
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    InvalidName(String),
+    /// Leases must last longer than zero.
+    InvalidTtl,
+    /// The locks column family has not been created.
+    Unavailable,
+    /// Another owner holds an unexpired lease.
+    Held {
+        owner: String,
+        expires_ms: u64,
+    },
+    /// The token is not that of the current, unexpired lease.
+    NotHeld,
+    Corrupt(String),
+    Encryption(EncryptionError),
+    Storage(Error),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::InvalidName(name) => write!(f, "invalid lock name \"{}\"", name),
+            LockError::InvalidTtl => write!(f, "ttl must be positive"),
+            LockError::Unavailable => write!(f, "locks are not available"),
+            LockError::Held { owner, expires_ms } => {
+                write!(f, "held by \"{}\" until {}", owner, expires_ms)
+            }
+            LockError::NotHeld => write!(f, "the lease has expired or was released"),
+            LockError::Corrupt(e) => write!(f, "corrupt lock: {}", e),
+            LockError::Encryption(e) => write!(f, "{}", e),
+            LockError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+impl From<Error> for LockError {
+    fn from(e: Error) -> Self {
+        LockError::Storage(e)
+    }
+}
+
+impl From<EncryptionError> for LockError {
+    fn from(e: EncryptionError) -> Self {
+        LockError::Encryption(e)
+    }
+}
+
+/// Named locks leased for a limited time, kept in their own column family
+/// and sealed like values. Each acquisition hands out a fencing token
+/// larger than any before it, so a resource can turn away a holder whose
+/// lease ran out unnoticed; locks are written with a synced WAL so a token
+/// handed out is never handed out again after a crash.
+#[derive(Debug, Default)]
+pub struct Locks {
+    lock: Mutex<()>,
+}
+
+impl Locks {
+    /// Creates the locks column family if the DB does not have it yet.
+    pub fn prepare(&self, db: &mut DB) -> Result<(), Error> {
+        if db.cf_handle(LOCK_COLUMN_FAMILY).is_none() {
+            db.create_cf(LOCK_COLUMN_FAMILY, &Options::default())?;
+        }
+        Ok(())
+    }
+
+    /// Leases `name` to `owner` for `ttl_ms` if it is free or its lease has
+    /// expired.
+    pub fn acquire(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        name: &str,
+        owner: &str,
+        ttl_ms: u64,
+    ) -> Result<Lease, LockError> {
+        check(name, ttl_ms)?;
+        let column_family = column_family(db)?;
+        let _locks = self.lock.lock().unwrap();
+        let _writes = encryption.hold_writes();
+
+        let mut lock = read(db, column_family, encryption, name)?.unwrap_or_default();
+        let now = record::unix_millis();
+        if let Some(lease) = lock.holder(now) {
+            return Err(LockError::Held {
+                owner: lease.owner.clone(),
+                expires_ms: lease.expires_ms,
+            });
+        }
+        lock.fence += 1;
+        let lease = Lease {
+            owner: owner.to_string(),
+            token: token(),
+            fence: lock.fence,
+            acquired_ms: now,
+            expires_ms: now.saturating_add(ttl_ms),
+        };
+        lock.lease = Some(lease.clone());
+        write(db, column_family, encryption, name, &lock)?;
+        Ok(lease)
+    }
+
+    /// Extends the lease holding `token` to `ttl_ms` from now. The fencing
+    /// token stays the same.
+    pub fn renew(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        name: &str,
+        token: &str,
+        ttl_ms: u64,
+    ) -> Result<Lease, LockError> {
+        check(name, ttl_ms)?;
+        let column_family = column_family(db)?;
+        let _locks = self.lock.lock().unwrap();
+        let _writes = encryption.hold_writes();
+
+        let mut lock = read(db, column_family, encryption, name)?.ok_or(LockError::NotHeld)?;
+        let now = record::unix_millis();
+        let lease = match lock.lease.as_mut() {
+            Some(lease) if lease.token == token && lease.expires_ms > now => lease,
+            _ => return Err(LockError::NotHeld),
+        };
+        lease.expires_ms = now.saturating_add(ttl_ms);
+        let lease = lease.clone();
+        write(db, column_family, encryption, name, &lock)?;
+        Ok(lease)
+    }
+
+    /// Ends the lease holding `token`.
+    pub fn release(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        name: &str,
+        token: &str,
+    ) -> Result<(), LockError> {
+        check_name(name)?;
+        let column_family = column_family(db)?;
+        let _locks = self.lock.lock().unwrap();
+        let _writes = encryption.hold_writes();
+
+        let mut lock = read(db, column_family, encryption, name)?.ok_or(LockError::NotHeld)?;
+        let now = record::unix_millis();
+        match lock.holder(now) {
+            Some(lease) if lease.token == token => {}
+            _ => return Err(LockError::NotHeld),
+        }
+        lock.lease = None;
+        write(db, column_family, encryption, name, &lock)?;
+        Ok(())
+    }
+
+    /// The lock with its lease, if unexpired.
+    pub fn status(&self, db: &DB, encryption: &Encryption, name: &str) -> Result<Lock, LockError> {
+        check_name(name)?;
+        let column_family = column_family(db)?;
+        let mut lock = read(db, column_family, encryption, name)?.unwrap_or_default();
+        let now = record::unix_millis();
+        if lock.holder(now).is_none() {
+            lock.lease = None;
+        }
+        Ok(lock)
+    }
+}
+
+fn column_family(db: &DB) -> Result<&ColumnFamily, LockError> {
+    db.cf_handle(LOCK_COLUMN_FAMILY)
+        .ok_or(LockError::Unavailable)
+}
+
+fn check_name(name: &str) -> Result<(), LockError> {
+    if name.is_empty() {
+        return Err(LockError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+fn check(name: &str, ttl_ms: u64) -> Result<(), LockError> {
+    check_name(name)?;
+    if ttl_ms == 0 {
+        return Err(LockError::InvalidTtl);
+    }
+    Ok(())
+}
+
+fn read(
+    db: &DB,
+    column_family: &ColumnFamily,
+    encryption: &Encryption,
+    name: &str,
+) -> Result<Option<Lock>, LockError> {
+    let Some(stored) = db.get_cf(column_family, name)? else {
+        return Ok(None);
+    };
+    let plaintext = encryption.open(name.as_bytes(), &stored)?;
+    serde_json::from_slice(&plaintext)
+        .map(Some)
+        .map_err(|e| LockError::Corrupt(e.to_string()))
+}
+
+fn write(
+    db: &DB,
+    column_family: &ColumnFamily,
+    encryption: &Encryption,
+    name: &str,
+    lock: &Lock,
+) -> Result<(), Error> {
+    let plaintext = serde_json::to_vec(lock).expect("locks serialize");
+    let mut batch = WriteBatch::default();
+    batch.put_cf(
+        column_family,
+        name,
+        encryption.seal(name.as_bytes(), &plaintext),
+    );
+    let mut options = WriteOptions::default();
+    options.set_sync(true);
+    db.write_opt(batch, &options)
+}
+
+fn token() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    record::hex(&bytes)
+}
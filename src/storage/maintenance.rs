@@ -0,0 +1,157 @@
+use crate::storage::dump;
+use rocksdb::{ColumnFamily, Error, DB, DEFAULT_COLUMN_FAMILY_NAME};
+use serde::Serialize;
+use std::fmt;
+
+/// Prefix of the property names RocksDB answers to.
+const PROPERTY_PREFIX: &str = "rocksdb.";
+
+/// Metadata of an SST file the DB currently uses.
+#[derive(Serialize, Debug, Clone)]
+pub struct LiveFile {
+    pub column_family: String,
+    pub name: String,
+    pub size: usize,
+    pub level: i32,
+    pub start_key: Option<String>,
+    pub end_key: Option<String>,
+    pub entries: u64,
+    pub deletions: u64,
+}
+
+#[derive(Debug)]
+pub enum MaintenanceError {
+    UnknownColumnFamily(String),
+    UnknownProperty(String),
+    /// RocksDB refused the options, being unknown, immutable or malformed.
+    InvalidOptions(Error),
+    Storage(Error),
+}
+
+impl fmt::Display for MaintenanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaintenanceError::UnknownColumnFamily(name) => {
+                write!(f, "unknown column family \"{}\"", name)
+            }
+            MaintenanceError::UnknownProperty(name) => write!(f, "unknown property \"{}\"", name),
+            MaintenanceError::InvalidOptions(e) => write!(f, "invalid options: {}", e),
+            MaintenanceError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MaintenanceError {}
+
+impl From<Error> for MaintenanceError {
+    fn from(e: Error) -> Self {
+        MaintenanceError::Storage(e)
+    }
+}
+
+/// Compacts keys in `[from, to]`, unbounded where not given, in the named
+/// column family or in all of them. Returns the column families compacted.
+pub fn compact(
+    db: &DB,
+    column_family: Option<&str>,
+    from: Option<&[u8]>,
+    to: Option<&[u8]>,
+) -> Result<Vec<String>, MaintenanceError> {
+    let names = names(db, column_family)?;
+    for name in &names {
+        match handle(db, name)? {
+            None => db.compact_range(from, to),
+            Some(column_family) => db.compact_range_cf(column_family, from, to),
+        }
+    }
+    Ok(names)
+}
+
+/// Flushes the memtables of the named column family or of all of them,
+/// waiting for the flushes to finish.
+pub fn flush(db: &DB, column_family: Option<&str>) -> Result<Vec<String>, MaintenanceError> {
+    let names = names(db, column_family)?;
+    for name in &names {
+        match handle(db, name)? {
+            None => db.flush()?,
+            Some(column_family) => db.flush_cf(column_family)?,
+        }
+    }
+    Ok(names)
+}
+
+/// Writes the WAL buffer out and syncs it to disk.
+pub fn sync_wal(db: &DB) -> Result<(), MaintenanceError> {
+    db.flush_wal(true)?;
+    Ok(())
+}
+
+/// Changes mutable options such as `write_buffer_size` or
+/// `disable_auto_compactions`, of the default column family unless another
+/// is named.
+pub fn set_options(
+    db: &DB,
+    column_family: Option<&str>,
+    options: &[(&str, &str)],
+) -> Result<(), MaintenanceError> {
+    let changed = match handle(db, column_family.unwrap_or_default())? {
+        None => db.set_options(options),
+        Some(column_family) => db.set_options_cf(column_family, options),
+    };
+    changed.map_err(MaintenanceError::InvalidOptions)
+}
+
+/// Reads a `rocksdb.*` property of the default or the named column family.
+pub fn property(
+    db: &DB,
+    column_family: Option<&str>,
+    name: &str,
+) -> Result<String, MaintenanceError> {
+    if !name.starts_with(PROPERTY_PREFIX) {
+        return Err(MaintenanceError::UnknownProperty(name.to_string()));
+    }
+    let value = match handle(db, column_family.unwrap_or_default())? {
+        None => db.property_value(name)?,
+        Some(column_family) => db.property_value_cf(column_family, name)?,
+    };
+    value.ok_or_else(|| MaintenanceError::UnknownProperty(name.to_string()))
+}
+
+pub fn live_files(db: &DB) -> Result<Vec<LiveFile>, MaintenanceError> {
+    let key = |key: Option<Vec<u8>>| key.map(|key| String::from_utf8_lossy(&key).into_owned());
+    Ok(db
+        .live_files()?
+        .into_iter()
+        .map(|file| LiveFile {
+            column_family: file.column_family_name,
+            name: file.name,
+            size: file.size,
+            level: file.level,
+            start_key: key(file.start_key),
+            end_key: key(file.end_key),
+            entries: file.num_entries,
+            deletions: file.num_deletions,
+        })
+        .collect())
+}
+
+fn names(db: &DB, column_family: Option<&str>) -> Result<Vec<String>, MaintenanceError> {
+    match column_family {
+        Some(name) => {
+            handle(db, name)?;
+            Ok(vec![name.to_string()])
+        }
+        None => Ok(dump::column_families(db)),
+    }
+}
+
+/// The handle of a column family, or `None` for the default one, which the
+/// plain methods of `DB` address. An empty name means the default one.
+fn handle<'a>(db: &'a DB, name: &str) -> Result<Option<&'a ColumnFamily>, MaintenanceError> {
+    if name.is_empty() || name == DEFAULT_COLUMN_FAMILY_NAME {
+        return Ok(None);
+    }
+    db.cf_handle(name)
+        .map(Some)
+        .ok_or_else(|| MaintenanceError::UnknownColumnFamily(name.to_string()))
+}
@@ -2,6 +2,54 @@
 //!
 //! This module handles all database operations and persistence:
 //! - RocksDB operations (get/put)
+//! - WAL tailing for change data capture
+//! - Checkpoints for follower bootstrap
+//! - Read-only and secondary open modes
+//! - Envelope encryption of values at rest
+//! - Optional compression of stored values
+//! - Chunked storage of large values
+//! - Record headers with write metadata
+//! - Retained history of replaced values
+//! - Secondary indexes on JSON values
+//! - JSON documents with patches and projections
+//! - JSON Schemas validating writes per key prefix
+//! - Durable queues with visibility timeouts and dead letters
+//! - Leased locks with fencing tokens
+//! - Hashes, sets and sorted sets as composite keys
+//! - Time series with aggregation and downsampling
+//! - Bulk imports of NDJSON, CSV and SST uploads
+//! - Snapshot dumps as NDJSON or SST files, and their restores
+//! - Manual compaction, flushes, options and properties
+//! - Bytes stored per namespace, for quotas
 //! - Future: caching, transactions, batch operations
 
+pub mod blob;
+pub mod checkpoint;
+pub mod compression;
+pub mod document;
+pub mod dump;
+pub mod encryption;
+pub mod history;
+pub mod import;
+pub mod index;
+pub mod lock;
+pub mod maintenance;
+pub mod mode;
+pub mod queue;
+pub mod record;
 pub mod rocksdb;
+pub mod schema;
+pub mod structures;
+pub mod timeseries;
+pub mod usage;
+pub mod wal;
+
+/// First character of the keys the server keeps for itself in the default
+/// column family: blob chunks, history, schemas and the entries of hashes,
+/// sets and sorted sets. Clients cannot read or write them directly.
+pub const RESERVED_KEY_PREFIX: char = '\0';
+
+/// Whether `key` is one the server keeps for itself.
+pub fn is_reserved_key(key: &str) -> bool {
+    key.starts_with(RESERVED_KEY_PREFIX)
+}
@@ -0,0 +1,143 @@
+use rocksdb::{ColumnFamilyDescriptor, Error, Options, DB, DEFAULT_COLUMN_FAMILY_NAME};
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How the server opened its data directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessMode {
+    ReadWrite,
+    /// A frozen view of the directory as it was at startup.
+    ReadOnly,
+    /// A view that follows a live primary process through its MANIFEST and
+    /// WAL, refreshed with `try_catch_up_with_primary`.
+    Secondary {
+        secondary_path: PathBuf,
+    },
+}
+
+impl AccessMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccessMode::ReadWrite => "read_write",
+            AccessMode::ReadOnly => "read_only",
+            AccessMode::Secondary { .. } => "secondary",
+        }
+    }
+
+    pub fn is_writable(&self) -> bool {
+        *self == AccessMode::ReadWrite
+    }
+}
+
+/// Tracks how fresh the opened view of the data directory is.
+#[derive(Debug)]
+pub struct DbView {
+    mode: AccessMode,
+    /// A secondary counts as stale once it has not caught up for this long.
+    max_staleness: Duration,
+    refreshed_at: Mutex<Instant>,
+    last_error: Mutex<Option<String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    pub ready: bool,
+    pub mode: &'static str,
+    pub staleness_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl DbView {
+    pub fn new(mode: AccessMode, max_staleness: Duration) -> Self {
+        DbView {
+            mode,
+            max_staleness,
+            refreshed_at: Mutex::new(Instant::now()),
+            last_error: Mutex::new(None),
+        }
+    }
+
+    pub fn read_write() -> Self {
+        DbView::new(AccessMode::ReadWrite, Duration::MAX)
+    }
+
+    pub fn mode(&self) -> &AccessMode {
+        &self.mode
+    }
+
+    pub fn record_refresh(&self) {
+        *self.refreshed_at.lock().unwrap() = Instant::now();
+        *self.last_error.lock().unwrap() = None;
+    }
+
+    pub fn record_error(&self, message: String) {
+        println!("Error catching up with primary: {}", message);
+        *self.last_error.lock().unwrap() = Some(message);
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        let staleness = match self.mode {
+            AccessMode::ReadWrite => Duration::ZERO,
+            _ => self.refreshed_at.lock().unwrap().elapsed(),
+        };
+        let ready = match self.mode {
+            AccessMode::Secondary { .. } => staleness <= self.max_staleness,
+            AccessMode::ReadWrite | AccessMode::ReadOnly => true,
+        };
+        Readiness {
+            ready,
+            mode: self.mode.name(),
+            staleness_ms: staleness.as_millis() as u64,
+            last_error: self.last_error.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Opens `path` with every column family it already has. The default one
+/// is configured with `opts`, the others with default options.
+pub fn open(opts: &Options, path: &Path, mode: &AccessMode) -> Result<DB, Error> {
+    let mut opts = opts.clone();
+    if let AccessMode::Secondary { .. } = mode {
+        // Secondaries must keep every table file open to follow the primary.
+        opts.set_max_open_files(-1);
+    }
+    // Once listed, the default column family no longer takes the DB options,
+    // which would drop the history compaction filter on every reopen.
+    let cfs = DB::list_cf(&opts, path)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|name| {
+            let cf_opts = if name == DEFAULT_COLUMN_FAMILY_NAME {
+                opts.clone()
+            } else {
+                Options::default()
+            };
+            ColumnFamilyDescriptor::new(name, cf_opts)
+        })
+        .collect::<Vec<_>>();
+    match mode {
+        AccessMode::ReadWrite => DB::open_cf_descriptors(&opts, path, cfs),
+        AccessMode::ReadOnly => DB::open_cf_descriptors_read_only(&opts, path, cfs, false),
+        AccessMode::Secondary { secondary_path } => {
+            DB::open_cf_descriptors_as_secondary(&opts, path, secondary_path.as_path(), cfs)
+        }
+    }
+}
+
+/// Periodically replays the primary's new MANIFEST and WAL entries.
+pub async fn catch_up_with_primary(db: Arc<DB>, view: Arc<DbView>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let db = db.clone();
+        match tokio::task::spawn_blocking(move || db.try_catch_up_with_primary()).await {
+            Ok(Ok(())) => view.record_refresh(),
+            Ok(Err(e)) => view.record_error(e.to_string()),
+            Err(e) => view.record_error(e.to_string()),
+        }
+    }
+}
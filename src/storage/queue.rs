@@ -0,0 +1,628 @@
+use crate::storage::{
+    encryption::{Encryption, EncryptionError},
+    record,
+};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use rocksdb::{ColumnFamily, Direction, Error, IteratorMode, Options, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    fmt,
+    hash::{Hash, Hasher},
+    sync::{Mutex, MutexGuard},
+};
+
+pub const QUEUE_COLUMN_FAMILY: &str = "queues";
+/// Deliveries after which a message is moved to the dead letters.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Keys are `<kind> \0 <queue> \0 <id>` for messages and dead letters, the
+/// id a big-endian `u64` so a queue reads in enqueue order;
+/// `READY \0 <queue> \0 <visible_ms> <id>` indexing messages by when they
+/// can next be delivered; `SEQUENCE \0 <queue>` for the last id handed out
+/// and `COUNTS \0 <queue>` for its depth.
+const MESSAGE: u8 = b'm';
+const DEAD_LETTER: u8 = b'd';
+const READY: u8 = b'r';
+const SEQUENCE: u8 = b's';
+const COUNTS: u8 = b'c';
+const ID_SIZE: usize = 8;
+/// Queue locks, each queue hashed to one, so a bounded set covers any
+/// number of queues.
+const LOCK_STRIPES: usize = 64;
+
+/// A message as stored and as handed to consumers.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Message {
+    pub id: u64,
+    pub body: String,
+    /// Deliveries so far.
+    pub attempts: u32,
+    pub enqueued_ms: u64,
+    /// Hidden from consumers until then, either delayed or delivered and
+    /// not yet acknowledged.
+    pub visible_ms: u64,
+    /// Handle of the latest delivery, required to ack or nack it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_ms: Option<u64>,
+}
+
+/// Messages of a queue by state, kept up to date by every operation so
+/// reading it costs one lookup.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Depth {
+    /// Enqueued or nacked and not delivered since, whether due now or
+    /// delayed.
+    pub waiting: u64,
+    /// Delivered and neither acked nor nacked, including deliveries whose
+    /// visibility timeout ran out before they were delivered again.
+    pub in_flight: u64,
+    pub dead_letters: u64,
+}
+
+#[derive(Debug)]
+pub enum QueueError {
+    InvalidName(String),
+    /// The queues column family has not been created.
+    Unavailable,
+    UnknownMessage(u64),
+    /// The message was redelivered, acked or nacked since this receipt was
+    /// handed out.
+    StaleReceipt(u64),
+    Corrupt(String),
+    Encryption(EncryptionError),
+    Storage(Error),
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::InvalidName(name) => write!(f, "invalid queue name \"{}\"", name),
+            QueueError::Unavailable => write!(f, "queues are not available"),
+            QueueError::UnknownMessage(id) => write!(f, "no message {}", id),
+            QueueError::StaleReceipt(id) => write!(f, "receipt of message {} is stale", id),
+            QueueError::Corrupt(e) => write!(f, "corrupt message: {}", e),
+            QueueError::Encryption(e) => write!(f, "{}", e),
+            QueueError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+impl From<Error> for QueueError {
+    fn from(e: Error) -> Self {
+        QueueError::Storage(e)
+    }
+}
+
+impl From<EncryptionError> for QueueError {
+    fn from(e: EncryptionError) -> Self {
+        QueueError::Encryption(e)
+    }
+}
+
+/// Durable queues in their own column family. Messages are sealed like
+/// values; operations on one queue run under its stripe's lock so a message is
+/// delivered to one consumer at a time.
+#[derive(Debug)]
+pub struct Queues {
+    max_attempts: u32,
+    locks: [Mutex<()>; LOCK_STRIPES],
+}
+
+impl Default for Queues {
+    fn default() -> Self {
+        Queues::new(DEFAULT_MAX_ATTEMPTS)
+    }
+}
+
+impl Queues {
+    pub fn new(max_attempts: u32) -> Self {
+        Queues {
+            max_attempts: max_attempts.max(1),
+            locks: [const { Mutex::new(()) }; LOCK_STRIPES],
+        }
+    }
+
+    /// Creates the queues column family if the DB does not have it yet, and
+    /// indexes and counts the queues written before depths were kept.
+    pub fn prepare(&self, db: &mut DB, encryption: &Encryption) -> Result<(), QueueError> {
+        if db.cf_handle(QUEUE_COLUMN_FAMILY).is_none() {
+            db.create_cf(QUEUE_COLUMN_FAMILY, &Options::default())?;
+        }
+        let column_family = column_family(db)?;
+        let sequences = db.iterator_cf(
+            column_family,
+            IteratorMode::From(&[SEQUENCE, 0], Direction::Forward),
+        );
+        for item in sequences {
+            let (key, _) = item?;
+            if key[0] != SEQUENCE {
+                break;
+            }
+            let name = String::from_utf8_lossy(&key[2..]).into_owned();
+            if db.get_cf(column_family, counts_key(&name))?.is_none() {
+                recount(db, column_family, encryption, &name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends a message, hidden for `delay_ms` first.
+    pub fn enqueue(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        name: &str,
+        body: String,
+        delay_ms: u64,
+    ) -> Result<Message, QueueError> {
+        check_name(name)?;
+        let column_family = column_family(db)?;
+        let _queue = self.lock(name);
+        let _writes = encryption.hold_writes();
+
+        let sequence = sequence_key(name);
+        let id = match db.get_cf(column_family, &sequence)? {
+            Some(last) => decode_id(&last)? + 1,
+            None => 1,
+        };
+        let now = record::unix_millis();
+        let message = Message {
+            id,
+            body,
+            attempts: 0,
+            enqueued_ms: now,
+            visible_ms: now.saturating_add(delay_ms),
+            receipt: None,
+            dead_ms: None,
+        };
+        let mut update = Update::new(db, column_family, encryption, name)?;
+        update
+            .batch
+            .put_cf(column_family, &sequence, id.to_be_bytes());
+        update.store(&message);
+        update.write(db)?;
+        Ok(message)
+    }
+
+    /// Delivers up to `max` visible messages in the order they became due,
+    /// hiding each for `visibility_ms` unless it is acked sooner. Messages
+    /// that were already delivered the maximum number of times are
+    /// dead-lettered instead. Reads only the messages that are due, walking
+    /// the ready-time index.
+    pub fn dequeue(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        name: &str,
+        visibility_ms: u64,
+        max: usize,
+    ) -> Result<Vec<Message>, QueueError> {
+        check_name(name)?;
+        let column_family = column_family(db)?;
+        let _queue = self.lock(name);
+        let _writes = encryption.hold_writes();
+
+        let now = record::unix_millis();
+        let mut update = Update::new(db, column_family, encryption, name)?;
+        let mut delivered = Vec::new();
+        let prefix = queue_prefix(READY, name);
+        let due = db.iterator_cf(
+            column_family,
+            IteratorMode::From(&prefix, Direction::Forward),
+        );
+        for item in due {
+            if delivered.len() == max {
+                break;
+            }
+            let (index_key, _) = item?;
+            if !index_key.starts_with(&prefix) {
+                break;
+            }
+            let (visible_ms, id) = decode_ready(&index_key[prefix.len()..])?;
+            if visible_ms > now {
+                break;
+            }
+            let key = entry_key(MESSAGE, name, id);
+            let Some(stored) = db.get_cf(column_family, &key)? else {
+                update.batch.delete_cf(column_family, &index_key);
+                continue;
+            };
+            let mut message = open(encryption, &key, &stored)?;
+            if message.attempts >= self.max_attempts {
+                update.bury(message, now);
+                continue;
+            }
+            update.remove(&message);
+            message.attempts += 1;
+            message.visible_ms = now.saturating_add(visibility_ms);
+            message.receipt = Some(receipt());
+            update.store(&message);
+            delivered.push(message);
+        }
+        update.write(db)?;
+        Ok(delivered)
+    }
+
+    /// Removes a delivered message for good.
+    pub fn ack(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        name: &str,
+        id: u64,
+        receipt: &str,
+    ) -> Result<(), QueueError> {
+        check_name(name)?;
+        let column_family = column_family(db)?;
+        let _queue = self.lock(name);
+        let _writes = encryption.hold_writes();
+
+        let key = entry_key(MESSAGE, name, id);
+        let message = delivered(db, column_family, encryption, &key, id, receipt)?;
+        let mut update = Update::new(db, column_family, encryption, name)?;
+        update.remove(&message);
+        update.write(db)?;
+        Ok(())
+    }
+
+    /// Hands a delivered message back, visible again after `delay_ms`, or
+    /// dead-letters it if it has used up its attempts. `true` if it was
+    /// dead-lettered.
+    pub fn nack(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        name: &str,
+        id: u64,
+        receipt: &str,
+        delay_ms: u64,
+    ) -> Result<bool, QueueError> {
+        check_name(name)?;
+        let column_family = column_family(db)?;
+        let _queue = self.lock(name);
+        let _writes = encryption.hold_writes();
+
+        let key = entry_key(MESSAGE, name, id);
+        let mut message = delivered(db, column_family, encryption, &key, id, receipt)?;
+        let now = record::unix_millis();
+        let mut update = Update::new(db, column_family, encryption, name)?;
+        let dead = message.attempts >= self.max_attempts;
+        if dead {
+            update.bury(message, now);
+        } else {
+            update.remove(&message);
+            message.visible_ms = now.saturating_add(delay_ms);
+            message.receipt = None;
+            update.store(&message);
+        }
+        update.write(db)?;
+        Ok(dead)
+    }
+
+    /// Up to `limit` dead letters of a queue, oldest first.
+    pub fn dead_letters(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        name: &str,
+        limit: usize,
+    ) -> Result<Vec<Message>, QueueError> {
+        check_name(name)?;
+        let column_family = column_family(db)?;
+        let prefix = queue_prefix(DEAD_LETTER, name);
+        let entries = db.iterator_cf(
+            column_family,
+            IteratorMode::From(&prefix, Direction::Forward),
+        );
+        let mut messages = Vec::new();
+        for item in entries {
+            if messages.len() == limit {
+                break;
+            }
+            let (key, stored) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            messages.push(open(encryption, &key, &stored)?);
+        }
+        Ok(messages)
+    }
+
+    pub fn depth(&self, db: &DB, name: &str) -> Result<Depth, QueueError> {
+        check_name(name)?;
+        counts(db, column_family(db)?, name)
+    }
+
+    /// Depth of every queue, by name, read from the counts alone.
+    pub fn depths(&self, db: &DB) -> Result<BTreeMap<String, Depth>, QueueError> {
+        let mut depths = BTreeMap::new();
+        let Some(column_family) = db.cf_handle(QUEUE_COLUMN_FAMILY) else {
+            return Ok(depths);
+        };
+        let entries = db.iterator_cf(
+            column_family,
+            IteratorMode::From(&[COUNTS, 0], Direction::Forward),
+        );
+        for item in entries {
+            let (key, stored) = item?;
+            if key[0] != COUNTS {
+                break;
+            }
+            let name = String::from_utf8_lossy(&key[2..]).into_owned();
+            depths.insert(name, decode_counts(&stored)?);
+        }
+        Ok(depths)
+    }
+
+    /// Holds the lock of the stripe `name` hashes to. Only queues sharing
+    /// it wait on each other.
+    fn lock(&self, name: &str) -> MutexGuard<'_, ()> {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        self.locks[hasher.finish() as usize % LOCK_STRIPES]
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Depth {
+    fn add(&mut self, message: &Message) {
+        match message.receipt {
+            Some(_) => self.in_flight += 1,
+            None => self.waiting += 1,
+        }
+    }
+
+    fn remove(&mut self, message: &Message) {
+        match message.receipt {
+            Some(_) => self.in_flight = self.in_flight.saturating_sub(1),
+            None => self.waiting = self.waiting.saturating_sub(1),
+        }
+    }
+}
+
+/// The writes of one operation on a queue: messages, their ready-time index
+/// entries and the depth they change, applied in one batch.
+struct Update<'a> {
+    column_family: &'a ColumnFamily,
+    encryption: &'a Encryption,
+    name: &'a str,
+    batch: WriteBatch,
+    depth: Depth,
+}
+
+impl<'a> Update<'a> {
+    fn new(
+        db: &DB,
+        column_family: &'a ColumnFamily,
+        encryption: &'a Encryption,
+        name: &'a str,
+    ) -> Result<Self, QueueError> {
+        Ok(Update {
+            column_family,
+            encryption,
+            name,
+            batch: WriteBatch::default(),
+            depth: counts(db, column_family, name)?,
+        })
+    }
+
+    /// Writes a message and indexes it by when it is next due.
+    fn store(&mut self, message: &Message) {
+        let key = entry_key(MESSAGE, self.name, message.id);
+        put(
+            &mut self.batch,
+            self.column_family,
+            self.encryption,
+            &key,
+            message,
+        );
+        let index_key = ready_key(self.name, message);
+        self.batch.put_cf(self.column_family, index_key, []);
+        self.depth.add(message);
+    }
+
+    /// Deletes a message as stored, with its index entry.
+    fn remove(&mut self, message: &Message) {
+        let key = entry_key(MESSAGE, self.name, message.id);
+        self.batch.delete_cf(self.column_family, key);
+        let index_key = ready_key(self.name, message);
+        self.batch.delete_cf(self.column_family, index_key);
+        self.depth.remove(message);
+    }
+
+    /// Moves a message from the queue to its dead letters.
+    fn bury(&mut self, mut message: Message, now: u64) {
+        self.remove(&message);
+        message.receipt = None;
+        message.dead_ms = Some(now);
+        let dead_key = entry_key(DEAD_LETTER, self.name, message.id);
+        put(
+            &mut self.batch,
+            self.column_family,
+            self.encryption,
+            &dead_key,
+            &message,
+        );
+        self.depth.dead_letters += 1;
+    }
+
+    fn write(mut self, db: &DB) -> Result<(), QueueError> {
+        let counts = serde_json::to_vec(&self.depth).expect("depths serialize");
+        self.batch
+            .put_cf(self.column_family, counts_key(self.name), counts);
+        db.write(self.batch)?;
+        Ok(())
+    }
+}
+
+fn column_family(db: &DB) -> Result<&ColumnFamily, QueueError> {
+    db.cf_handle(QUEUE_COLUMN_FAMILY)
+        .ok_or(QueueError::Unavailable)
+}
+
+fn check_name(name: &str) -> Result<(), QueueError> {
+    if name.is_empty() || name.contains('\0') {
+        return Err(QueueError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+/// The message under `key` if `receipt` is the handle of its latest,
+/// unanswered delivery.
+fn delivered(
+    db: &DB,
+    column_family: &ColumnFamily,
+    encryption: &Encryption,
+    key: &[u8],
+    id: u64,
+    receipt: &str,
+) -> Result<Message, QueueError> {
+    let stored = db
+        .get_cf(column_family, key)?
+        .ok_or(QueueError::UnknownMessage(id))?;
+    let message = open(encryption, key, &stored)?;
+    if message.receipt.as_deref() != Some(receipt) {
+        return Err(QueueError::StaleReceipt(id));
+    }
+    Ok(message)
+}
+
+/// Indexes and counts the messages of a queue written before either was
+/// kept.
+fn recount(
+    db: &DB,
+    column_family: &ColumnFamily,
+    encryption: &Encryption,
+    name: &str,
+) -> Result<(), QueueError> {
+    let mut batch = WriteBatch::default();
+    let mut depth = Depth::default();
+    let prefix = queue_prefix(MESSAGE, name);
+    let entries = db.iterator_cf(
+        column_family,
+        IteratorMode::From(&prefix, Direction::Forward),
+    );
+    for item in entries {
+        let (key, stored) = item?;
+        if !key.starts_with(&prefix) {
+            break;
+        }
+        let message = open(encryption, &key, &stored)?;
+        batch.put_cf(column_family, ready_key(name, &message), []);
+        depth.add(&message);
+    }
+    let prefix = queue_prefix(DEAD_LETTER, name);
+    let entries = db.iterator_cf(
+        column_family,
+        IteratorMode::From(&prefix, Direction::Forward),
+    );
+    for item in entries {
+        let (key, _) = item?;
+        if !key.starts_with(&prefix) {
+            break;
+        }
+        depth.dead_letters += 1;
+    }
+    let counts = serde_json::to_vec(&depth).expect("depths serialize");
+    batch.put_cf(column_family, counts_key(name), counts);
+    db.write(batch)?;
+    Ok(())
+}
+
+fn counts(db: &DB, column_family: &ColumnFamily, name: &str) -> Result<Depth, QueueError> {
+    match db.get_cf(column_family, counts_key(name))? {
+        Some(stored) => decode_counts(&stored),
+        None => Ok(Depth::default()),
+    }
+}
+
+fn decode_counts(stored: &[u8]) -> Result<Depth, QueueError> {
+    serde_json::from_slice(stored).map_err(|e| QueueError::Corrupt(e.to_string()))
+}
+
+fn put(
+    batch: &mut WriteBatch,
+    column_family: &ColumnFamily,
+    encryption: &Encryption,
+    key: &[u8],
+    message: &Message,
+) {
+    let plaintext = serde_json::to_vec(message).expect("messages serialize");
+    batch.put_cf(column_family, key, encryption.seal(key, &plaintext));
+}
+
+fn open(encryption: &Encryption, key: &[u8], stored: &[u8]) -> Result<Message, QueueError> {
+    let plaintext = encryption.open(key, stored)?;
+    serde_json::from_slice(&plaintext).map_err(|e| QueueError::Corrupt(e.to_string()))
+}
+
+/// Prefixes of the messages and dead letters of every queue whose name
+/// starts with `prefix`.
+pub fn entries_under(prefix: &[u8]) -> Vec<Vec<u8>> {
+    [MESSAGE, DEAD_LETTER]
+        .iter()
+        .map(|kind| [&[*kind, 0], prefix].concat())
+        .collect()
+}
+
+fn queue_prefix(kind: u8, name: &str) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(name.len() + 3);
+    prefix.push(kind);
+    prefix.push(0);
+    prefix.extend_from_slice(name.as_bytes());
+    prefix.push(0);
+    prefix
+}
+
+fn entry_key(kind: u8, name: &str, id: u64) -> Vec<u8> {
+    let mut key = queue_prefix(kind, name);
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+fn ready_key(name: &str, message: &Message) -> Vec<u8> {
+    let mut key = queue_prefix(READY, name);
+    key.extend_from_slice(&message.visible_ms.to_be_bytes());
+    key.extend_from_slice(&message.id.to_be_bytes());
+    key
+}
+
+/// The due time and id of a ready-time index entry, after its prefix.
+fn decode_ready(bytes: &[u8]) -> Result<(u64, u64), QueueError> {
+    if bytes.len() != 2 * ID_SIZE {
+        return Err(QueueError::Corrupt("malformed ready index".to_string()));
+    }
+    let (visible_ms, id) = bytes.split_at(ID_SIZE);
+    Ok((decode_id(visible_ms)?, decode_id(id)?))
+}
+
+fn counts_key(name: &str) -> Vec<u8> {
+    let mut key = vec![COUNTS, 0];
+    key.extend_from_slice(name.as_bytes());
+    key
+}
+
+fn sequence_key(name: &str) -> Vec<u8> {
+    let mut key = vec![SEQUENCE, 0];
+    key.extend_from_slice(name.as_bytes());
+    key
+}
+
+fn decode_id(bytes: &[u8]) -> Result<u64, QueueError> {
+    let bytes: [u8; ID_SIZE] = bytes
+        .try_into()
+        .map_err(|_| QueueError::Corrupt("id is not a u64".to_string()))?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn receipt() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    record::hex(&bytes)
+}
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Prefix of a value wrapped in a record header. Values written before
+/// headers were introduced have none and are read as bare bodies.
+const MAGIC: &[u8] = b"\0REC\x01";
+const LENGTH_SIZE: usize = 4;
+
+/// Version locks, each key hashed to one, so writers of different keys
+/// rarely wait on each other.
+const VERSION_STRIPES: usize = 256;
+static VERSIONS: [Mutex<()>; VERSION_STRIPES] = [const { Mutex::new(()) }; VERSION_STRIPES];
+
+/// Metadata stored in front of each value. Encoded as JSON so fields can be
+/// added without rewriting older records.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Header {
+    /// When the value was last written, in milliseconds since the epoch.
+    pub modified_ms: u64,
+    /// SHA-256 of the value as the client sent it.
+    pub sha256: String,
+    /// When the key was first written. Zero for records written before
+    /// creation times were kept.
+    #[serde(default)]
+    pub created_ms: u64,
+    /// Counts the writes to the key, starting at 1.
+    #[serde(default)]
+    pub version: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Client that wrote the value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl Header {
+    /// Header for a value being written now.
+    pub fn new(sha256: String) -> Self {
+        let now = unix_millis();
+        Header {
+            modified_ms: now,
+            sha256,
+            created_ms: now,
+            version: 1,
+            ..Header::default()
+        }
+    }
+
+    /// Continues the history of the value being replaced: keeps its
+    /// creation time and takes the next version.
+    pub fn succeed(&mut self, previous: Option<&Record<impl Sized>>) {
+        let Some(previous) = previous else {
+            return;
+        };
+        match &previous.header {
+            Some(header) => {
+                self.version = header.version.max(1) + 1;
+                if header.created_ms > 0 {
+                    self.created_ms = header.created_ms;
+                } else {
+                    self.created_ms = header.modified_ms;
+                }
+            }
+            // Values written without a header count as the first version.
+            None => self.version = 2,
+        }
+    }
+}
+
+/// A stored value split into its header, if it has one, and its body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record<T = Vec<u8>> {
+    pub header: Option<Header>,
+    pub body: T,
+}
+
+impl<T> Record<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Record<U> {
+        Record {
+            header: self.header,
+            body: f(self.body),
+        }
+    }
+}
+
+impl Record {
+    /// SHA-256 of the value, from the header or, for older values, computed
+    /// from the body.
+    pub fn sha256(&self) -> String {
+        match &self.header {
+            Some(header) => header.sha256.clone(),
+            None => digest(&self.body),
+        }
+    }
+}
+
+/// Held while reading the record a write of `key` replaces, so concurrent
+/// writers of a key never take the same version. Only writers of keys that
+/// share its stripe wait.
+pub fn hold_versions(key: &[u8]) -> MutexGuard<'static, ()> {
+    lock_stripe(stripe(key))
+}
+
+/// `hold_versions` for every key a batch writes, taking the stripes in
+/// order so batches never deadlock with each other.
+pub fn hold_versions_of<'k>(
+    keys: impl IntoIterator<Item = &'k [u8]>,
+) -> Vec<MutexGuard<'static, ()>> {
+    let mut stripes: Vec<usize> = keys.into_iter().map(stripe).collect();
+    stripes.sort_unstable();
+    stripes.dedup();
+    stripes.into_iter().map(lock_stripe).collect()
+}
+
+fn stripe(key: &[u8]) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() as usize % VERSION_STRIPES
+}
+
+fn lock_stripe(stripe: usize) -> MutexGuard<'static, ()> {
+    VERSIONS[stripe]
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Prefixes `body` with `header`.
+pub fn wrap(header: &Header, body: &[u8]) -> Vec<u8> {
+    let encoded = serde_json::to_vec(header).unwrap_or_default();
+    let mut wrapped = Vec::with_capacity(MAGIC.len() + LENGTH_SIZE + encoded.len() + body.len());
+    wrapped.extend_from_slice(MAGIC);
+    wrapped.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+    wrapped.extend_from_slice(&encoded);
+    wrapped.extend_from_slice(body);
+    wrapped
+}
+
+/// Splits a stored value into header and body. A missing or unreadable
+/// header leaves the value whole.
+pub fn unwrap(stored: Vec<u8>) -> Record {
+    match split(&stored) {
+        Some((header, offset)) => Record {
+            header: Some(header),
+            body: stored[offset..].to_vec(),
+        },
+        None => Record {
+            header: None,
+            body: stored,
+        },
+    }
+}
+
+/// The body of a stored value, without copying.
+pub fn body(stored: &[u8]) -> &[u8] {
+    match split(stored) {
+        Some((_, offset)) => &stored[offset..],
+        None => stored,
+    }
+}
+
+fn split(stored: &[u8]) -> Option<(Header, usize)> {
+    let rest = stored.strip_prefix(MAGIC)?;
+    let length: [u8; LENGTH_SIZE] = rest.get(..LENGTH_SIZE)?.try_into().ok()?;
+    let length = u32::from_be_bytes(length) as usize;
+    let encoded = rest.get(LENGTH_SIZE..LENGTH_SIZE + length)?;
+    let header = serde_json::from_slice(encoded).ok()?;
+    Some((header, MAGIC.len() + LENGTH_SIZE + length))
+}
+
+/// Hex SHA-256 of `data`.
+pub fn digest(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -1,4 +1,20 @@
-use rocksdb::{Error, DB};
+use crate::storage::{
+    blob::{self, BlobError, Stored},
+    encryption::{Encryption, EncryptionError},
+    history::History,
+    index::Indexes,
+    record::{self, Header, Record},
+    structures,
+};
+use rocksdb::{Direction, Error, IteratorMode, WriteBatch, DB};
+
+/// What a write keeps in step with the value it stores.
+pub struct Store<'a> {
+    pub db: &'a DB,
+    pub encryption: &'a Encryption,
+    pub history: &'a History,
+    pub indexes: &'a Indexes,
+}
 
 pub fn put(db: &DB, key: &String, value: &String) -> Result<(), Error> {
     match db.put(key.as_bytes(), value.as_bytes()) {
@@ -20,3 +36,214 @@ pub fn get(db: &DB, key: &String) -> Result<Option<String>, Error> {
         }
     }
 }
+
+/// Writes `value` behind `header`, encrypted when encryption is enabled.
+/// The header takes the next version of the key, the value it replaces
+/// goes to history when that is kept and indexes are updated in the same
+/// batch. Replacing a chunked value also drops its chunks. Returns the
+/// header as written.
+pub fn put_encrypted(
+    db: &DB,
+    encryption: &Encryption,
+    history: &History,
+    indexes: &Indexes,
+    key: &str,
+    header: Header,
+    value: Vec<u8>,
+) -> Result<Header, Error> {
+    update_encrypted(db, encryption, history, indexes, key, |_| {
+        Ok::<_, Error>((header, value))
+    })
+}
+
+/// Like `put_encrypted`, with the header and value made by `update` from
+/// the value being replaced, `None` if there is none or it cannot be read.
+/// Other writes of the key wait until it is stored, so nothing lands in
+/// between; an error from `update` leaves the key untouched.
+pub fn update_encrypted<E: From<Error>>(
+    db: &DB,
+    encryption: &Encryption,
+    history: &History,
+    indexes: &Indexes,
+    key: &str,
+    update: impl FnOnce(Option<&Record<Stored>>) -> Result<(Header, Vec<u8>), E>,
+) -> Result<Header, E> {
+    let _writes = encryption.hold_writes();
+    let _versions = record::hold_versions(key.as_bytes());
+    let previous = match blob::lookup(db, encryption, key.as_bytes()) {
+        Ok(previous) => previous,
+        Err(BlobError::Storage(e)) => return Err(e.into()),
+        // An unreadable previous value is simply overwritten.
+        Err(_) => None,
+    };
+    let (header, value) = update(previous.as_ref())?;
+
+    let store = Store {
+        db,
+        encryption,
+        history,
+        indexes,
+    };
+    let mut batch = WriteBatch::default();
+    let header = stage_put(&store, &mut batch, key, previous.as_ref(), header, &value)?;
+    match db.write(batch) {
+        Ok(_) => Ok(header),
+        Err(e) => {
+            println!("Error put key \"{:?}\": {:}", key, e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Adds to `batch` everything `put_encrypted` writes for `key` replacing
+/// `previous`: the sealed value behind `header`, which takes the next
+/// version, the index changes, the chunks of a replaced chunked value to
+/// drop and the replaced value for history. Call with writes to the key
+/// held, and for each key at most once per batch. Returns the header as
+/// staged.
+pub fn stage_put(
+    store: &Store,
+    batch: &mut WriteBatch,
+    key: &str,
+    previous: Option<&Record<Stored>>,
+    header: Header,
+    value: &[u8],
+) -> Result<Header, Error> {
+    let header = stage_upkeep(store, batch, key, previous, header, value)?;
+    let sealed = store
+        .encryption
+        .seal(key.as_bytes(), &record::wrap(&header, value));
+    batch.put(key.as_bytes(), sealed);
+    Ok(header)
+}
+
+/// Adds to `batch` what `stage_put` writes besides the value itself, for a
+/// value written some other way. Reads the value `key` holds, so call before
+/// the new one is written.
+pub fn stage_upkeep(
+    store: &Store,
+    batch: &mut WriteBatch,
+    key: &str,
+    previous: Option<&Record<Stored>>,
+    mut header: Header,
+    value: &[u8],
+) -> Result<Header, Error> {
+    header.succeed(previous);
+    let replaced = previous.map(|previous| &previous.body);
+    store
+        .indexes
+        .update(store.db, batch, key.as_bytes(), replaced, Some(value));
+    if let Some(Record {
+        body: Stored::Blob(previous),
+        ..
+    }) = previous
+    {
+        blob::delete_chunks(batch, previous);
+    }
+    store.history.keep(
+        store.db,
+        store.encryption,
+        batch,
+        key.as_bytes(),
+        Some(&mut header),
+    )?;
+    Ok(header)
+}
+
+/// Reads a value written by `put_encrypted` or by `put` before encryption
+/// was enabled, split from its header. Values stored compressed are returned
+/// still compressed.
+pub fn get_decrypted(
+    db: &DB,
+    encryption: &Encryption,
+    key: &str,
+) -> Result<Option<Record>, EncryptionError> {
+    let stored = match db.get(key.as_bytes()) {
+        Ok(Some(stored)) => stored,
+        Ok(None) => return Ok(None),
+        Err(e) => {
+            println!("Error get key \"{:?}\": {:}", key, e);
+            return Err(e.into());
+        }
+    };
+    match encryption.open(key.as_bytes(), &stored) {
+        Ok(value) => Ok(Some(record::unwrap(value))),
+        Err(e) => {
+            println!("Error decrypting key \"{:?}\": {:}", key, e);
+            Err(e)
+        }
+    }
+}
+
+/// Deletes a key along with the chunks of a chunked value, its index
+/// entries and the hashes, sets and sorted sets stored at it, keeping its
+/// value in history when that is kept. Returns whether the key held a value
+/// or a structure.
+pub fn delete(
+    db: &DB,
+    encryption: &Encryption,
+    history: &History,
+    indexes: &Indexes,
+    key: &str,
+) -> Result<bool, Error> {
+    let _writes = encryption.hold_writes();
+    let _versions = record::hold_versions(key.as_bytes());
+    let mut batch = WriteBatch::default();
+    let existed = match blob::lookup(db, encryption, key.as_bytes()) {
+        Ok(None) => false,
+        Ok(Some(Record {
+            body: Stored::Blob(previous),
+            ..
+        })) => {
+            blob::delete_chunks(&mut batch, &previous);
+            true
+        }
+        Ok(Some(previous)) => {
+            indexes.update(db, &mut batch, key.as_bytes(), Some(&previous.body), None);
+            true
+        }
+        Err(BlobError::Storage(e)) => return Err(e),
+        Err(_) => true,
+    };
+    let structures = structures::stage_delete(db, &mut batch, key)?;
+    if !existed && !structures {
+        return Ok(false);
+    }
+    if existed {
+        history.keep(db, encryption, &mut batch, key.as_bytes(), None)?;
+        batch.delete(key.as_bytes());
+    }
+    match db.write(batch) {
+        Ok(_) => Ok(true),
+        Err(e) => {
+            println!("Error delete key \"{:?}\": {:}", key, e);
+            Err(e)
+        }
+    }
+}
+
+/// Entries from `from` on whose keys start with `prefix`, in key order and
+/// decrypted.
+pub fn scan_decrypted<'a>(
+    db: &'a DB,
+    encryption: &'a Encryption,
+    prefix: &'a [u8],
+    from: &[u8],
+) -> impl Iterator<Item = Result<(Box<[u8]>, Vec<u8>), EncryptionError>> + 'a {
+    db.iterator(IteratorMode::From(from, Direction::Forward))
+        .take_while(move |item| match item {
+            Ok((key, _)) => key.starts_with(prefix),
+            Err(_) => true,
+        })
+        .map(move |item| {
+            let (key, stored) = item?;
+            let value = encryption.open(&key, &stored)?;
+            Ok((key, value))
+        })
+}
+
+/// Adds `value` under `key` to `batch`, encrypted when encryption is
+/// enabled.
+pub fn put_sealed(batch: &mut WriteBatch, encryption: &Encryption, key: &[u8], value: &[u8]) {
+    batch.put(key, encryption.seal(key, value));
+}
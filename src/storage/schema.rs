@@ -0,0 +1,275 @@
+use crate::storage::{
+    encryption::{Encryption, EncryptionError},
+    queue::QUEUE_COLUMN_FAMILY,
+    timeseries::TIMESERIES_COLUMN_FAMILY,
+};
+use jsonschema::Validator;
+use rocksdb::{Direction, Error, IteratorMode, DB};
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, sync::RwLock};
+
+/// Schemas live under `SCHEMA_PREFIX <prefix>` or
+/// `COLUMN_FAMILY_SCHEMA_PREFIX <column family>`, sealed like values, so
+/// they replicate and survive restarts with the data they describe.
+const SCHEMA_PREFIX: &[u8] = b"\0schema\0";
+const COLUMN_FAMILY_SCHEMA_PREFIX: &[u8] = b"\0cfschema\0";
+
+/// Column families whose writes a schema can be attached to. Values, hashes,
+/// sets and sorted sets all live in the default column family and take the
+/// schemas of their key prefixes; the empty prefix covers the whole of it.
+pub const SCHEMA_COLUMN_FAMILIES: [&str; 2] = [QUEUE_COLUMN_FAMILY, TIMESERIES_COLUMN_FAMILY];
+
+/// JSON Schemas attached to key prefixes and column families. Values under
+/// a prefix with a schema must be JSON documents valid against it, and
+/// against the schema of every shorter prefix as well. Queue messages and
+/// time-series points must be valid against the schema of their column
+/// family.
+#[derive(Default)]
+pub struct Schemas {
+    namespaces: RwLock<BTreeMap<Scope, Namespace>>,
+}
+
+/// What a schema is attached to.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Prefix(String),
+    ColumnFamily(String),
+}
+
+struct Namespace {
+    schema: Value,
+    validator: Validator,
+}
+
+/// One way a value fails a schema.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Prefix or column family whose schema the value fails.
+    #[serde(flatten)]
+    pub scope: Scope,
+    /// JSON pointer to the failing part of the value.
+    pub path: String,
+    /// JSON pointer to the failing keyword of the schema.
+    pub schema_path: String,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Invalid(String),
+    /// The column family holds nothing a schema can be attached to.
+    UnknownColumnFamily(String),
+    Encryption(EncryptionError),
+    Storage(Error),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Invalid(e) => write!(f, "invalid schema: {}", e),
+            SchemaError::UnknownColumnFamily(name) => {
+                write!(f, "column family \"{}\" cannot have a schema", name)
+            }
+            SchemaError::Encryption(e) => write!(f, "{}", e),
+            SchemaError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<Error> for SchemaError {
+    fn from(e: Error) -> Self {
+        SchemaError::Storage(e)
+    }
+}
+
+impl fmt::Debug for Schemas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let namespaces = self.namespaces.read().unwrap();
+        f.debug_struct("Schemas")
+            .field("scopes", &namespaces.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Schemas {
+    /// The schemas stored in `db`.
+    pub fn load(db: &DB, encryption: &Encryption) -> Result<Self, SchemaError> {
+        let schemas = Schemas::default();
+        schemas.reload(db, encryption)?;
+        Ok(schemas)
+    }
+
+    /// Re-reads the stored schemas, e.g. after a follower that received
+    /// them through replication is promoted.
+    pub fn reload(&self, db: &DB, encryption: &Encryption) -> Result<(), SchemaError> {
+        let mut namespaces = BTreeMap::new();
+        for (marker, scope) in [
+            (SCHEMA_PREFIX, Scope::Prefix as fn(String) -> Scope),
+            (COLUMN_FAMILY_SCHEMA_PREFIX, Scope::ColumnFamily),
+        ] {
+            let entries = db.iterator(IteratorMode::From(marker, Direction::Forward));
+            for item in entries {
+                let (key, stored) = item?;
+                let Some(name) = key.strip_prefix(marker) else {
+                    break;
+                };
+                let scope = scope(String::from_utf8_lossy(name).into_owned());
+                let plaintext = encryption
+                    .open(&key, &stored)
+                    .map_err(SchemaError::Encryption)?;
+                let schema = serde_json::from_slice(&plaintext)
+                    .map_err(|e| SchemaError::Invalid(format!("for {}: {}", scope, e)))?;
+                namespaces.insert(scope, Namespace::compile(schema)?);
+            }
+        }
+        *self.namespaces.write().unwrap() = namespaces;
+        Ok(())
+    }
+
+    /// Attaches `schema` to `scope`, replacing the one it had. Values
+    /// already stored are not checked.
+    pub fn attach(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        scope: &Scope,
+        schema: Value,
+    ) -> Result<(), SchemaError> {
+        if let Scope::ColumnFamily(name) = scope {
+            if !SCHEMA_COLUMN_FAMILIES.contains(&name.as_str()) {
+                return Err(SchemaError::UnknownColumnFamily(name.clone()));
+            }
+        }
+        let namespace = Namespace::compile(schema)?;
+        let key = scope.key();
+        let _writes = encryption.hold_writes();
+        db.put(
+            &key,
+            encryption.seal(&key, namespace.schema.to_string().as_bytes()),
+        )?;
+        self.namespaces
+            .write()
+            .unwrap()
+            .insert(scope.clone(), namespace);
+        Ok(())
+    }
+
+    /// Removes the schema of `scope`. `false` if it had none.
+    pub fn detach(&self, db: &DB, scope: &Scope) -> Result<bool, Error> {
+        if !self.namespaces.read().unwrap().contains_key(scope) {
+            return Ok(false);
+        }
+        db.delete(scope.key())?;
+        Ok(self.namespaces.write().unwrap().remove(scope).is_some())
+    }
+
+    /// Every prefix and column family with its schema, prefixes first and
+    /// each in name order.
+    pub fn list(&self) -> Vec<(Scope, Value)> {
+        self.namespaces
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(scope, namespace)| (scope.clone(), namespace.schema.clone()))
+            .collect()
+    }
+
+    /// Whether a prefix of `key` has a schema.
+    pub fn covers(&self, key: &str) -> bool {
+        self.namespaces
+            .read()
+            .unwrap()
+            .keys()
+            .any(|scope| matches!(scope, Scope::Prefix(prefix) if key.starts_with(prefix.as_str())))
+    }
+
+    /// Whether the column family `name` has a schema.
+    pub fn covers_column_family(&self, name: &str) -> bool {
+        self.namespaces
+            .read()
+            .unwrap()
+            .contains_key(&Scope::ColumnFamily(name.to_string()))
+    }
+
+    /// Checks `document`, the new value of `key`, against the schema of
+    /// every prefix of the key.
+    pub fn validate(&self, key: &str, document: &Value) -> Result<(), Vec<Violation>> {
+        self.check(
+            document,
+            |scope| matches!(scope, Scope::Prefix(prefix) if key.starts_with(prefix.as_str())),
+        )
+    }
+
+    /// Checks `document`, written to the column family `name`, against the
+    /// column family's schema.
+    pub fn validate_column_family(
+        &self,
+        name: &str,
+        document: &Value,
+    ) -> Result<(), Vec<Violation>> {
+        self.check(
+            document,
+            |scope| matches!(scope, Scope::ColumnFamily(column_family) if column_family == name),
+        )
+    }
+
+    fn check(
+        &self,
+        document: &Value,
+        applies: impl Fn(&Scope) -> bool,
+    ) -> Result<(), Vec<Violation>> {
+        let namespaces = self.namespaces.read().unwrap();
+        let violations: Vec<Violation> = namespaces
+            .iter()
+            .filter(|(scope, _)| applies(scope))
+            .flat_map(|(scope, namespace)| {
+                namespace
+                    .validator
+                    .iter_errors(document)
+                    .map(|error| Violation {
+                        scope: scope.clone(),
+                        path: error.instance_path.to_string(),
+                        schema_path: error.schema_path.to_string(),
+                        message: error.to_string(),
+                    })
+            })
+            .collect();
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(violations),
+        }
+    }
+}
+
+impl Scope {
+    fn key(&self) -> Vec<u8> {
+        let (marker, name) = match self {
+            Scope::Prefix(prefix) => (SCHEMA_PREFIX, prefix),
+            Scope::ColumnFamily(name) => (COLUMN_FAMILY_SCHEMA_PREFIX, name),
+        };
+        let mut key = marker.to_vec();
+        key.extend_from_slice(name.as_bytes());
+        key
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Prefix(prefix) => write!(f, "prefix \"{}\"", prefix),
+            Scope::ColumnFamily(name) => write!(f, "column family \"{}\"", name),
+        }
+    }
+}
+
+impl Namespace {
+    fn compile(schema: Value) -> Result<Self, SchemaError> {
+        let validator =
+            jsonschema::validator_for(&schema).map_err(|e| SchemaError::Invalid(e.to_string()))?;
+        Ok(Namespace { schema, validator })
+    }
+}
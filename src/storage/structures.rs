@@ -0,0 +1,530 @@
+use crate::storage::{
+    encryption::{Encryption, EncryptionError},
+    index::{order_number, unorder_number},
+    record,
+    rocksdb::{put_sealed, scan_decrypted},
+    schema::{Schemas, Violation},
+};
+use rocksdb::{Direction, Error, IteratorMode, WriteBatch, DB};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// Hash fields live under `HASH_PREFIX <key> \0 <field>`, set members under
+/// `SET_PREFIX <key> \0 <member>`. A sorted-set member is kept twice: under
+/// `ZSET_MEMBER_PREFIX <key> \0 <member>` holding its score, and under
+/// `ZSET_SCORE_PREFIX <key> \0 <ordered score> <member>` so members read in
+/// score order. Fields, members and scores are part of the keys and so are
+/// not encrypted; hash values and stored scores are.
+///
+/// A structure under a prefix with a schema must stay valid against it as
+/// a JSON document: a hash as an object of its fields, a set as an array of
+/// its members in byte order and a sorted set as an object of member to
+/// score.
+const HASH_PREFIX: &[u8] = b"\0hash\0";
+const SET_PREFIX: &[u8] = b"\0set\0";
+const ZSET_MEMBER_PREFIX: &[u8] = b"\0zmem\0";
+const ZSET_SCORE_PREFIX: &[u8] = b"\0zscore\0";
+const SCORE_SIZE: usize = 8;
+
+/// The types of structure a key can hold, alongside each other and a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Hash,
+    Set,
+    SortedSet,
+}
+
+impl Kind {
+    fn prefixes(self) -> &'static [&'static [u8]] {
+        match self {
+            Kind::Hash => &[HASH_PREFIX],
+            Kind::Set => &[SET_PREFIX],
+            Kind::SortedSet => &[ZSET_MEMBER_PREFIX, ZSET_SCORE_PREFIX],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StructureError {
+    /// Keys cannot contain NUL bytes, which separate them from fields.
+    InvalidKey(String),
+    InvalidScore(String),
+    /// The structure as written would not match its schema.
+    Invalid(Vec<Violation>),
+    Corrupt(String),
+    Encryption(EncryptionError),
+    Storage(Error),
+}
+
+impl fmt::Display for StructureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StructureError::InvalidKey(key) => write!(f, "invalid key {:?}", key),
+            StructureError::InvalidScore(member) => {
+                write!(f, "score of member \"{}\" is not a number", member)
+            }
+            StructureError::Invalid(_) => write!(f, "value does not match its schema"),
+            StructureError::Corrupt(e) => write!(f, "corrupt entry: {}", e),
+            StructureError::Encryption(e) => write!(f, "{}", e),
+            StructureError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StructureError {}
+
+impl From<Error> for StructureError {
+    fn from(e: Error) -> Self {
+        StructureError::Storage(e)
+    }
+}
+
+impl From<EncryptionError> for StructureError {
+    fn from(e: EncryptionError) -> Self {
+        StructureError::Encryption(e)
+    }
+}
+
+/// Sets `fields` of the hash at `key`. Returns how many were new.
+pub fn hset(
+    db: &DB,
+    encryption: &Encryption,
+    schemas: &Schemas,
+    key: &str,
+    fields: &BTreeMap<String, String>,
+) -> Result<u64, StructureError> {
+    let prefix = prefix(HASH_PREFIX, key)?;
+    let _writes = encryption.hold_writes();
+    let _versions = record::hold_versions(key.as_bytes());
+    if schemas.covers(key) {
+        let mut hash = hgetall(db, encryption, key, None, usize::MAX)?;
+        hash.extend(fields.clone());
+        check(schemas, key, &hash)?;
+    }
+    let mut batch = WriteBatch::default();
+    let mut added = 0;
+    for (field, value) in fields {
+        let entry = entry_key(&prefix, field.as_bytes());
+        if db.get(&entry)?.is_none() {
+            added += 1;
+        }
+        put_sealed(&mut batch, encryption, &entry, value.as_bytes());
+    }
+    db.write(batch)?;
+    Ok(added)
+}
+
+pub fn hget(
+    db: &DB,
+    encryption: &Encryption,
+    key: &str,
+    field: &str,
+) -> Result<Option<String>, StructureError> {
+    let entry = entry_key(&prefix(HASH_PREFIX, key)?, field.as_bytes());
+    let Some(stored) = db.get(&entry)? else {
+        return Ok(None);
+    };
+    let value = encryption.open(&entry, &stored)?;
+    Ok(Some(String::from_utf8_lossy(&value).into_owned()))
+}
+
+/// Up to `limit` fields of the hash at `key` with their values, in byte
+/// order from the first field after `after`; empty if there are none.
+pub fn hgetall(
+    db: &DB,
+    encryption: &Encryption,
+    key: &str,
+    after: Option<&str>,
+    limit: usize,
+) -> Result<BTreeMap<String, String>, StructureError> {
+    let prefix = prefix(HASH_PREFIX, key)?;
+    let mut fields = BTreeMap::new();
+    let from = page_start(&prefix, after);
+    for item in scan_decrypted(db, encryption, &prefix, &from).take(limit) {
+        let (entry, value) = item?;
+        fields.insert(
+            String::from_utf8_lossy(&entry[prefix.len()..]).into_owned(),
+            String::from_utf8_lossy(&value).into_owned(),
+        );
+    }
+    Ok(fields)
+}
+
+/// Adds `members` to the set at `key`. Returns how many were new.
+pub fn sadd(
+    db: &DB,
+    encryption: &Encryption,
+    schemas: &Schemas,
+    key: &str,
+    members: &[String],
+) -> Result<u64, StructureError> {
+    let prefix = prefix(SET_PREFIX, key)?;
+    let _writes = encryption.hold_writes();
+    let _versions = record::hold_versions(key.as_bytes());
+    if schemas.covers(key) {
+        let mut set: BTreeSet<String> = smembers(db, encryption, key, None, usize::MAX)?
+            .into_iter()
+            .collect();
+        set.extend(members.iter().cloned());
+        check(schemas, key, &set)?;
+    }
+    let mut batch = WriteBatch::default();
+    let mut added = 0;
+    for member in members.iter().collect::<BTreeSet<_>>() {
+        let entry = entry_key(&prefix, member.as_bytes());
+        if db.get(&entry)?.is_none() {
+            added += 1;
+            put_sealed(&mut batch, encryption, &entry, &[]);
+        }
+    }
+    db.write(batch)?;
+    Ok(added)
+}
+
+/// Up to `limit` members of the set at `key` in byte order from the first
+/// member after `after`; empty if there are none.
+pub fn smembers(
+    db: &DB,
+    encryption: &Encryption,
+    key: &str,
+    after: Option<&str>,
+    limit: usize,
+) -> Result<Vec<String>, StructureError> {
+    let prefix = prefix(SET_PREFIX, key)?;
+    let from = page_start(&prefix, after);
+    scan_decrypted(db, encryption, &prefix, &from)
+        .take(limit)
+        .map(|item| {
+            let (entry, _) = item?;
+            Ok(String::from_utf8_lossy(&entry[prefix.len()..]).into_owned())
+        })
+        .collect()
+}
+
+/// Adds `members` to the sorted set at `key`, or moves them to their new
+/// score. Returns how many were new.
+pub fn zadd(
+    db: &DB,
+    encryption: &Encryption,
+    schemas: &Schemas,
+    key: &str,
+    members: &BTreeMap<String, f64>,
+) -> Result<u64, StructureError> {
+    let member_prefix = prefix(ZSET_MEMBER_PREFIX, key)?;
+    let score_prefix = prefix(ZSET_SCORE_PREFIX, key)?;
+    if let Some((member, _)) = members.iter().find(|(_, score)| score.is_nan()) {
+        return Err(StructureError::InvalidScore(member.clone()));
+    }
+    let _writes = encryption.hold_writes();
+    let _versions = record::hold_versions(key.as_bytes());
+    if schemas.covers(key) {
+        let mut scores = zscores(db, encryption, &member_prefix)?;
+        scores.extend(
+            members
+                .iter()
+                .map(|(member, &score)| (member.clone(), normalize(score))),
+        );
+        check(schemas, key, &scores)?;
+    }
+    let mut batch = WriteBatch::default();
+    let mut added = 0;
+    for (member, &score) in members {
+        let score = normalize(score);
+        let member_entry = entry_key(&member_prefix, member.as_bytes());
+        match db.get(&member_entry)? {
+            Some(stored) => {
+                let previous = decode_score(&encryption.open(&member_entry, &stored)?)?;
+                batch.delete(score_key(&score_prefix, previous, member));
+            }
+            None => added += 1,
+        }
+        put_sealed(&mut batch, encryption, &member_entry, &score.to_be_bytes());
+        let score_entry = score_key(&score_prefix, score, member);
+        put_sealed(&mut batch, encryption, &score_entry, &[]);
+    }
+    db.write(batch)?;
+    Ok(added)
+}
+
+/// Removes `fields` from the hash at `key`. Returns how many it had.
+pub fn hdel(
+    db: &DB,
+    encryption: &Encryption,
+    schemas: &Schemas,
+    key: &str,
+    fields: &[String],
+) -> Result<u64, StructureError> {
+    let prefix = prefix(HASH_PREFIX, key)?;
+    let _writes = encryption.hold_writes();
+    let _versions = record::hold_versions(key.as_bytes());
+    if schemas.covers(key) {
+        let mut hash = hgetall(db, encryption, key, None, usize::MAX)?;
+        hash.retain(|field, _| !fields.contains(field));
+        check(schemas, key, &hash)?;
+    }
+    remove_entries(db, &prefix, fields)
+}
+
+/// Removes `members` from the set at `key`. Returns how many it had.
+pub fn srem(
+    db: &DB,
+    encryption: &Encryption,
+    schemas: &Schemas,
+    key: &str,
+    members: &[String],
+) -> Result<u64, StructureError> {
+    let prefix = prefix(SET_PREFIX, key)?;
+    let _writes = encryption.hold_writes();
+    let _versions = record::hold_versions(key.as_bytes());
+    if schemas.covers(key) {
+        let mut set: BTreeSet<String> = smembers(db, encryption, key, None, usize::MAX)?
+            .into_iter()
+            .collect();
+        set.retain(|member| !members.contains(member));
+        check(schemas, key, &set)?;
+    }
+    remove_entries(db, &prefix, members)
+}
+
+/// Removes `members` from the sorted set at `key`. Returns how many it had.
+pub fn zrem(
+    db: &DB,
+    encryption: &Encryption,
+    schemas: &Schemas,
+    key: &str,
+    members: &[String],
+) -> Result<u64, StructureError> {
+    let member_prefix = prefix(ZSET_MEMBER_PREFIX, key)?;
+    let score_prefix = prefix(ZSET_SCORE_PREFIX, key)?;
+    let _writes = encryption.hold_writes();
+    let _versions = record::hold_versions(key.as_bytes());
+    if schemas.covers(key) {
+        let mut scores = zscores(db, encryption, &member_prefix)?;
+        scores.retain(|member, _| !members.contains(member));
+        check(schemas, key, &scores)?;
+    }
+    let mut batch = WriteBatch::default();
+    let mut removed = 0;
+    for member in members.iter().collect::<BTreeSet<_>>() {
+        let member_entry = entry_key(&member_prefix, member.as_bytes());
+        let Some(stored) = db.get(&member_entry)? else {
+            continue;
+        };
+        let score = decode_score(&encryption.open(&member_entry, &stored)?)?;
+        batch.delete(&member_entry);
+        batch.delete(score_key(&score_prefix, score, member));
+        removed += 1;
+    }
+    db.write(batch)?;
+    Ok(removed)
+}
+
+/// Deletes the whole structure of `kind` at `key`. Returns how many fields
+/// or members it had.
+pub fn delete(
+    db: &DB,
+    encryption: &Encryption,
+    kind: Kind,
+    key: &str,
+) -> Result<u64, StructureError> {
+    let prefixes = kind
+        .prefixes()
+        .iter()
+        .map(|kind| prefix(kind, key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let _writes = encryption.hold_writes();
+    let _versions = record::hold_versions(key.as_bytes());
+    let mut removed = 0;
+    for item in db.iterator(IteratorMode::From(&prefixes[0], Direction::Forward)) {
+        let (entry, _) = item?;
+        if !entry.starts_with(&prefixes[0]) {
+            break;
+        }
+        removed += 1;
+    }
+    let mut batch = WriteBatch::default();
+    for prefix in &prefixes {
+        batch.delete_range(prefix.as_slice(), prefix_end(prefix).as_slice());
+    }
+    db.write(batch)?;
+    Ok(removed)
+}
+
+/// Adds deletion of every structure at `key` to `batch`, for deleting the
+/// key as a whole. Call with writes to the key held. Returns whether there
+/// was any.
+pub fn stage_delete(db: &DB, batch: &mut WriteBatch, key: &str) -> Result<bool, Error> {
+    // Structure keys cannot contain NUL, so there is none to delete.
+    if key.contains('\0') {
+        return Ok(false);
+    }
+    let mut found = false;
+    for kind in [
+        HASH_PREFIX,
+        SET_PREFIX,
+        ZSET_MEMBER_PREFIX,
+        ZSET_SCORE_PREFIX,
+    ] {
+        let prefix = [kind, key.as_bytes(), &[0]].concat();
+        let mut entries = db.iterator(IteratorMode::From(&prefix, Direction::Forward));
+        if let Some(item) = entries.next() {
+            let (entry, _) = item?;
+            if entry.starts_with(&prefix) {
+                found = true;
+                batch.delete_range(prefix.as_slice(), prefix_end(&prefix).as_slice());
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Members of the sorted set at `key` with scores from `min` to `max`
+/// inclusive, lowest score first and members of equal score in byte order.
+pub fn zrangebyscore(
+    db: &DB,
+    encryption: &Encryption,
+    key: &str,
+    min: f64,
+    max: f64,
+    limit: usize,
+) -> Result<Vec<(String, f64)>, StructureError> {
+    let prefix = prefix(ZSET_SCORE_PREFIX, key)?;
+    let mut from = prefix.clone();
+    from.extend_from_slice(&order_number(normalize(min)));
+    let mut members = Vec::new();
+    for item in scan_decrypted(db, encryption, &prefix, &from) {
+        let (entry, _) = item?;
+        let Some((score, member)) = entry[prefix.len()..].split_first_chunk::<SCORE_SIZE>() else {
+            return Err(StructureError::Corrupt(
+                "score entry is too short".to_string(),
+            ));
+        };
+        let score = unorder_number(*score);
+        if score > max || members.len() == limit {
+            break;
+        }
+        members.push((String::from_utf8_lossy(member).into_owned(), score));
+    }
+    Ok(members)
+}
+
+/// Prefixes of the entries of every structure whose key starts with
+/// `prefix`.
+pub fn entries_under(prefix: &[u8]) -> Vec<Vec<u8>> {
+    [
+        HASH_PREFIX,
+        SET_PREFIX,
+        ZSET_MEMBER_PREFIX,
+        ZSET_SCORE_PREFIX,
+    ]
+    .iter()
+    .map(|kind| [*kind, prefix].concat())
+    .collect()
+}
+
+/// Deletes the entries of `names` under `prefix`. Returns how many there
+/// were.
+fn remove_entries(db: &DB, prefix: &[u8], names: &[String]) -> Result<u64, StructureError> {
+    let mut batch = WriteBatch::default();
+    let mut removed = 0;
+    for name in names.iter().collect::<BTreeSet<_>>() {
+        let entry = entry_key(prefix, name.as_bytes());
+        if db.get(&entry)?.is_some() {
+            batch.delete(&entry);
+            removed += 1;
+        }
+    }
+    db.write(batch)?;
+    Ok(removed)
+}
+
+/// Every member of a sorted set with its score, from its member entries.
+fn zscores(
+    db: &DB,
+    encryption: &Encryption,
+    member_prefix: &[u8],
+) -> Result<BTreeMap<String, f64>, StructureError> {
+    let mut scores = BTreeMap::new();
+    for item in scan_decrypted(db, encryption, member_prefix, member_prefix) {
+        let (entry, stored) = item?;
+        let member = String::from_utf8_lossy(&entry[member_prefix.len()..]).into_owned();
+        scores.insert(member, decode_score(&stored)?);
+    }
+    Ok(scores)
+}
+
+/// Where a page of entries under `prefix` starts: the first entry after
+/// `after`, or the first one.
+fn page_start(prefix: &[u8], after: Option<&str>) -> Vec<u8> {
+    match after {
+        Some(after) => {
+            let mut start = entry_key(prefix, after.as_bytes());
+            start.push(0);
+            start
+        }
+        None => prefix.to_vec(),
+    }
+}
+
+/// The first key past every key starting with `prefix`, which ends in NUL.
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    if let Some(last) = end.last_mut() {
+        *last += 1;
+    }
+    end
+}
+
+/// Validates `structure` as the new document of `key` against the schemas
+/// of its prefixes.
+fn check(schemas: &Schemas, key: &str, structure: &impl Serialize) -> Result<(), StructureError> {
+    let document = serde_json::to_value(structure).unwrap_or(Value::Null);
+    schemas
+        .validate(key, &document)
+        .map_err(StructureError::Invalid)
+}
+
+/// `kind <key> \0`, the prefix of every entry of the structure at `key`.
+fn prefix(kind: &[u8], key: &str) -> Result<Vec<u8>, StructureError> {
+    if key.contains('\0') {
+        return Err(StructureError::InvalidKey(key.to_string()));
+    }
+    let mut prefix = Vec::with_capacity(kind.len() + key.len() + 1);
+    prefix.extend_from_slice(kind);
+    prefix.extend_from_slice(key.as_bytes());
+    prefix.push(0);
+    Ok(prefix)
+}
+
+fn entry_key(prefix: &[u8], suffix: &[u8]) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(prefix.len() + suffix.len());
+    entry.extend_from_slice(prefix);
+    entry.extend_from_slice(suffix);
+    entry
+}
+
+fn score_key(prefix: &[u8], score: f64, member: &str) -> Vec<u8> {
+    let mut entry = entry_key(prefix, &order_number(score));
+    entry.extend_from_slice(member.as_bytes());
+    entry
+}
+
+/// Zero without its sign, which would otherwise sort before it.
+fn normalize(score: f64) -> f64 {
+    if score == 0.0 {
+        0.0
+    } else {
+        score
+    }
+}
+
+fn decode_score(stored: &[u8]) -> Result<f64, StructureError> {
+    let bytes: [u8; SCORE_SIZE] = stored
+        .try_into()
+        .map_err(|_| StructureError::Corrupt("score is not an f64".to_string()))?;
+    Ok(f64::from_be_bytes(bytes))
+}
@@ -0,0 +1,579 @@
+use crate::{
+    replication::Replication,
+    storage::{
+        encryption::{Encryption, EncryptionError},
+        record,
+    },
+};
+use rocksdb::{ColumnFamily, Direction, Error, IteratorMode, Options, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub const TIMESERIES_COLUMN_FAMILY: &str = "timeseries";
+/// Width of the buckets raw points are downsampled into.
+pub const DEFAULT_RESOLUTION_MS: u64 = 60_000;
+
+/// Keys are `<kind> \0 <series> \0 <timestamp>` for raw points and
+/// downsampled buckets, the timestamp a big-endian `u64` of Unix
+/// milliseconds so a series reads in time order, and `HORIZON \0 <series>`
+/// for the time before which raw points have been downsampled. Points found
+/// behind the horizon arrived late and are read as part of their bucket.
+/// Points are an `f64` and buckets a `Summary`, both sealed like values.
+const POINT: u8 = b'p';
+const BUCKET: u8 = b'b';
+const HORIZON: u8 = b'h';
+const TIMESTAMP_SIZE: usize = 8;
+const SUMMARY_SIZE: usize = 32;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    /// Unix milliseconds.
+    pub timestamp: u64,
+    pub value: f64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation {
+    #[default]
+    Avg,
+    Min,
+    Max,
+    Sum,
+    Count,
+}
+
+/// Points from `from` to `to` inclusive, at most `limit` of them. With a
+/// `step`, points are aggregated into buckets of that many milliseconds,
+/// each reported at its start. Downsampled stretches report one point per
+/// bucket; a step that is a multiple of the resolution aggregates them
+/// exactly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Selection {
+    pub from: u64,
+    pub to: u64,
+    pub step: Option<u64>,
+    pub aggregation: Aggregation,
+    pub limit: usize,
+}
+
+/// What is kept of the points of a bucket once they are downsampled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Summary {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+/// How much of a write landed ahead of the horizon of its series; the rest
+/// was older and is read as part of its bucket.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Appended {
+    pub points: u64,
+    pub downsampled: u64,
+}
+
+#[derive(Debug)]
+pub enum TimeSeriesError {
+    InvalidName(String),
+    /// Values must be finite numbers.
+    InvalidValue(u64),
+    InvalidStep,
+    /// The time series column family has not been created.
+    Unavailable,
+    Corrupt(String),
+    Encryption(EncryptionError),
+    Storage(Error),
+}
+
+impl fmt::Display for TimeSeriesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeSeriesError::InvalidName(name) => write!(f, "invalid series name \"{}\"", name),
+            TimeSeriesError::InvalidValue(timestamp) => {
+                write!(f, "value at {} is not a finite number", timestamp)
+            }
+            TimeSeriesError::InvalidStep => write!(f, "step must be positive"),
+            TimeSeriesError::Unavailable => write!(f, "time series are not available"),
+            TimeSeriesError::Corrupt(e) => write!(f, "corrupt entry: {}", e),
+            TimeSeriesError::Encryption(e) => write!(f, "{}", e),
+            TimeSeriesError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TimeSeriesError {}
+
+impl From<Error> for TimeSeriesError {
+    fn from(e: Error) -> Self {
+        TimeSeriesError::Storage(e)
+    }
+}
+
+impl From<EncryptionError> for TimeSeriesError {
+    fn from(e: EncryptionError) -> Self {
+        TimeSeriesError::Encryption(e)
+    }
+}
+
+/// Time series in their own column family. Raw points older than the raw
+/// retention are downsampled into buckets of `resolution_ms`, and buckets
+/// older than the bucket retention are dropped, so a series stays bounded.
+/// Appends and downsampling run under one lock so no raw point lands behind
+/// a horizon that has moved past it.
+#[derive(Debug)]
+pub struct TimeSeries {
+    resolution_ms: u64,
+    raw_retention_ms: Option<u64>,
+    bucket_retention_ms: Option<u64>,
+    lock: Mutex<()>,
+}
+
+impl Default for TimeSeries {
+    fn default() -> Self {
+        TimeSeries::new(DEFAULT_RESOLUTION_MS, None, None)
+    }
+}
+
+impl TimeSeries {
+    pub fn new(
+        resolution_ms: u64,
+        raw_retention_ms: Option<u64>,
+        bucket_retention_ms: Option<u64>,
+    ) -> Self {
+        TimeSeries {
+            resolution_ms: resolution_ms.max(1),
+            raw_retention_ms,
+            bucket_retention_ms,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Whether raw points are ever downsampled.
+    pub fn is_downsampling(&self) -> bool {
+        self.raw_retention_ms.is_some()
+    }
+
+    /// Creates the time series column family if the DB does not have it yet.
+    pub fn prepare(&self, db: &mut DB) -> Result<(), Error> {
+        if db.cf_handle(TIMESERIES_COLUMN_FAMILY).is_none() {
+            db.create_cf(TIMESERIES_COLUMN_FAMILY, &Options::default())?;
+        }
+        Ok(())
+    }
+
+    /// Appends `points` to a series, replacing points at the same
+    /// timestamps, so a retried write stores nothing twice. Points behind
+    /// the horizon of the series are read as part of their bucket.
+    pub fn append(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        series: &str,
+        points: &[Point],
+    ) -> Result<Appended, TimeSeriesError> {
+        check_name(series)?;
+        if let Some(point) = points.iter().find(|point| !point.value.is_finite()) {
+            return Err(TimeSeriesError::InvalidValue(point.timestamp));
+        }
+        let column_family = column_family(db)?;
+        let _series = self.lock.lock().unwrap();
+        let _writes = encryption.hold_writes();
+
+        let mut batch = WriteBatch::default();
+        let horizon_key = horizon_key(series);
+        let horizon = match db.get_cf(column_family, &horizon_key)? {
+            Some(horizon) => decode_timestamp(&horizon)?,
+            None => {
+                batch.put_cf(column_family, &horizon_key, 0u64.to_be_bytes());
+                0
+            }
+        };
+        let mut appended = Appended::default();
+        for point in points {
+            let key = entry_key(POINT, series, point.timestamp);
+            let sealed = encryption.seal(&key, &point.value.to_be_bytes());
+            batch.put_cf(column_family, &key, sealed);
+            match point.timestamp < horizon {
+                true => appended.downsampled += 1,
+                false => appended.points += 1,
+            }
+        }
+        db.write(batch)?;
+        Ok(appended)
+    }
+
+    /// Points of a series as `selection` asks for them, oldest first.
+    pub fn range(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        series: &str,
+        selection: Selection,
+    ) -> Result<Vec<Point>, TimeSeriesError> {
+        let Selection {
+            from,
+            to,
+            step,
+            aggregation,
+            limit,
+        } = selection;
+        check_name(series)?;
+        if step == Some(0) {
+            return Err(TimeSeriesError::InvalidStep);
+        }
+        let column_family = column_family(db)?;
+        let horizon = match db.get_cf(column_family, horizon_key(series))? {
+            Some(horizon) => decode_timestamp(&horizon)?,
+            None => return Ok(Vec::new()),
+        };
+
+        // Entries arrive in time order, so the points reported only ever
+        // grow at the end and the scan stops once `limit` are complete.
+        let early = match from < horizon {
+            true => from..=to.min(horizon - 1),
+            false => RangeInclusive::new(1, 0),
+        };
+        let buckets =
+            scan(db, column_family, encryption, BUCKET, series, early.clone()).map(|entry| {
+                let (timestamp, stored) = entry?;
+                Ok((timestamp, Summary::decode(&stored)?))
+            });
+        let late = scan(db, column_family, encryption, POINT, series, early).map(|entry| {
+            let (timestamp, stored) = entry?;
+            let bucket = timestamp - timestamp % self.resolution_ms;
+            Ok((bucket, Summary::of(decode_value(&stored)?)))
+        });
+        let raw = scan(
+            db,
+            column_family,
+            encryption,
+            POINT,
+            series,
+            from.max(horizon)..=to,
+        )
+        .map(|entry| {
+            let (timestamp, stored) = entry?;
+            Ok((timestamp, Summary::of(decode_value(&stored)?)))
+        });
+
+        let mut points: Vec<(u64, Summary)> = Vec::new();
+        for entry in in_order(buckets, late).chain(raw) {
+            let (timestamp, summary) = entry?;
+            let at = match step {
+                None => timestamp,
+                Some(step) => timestamp - timestamp % step,
+            };
+            let reported = points.len();
+            match points.last_mut() {
+                Some((last, so_far)) if *last == at => so_far.merge(&summary),
+                _ if reported == limit => break,
+                _ => points.push((at, summary)),
+            }
+        }
+        Ok(points
+            .into_iter()
+            .map(|(timestamp, summary)| Point {
+                timestamp,
+                value: summary.value(aggregation),
+            })
+            .collect())
+    }
+
+    /// Downsamples the raw points of every series that have outlived the
+    /// raw retention and drops buckets that have outlived theirs. Returns
+    /// how many raw points were downsampled.
+    pub fn downsample(
+        &self,
+        db: &DB,
+        encryption: &Encryption,
+        now: u64,
+    ) -> Result<u64, TimeSeriesError> {
+        let Some(raw_retention_ms) = self.raw_retention_ms else {
+            return Ok(0);
+        };
+        let column_family = column_family(db)?;
+        let _series = self.lock.lock().unwrap();
+        let _writes = encryption.hold_writes();
+
+        let cutoff = now.saturating_sub(raw_retention_ms);
+        let cutoff = cutoff - cutoff % self.resolution_ms;
+        let mut downsampled = 0;
+        for (series, horizon) in horizons(db, column_family)? {
+            let mut batch = WriteBatch::default();
+            if cutoff > horizon {
+                let mut buckets = BTreeMap::new();
+                let expired = horizon..=cutoff - 1;
+                for entry in scan(db, column_family, encryption, POINT, &series, expired) {
+                    let (timestamp, stored) = entry?;
+                    let bucket = timestamp - timestamp % self.resolution_ms;
+                    add(&mut buckets, bucket, Summary::of(decode_value(&stored)?));
+                    batch.delete_cf(column_family, entry_key(POINT, &series, timestamp));
+                    downsampled += 1;
+                }
+                merge_buckets(db, column_family, encryption, &mut batch, &series, buckets)?;
+                batch.put_cf(column_family, horizon_key(&series), cutoff.to_be_bytes());
+            }
+            if let Some(bucket_retention_ms) = self.bucket_retention_ms {
+                let expired = now.saturating_sub(bucket_retention_ms);
+                batch.delete_range_cf(
+                    column_family,
+                    entry_key(BUCKET, &series, 0),
+                    entry_key(BUCKET, &series, expired),
+                );
+                // Late points go with the buckets they are read as part of.
+                batch.delete_range_cf(
+                    column_family,
+                    entry_key(POINT, &series, 0),
+                    entry_key(POINT, &series, expired.min(horizon.max(cutoff))),
+                );
+            }
+            db.write(batch)?;
+        }
+        Ok(downsampled)
+    }
+}
+
+/// Downsamples every `interval` while this server is a primary; followers
+/// receive the result through replication.
+pub async fn downsample_periodically(
+    series: Arc<TimeSeries>,
+    db: Arc<DB>,
+    encryption: Arc<Encryption>,
+    replication: Arc<Replication>,
+    interval: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        if replication.is_follower() {
+            continue;
+        }
+        let (series, db, encryption) = (series.clone(), db.clone(), encryption.clone());
+        let downsample = move || series.downsample(&db, &encryption, record::unix_millis());
+        match tokio::task::spawn_blocking(downsample).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => println!("Error downsampling time series: {}", e),
+            Err(e) => println!("Error downsampling time series: {}", e),
+        }
+    }
+}
+
+impl Summary {
+    fn of(value: f64) -> Self {
+        Summary {
+            count: 1,
+            sum: value,
+            min: value,
+            max: value,
+        }
+    }
+
+    fn merge(&mut self, other: &Summary) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    fn value(&self, aggregation: Aggregation) -> f64 {
+        match aggregation {
+            Aggregation::Avg => self.sum / self.count as f64,
+            Aggregation::Min => self.min,
+            Aggregation::Max => self.max,
+            Aggregation::Sum => self.sum,
+            Aggregation::Count => self.count as f64,
+        }
+    }
+
+    fn encode(&self) -> [u8; SUMMARY_SIZE] {
+        let mut bytes = [0u8; SUMMARY_SIZE];
+        bytes[..8].copy_from_slice(&self.count.to_be_bytes());
+        bytes[8..16].copy_from_slice(&self.sum.to_be_bytes());
+        bytes[16..24].copy_from_slice(&self.min.to_be_bytes());
+        bytes[24..].copy_from_slice(&self.max.to_be_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, TimeSeriesError> {
+        let bytes: &[u8; SUMMARY_SIZE] = bytes
+            .try_into()
+            .map_err(|_| TimeSeriesError::Corrupt("bucket is not a summary".to_string()))?;
+        let field = |at: usize| <[u8; 8]>::try_from(&bytes[at..at + 8]).unwrap();
+        Ok(Summary {
+            count: u64::from_be_bytes(field(0)),
+            sum: f64::from_be_bytes(field(8)),
+            min: f64::from_be_bytes(field(16)),
+            max: f64::from_be_bytes(field(24)),
+        })
+    }
+}
+
+fn add(buckets: &mut BTreeMap<u64, Summary>, timestamp: u64, summary: Summary) {
+    buckets
+        .entry(timestamp)
+        .and_modify(|bucket| bucket.merge(&summary))
+        .or_insert(summary);
+}
+
+/// Entries of two streams, each in time order, in time order.
+fn in_order<T>(
+    first: impl Iterator<Item = Result<(u64, T), TimeSeriesError>>,
+    second: impl Iterator<Item = Result<(u64, T), TimeSeriesError>>,
+) -> impl Iterator<Item = Result<(u64, T), TimeSeriesError>> {
+    let (mut first, mut second) = (first.peekable(), second.peekable());
+    std::iter::from_fn(move || {
+        let take_first = match (first.peek(), second.peek()) {
+            (Some(Ok((a, _))), Some(Ok((b, _)))) => a <= b,
+            (Some(_), _) => true,
+            (None, _) => false,
+        };
+        match take_first {
+            true => first.next(),
+            false => second.next(),
+        }
+    })
+}
+
+/// Adds `buckets` to the ones a series already has.
+fn merge_buckets(
+    db: &DB,
+    column_family: &ColumnFamily,
+    encryption: &Encryption,
+    batch: &mut WriteBatch,
+    series: &str,
+    buckets: BTreeMap<u64, Summary>,
+) -> Result<(), TimeSeriesError> {
+    for (timestamp, mut summary) in buckets {
+        let key = entry_key(BUCKET, series, timestamp);
+        if let Some(stored) = db.get_cf(column_family, &key)? {
+            summary.merge(&Summary::decode(&encryption.open(&key, &stored)?)?);
+        }
+        batch.put_cf(
+            column_family,
+            &key,
+            encryption.seal(&key, &summary.encode()),
+        );
+    }
+    Ok(())
+}
+
+fn column_family(db: &DB) -> Result<&ColumnFamily, TimeSeriesError> {
+    db.cf_handle(TIMESERIES_COLUMN_FAMILY)
+        .ok_or(TimeSeriesError::Unavailable)
+}
+
+fn check_name(series: &str) -> Result<(), TimeSeriesError> {
+    if series.is_empty() || series.contains('\0') {
+        return Err(TimeSeriesError::InvalidName(series.to_string()));
+    }
+    Ok(())
+}
+
+/// Entries of one kind of a series within `range`, oldest first, with
+/// their timestamps and decrypted values, read as they are consumed.
+fn scan<'a>(
+    db: &'a DB,
+    column_family: &'a ColumnFamily,
+    encryption: &'a Encryption,
+    kind: u8,
+    series: &str,
+    range: RangeInclusive<u64>,
+) -> impl Iterator<Item = Result<(u64, Vec<u8>), TimeSeriesError>> + 'a {
+    let prefix = series_prefix(kind, series);
+    let start = entry_key(kind, series, *range.start());
+    let (end, empty) = (*range.end(), range.is_empty());
+    let entries = db.iterator_cf(
+        column_family,
+        IteratorMode::From(&start, Direction::Forward),
+    );
+    entries.take_while(move |_| !empty).map_while(move |item| {
+        let (key, stored) = match item {
+            Ok(item) => item,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let timestamp = match decode_timestamp(key.strip_prefix(prefix.as_slice())?) {
+            Ok(timestamp) => timestamp,
+            Err(e) => return Some(Err(e)),
+        };
+        if timestamp > end {
+            return None;
+        }
+        Some(
+            encryption
+                .open(&key, &stored)
+                .map(|value| (timestamp, value))
+                .map_err(TimeSeriesError::from),
+        )
+    })
+}
+
+/// Every series with its horizon.
+fn horizons(db: &DB, column_family: &ColumnFamily) -> Result<Vec<(String, u64)>, TimeSeriesError> {
+    let prefix = [HORIZON, 0];
+    let mut horizons = Vec::new();
+    for item in db.iterator_cf(
+        column_family,
+        IteratorMode::From(&prefix, Direction::Forward),
+    ) {
+        let (key, horizon) = item?;
+        let Some(series) = key.strip_prefix(prefix.as_slice()) else {
+            break;
+        };
+        horizons.push((
+            String::from_utf8_lossy(series).into_owned(),
+            decode_timestamp(&horizon)?,
+        ));
+    }
+    Ok(horizons)
+}
+
+/// Prefixes of the points and buckets of every series whose name starts
+/// with `prefix`.
+pub fn entries_under(prefix: &[u8]) -> Vec<Vec<u8>> {
+    [POINT, BUCKET]
+        .iter()
+        .map(|kind| [&[*kind, 0], prefix].concat())
+        .collect()
+}
+
+fn series_prefix(kind: u8, series: &str) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(series.len() + 3 + TIMESTAMP_SIZE);
+    prefix.push(kind);
+    prefix.push(0);
+    prefix.extend_from_slice(series.as_bytes());
+    prefix.push(0);
+    prefix
+}
+
+fn entry_key(kind: u8, series: &str, timestamp: u64) -> Vec<u8> {
+    let mut key = series_prefix(kind, series);
+    key.extend_from_slice(&timestamp.to_be_bytes());
+    key
+}
+
+fn horizon_key(series: &str) -> Vec<u8> {
+    let mut key = vec![HORIZON, 0];
+    key.extend_from_slice(series.as_bytes());
+    key
+}
+
+fn decode_timestamp(bytes: &[u8]) -> Result<u64, TimeSeriesError> {
+    let bytes: [u8; TIMESTAMP_SIZE] = bytes
+        .try_into()
+        .map_err(|_| TimeSeriesError::Corrupt("timestamp is not a u64".to_string()))?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn decode_value(bytes: &[u8]) -> Result<f64, TimeSeriesError> {
+    let bytes: [u8; 8] = bytes
+        .try_into()
+        .map_err(|_| TimeSeriesError::Corrupt("point is not an f64".to_string()))?;
+    Ok(f64::from_be_bytes(bytes))
+}
@@ -0,0 +1,68 @@
+use crate::storage::{
+    blob,
+    encryption::Encryption,
+    queue::{self, QUEUE_COLUMN_FAMILY},
+    structures,
+    timeseries::{self, TIMESERIES_COLUMN_FAMILY},
+    RESERVED_KEY_PREFIX,
+};
+use rocksdb::{ColumnFamily, Direction, Error, IteratorMode, DB};
+
+/// Bytes stored, keys included, for everything named by keys starting with
+/// `prefix`: values, chunked ones with their chunks, uploads in progress,
+/// the entries of hashes, sets and sorted sets, and the messages and points
+/// of the queues and time series whose names start with it. Read from the
+/// live data, memtables included. Replaced values kept in history are not
+/// counted.
+pub fn namespace_size(db: &DB, encryption: &Encryption, prefix: &[u8]) -> Result<u64, Error> {
+    let mut size = 0;
+    for item in db.iterator(IteratorMode::From(prefix, Direction::Forward)) {
+        let (key, stored) = item?;
+        if !key.starts_with(prefix) {
+            break;
+        }
+        // Only an empty prefix reaches internal entries, counted below.
+        if key.first() == Some(&(RESERVED_KEY_PREFIX as u8)) {
+            continue;
+        }
+        size += (key.len() + stored.len()) as u64;
+        if let Some(manifest) = blob::manifest_of(encryption, &key, &stored) {
+            size += manifest.size;
+        }
+    }
+    size += blob::uploading_under(db, prefix)?;
+    for entries in structures::entries_under(prefix) {
+        size += prefix_size(db, None, &entries)?;
+    }
+    for (name, prefixes) in [
+        (QUEUE_COLUMN_FAMILY, queue::entries_under(prefix)),
+        (TIMESERIES_COLUMN_FAMILY, timeseries::entries_under(prefix)),
+    ] {
+        let Some(column_family) = db.cf_handle(name) else {
+            continue;
+        };
+        for entries in prefixes {
+            size += prefix_size(db, Some(column_family), &entries)?;
+        }
+    }
+    Ok(size)
+}
+
+/// Bytes of the entries starting with `prefix` in `column_family`, the
+/// default one when `None`.
+fn prefix_size(db: &DB, column_family: Option<&ColumnFamily>, prefix: &[u8]) -> Result<u64, Error> {
+    let mode = IteratorMode::From(prefix, Direction::Forward);
+    let entries = match column_family {
+        Some(column_family) => db.iterator_cf(column_family, mode),
+        None => db.iterator(mode),
+    };
+    let mut size = 0;
+    for item in entries {
+        let (key, stored) = item?;
+        if !key.starts_with(prefix) {
+            break;
+        }
+        size += (key.len() + stored.len()) as u64;
+    }
+    Ok(size)
+}
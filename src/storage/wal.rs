@@ -0,0 +1,365 @@
+use crate::storage::RESERVED_KEY_PREFIX;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use rocksdb::{AsColumnFamilyRef, Error, ErrorKind, WriteBatch, DB};
+use serde::Serialize;
+use std::fmt;
+
+// Record tags from RocksDB's `db/dbformat.h`.
+const TYPE_DELETION: u8 = 0x0;
+const TYPE_VALUE: u8 = 0x1;
+const TYPE_MERGE: u8 = 0x2;
+const TYPE_LOG_DATA: u8 = 0x3;
+const TYPE_CF_DELETION: u8 = 0x4;
+const TYPE_CF_VALUE: u8 = 0x5;
+const TYPE_CF_MERGE: u8 = 0x6;
+const TYPE_SINGLE_DELETION: u8 = 0x7;
+const TYPE_CF_SINGLE_DELETION: u8 = 0x8;
+const TYPE_BEGIN_PREPARE_XID: u8 = 0x9;
+const TYPE_END_PREPARE_XID: u8 = 0xA;
+const TYPE_COMMIT_XID: u8 = 0xB;
+const TYPE_ROLLBACK_XID: u8 = 0xC;
+const TYPE_NOOP: u8 = 0xD;
+const TYPE_CF_RANGE_DELETION: u8 = 0xE;
+const TYPE_RANGE_DELETION: u8 = 0xF;
+const TYPE_BEGIN_PERSISTED_PREPARE_XID: u8 = 0x12;
+const TYPE_BEGIN_UNPREPARE_XID: u8 = 0x13;
+const TYPE_COMMIT_XID_AND_TIMESTAMP: u8 = 0x15;
+
+const BATCH_HEADER_SIZE: usize = 12;
+
+/// Maps a stored value, given its key, to the value reported as changed.
+pub type ValueOpener<'a> = dyn Fn(&[u8], &[u8]) -> Vec<u8> + 'a;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOp {
+    Put,
+    Delete,
+    SingleDelete,
+    Merge,
+    DeleteRange,
+}
+
+/// How the key, value and end key of a change are written.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeEncoding {
+    Utf8,
+    /// Used when any of them is not valid UTF-8.
+    Base64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub seq: u64,
+    pub op: ChangeOp,
+    pub column_family_id: u32,
+    /// Set for a reserved key of the default column family or a record of
+    /// another column family, which are not keys clients wrote.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub internal: bool,
+    pub encoding: ChangeEncoding,
+    pub key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_key: Option<String>,
+}
+
+/// Which changes a read returns. By default only those of client keys.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeFilter {
+    /// Also return internal changes.
+    pub internal: bool,
+    /// Column families never returned, by id, such as those holding
+    /// secrets.
+    pub excluded: Vec<u32>,
+}
+
+impl ChangeFilter {
+    fn accepts(&self, change: &Change) -> bool {
+        (self.internal || !change.internal) && !self.excluded.contains(&change.column_family_id)
+    }
+}
+
+/// The id write batches refer to `column_family` by, which RocksDB only
+/// exposes through the batches themselves.
+pub fn column_family_id(column_family: &impl AsColumnFamilyRef) -> u32 {
+    let mut probe = WriteBatch::default();
+    probe.put_cf(column_family, b"", b"");
+    decode_batch(0, &probe)
+        .ok()
+        .and_then(|changes| changes.first().map(|change| change.column_family_id))
+        .unwrap_or_default()
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChangeBatch {
+    pub records: Vec<Change>,
+    /// Last sequence number covered by `records`; pass it back as `since`.
+    pub next: u64,
+}
+
+#[derive(Debug)]
+pub enum ChangesError {
+    /// The WAL no longer holds the batch right after `since`.
+    Purged {
+        since: u64,
+        oldest_available: Option<u64>,
+    },
+    Malformed(String),
+    Storage(Error),
+}
+
+impl fmt::Display for ChangesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangesError::Purged {
+                since,
+                oldest_available: Some(oldest),
+            } => write!(
+                f,
+                "sequence {} has been purged from the WAL, oldest available is {}",
+                since + 1,
+                oldest
+            ),
+            ChangesError::Purged { since, .. } => {
+                write!(f, "sequence {} has been purged from the WAL", since + 1)
+            }
+            ChangesError::Malformed(message) => write!(f, "malformed write batch: {}", message),
+            ChangesError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ChangesError {}
+
+impl From<Error> for ChangesError {
+    fn from(e: Error) -> Self {
+        ChangesError::Storage(e)
+    }
+}
+
+/// Reads up to `limit` changes committed after sequence number `since`.
+///
+/// `since` must be 0 or a `next` value from an earlier call. Whole write
+/// batches are returned, so the result may exceed `limit` by the size of the
+/// last batch.
+/// Only changes of client keys are returned, so `next` may move past
+/// batches that contribute no records.
+pub fn changes_since(db: &DB, since: u64, limit: usize) -> Result<ChangeBatch, ChangesError> {
+    changes_since_with(db, since, limit, &ChangeFilter::default(), &|_, value| {
+        value.to_vec()
+    })
+}
+
+/// Like `changes_since`, but returns the changes `filter` accepts and
+/// passes each put or merge value through `open` along with its key, e.g.
+/// to decrypt it.
+pub fn changes_since_with(
+    db: &DB,
+    since: u64,
+    limit: usize,
+    filter: &ChangeFilter,
+    open: &ValueOpener<'_>,
+) -> Result<ChangeBatch, ChangesError> {
+    let mut batch = ChangeBatch {
+        records: Vec::new(),
+        next: since,
+    };
+    for (seq, write_batch) in batches_since(db, since, limit)? {
+        let changes = decode_batch_with(seq, &write_batch, open)?;
+        batch
+            .records
+            .extend(changes.into_iter().filter(|change| filter.accepts(change)));
+        batch.next = last_sequence(seq, &write_batch);
+    }
+    Ok(batch)
+}
+
+/// Reads the raw write batches committed after sequence number `since`,
+/// stopping once they hold at least `limit` records.
+pub fn batches_since(
+    db: &DB,
+    since: u64,
+    limit: usize,
+) -> Result<Vec<(u64, WriteBatch)>, ChangesError> {
+    let mut batches = Vec::new();
+    if db.latest_sequence_number() <= since {
+        return Ok(batches);
+    }
+
+    let updates = match db.get_updates_since(since) {
+        Ok(updates) => updates,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(ChangesError::Purged {
+                since,
+                oldest_available: None,
+            })
+        }
+        Err(e) => {
+            println!("Error reading WAL since {}: {:}", since, e);
+            return Err(ChangesError::Storage(e));
+        }
+    };
+    let mut records = 0;
+    for update in updates {
+        let (seq, write_batch) = update?;
+        if batches.is_empty() && seq > since + 1 {
+            return Err(ChangesError::Purged {
+                since,
+                oldest_available: Some(seq),
+            });
+        }
+        records += write_batch.len();
+        batches.push((seq, write_batch));
+        if records >= limit {
+            break;
+        }
+    }
+    Ok(batches)
+}
+
+/// Sequence number of the last record in a batch starting at `seq`.
+pub fn last_sequence(seq: u64, batch: &WriteBatch) -> u64 {
+    seq + batch.len().max(1) as u64 - 1
+}
+
+/// Decodes the serialized form of a `WriteBatch` whose first record carries
+/// sequence number `seq`, internal changes included.
+pub fn decode_batch(seq: u64, batch: &WriteBatch) -> Result<Vec<Change>, ChangesError> {
+    decode_batch_with(seq, batch, &|_, value| value.to_vec())
+}
+
+/// Like `decode_batch`, but passes each put or merge value through `open`.
+pub fn decode_batch_with(
+    seq: u64,
+    batch: &WriteBatch,
+    open: &ValueOpener<'_>,
+) -> Result<Vec<Change>, ChangesError> {
+    let data = batch.data();
+    if data.len() < BATCH_HEADER_SIZE {
+        return Err(ChangesError::Malformed(format!(
+            "batch of {} bytes is smaller than its header",
+            data.len()
+        )));
+    }
+
+    let mut reader = Reader {
+        data: &data[BATCH_HEADER_SIZE..],
+    };
+    let mut changes = Vec::new();
+    let mut next_seq = seq;
+    while !reader.is_empty() {
+        let tag = reader.byte()?;
+        let (op, column_family_id) = match tag {
+            TYPE_VALUE => (ChangeOp::Put, 0),
+            TYPE_DELETION => (ChangeOp::Delete, 0),
+            TYPE_SINGLE_DELETION => (ChangeOp::SingleDelete, 0),
+            TYPE_MERGE => (ChangeOp::Merge, 0),
+            TYPE_RANGE_DELETION => (ChangeOp::DeleteRange, 0),
+            TYPE_CF_VALUE => (ChangeOp::Put, reader.varint32()?),
+            TYPE_CF_DELETION => (ChangeOp::Delete, reader.varint32()?),
+            TYPE_CF_SINGLE_DELETION => (ChangeOp::SingleDelete, reader.varint32()?),
+            TYPE_CF_MERGE => (ChangeOp::Merge, reader.varint32()?),
+            TYPE_CF_RANGE_DELETION => (ChangeOp::DeleteRange, reader.varint32()?),
+            TYPE_LOG_DATA | TYPE_END_PREPARE_XID | TYPE_COMMIT_XID | TYPE_ROLLBACK_XID => {
+                reader.slice()?;
+                continue;
+            }
+            TYPE_COMMIT_XID_AND_TIMESTAMP => {
+                reader.slice()?;
+                reader.slice()?;
+                continue;
+            }
+            TYPE_NOOP
+            | TYPE_BEGIN_PREPARE_XID
+            | TYPE_BEGIN_PERSISTED_PREPARE_XID
+            | TYPE_BEGIN_UNPREPARE_XID => continue,
+            other => {
+                return Err(ChangesError::Malformed(format!(
+                    "unsupported record tag {:#x}",
+                    other
+                )))
+            }
+        };
+
+        let key = reader.slice()?;
+        let (value, end_key) = match op {
+            ChangeOp::Put | ChangeOp::Merge => (Some(open(key, reader.slice()?)), None),
+            ChangeOp::DeleteRange => (None, Some(reader.slice()?)),
+            ChangeOp::Delete | ChangeOp::SingleDelete => (None, None),
+        };
+        let internal = column_family_id != 0 || key.first() == Some(&(RESERVED_KEY_PREFIX as u8));
+        let fields = [Some(key), value.as_deref(), end_key];
+        let encoding = if fields
+            .iter()
+            .flatten()
+            .all(|field| std::str::from_utf8(field).is_ok())
+        {
+            ChangeEncoding::Utf8
+        } else {
+            ChangeEncoding::Base64
+        };
+        let encode = |field: &[u8]| match encoding {
+            ChangeEncoding::Utf8 => String::from_utf8_lossy(field).into_owned(),
+            ChangeEncoding::Base64 => STANDARD.encode(field),
+        };
+        changes.push(Change {
+            seq: next_seq,
+            op,
+            column_family_id,
+            internal,
+            encoding,
+            key: encode(key),
+            value: value.as_deref().map(encode),
+            end_key: end_key.map(encode),
+        });
+        next_seq += 1;
+    }
+    Ok(changes)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn byte(&mut self) -> Result<u8, ChangesError> {
+        let (first, rest) = self
+            .data
+            .split_first()
+            .ok_or_else(|| ChangesError::Malformed("unexpected end of batch".to_string()))?;
+        self.data = rest;
+        Ok(*first)
+    }
+
+    fn varint32(&mut self) -> Result<u32, ChangesError> {
+        let mut result: u32 = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            result |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err(ChangesError::Malformed("varint32 too long".to_string()))
+    }
+
+    fn slice(&mut self) -> Result<&'a [u8], ChangesError> {
+        let len = self.varint32()? as usize;
+        if len > self.data.len() {
+            return Err(ChangesError::Malformed(format!(
+                "slice of {} bytes overruns batch",
+                len
+            )));
+        }
+        let (slice, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(slice)
+    }
+}
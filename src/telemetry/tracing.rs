@@ -1,10 +1,10 @@
 use axum::http::HeaderMap;
 use opentelemetry::{
     global::{self, BoxedSpan},
-    trace::Tracer,
+    trace::{TraceContextExt, Tracer},
     Context,
 };
-use opentelemetry_http::HeaderExtractor;
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
 
 pub fn extract_context_from_request(header: &HeaderMap) -> Context {
     global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(header)))
@@ -17,3 +17,18 @@ pub fn current_span(parent_cx: Context, span_name: &str) -> BoxedSpan {
         .with_kind(opentelemetry::trace::SpanKind::Server)
         .start_with_context(&tracer, &parent_cx)
 }
+
+pub fn inject_context_into_request(cx: &Context, header: &mut HeaderMap) {
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(cx, &mut HeaderInjector(header))
+    })
+}
+
+/// Trace id of the span in `cx`, when it belongs to a sampled or remote trace.
+pub fn trace_id(cx: &Context) -> Option<String> {
+    let span = cx.span();
+    let span_context = span.span_context();
+    span_context
+        .is_valid()
+        .then(|| span_context.trace_id().to_string())
+}
@@ -0,0 +1,149 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use h_rocksdb::{
+    api::routes,
+    auth::{
+        acl::{AccessControl, Permission},
+        audit::AuditLog,
+        AuthConfig, Authenticator,
+    },
+    AppState,
+};
+use std::{
+    fs,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tempfile::TempDir;
+
+const AUTH_CONFIG: &str = r#"{
+    "api_keys": [
+        {"principal": "team-a", "key": "key-a"},
+        {"principal": "ops", "key": "key-ops"}
+    ]
+}"#;
+
+const ACL_CONFIG: &str = r#"{
+    "rules": [
+        {"principal": "team-a", "prefix": "team-a/", "permissions": ["read", "write"]},
+        {"principal": "*", "prefix": "public/", "permissions": ["read"]},
+        {"principal": "ops", "permissions": ["admin"]}
+    ]
+}"#;
+
+fn create_test_state(temp_dir: &TempDir) -> AppState {
+    let acl_path = temp_dir.path().join("acl.json");
+    fs::write(&acl_path, ACL_CONFIG).unwrap();
+    let config: AuthConfig = serde_json::from_str(AUTH_CONFIG).unwrap();
+
+    let mut state = common::create_test_state(temp_dir);
+    state.auth = Arc::new(Authenticator::new(config));
+    state.acl = Arc::new(AccessControl::load(&acl_path).unwrap());
+    state.audit = Arc::new(AuditLog::open(&temp_dir.path().join("audit.log")).unwrap());
+    state
+}
+
+async fn send(app: &Router, uri: &str, api_key: &str) -> StatusCode {
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("x-api-key", api_key)
+        .body(Body::from("value"))
+        .unwrap();
+    let (status, _, _) = common::respond(app, request).await;
+    status
+}
+
+fn rewrite(path: &Path, contents: &str) {
+    fs::write(path, contents).unwrap();
+    // Make sure the change is visible even on coarse mtime filesystems.
+    let file = fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(5))
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_rules_limit_principals_to_their_prefix() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let status = send(&app, "/put?key=team-a/key1", "key-a").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let status = send(&app, "/get?key=team-a/key1", "key-a").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let status = send(&app, "/put?key=team-b/key1", "key-a").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let status = send(&app, "/put?key=public/key1", "key-a").await;
+    assert_eq!(
+        status,
+        StatusCode::FORBIDDEN,
+        "The wildcard rule only grants reads"
+    );
+
+    let status = send(&app, "/get?key=public/key1", "key-a").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let status = send(&app, "/admin/promote", "key-a").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let status = send(&app, "/put?key=team-b/key1", "key-ops").await;
+    assert_eq!(status, StatusCode::OK, "Admin implies write");
+}
+
+#[tokio::test]
+async fn test_denials_are_audit_logged() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let status = send(&app, "/put?key=team-b/key1", "key-a").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let log = fs::read_to_string(temp_dir.path().join("audit.log")).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 1);
+    let event: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(event["principal"], "team-a");
+    assert_eq!(event["outcome"], "denied");
+    assert_eq!(event["access"]["permission"], "write");
+    assert_eq!(event["access"]["key"], "team-b/key1");
+}
+
+#[tokio::test]
+async fn test_rules_reload_when_file_changes() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir);
+    let acl = state.acl.clone();
+    let app = routes::router(state);
+
+    let status = send(&app, "/put?key=team-b/key1", "key-a").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let acl_path = temp_dir.path().join("acl.json");
+    rewrite(
+        &acl_path,
+        r#"{"rules": [{"principal": "team-a", "prefix": "team-", "permissions": ["write"]}]}"#,
+    );
+    acl.reload_if_changed();
+    assert_eq!(acl.current().rules[0].permissions, vec![Permission::Write]);
+
+    let status = send(&app, "/put?key=team-b/key1", "key-a").await;
+    assert_eq!(status, StatusCode::OK);
+
+    rewrite(&acl_path, "not json");
+    acl.reload_if_changed();
+    let status = send(&app, "/put?key=team-b/key1", "key-a").await;
+    assert_eq!(
+        status,
+        StatusCode::OK,
+        "A broken file should keep the previous rules"
+    );
+}
@@ -19,9 +19,7 @@ fn create_test_app() -> (Router, TempDir) {
     opts.create_if_missing(true);
     let db = DB::open(&opts, path).expect("Failed to open test database");
 
-    let state = AppState {
-        rocksdb: Arc::new(db),
-    };
+    let state = AppState::new(Arc::new(db));
 
     let app = Router::new()
         .route("/put", post(handlers::put))
@@ -0,0 +1,155 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use h_rocksdb::{
+    api::routes,
+    auth::{
+        audit::{verify_chain, AuditEvent, AuditLog, Outcome, ValueDigest, GENESIS_HASH},
+        AuthConfig, Authenticator,
+    },
+    AppState,
+};
+use std::{fs, sync::Arc};
+use tempfile::TempDir;
+
+fn create_test_state(temp_dir: &TempDir) -> AppState {
+    let config: AuthConfig =
+        serde_json::from_str(r#"{"api_keys": [{"principal": "ops", "key": "key-ops"}]}"#).unwrap();
+    let mut state = common::create_test_state(temp_dir);
+    state.auth = Arc::new(Authenticator::new(config));
+    state.audit = Arc::new(AuditLog::open(&temp_dir.path().join("audit.log")).unwrap());
+    state
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, String) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("x-api-key", "key-ops")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let (status, _, body) = common::respond(app, request).await;
+    (status, String::from_utf8(body).unwrap())
+}
+
+#[tokio::test]
+async fn test_mutations_are_recorded_and_queryable() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let (status, _) = send(&app, "POST", "/put?key=key1", "value1").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "POST", "/get?key=key1", "").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "POST", "/admin/promote", "").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(&app, "GET", "/admin/audit", "").await;
+    assert_eq!(status, StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["chain"]["valid"], true);
+
+    let records = json["records"].as_array().unwrap();
+    assert_eq!(records.len(), 2, "Reads should not be audited");
+    assert_eq!(records[0]["seq"], 1);
+    assert_eq!(records[0]["prev_hash"], GENESIS_HASH);
+    assert_eq!(records[0]["principal"], "ops");
+    assert_eq!(records[0]["route"], "/put");
+    assert_eq!(records[0]["key"], "key1");
+    assert_eq!(
+        records[0]["value_sha256"],
+        ValueDigest::of(b"value1").0.as_str()
+    );
+    assert_eq!(records[0]["outcome"], "succeeded");
+    assert_eq!(records[1]["route"], "/admin/promote");
+    assert_eq!(records[1]["prev_hash"], records[0]["hash"]);
+
+    let (_, body) = send(&app, "GET", "/admin/audit?key_prefix=key", "").await;
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["records"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_tampering_breaks_the_chain() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    send(&app, "POST", "/put?key=key1", "value1").await;
+    send(&app, "POST", "/put?key=key2", "value2").await;
+
+    let path = temp_dir.path().join("audit.log");
+    let log = fs::read_to_string(&path).unwrap();
+    fs::write(&path, log.replacen("\"key1\"", "\"key9\"", 1)).unwrap();
+
+    let (status, body) = send(&app, "GET", "/admin/audit", "").await;
+    assert_eq!(status, StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["chain"]["valid"], false);
+    assert_eq!(json["chain"]["broken_at"], 1);
+}
+
+#[test]
+fn test_rotation_keeps_the_chain() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let path = temp_dir.path().join("audit.log");
+
+    {
+        let log = AuditLog::open_with_rotation(&path, 1).unwrap();
+        for _ in 0..3 {
+            log.record(AuditEvent::new(
+                "POST".to_string(),
+                "/put".to_string(),
+                Outcome::Succeeded,
+            ));
+        }
+    }
+    let rotated = fs::read_dir(temp_dir.path()).unwrap().count();
+    assert_eq!(rotated, 3, "Each record should start a new file");
+
+    let log = AuditLog::open_with_rotation(&path, 1).unwrap();
+    log.record(AuditEvent::new(
+        "POST".to_string(),
+        "/admin/promote".to_string(),
+        Outcome::Succeeded,
+    ));
+
+    let records = log.read_all().unwrap();
+    let seqs: Vec<u64> = records.iter().map(|record| record.seq).collect();
+    assert_eq!(seqs, vec![1, 2, 3, 4]);
+    assert!(verify_chain(&records, Some(GENESIS_HASH)).valid);
+}
+
+#[test]
+fn test_queries_skip_earlier_files_and_notice_changed_ones() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let path = temp_dir.path().join("audit.log");
+    let log = AuditLog::open_with_rotation(&path, 1).unwrap();
+    for key in ["a", "b", "c", "d"] {
+        let mut event = AuditEvent::new("POST".to_string(), "/put".to_string(), Outcome::Succeeded);
+        event.key = Some(key.to_string());
+        log.record(event);
+    }
+    assert!(log.chain_status().unwrap().valid);
+
+    // Files before the one holding seq 3 are not read, so an unreadable
+    // one does not get in the way.
+    let first = temp_dir.path().join(format!("audit.log.{:020}", 2));
+    let original = fs::read_to_string(&first).unwrap();
+    fs::write(&first, "not a record\n").unwrap();
+    let mut seqs = Vec::new();
+    log.scan(2, |record| {
+        seqs.push(record.seq);
+        true
+    })
+    .unwrap();
+    assert_eq!(seqs, vec![3, 4]);
+
+    fs::write(&first, original.replacen("\"a\"", "\"z\"", 1)).unwrap();
+    let status = log.chain_status().unwrap();
+    assert!(!status.valid);
+    assert_eq!(status.broken_at, Some(1));
+}
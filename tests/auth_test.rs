@@ -0,0 +1,146 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    middleware::from_fn_with_state,
+    routing::post,
+    Extension, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use h_rocksdb::{
+    api::{middleware::authenticate, routes},
+    auth::{AuthConfig, Authenticator, Principal},
+    AppState,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tempfile::TempDir;
+use tower::util::ServiceExt;
+
+const AUTH_CONFIG: &str = r#"{
+    "api_keys": [{"principal": "team-a", "key": "key-a"}],
+    "jwt": {"keys": [{"kid": "2024", "secret": "jwt-secret"}], "issuer": "auth.example"}
+}"#;
+
+fn create_test_state() -> (AppState, TempDir) {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let config: AuthConfig = serde_json::from_str(AUTH_CONFIG).unwrap();
+    let mut state = common::create_test_state(&temp_dir);
+    state.auth = Arc::new(Authenticator::new(config));
+    (state, temp_dir)
+}
+
+fn sign_token(secret: &str, sub: &str, exp_offset: i64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT","kid":"2024"}"#);
+    let claims = serde_json::json!({
+        "sub": sub,
+        "iss": "auth.example",
+        "exp": now + exp_offset,
+    });
+    let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+    let signed = format!("{}.{}", header, payload);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(signed.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+    format!("{}.{}", signed, signature)
+}
+
+async fn send(app: &Router, uri: &str, header: Option<(&str, &str)>) -> (StatusCode, String) {
+    let mut request = Request::builder().method("POST").uri(uri);
+    if let Some((name, value)) = header {
+        request = request.header(name, value);
+    }
+    let (status, _, body) = common::respond(app, request.body(Body::empty()).unwrap()).await;
+    (status, String::from_utf8(body).unwrap())
+}
+
+#[tokio::test]
+async fn test_missing_credentials_return_structured_401() {
+    let (state, _temp_dir) = create_test_state();
+    let app = routes::router(state);
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/get?key=key1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["error"], "missing_credentials");
+
+    let request = Request::builder()
+        .uri("/ready")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(
+        response.status(),
+        StatusCode::OK,
+        "Readiness probes should not need credentials"
+    );
+}
+
+#[tokio::test]
+async fn test_api_key_authentication() {
+    let (state, _temp_dir) = create_test_state();
+    let app = routes::router(state);
+
+    let (status, _) = send(&app, "/get?key=key1", Some(("x-api-key", "key-a"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(
+        &app,
+        "/get?key=key1",
+        Some(("authorization", "Bearer key-a")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = send(&app, "/get?key=key1", Some(("x-api-key", "key-b"))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(body.contains("invalid_api_key"));
+}
+
+#[tokio::test]
+async fn test_jwt_principal_reaches_handlers() {
+    let (state, _temp_dir) = create_test_state();
+    let app = Router::new()
+        .route(
+            "/whoami",
+            post(|Extension(principal): Extension<Principal>| async move { principal.name }),
+        )
+        .layer(from_fn_with_state(state.clone(), authenticate))
+        .with_state(state);
+
+    let token = sign_token("jwt-secret", "alice", 300);
+    let bearer = format!("Bearer {}", token);
+    let (status, body) = send(&app, "/whoami", Some(("authorization", &bearer))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "alice");
+
+    let token = sign_token("jwt-secret", "alice", -3600);
+    let bearer = format!("Bearer {}", token);
+    let (status, body) = send(&app, "/whoami", Some(("authorization", &bearer))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(body.contains("token_expired"));
+
+    let token = sign_token("wrong-secret", "mallory", 300);
+    let bearer = format!("Bearer {}", token);
+    let (status, body) = send(&app, "/whoami", Some(("authorization", &bearer))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(body.contains("invalid_token"));
+}
@@ -0,0 +1,234 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Request, StatusCode},
+    Router,
+};
+use common::create_test_state;
+use h_rocksdb::{
+    api::routes,
+    limits::{Limits, LimitsConfig},
+    storage::{
+        blob::{self, BlobWriter, CHUNK_SIZE},
+        encryption::Encryption,
+        history::History,
+        index::Indexes,
+    },
+};
+use rocksdb::{IteratorMode, DB};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn blob_of(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+async fn upload(app: &Router, key: &str, value: &[u8]) -> serde_json::Value {
+    let frames: Vec<Result<Vec<u8>, std::io::Error>> = value
+        .chunks(300_000)
+        .map(|frame| Ok(frame.to_vec()))
+        .collect();
+    let request = Request::builder()
+        .method("PUT")
+        .uri(format!("/blob?key={}", key))
+        .body(Body::from_stream(futures_util::stream::iter(frames)))
+        .unwrap();
+    let (status, _, body) = common::respond(app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn download(
+    app: &Router,
+    uri: &str,
+    range: Option<&str>,
+) -> (StatusCode, HeaderMap, Vec<u8>) {
+    let mut request = Request::builder().method("GET").uri(uri);
+    if let Some(range) = range {
+        request = request.header(header::RANGE, range);
+    }
+    common::respond(app, request.body(Body::empty()).unwrap()).await
+}
+
+fn key_count(db: &DB) -> usize {
+    db.iterator(IteratorMode::Start).count()
+}
+
+#[tokio::test]
+async fn test_streamed_upload_and_ranged_download() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+    let value = blob_of(CHUNK_SIZE * 2 + CHUNK_SIZE / 2);
+
+    let uploaded = upload(&app, "big", &value).await;
+    assert_eq!(uploaded["size"], value.len());
+    assert_eq!(uploaded["chunks"], 3);
+
+    let (status, headers, body) = download(&app, "/blob?key=big", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::ACCEPT_RANGES], "bytes");
+    assert!(body == value);
+
+    let (start, end) = (CHUNK_SIZE - 6, CHUNK_SIZE + 9);
+    let range = format!("bytes={}-{}", start, end);
+    let (status, headers, body) = download(&app, "/blob?key=big", Some(&range)).await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        headers[header::CONTENT_RANGE],
+        format!("bytes {}-{}/{}", start, end, value.len()).as_str()
+    );
+    assert_eq!(body, &value[start..=end], "Ranges span chunk boundaries");
+
+    let (status, _, body) = download(&app, "/blob?key=big", Some("bytes=-10")).await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body, &value[value.len() - 10..]);
+
+    let (status, headers, _) = download(&app, "/blob?key=big", Some("bytes=99999999-")).await;
+    assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        headers[header::CONTENT_RANGE],
+        format!("bytes */{}", value.len()).as_str()
+    );
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/get?key=big")
+        .body(Body::empty())
+        .unwrap();
+    let (status, _, _) = common::respond(&app, request).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_replacing_a_blob_drops_its_chunks() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir);
+    let db = state.rocksdb.clone();
+    let app = routes::router(state);
+
+    upload(&app, "big", &blob_of(CHUNK_SIZE * 2)).await;
+    assert_eq!(key_count(&db), 3, "Manifest plus two chunks");
+
+    upload(&app, "big", &blob_of(10)).await;
+    assert_eq!(key_count(&db), 2, "Manifest plus one chunk");
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/put?key=big")
+        .body(Body::from("small"))
+        .unwrap();
+    let (status, _, _) = common::respond(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(key_count(&db), 1);
+}
+
+#[tokio::test]
+async fn test_plain_values_are_served_as_bytes() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir);
+    state.rocksdb.put(b"key1", b"0123456789").unwrap();
+    let app = routes::router(state);
+
+    let (status, _, body) = download(&app, "/blob?key=key1", Some("bytes=2-4")).await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body, b"234");
+
+    let (status, _, _) = download(&app, "/blob?key=missing", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_chunked_uploads_are_held_to_quota_and_cleaned_up() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mut state = create_test_state(&temp_dir);
+    let config: LimitsConfig = serde_json::from_str(&format!(
+        r#"{{"quotas": [{{"prefix": "big", "max_bytes": {}}}]}}"#,
+        CHUNK_SIZE * 2
+    ))
+    .unwrap();
+    state.limits = Arc::new(Limits::new(config));
+    let db = state.rocksdb.clone();
+    let app = routes::router(state);
+
+    // No Content-Length: the quota is checked frame by frame.
+    let frames: Vec<Result<Vec<u8>, std::io::Error>> = blob_of(CHUNK_SIZE * 3)
+        .chunks(300_000)
+        .map(|frame| Ok(frame.to_vec()))
+        .collect();
+    let request = Request::builder()
+        .method("PUT")
+        .uri("/blob?key=big")
+        .body(Body::from_stream(futures_util::stream::iter(frames)))
+        .unwrap();
+    let (status, _, _) = common::respond(&app, request).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(key_count(&db), 0, "Refused uploads leave no chunks");
+
+    let frames: Vec<Result<Vec<u8>, std::io::Error>> = vec![
+        Ok(blob_of(CHUNK_SIZE + 1)),
+        Err(std::io::Error::other("client went away")),
+    ];
+    let request = Request::builder()
+        .method("PUT")
+        .uri("/blob?key=small")
+        .body(Body::from_stream(futures_util::stream::iter(frames)))
+        .unwrap();
+    let (status, _, _) = common::respond(&app, request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(key_count(&db), 0, "Aborted uploads leave no chunks");
+}
+
+#[tokio::test]
+async fn test_measured_quota_usage_counts_blob_chunks() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mut state = create_test_state(&temp_dir);
+    let config: LimitsConfig = serde_json::from_str(&format!(
+        r#"{{"quotas": [{{"prefix": "big", "max_bytes": {}}}]}}"#,
+        CHUNK_SIZE * 3
+    ))
+    .unwrap();
+    state.limits = Arc::new(Limits::new(config));
+    let (limits, db, encryption) = (
+        state.limits.clone(),
+        state.rocksdb.clone(),
+        state.encryption.clone(),
+    );
+    let (history, indexes) = (History::disabled(), Indexes::disabled());
+    let app = routes::router(state);
+
+    upload(&app, "big/one", &blob_of(CHUNK_SIZE * 2)).await;
+    // Unfinished uploads count as well.
+    let mut writer = BlobWriter::new(&db, &encryption, &history, &indexes, b"big/two");
+    writer.write(&blob_of(CHUNK_SIZE)).unwrap();
+    limits.measure_all(&db, &encryption);
+
+    let request = Request::builder()
+        .method("PUT")
+        .uri("/blob?key=big/three")
+        .body(Body::from(blob_of(1000)))
+        .unwrap();
+    let (status, _, _) = common::respond(&app, request).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    drop(writer);
+}
+
+#[tokio::test]
+async fn test_interrupted_uploads_are_swept() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir);
+    let (encryption, history, indexes) = (
+        Encryption::disabled(),
+        History::disabled(),
+        Indexes::disabled(),
+    );
+    let mut writer = BlobWriter::new(&state.rocksdb, &encryption, &history, &indexes, b"big");
+    writer.write(&blob_of(CHUNK_SIZE * 2)).unwrap();
+    // As if the server died mid-upload.
+    std::mem::forget(writer);
+    assert_eq!(key_count(&state.rocksdb), 3);
+
+    assert_eq!(blob::sweep_uploads(&state.rocksdb).unwrap(), 1);
+    assert_eq!(key_count(&state.rocksdb), 0);
+    assert_eq!(blob::sweep_uploads(&state.rocksdb).unwrap(), 0);
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Request, StatusCode},
+    Router,
+};
+use h_rocksdb::{
+    api::{
+        caching::{CacheConfig, CachePolicy},
+        routes,
+    },
+    AppState,
+};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn create_test_state(temp_dir: &TempDir) -> AppState {
+    let config: CacheConfig = serde_json::from_str(
+        r#"{
+            "rules": [
+                {"prefix": "public/", "cache_control": "public, max-age=60"},
+                {"prefix": "public/live/", "cache_control": "no-store"}
+            ],
+            "default": "no-cache"
+        }"#,
+    )
+    .unwrap();
+    let mut state = common::create_test_state(temp_dir);
+    state.cache = Arc::new(CachePolicy::new(config));
+    state
+}
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    headers: &[(header::HeaderName, &str)],
+    body: &str,
+) -> (StatusCode, HeaderMap, String) {
+    let mut request = Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    let request = request.body(Body::from(body.to_string())).unwrap();
+    let (status, headers, body) = common::respond(app, request).await;
+    (status, headers, String::from_utf8(body).unwrap())
+}
+
+#[tokio::test]
+async fn test_conditional_get_returns_304() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+    send(&app, "PUT", "/v1/kv/public/key1", &[], "value1").await;
+
+    let (status, headers, _) = send(&app, "GET", "/v1/kv/public/key1", &[], "").await;
+    assert_eq!(status, StatusCode::OK);
+    let etag = headers[header::ETAG].to_str().unwrap().to_string();
+    let last_modified = headers[header::LAST_MODIFIED].to_str().unwrap().to_string();
+    assert!(etag.starts_with("W/\""));
+    assert_eq!(headers[header::CACHE_CONTROL], "public, max-age=60");
+
+    let (status, headers, body) = send(
+        &app,
+        "GET",
+        "/v1/kv/public/key1",
+        &[(header::IF_NONE_MATCH, &etag)],
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert_eq!(headers[header::ETAG], etag.as_str());
+    assert!(body.is_empty());
+
+    let (status, _, _) = send(
+        &app,
+        "GET",
+        "/v1/kv/public/key1",
+        &[(header::IF_MODIFIED_SINCE, &last_modified)],
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+    let (status, _, _) = send(
+        &app,
+        "GET",
+        "/v1/kv/public/key1",
+        &[(header::IF_MODIFIED_SINCE, "Thu, 01 Jan 2015 00:00:00 GMT")],
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    send(&app, "PUT", "/v1/kv/public/key1", &[], "value2").await;
+    let (status, headers, _) = send(
+        &app,
+        "GET",
+        "/v1/kv/public/key1",
+        &[(header::IF_NONE_MATCH, &etag)],
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "A new value has a new ETag");
+    assert_ne!(headers[header::ETAG], etag.as_str());
+}
+
+#[tokio::test]
+async fn test_cache_control_by_namespace() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir);
+    state
+        .rocksdb
+        .put(b"legacy", b"\"written directly\"")
+        .unwrap();
+    let app = routes::router(state);
+    send(&app, "PUT", "/v1/kv/public/live/key1", &[], "value1").await;
+
+    let (_, headers, _) = send(&app, "GET", "/v1/kv/public/live/key1", &[], "").await;
+    assert_eq!(
+        headers[header::CACHE_CONTROL],
+        "no-store",
+        "Longest prefix wins"
+    );
+
+    let (status, headers, _) = send(&app, "POST", "/get?key=legacy", &[], "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CACHE_CONTROL], "no-cache");
+    assert!(headers.contains_key(header::ETAG));
+    assert!(
+        !headers.contains_key(header::LAST_MODIFIED),
+        "Values written without a header have no modification time"
+    );
+}
+
+#[tokio::test]
+async fn test_blob_and_head_revalidate() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+    send(&app, "PUT", "/blob?key=public/blob1", &[], "blob contents").await;
+
+    let (status, headers, body) = send(&app, "GET", "/blob?key=public/blob1", &[], "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "blob contents");
+    let etag = headers[header::ETAG].to_str().unwrap().to_string();
+
+    let (status, _, _) = send(
+        &app,
+        "GET",
+        "/blob?key=public/blob1",
+        &[(header::IF_NONE_MATCH, &etag)],
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+    let (status, headers, _) = send(
+        &app,
+        "HEAD",
+        "/v1/kv/public/blob1",
+        &[(header::IF_NONE_MATCH, "\"other\", *")],
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert_eq!(headers[header::ETAG], etag.as_str());
+}
@@ -0,0 +1,139 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    routing::get,
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use h_rocksdb::{
+    api::changes,
+    storage::wal::{changes_since, changes_since_with, ChangeEncoding, ChangeFilter, ChangeOp},
+    AppState,
+};
+use rocksdb::{Options, WriteBatch, DB};
+use std::sync::Arc;
+use tempfile::TempDir;
+use tower::util::ServiceExt;
+
+fn create_test_db() -> (DB, TempDir) {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let path = temp_dir.path();
+
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    let db = DB::open(&opts, path).expect("Failed to open test database");
+
+    (db, temp_dir)
+}
+
+#[test]
+fn test_changes_since_decodes_batches() {
+    let (db, _temp_dir) = create_test_db();
+    db.put(b"key1", b"value1").unwrap();
+
+    let mut batch = WriteBatch::default();
+    batch.put(b"key2", b"value2");
+    batch.delete(b"key1");
+    db.write(batch).unwrap();
+
+    let result = changes_since(&db, 0, 100).expect("Reading changes should succeed");
+    assert_eq!(result.records.len(), 3);
+    assert_eq!(result.records[0].op, ChangeOp::Put);
+    assert_eq!(result.records[0].key, "key1");
+    assert_eq!(result.records[0].value.as_deref(), Some("value1"));
+    assert_eq!(result.records[1].key, "key2");
+    assert_eq!(result.records[2].op, ChangeOp::Delete);
+    assert_eq!(result.records[2].value, None);
+
+    let seqs: Vec<u64> = result.records.iter().map(|r| r.seq).collect();
+    assert_eq!(seqs, vec![1, 2, 3]);
+    assert_eq!(result.next, 3);
+}
+
+#[test]
+fn test_changes_since_resumes_from_next() {
+    let (db, _temp_dir) = create_test_db();
+    db.put(b"key1", b"value1").unwrap();
+
+    let first = changes_since(&db, 0, 100).unwrap();
+    assert_eq!(first.records.len(), 1);
+
+    let empty = changes_since(&db, first.next, 100).unwrap();
+    assert!(empty.records.is_empty(), "No changes should be pending");
+    assert_eq!(empty.next, first.next);
+
+    db.put(b"key2", b"value2").unwrap();
+    let second = changes_since(&db, first.next, 100).unwrap();
+    assert_eq!(second.records.len(), 1);
+    assert_eq!(second.records[0].key, "key2");
+}
+
+#[test]
+fn test_binary_changes_are_base64() {
+    let (db, _temp_dir) = create_test_db();
+    db.put(b"text", b"value").unwrap();
+    db.put(b"bin\xff", b"\x00\x9f").unwrap();
+
+    let result = changes_since(&db, 0, 100).unwrap();
+    assert_eq!(result.records[0].encoding, ChangeEncoding::Utf8);
+    assert_eq!(result.records[0].key, "text");
+    assert_eq!(result.records[1].encoding, ChangeEncoding::Base64);
+    assert_eq!(result.records[1].key, STANDARD.encode(b"bin\xff"));
+    assert_eq!(
+        result.records[1].value.as_deref(),
+        Some(STANDARD.encode(b"\x00\x9f").as_str())
+    );
+}
+
+#[test]
+fn test_internal_changes_are_left_out_unless_asked_for() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    let db = DB::open_cf(&opts, temp_dir.path(), ["extra"]).unwrap();
+    let mut batch = WriteBatch::default();
+    batch.put(b"key1", b"value1");
+    batch.put(b"\0hist\0key1", b"old");
+    batch.put_cf(db.cf_handle("extra").unwrap(), b"key2", b"value2");
+    db.write(batch).unwrap();
+
+    let result = changes_since(&db, 0, 100).unwrap();
+    let keys: Vec<_> = result.records.iter().map(|r| r.key.as_str()).collect();
+    assert_eq!(keys, vec!["key1"]);
+    assert!(!result.records[0].internal);
+    assert_eq!(result.next, 3);
+
+    let filter = ChangeFilter {
+        internal: true,
+        excluded: Vec::new(),
+    };
+    let result = changes_since_with(&db, 0, 100, &filter, &|_, value| value.to_vec()).unwrap();
+    assert_eq!(result.records.len(), 3);
+    assert!(result.records[1].internal && result.records[2].internal);
+    assert_ne!(result.records[2].column_family_id, 0);
+}
+
+#[tokio::test]
+async fn test_changes_endpoint() {
+    let (db, _temp_dir) = create_test_db();
+    db.put(b"key1", b"value1").unwrap();
+
+    let state = AppState::new(Arc::new(db));
+    let app = Router::new()
+        .route("/changes", get(changes::changes))
+        .with_state(state);
+
+    let request = Request::builder()
+        .uri("/changes?since=0")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["next"], 1);
+    assert_eq!(json["records"][0]["op"], "put");
+    assert_eq!(json["records"][0]["key"], "key1");
+}
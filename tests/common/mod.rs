@@ -0,0 +1,80 @@
+//! Fixtures shared by the integration tests.
+//!
+//! Every test binary compiles this module on its own and uses only part of
+//! it, hence the `dead_code` allowance.
+#![allow(dead_code)]
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{HeaderMap, Request, StatusCode},
+    Router,
+};
+use h_rocksdb::AppState;
+use rocksdb::{Options, DB};
+use serde_json::Value;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tower::util::ServiceExt;
+
+/// Opens (creating it if needed) the test DB with only the default column
+/// family.
+pub fn open_db(temp_dir: &TempDir) -> DB {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    DB::open(&opts, temp_dir.path().join("rocks.db")).expect("Failed to open test database")
+}
+
+/// Opens the test DB with whatever column families it already has, so a
+/// feature can create its own on first use.
+pub fn open_db_with_column_families(temp_dir: &TempDir) -> DB {
+    let path = temp_dir.path().join("rocks.db");
+
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    let column_families = DB::list_cf(&opts, &path).unwrap_or_default();
+    DB::open_cf(&opts, &path, column_families).expect("Failed to open test database")
+}
+
+/// Opens the test DB with an `extra` column family next to the default one.
+pub fn open_db_with_extra_column_family(temp_dir: &TempDir) -> DB {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    DB::open_cf(&opts, temp_dir.path().join("rocks.db"), ["extra"])
+        .expect("Failed to open test database")
+}
+
+/// A state with every feature at its default.
+pub fn create_test_state(temp_dir: &TempDir) -> AppState {
+    AppState::new(Arc::new(open_db(temp_dir)))
+}
+
+/// Runs `request` through the router and collects the whole response.
+pub async fn respond(app: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, headers, body.to_vec())
+}
+
+/// Sends `body` and parses the response as JSON, `Null` if it isn't.
+pub async fn send(app: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, Value) {
+    let (status, _, body) = respond(app, request(method, uri, body.to_string())).await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// Sends `body` and returns the response as text.
+pub async fn send_text(app: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, String) {
+    let (status, _, body) = respond(app, request(method, uri, body.to_string())).await;
+    (status, String::from_utf8(body).unwrap())
+}
+
+/// A request with no headers beyond what `method`, `uri` and `body` imply.
+pub fn request(method: &str, uri: &str, body: impl Into<Body>) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(body.into())
+        .unwrap()
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Request, StatusCode},
+    Router,
+};
+use common::create_test_state;
+use h_rocksdb::{
+    api::routes,
+    storage::{
+        compression::{self, Codec},
+        record,
+    },
+};
+use tempfile::TempDir;
+
+async fn send(
+    app: &Router,
+    uri: &str,
+    headers: &[(header::HeaderName, &str)],
+    body: Vec<u8>,
+) -> (StatusCode, HeaderMap, Vec<u8>) {
+    let mut request = Request::builder().method("POST").uri(uri);
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    common::respond(app, request.body(Body::from(body)).unwrap()).await
+}
+
+#[tokio::test]
+async fn test_compressed_requests_and_responses() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+    let value = "a fairly repetitive value ".repeat(20);
+
+    for codec in [Codec::Gzip, Codec::Zstd, Codec::Br] {
+        let uri = format!("/put?key={}", codec.name());
+        let body = codec.compress(value.as_bytes()).unwrap();
+        let (status, _, _) = send(
+            &app,
+            &uri,
+            &[(header::CONTENT_ENCODING, codec.name())],
+            body,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let uri = format!("/get?key={}", codec.name());
+        let (status, headers, body) = send(
+            &app,
+            &uri,
+            &[(header::ACCEPT_ENCODING, codec.name())],
+            Vec::new(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_ENCODING], codec.name());
+        let json = codec.decompress(&body).unwrap();
+        assert_eq!(serde_json::from_slice::<String>(&json).unwrap(), value);
+    }
+
+    let (status, _, _) = send(
+        &app,
+        "/put?key=key1",
+        &[(header::CONTENT_ENCODING, "compress")],
+        b"value1".to_vec(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn test_precompressed_values_are_sent_as_stored() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mut state = create_test_state(&temp_dir);
+    state.stored_compression = Some(Codec::Zstd);
+    let db = state.rocksdb.clone();
+    let app = routes::router(state);
+    let value = "a fairly repetitive value ".repeat(20);
+
+    let (status, _, _) = send(&app, "/put?key=key1", &[], value.clone().into_bytes()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let stored = db.get(b"key1").unwrap().unwrap();
+    let (codec, packed_body) =
+        compression::unpack(record::body(&stored)).expect("Value should be packed");
+    assert_eq!(codec, Codec::Zstd);
+    assert!(stored.len() < value.len());
+
+    let (_, headers, body) = send(
+        &app,
+        "/get?key=key1",
+        &[(header::ACCEPT_ENCODING, "gzip;q=0.5, zstd")],
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(headers[header::CONTENT_ENCODING], "zstd");
+    assert_eq!(
+        body, packed_body,
+        "Stored bytes are sent without recompressing"
+    );
+
+    let (status, headers, _) = send(
+        &app,
+        "/get?key=key1",
+        &[(header::ACCEPT_ENCODING, "*, zstd;q=0")],
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(
+        headers
+            .get(header::CONTENT_ENCODING)
+            .map(|value| value.as_bytes()),
+        Some(&b"zstd"[..]),
+        "An explicit q=0 refuses the codec whatever * allows"
+    );
+
+    let (status, headers, body) = send(&app, "/get?key=key1", &[], Vec::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers.get(header::CONTENT_ENCODING).is_none());
+    assert_eq!(serde_json::from_slice::<String>(&body).unwrap(), value);
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use h_rocksdb::{api::routes, storage::document::Documents, AppState};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn create_test_state(temp_dir: &TempDir) -> AppState {
+    let mut state = common::create_test_state(temp_dir);
+    state.documents = Arc::new(Documents::new(vec!["doc/".to_string()]));
+    state
+}
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    content_type: &str,
+    body: &str,
+) -> (StatusCode, String) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", content_type)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let (status, _, body) = common::respond(app, request).await;
+    (status, String::from_utf8_lossy(&body).into_owned())
+}
+
+async fn projected(app: &Router, method: &str, uri: &str) -> Value {
+    let (status, body) = send(app, method, uri, "application/json", "").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    serde_json::from_str(&body).unwrap()
+}
+
+/// Whole values are returned as a JSON string holding the stored text.
+async fn document(app: &Router, uri: &str) -> Value {
+    match projected(app, "GET", uri).await {
+        Value::String(text) => serde_json::from_str(&text).unwrap(),
+        value => panic!("expected the stored text, got {}", value),
+    }
+}
+
+#[tokio::test]
+async fn test_merge_patch_creates_and_updates_documents() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let (status, _) = send(&app, "PUT", "/v1/kv/doc/1", "text/plain", "{not json").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let merge = "application/merge-patch+json";
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        "/v1/kv/doc/1",
+        merge,
+        r#"{"name": "Ann", "address": {"city": "Oslo", "zip": "0150"}}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, body) = send(
+        &app,
+        "POST",
+        "/patch?key=doc/1",
+        merge,
+        r#"{"address": {"zip": null}, "age": 31}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let expected = json!({"name": "Ann", "address": {"city": "Oslo"}, "age": 31});
+    assert_eq!(serde_json::from_str::<Value>(&body).unwrap(), expected);
+    assert_eq!(document(&app, "/v1/kv/doc/1").await, expected);
+
+    let (status, _) = send(&app, "PATCH", "/v1/kv/doc/1", "text/plain", "{}").await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn test_json_patch_applies_atomically() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+    let json_patch = "application/json-patch+json";
+
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        "/v1/kv/doc/2",
+        json_patch,
+        r#"[{"op": "add", "path": "/a", "value": 1}]"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    send(
+        &app,
+        "PUT",
+        "/v1/kv/doc/2",
+        "application/json",
+        r#"{"a": 1}"#,
+    )
+    .await;
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        "/v1/kv/doc/2",
+        json_patch,
+        r#"[{"op": "add", "path": "/b", "value": 2}, {"op": "test", "path": "/a", "value": 5}]"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(document(&app, "/v1/kv/doc/2").await, json!({"a": 1}));
+
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        "/v1/kv/doc/2",
+        json_patch,
+        r#"[{"op": "test", "path": "/a", "value": 1}, {"op": "replace", "path": "/a", "value": [1, 2]}]"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(document(&app, "/v1/kv/doc/2").await, json!({"a": [1, 2]}));
+
+    let (status, _) = send(&app, "PATCH", "/v1/kv/doc/2", json_patch, "[{").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_fields_project_documents() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let user = json!({
+        "name": "Ann",
+        "email": "ann@example.com",
+        "address": {"city": "Oslo", "zip": "0150"}
+    });
+    send(
+        &app,
+        "PUT",
+        "/v1/kv/doc/3",
+        "application/json",
+        &user.to_string(),
+    )
+    .await;
+
+    let expected = json!({"name": "Ann", "address": {"city": "Oslo"}});
+    assert_eq!(
+        projected(&app, "GET", "/v1/kv/doc/3?fields=name,address/city,missing").await,
+        expected
+    );
+    assert_eq!(
+        projected(&app, "POST", "/get?key=doc/3&fields=/name,/address/city").await,
+        expected
+    );
+
+    send(&app, "PUT", "/v1/kv/plain", "text/plain", "just text").await;
+    let (status, _) = send(&app, "GET", "/v1/kv/plain?fields=name", "", "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
@@ -0,0 +1,297 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use h_rocksdb::{
+    api::routes,
+    storage::{
+        encryption::Encryption,
+        history::{History, Retention},
+        index::{IndexConfig, Indexes},
+    },
+    AppState,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn create_test_state(temp_dir: &TempDir) -> AppState {
+    AppState::new(Arc::new(common::open_db_with_extra_column_family(temp_dir)))
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: Vec<u8>) -> (StatusCode, Vec<u8>) {
+    let (status, _, body) = common::respond(app, common::request(method, uri, body)).await;
+    (status, body)
+}
+
+async fn restore(app: &Router, on_conflict: &str, dump: Vec<u8>) -> (StatusCode, Value) {
+    let uri = format!("/admin/restore?on_conflict={on_conflict}");
+    let (status, body) = send(app, "POST", &uri, dump).await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn value(app: &Router, key: &str) -> Option<String> {
+    let (status, value) = send(app, "GET", &format!("/v1/kv/{key}"), Vec::new()).await;
+    match status {
+        StatusCode::OK => Some(serde_json::from_slice(&value).unwrap()),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn test_ndjson_export_restores_into_another_db() {
+    let source_dir = TempDir::new().expect("Failed to create temp directory");
+    let source = create_test_state(&source_dir);
+    let app = routes::router(source.clone());
+    for key in ["app/a", "app/b", "other"] {
+        send(
+            &app,
+            "PUT",
+            &format!("/v1/kv/{key}"),
+            key.as_bytes().to_vec(),
+        )
+        .await;
+    }
+    let extra = source.rocksdb.cf_handle("extra").unwrap();
+    source
+        .rocksdb
+        .put_cf(extra, b"app/raw", b"\x00\x01")
+        .unwrap();
+
+    let (status, dump) = send(&app, "GET", "/admin/export?prefix=app/", Vec::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<Value> = String::from_utf8(dump.clone())
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let families: Vec<&Value> = lines.iter().map(|line| &line["column_family"]).collect();
+    assert_eq!(families, ["default", "default", "extra"]);
+    let (_, only_extra) = send(&app, "GET", "/admin/export?column_family=extra", Vec::new()).await;
+    assert_eq!(String::from_utf8(only_extra).unwrap().lines().count(), 1);
+    let (status, _) = send(&app, "GET", "/admin/export?column_family=nope", Vec::new()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let target_dir = TempDir::new().expect("Failed to create temp directory");
+    let target = create_test_state(&target_dir);
+    let restored_app = routes::router(target.clone());
+    let (status, restored) = restore(&restored_app, "overwrite", dump).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(restored["restored"], 3);
+    assert_eq!(
+        value(&restored_app, "app/a").await.as_deref(),
+        Some("app/a")
+    );
+    assert_eq!(
+        value(&restored_app, "app/b").await.as_deref(),
+        Some("app/b")
+    );
+    assert_eq!(value(&restored_app, "other").await, None);
+    let extra = target.rocksdb.cf_handle("extra").unwrap();
+    assert_eq!(
+        target.rocksdb.get_cf(extra, b"app/raw").unwrap().as_deref(),
+        Some(&b"\x00\x01"[..])
+    );
+    let (_, meta) = send(&restored_app, "GET", "/meta?key=app/a", Vec::new()).await;
+    let meta: Value = serde_json::from_slice(&meta).unwrap();
+    assert_eq!(meta["version"], 1);
+}
+
+#[tokio::test]
+async fn test_restore_conflict_policies() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+    send(&app, "PUT", "/v1/kv/a", b"old a".to_vec()).await;
+    send(&app, "PUT", "/v1/kv/b", b"old b".to_vec()).await;
+    let (_, dump) = send(&app, "GET", "/admin/export", Vec::new()).await;
+    send(&app, "PUT", "/v1/kv/a", b"new a".to_vec()).await;
+    send(&app, "DELETE", "/v1/kv/b", Vec::new()).await;
+
+    let (status, _) = restore(&app, "fail", dump.clone()).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(value(&app, "b").await, None);
+
+    let (status, restored) = restore(&app, "skip", dump.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(restored["restored"], 1);
+    assert_eq!(restored["skipped"], 1);
+    assert_eq!(value(&app, "a").await.as_deref(), Some("new a"));
+    assert_eq!(value(&app, "b").await.as_deref(), Some("old b"));
+
+    let (_, restored) = restore(&app, "overwrite", dump).await;
+    assert_eq!(restored["restored"], 2);
+    assert_eq!(value(&app, "a").await.as_deref(), Some("old a"));
+
+    let (status, _) = restore(&app, "overwrite", b"{\"key\": 1}".to_vec()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = restore(&app, "sometimes", Vec::new()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_sst_export_ingests_into_another_db() {
+    let source_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&source_dir));
+    for key in ["k1", "k2", "skip"] {
+        send(
+            &app,
+            "PUT",
+            &format!("/v1/kv/{key}"),
+            key.as_bytes().to_vec(),
+        )
+        .await;
+    }
+    let (status, sst) = send(&app, "GET", "/admin/export?format=sst&prefix=k", Vec::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "GET", "/admin/export?format=sst&prefix=z", Vec::new()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let target_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&target_dir);
+    let file = target_dir.path().join("export.sst");
+    std::fs::write(&file, sst).unwrap();
+    state.rocksdb.ingest_external_file(vec![&file]).unwrap();
+    let target = routes::router(state);
+    assert_eq!(value(&target, "k1").await.as_deref(), Some("k1"));
+    assert_eq!(value(&target, "k2").await.as_deref(), Some("k2"));
+    assert_eq!(value(&target, "skip").await, None);
+}
+
+#[tokio::test]
+async fn test_prefix_export_carries_chunks_and_structures() {
+    let source_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&source_dir));
+    let big: Vec<u8> = (0..1_500_000).map(|i| (i % 251) as u8).collect();
+    let (status, _) = send(&app, "PUT", "/blob?key=app/big", big.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    send(
+        &app,
+        "POST",
+        "/v1/hash/app/user",
+        br#"{"name": "Ann"}"#.to_vec(),
+    )
+    .await;
+    send(&app, "POST", "/v1/set/app/tags", br#"["red"]"#.to_vec()).await;
+    send(
+        &app,
+        "POST",
+        "/v1/zset/app/scores",
+        br#"{"ann": 3}"#.to_vec(),
+    )
+    .await;
+    send(
+        &app,
+        "POST",
+        "/v1/hash/other",
+        br#"{"name": "Bob"}"#.to_vec(),
+    )
+    .await;
+
+    let (status, dump) = send(&app, "GET", "/admin/export?prefix=app/", Vec::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        "GET",
+        "/admin/export?format=sst&prefix=app/",
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let target_dir = TempDir::new().expect("Failed to create temp directory");
+    let target = routes::router(create_test_state(&target_dir));
+    let (status, restored) = restore(&target, "fail", dump).await;
+    assert_eq!(status, StatusCode::OK, "{}", restored);
+    let (status, downloaded) = send(&target, "GET", "/blob?key=app/big", Vec::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(downloaded == big);
+    let (_, user) = send(&target, "GET", "/v1/hash/app/user", Vec::new()).await;
+    let user: Value = serde_json::from_slice(&user).unwrap();
+    assert_eq!(user, json!({"name": "Ann"}));
+    let (_, tags) = send(&target, "GET", "/v1/set/app/tags", Vec::new()).await;
+    assert_eq!(
+        serde_json::from_slice::<Value>(&tags).unwrap(),
+        json!(["red"])
+    );
+    let (_, scores) = send(&target, "GET", "/v1/zset/app/scores", Vec::new()).await;
+    let scores: Value = serde_json::from_slice(&scores).unwrap();
+    assert_eq!(scores[0]["member"], "ann");
+    let (_, other) = send(&target, "GET", "/v1/hash/other", Vec::new()).await;
+    assert_eq!(serde_json::from_slice::<Value>(&other).unwrap(), json!({}));
+}
+
+#[tokio::test]
+async fn test_failed_restore_writes_nothing() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+    send(&app, "PUT", "/v1/kv/k1499", b"existing".to_vec()).await;
+
+    let dump: String = (0..1500)
+        .map(|i| {
+            let line = json!({
+                "column_family": "default",
+                "key": STANDARD.encode(format!("k{i}")),
+                "value": STANDARD.encode(format!("v{i}")),
+            });
+            format!("{line}\n")
+        })
+        .collect();
+    let (status, _) = restore(&app, "fail", dump.into_bytes()).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(value(&app, "k0").await, None);
+    assert_eq!(value(&app, "k1499").await.as_deref(), Some("existing"));
+}
+
+#[tokio::test]
+async fn test_restore_keeps_history_and_indexes() {
+    let source_dir = TempDir::new().expect("Failed to create temp directory");
+    let source = routes::router(create_test_state(&source_dir));
+    let document = br#"{"email": "new@example.com"}"#.to_vec();
+    send(&source, "PUT", "/v1/kv/user/1", document).await;
+    let (_, dump) = send(&source, "GET", "/admin/export", Vec::new()).await;
+
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mut db = common::open_db(&temp_dir);
+    let config: IndexConfig =
+        serde_json::from_value(json!({"indexes": [{"name": "by_email", "pointer": "/email"}]}))
+            .unwrap();
+    let indexes = Indexes::new(config);
+    indexes
+        .prepare(&mut db, &Encryption::disabled())
+        .expect("Failed to build indexes");
+    let mut state = AppState::new(Arc::new(db));
+    state.indexes = Arc::new(indexes);
+    state.history = Arc::new(History::new(Retention {
+        max_versions: Some(5),
+        max_age_ms: None,
+    }));
+    let app = routes::router(state);
+    let old = br#"{"email": "old@example.com"}"#.to_vec();
+    send(&app, "PUT", "/v1/kv/user/1", old).await;
+
+    let (status, _) = restore(&app, "overwrite", dump).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, found) = send(
+        &app,
+        "GET",
+        "/query?index=by_email&eq=new@example.com",
+        Vec::new(),
+    )
+    .await;
+    let found: Value = serde_json::from_slice(&found).unwrap();
+    assert_eq!(found["keys"], json!(["user/1"]));
+    let (_, found) = send(
+        &app,
+        "GET",
+        "/query?index=by_email&eq=old@example.com",
+        Vec::new(),
+    )
+    .await;
+    let found: Value = serde_json::from_slice(&found).unwrap();
+    assert_eq!(found["keys"], json!([]));
+    let (_, history) = send(&app, "GET", "/history?key=user/1", Vec::new()).await;
+    let history: Value = serde_json::from_slice(&history).unwrap();
+    let versions = history["versions"].as_array().unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[1]["value"], r#"{"email": "old@example.com"}"#);
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::send_text;
+use h_rocksdb::{
+    api::routes,
+    storage::encryption::{Encryption, EncryptionError, KeyFile},
+    AppState,
+};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tempfile::TempDir;
+
+fn key_file(active: &str, ids: &[&str]) -> KeyFile {
+    let keys: HashMap<String, String> = ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.to_string(), STANDARD.encode([i as u8 + 1; 32])))
+        .collect();
+    KeyFile {
+        active_key: active.to_string(),
+        keys,
+    }
+}
+
+fn create_test_state(temp_dir: &TempDir, encryption: Encryption) -> AppState {
+    let mut state = common::create_test_state(temp_dir);
+    state.encryption = Arc::new(encryption);
+    state
+}
+
+#[tokio::test]
+async fn test_values_are_stored_encrypted() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let encryption = Encryption::new(key_file("k1", &["k1"])).unwrap();
+    let state = create_test_state(&temp_dir, encryption);
+    let db = state.rocksdb.clone();
+    let app = routes::router(state);
+
+    let (status, _) = send_text(&app, "POST", "/put?key=key1", "secret value").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let stored = db.get(b"key1").unwrap().unwrap();
+    assert!(!String::from_utf8_lossy(&stored).contains("secret value"));
+    assert_eq!(Encryption::key_id(&stored).as_deref(), Some("k1"));
+
+    let (status, body) = send_text(&app, "POST", "/get?key=key1", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "\"secret value\"");
+
+    let (_, body) = send_text(&app, "GET", "/changes?since=0", "").await;
+    assert!(body.contains("secret value"), "Change feed shows plaintext");
+}
+
+#[test]
+fn test_plaintext_passes_through_and_moved_values_fail() {
+    let encryption = Encryption::new(key_file("k1", &["k1"])).unwrap();
+
+    let legacy = encryption
+        .open(b"key1", b"written before encryption")
+        .unwrap();
+    assert_eq!(legacy, b"written before encryption");
+
+    let sealed = encryption.seal(b"key1", b"value1");
+    assert_eq!(encryption.open(b"key1", &sealed).unwrap(), b"value1");
+    assert!(matches!(
+        encryption.open(b"key2", &sealed),
+        Err(EncryptionError::Corrupt(_))
+    ));
+
+    let other = Encryption::new(key_file("k2", &["k2"])).unwrap();
+    assert!(matches!(
+        other.open(b"key1", &sealed),
+        Err(EncryptionError::UnknownKey(id)) if id == "k1"
+    ));
+}
+
+#[tokio::test]
+async fn test_reencrypt_rotates_to_active_key() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let old = Encryption::new(key_file("k1", &["k1"])).unwrap();
+    {
+        let state = create_test_state(&temp_dir, old);
+        let app = routes::router(state.clone());
+        send_text(&app, "POST", "/put?key=key1", "value1").await;
+        state.rocksdb.put(b"key2", b"legacy").unwrap();
+    }
+
+    let rotated = Encryption::new(key_file("k2", &["k1", "k2"])).unwrap();
+    let state = create_test_state(&temp_dir, rotated);
+    let db = state.rocksdb.clone();
+    let app = routes::router(state);
+
+    let (status, _) = send_text(&app, "POST", "/admin/encryption/reencrypt", "").await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    let mut status = serde_json::Value::Null;
+    for _ in 0..50 {
+        let (_, body) = send_text(&app, "GET", "/admin/encryption", "").await;
+        status = serde_json::from_str(&body).unwrap();
+        if status["reencrypt"]["running"] == false {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(status["active_key"], "k2");
+    assert_eq!(status["reencrypt"]["running"], false);
+    assert_eq!(status["reencrypt"]["scanned"], 2);
+    assert_eq!(status["reencrypt"]["rewritten"], 2);
+
+    for key in ["key1", "key2"] {
+        let stored = db.get(key.as_bytes()).unwrap().unwrap();
+        assert_eq!(Encryption::key_id(&stored).as_deref(), Some("k2"));
+    }
+    let (_, body) = send_text(&app, "POST", "/get?key=key2", "").await;
+    assert_eq!(body, "\"legacy\"");
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use common::send_text;
+use h_rocksdb::{
+    api::routes,
+    storage::{
+        history::{self, History, Retention},
+        mode::{self, AccessMode},
+    },
+    AppState,
+};
+use rocksdb::{IteratorMode, Options};
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+use tempfile::TempDir;
+
+fn create_test_state(temp_dir: &TempDir, retention: Retention) -> AppState {
+    let path = temp_dir.path().join("rocks.db");
+    let history = History::new(retention);
+
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    history.configure(&mut opts);
+    // Opened the way the server opens it, so reopening behaves the same.
+    let db =
+        mode::open(&opts, &path, &AccessMode::ReadWrite).expect("Failed to open test database");
+    let mut state = AppState::new(Arc::new(db));
+    state.history = Arc::new(history);
+    state
+}
+
+/// Writes `value`, making sure it gets its own millisecond.
+async fn put(app: &Router, key: &str, value: &str) {
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let (status, _) = send_text(app, "POST", &format!("/put?key={}", key), value).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn versions(app: &Router, key: &str) -> Vec<Value> {
+    let (status, body) = send_text(app, "GET", &format!("/history?key={}", key), "").await;
+    assert_eq!(status, StatusCode::OK);
+    let history: Value = serde_json::from_str(&body).unwrap();
+    history["versions"].as_array().unwrap().clone()
+}
+
+#[tokio::test]
+async fn test_history_and_as_of() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir, Retention::default()));
+
+    put(&app, "config", "v1").await;
+    put(&app, "config", "v2").await;
+    put(&app, "config", "v3").await;
+
+    let versions = versions(&app, "config").await;
+    let values: Vec<_> = versions
+        .iter()
+        .map(|entry| entry["value"].clone())
+        .collect();
+    assert_eq!(values, vec!["v3", "v2", "v1"]);
+    assert_eq!(versions[0]["version"], 3);
+    assert!(versions[0]["replaced_ms"].is_null());
+    assert_eq!(versions[2]["version"], 1);
+    assert_eq!(versions[2]["replaced_ms"], versions[1]["modified_ms"]);
+
+    let first_written = versions[2]["modified_ms"].as_u64().unwrap();
+    let second_written = versions[1]["modified_ms"].as_u64().unwrap();
+    let as_of = |ms: u64| format!("/get?key=config&as_of={}", ms);
+    assert_eq!(
+        send_text(&app, "POST", &as_of(first_written), "").await,
+        (StatusCode::OK, "\"v1\"".to_string())
+    );
+    assert_eq!(
+        send_text(&app, "POST", &as_of(second_written), "").await,
+        (StatusCode::OK, "\"v2\"".to_string())
+    );
+    assert_eq!(
+        send_text(&app, "POST", &as_of(u64::MAX), "").await,
+        (StatusCode::OK, "\"v3\"".to_string())
+    );
+    let (status, _) = send_text(&app, "POST", &as_of(first_written - 1), "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_versions_continue_after_delete() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir, Retention::default()));
+
+    put(&app, "config", "v1").await;
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    send_text(&app, "DELETE", "/v1/kv/config", "").await;
+    put(&app, "config", "v2").await;
+
+    let versions = versions(&app, "config").await;
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0]["version"], 2);
+    assert_eq!(versions[0]["value"], "v2");
+    assert_eq!(versions[1]["value"], "v1");
+
+    let deleted_ms = versions[1]["replaced_ms"].as_u64().unwrap();
+    let (status, _) = send_text(
+        &app,
+        "POST",
+        &format!("/get?key=config&as_of={}", deleted_ms),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "The key was deleted then");
+}
+
+#[tokio::test]
+async fn test_retention_prunes_versions() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let retention = Retention {
+        max_versions: Some(2),
+        max_age_ms: Some(60_000),
+    };
+    let state = create_test_state(&temp_dir, retention);
+    let db = state.rocksdb.clone();
+    let app = routes::router(state);
+
+    for value in ["v1", "v2", "v3", "v4", "v5"] {
+        put(&app, "config", value).await;
+    }
+    let versions = versions(&app, "config").await;
+    let values: Vec<_> = versions
+        .iter()
+        .map(|entry| entry["value"].clone())
+        .collect();
+    assert_eq!(values, vec!["v5", "v4", "v3"]);
+
+    let now = versions[0]["modified_ms"].as_u64().unwrap();
+    let entries: Vec<_> = db
+        .iterator(IteratorMode::Start)
+        .map(|item| item.unwrap().0)
+        .filter(|key| key.starts_with(b"\0hist\0"))
+        .collect();
+    assert_eq!(entries.len(), 2);
+    for entry in &entries {
+        assert!(!history::is_expired(entry, 60_000, now));
+        assert!(history::is_expired(entry, 60_000, now + 61_000));
+    }
+    assert!(!history::is_expired(b"config", 0, u64::MAX));
+}
+
+#[tokio::test]
+async fn test_chunked_values_are_left_out_of_history() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir, Retention::default()));
+
+    put(&app, "config", "v1").await;
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let (status, _) = send_text(&app, "PUT", "/blob?key=config", "chunked").await;
+    assert_eq!(status, StatusCode::OK);
+    put(&app, "config", "v3").await;
+
+    let versions = versions(&app, "config").await;
+    let numbers: Vec<_> = versions
+        .iter()
+        .map(|entry| entry["version"].clone())
+        .collect();
+    assert_eq!(numbers, vec![3, 1]);
+    assert_eq!(versions[1]["value"], "v1");
+
+    let first_written = versions[1]["modified_ms"].as_u64().unwrap();
+    let blob_replaced = versions[0]["modified_ms"].as_u64().unwrap();
+    let as_of = |ms: u64| format!("/get?key=config&as_of={}", ms);
+    assert_eq!(
+        send_text(&app, "POST", &as_of(first_written), "").await,
+        (StatusCode::OK, "\"v1\"".to_string())
+    );
+    let (status, _) = send_text(&app, "POST", &as_of(blob_replaced - 1), "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "The blob's chunks are gone");
+}
+
+#[tokio::test]
+async fn test_retention_survives_reopening() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let retention = Retention {
+        max_versions: None,
+        max_age_ms: Some(1),
+    };
+    let app = routes::router(create_test_state(&temp_dir, retention));
+    for value in ["v1", "v2", "v3"] {
+        put(&app, "config", value).await;
+    }
+    drop(app);
+
+    let state = create_test_state(&temp_dir, retention);
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    state.rocksdb.compact_range(None::<&[u8]>, None::<&[u8]>);
+    let entries = state
+        .rocksdb
+        .iterator(IteratorMode::Start)
+        .filter(|item| item.as_ref().unwrap().0.starts_with(b"\0hist\0"))
+        .count();
+    assert_eq!(entries, 0, "Expired versions are pruned after a reopen");
+}
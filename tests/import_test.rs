@@ -0,0 +1,232 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::create_test_state;
+use h_rocksdb::{
+    api::routes,
+    storage::{
+        encryption::Encryption,
+        history::{History, Retention},
+        index::{IndexConfig, Indexes},
+        wal::{changes_since, ChangeEncoding},
+    },
+    AppState,
+};
+use rocksdb::{Options, SstFileWriter};
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tempfile::TempDir;
+
+async fn send(app: &Router, method: &str, uri: &str, body: Vec<u8>) -> (StatusCode, Value) {
+    let (status, _, body) = common::respond(app, common::request(method, uri, body)).await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// Starts an import and waits for it to end.
+async fn import(app: &Router, format: &str, upload: Vec<u8>) -> Value {
+    let (status, started) = send(
+        app,
+        "POST",
+        &format!("/admin/import?format={format}"),
+        upload,
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", started);
+    let uri = format!("/admin/import/{}", started["id"].as_str().unwrap());
+    for _ in 0..500 {
+        let (status, progress) = send(app, "GET", &uri, Vec::new()).await;
+        assert_eq!(status, StatusCode::OK);
+        if progress["phase"] == "done" || progress["phase"] == "failed" {
+            return progress;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("import did not finish");
+}
+
+async fn value(app: &Router, key: &str) -> Option<String> {
+    let (status, value) = send(app, "GET", &format!("/v1/kv/{key}"), Vec::new()).await;
+    match status {
+        StatusCode::OK => Some(value.as_str().unwrap().to_string()),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn test_ndjson_import_reports_row_errors() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+    send(&app, "PUT", "/v1/kv/b", b"before".to_vec()).await;
+
+    let upload = [
+        r#"{"key": "b", "value": "first"}"#,
+        r#"{"key": "a", "value": {"n": 1}}"#,
+        "{not json",
+        "",
+        r#"{"key": "\u0000hidden", "value": "x"}"#,
+        r#"{"key": "b", "value": "second"}"#,
+    ]
+    .join("\n");
+    let progress = import(&app, "ndjson", upload.into_bytes()).await;
+    assert_eq!(progress["phase"], "done");
+    assert_eq!(progress["rows_read"], 6);
+    assert_eq!(progress["keys_imported"], 2);
+    assert_eq!(progress["rows_rejected"], 2);
+    let rows: Vec<&Value> = progress["row_errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| &error["row"])
+        .collect();
+    assert_eq!(rows, [3, 5]);
+
+    assert_eq!(value(&app, "a").await.as_deref(), Some(r#"{"n":1}"#));
+    assert_eq!(value(&app, "b").await.as_deref(), Some("second"));
+    let (_, meta) = send(&app, "GET", "/meta?key=b", Vec::new()).await;
+    assert_eq!(meta["version"], 2);
+    assert_eq!(meta["modified_by"], "anonymous");
+
+    let (_, imports) = send(&app, "GET", "/admin/import", Vec::new()).await;
+    assert_eq!(imports.as_array().unwrap().len(), 1);
+    let (status, _) = send(&app, "GET", "/admin/import/unknown", Vec::new()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_csv_import_validates_documents() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+    let schema = json!({
+        "type": "object",
+        "required": ["name"],
+    });
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/admin/schemas?prefix=user/",
+        schema.to_string().into_bytes(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let upload = "\
+plain,\"hello, world\"
+user/1,\"{\"\"name\"\": \"\"Ann\"\"}\"
+user/2,\"{\"\"age\"\": 3}\"
+only-a-key
+";
+    let progress = import(&app, "csv", upload.as_bytes().to_vec()).await;
+    assert_eq!(progress["phase"], "done");
+    assert_eq!(progress["keys_imported"], 2);
+    assert_eq!(progress["row_errors"][0]["row"], 3);
+    assert_eq!(progress["row_errors"][1]["row"], 4);
+
+    assert_eq!(value(&app, "plain").await.as_deref(), Some("hello, world"));
+    assert_eq!(
+        value(&app, "user/1").await.as_deref(),
+        Some(r#"{"name": "Ann"}"#)
+    );
+    assert_eq!(value(&app, "user/2").await, None);
+    let (_, meta) = send(&app, "GET", "/meta?key=user/1", Vec::new()).await;
+    assert_eq!(meta["content_type"], "application/json");
+}
+
+#[tokio::test]
+async fn test_prebuilt_sst_files_are_checked_and_ingested() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let sst = temp_dir.path().join("upload.sst");
+    let options = Options::default();
+    let mut writer = SstFileWriter::create(&options);
+    writer.open(&sst).unwrap();
+    writer.put(b"\0hist\0k1", b"forged").unwrap();
+    writer.put(b"forged", b"\0BLB\x01{}").unwrap();
+    writer.put(b"k1", b"v1").unwrap();
+    writer.put(b"k2", b"v2").unwrap();
+    writer.finish().unwrap();
+    let upload = std::fs::read(&sst).unwrap();
+
+    let mut state = create_test_state(&temp_dir);
+    state.history = Arc::new(History::new(Retention {
+        max_versions: Some(5),
+        max_age_ms: None,
+    }));
+    let app = routes::router(state.clone());
+    send(&app, "PUT", "/v1/kv/k1", b"v0".to_vec()).await;
+    let progress = import(&app, "sst", upload).await;
+    assert_eq!(progress["phase"], "done");
+    assert_eq!(progress["keys_imported"], 2);
+    let rows: Vec<&Value> = progress["row_errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| &error["row"])
+        .collect();
+    assert_eq!(rows, [1, 2]);
+    assert_eq!(value(&app, "k1").await.as_deref(), Some("v1"));
+    assert_eq!(value(&app, "k2").await.as_deref(), Some("v2"));
+    assert_eq!(value(&app, "forged").await, None);
+
+    // Replaced values reach history, while ingested values skip the WAL and
+    // with it the change feed.
+    let (_, history) = send(&app, "GET", "/history?key=k1", Vec::new()).await;
+    let versions: Vec<&Value> = history["versions"].as_array().unwrap().iter().collect();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[1]["value"], "v0");
+    let (_, meta) = send(&app, "GET", "/meta?key=k1", Vec::new()).await;
+    assert_eq!(meta["version"], 2);
+    let changes = changes_since(&state.rocksdb, 0, 100).unwrap();
+    // Stored values carry a binary record header, so records are in base64.
+    let keys: Vec<Vec<u8>> = changes
+        .records
+        .iter()
+        .map(|record| match record.encoding {
+            ChangeEncoding::Utf8 => record.key.clone().into_bytes(),
+            ChangeEncoding::Base64 => STANDARD.decode(&record.key).unwrap(),
+        })
+        .collect();
+    assert_eq!(keys, [b"k1".to_vec()]);
+}
+
+#[tokio::test]
+async fn test_imported_documents_are_indexed() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mut db = common::open_db(&temp_dir);
+    let config: IndexConfig =
+        serde_json::from_str(r#"{"indexes": [{"name": "by_email", "pointer": "/email"}]}"#)
+            .unwrap();
+    let indexes = Indexes::new(config);
+    indexes
+        .prepare(&mut db, &Encryption::disabled())
+        .expect("Failed to build indexes");
+    let mut state = AppState::new(Arc::new(db));
+    state.indexes = Arc::new(indexes);
+    let app = routes::router(state);
+    send(
+        &app,
+        "PUT",
+        "/v1/kv/user/1",
+        br#"{"email": "ann@example.com"}"#.to_vec(),
+    )
+    .await;
+
+    let upload = [
+        r#"{"key": "user/1", "value": {"email": "anna@example.com"}}"#,
+        r#"{"key": "user/2", "value": {"email": "bob@example.com"}}"#,
+    ]
+    .join("\n");
+    let progress = import(&app, "ndjson", upload.into_bytes()).await;
+    assert_eq!(progress["keys_imported"], 2);
+
+    // The entry of the replaced value goes with it.
+    for (email, expected) in [
+        ("ann@example.com", json!([])),
+        ("anna@example.com", json!(["user/1"])),
+        ("bob@example.com", json!(["user/2"])),
+    ] {
+        let uri = format!("/query?index=by_email&eq={email}");
+        let (status, body) = send(&app, "GET", &uri, Vec::new()).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["keys"], expected, "{}", email);
+    }
+}
@@ -0,0 +1,192 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::send;
+use h_rocksdb::{
+    api::routes,
+    storage::{
+        encryption::{Encryption, KeyFile},
+        index::{IndexConfig, Indexes},
+    },
+    AppState,
+};
+use rocksdb::DB;
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc};
+use tempfile::TempDir;
+
+const INDEXES: &str = r#"{
+    "indexes": [
+        {"name": "by_email", "pointer": "/email"},
+        {"name": "by_age", "pointer": "/age"},
+        {"name": "by_tag", "pointer": "/tags"}
+    ]
+}"#;
+
+/// Opens a DB, lets `seed` write to it directly, then builds the indexes.
+fn create_test_state(temp_dir: &TempDir, seed: impl FnOnce(&DB)) -> AppState {
+    let mut db = common::open_db(temp_dir);
+    seed(&db);
+
+    let config: IndexConfig = serde_json::from_str(INDEXES).unwrap();
+    let indexes = Indexes::new(config);
+    indexes
+        .prepare(&mut db, &Encryption::disabled())
+        .expect("Failed to build indexes");
+    let mut state = AppState::new(Arc::new(db));
+    state.indexes = Arc::new(indexes);
+    state
+}
+
+async fn keys(app: &Router, query: &str) -> Value {
+    let (status, body) = send(app, "GET", &format!("/query?{}", query), "").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body["keys"].clone()
+}
+
+#[tokio::test]
+async fn test_indexes_follow_writes() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir, |_| {}));
+
+    let users = [
+        ("user/1", json!({"email": "ann@example.com", "age": 31})),
+        ("user/2", json!({"email": "bob@example.com", "age": 9})),
+        ("user/3", json!({"email": "cat@example.com", "age": 45})),
+        ("user/4", json!({"email": "dan@example.com", "age": -2.5})),
+    ];
+    for (key, user) in &users {
+        send(&app, "PUT", &format!("/v1/kv/{}", key), &user.to_string()).await;
+    }
+
+    assert_eq!(
+        keys(&app, "index=by_email&eq=bob@example.com").await,
+        json!(["user/2"])
+    );
+    assert_eq!(
+        keys(&app, "index=by_age&from=9&to=31").await,
+        json!(["user/2", "user/1"])
+    );
+    assert_eq!(
+        keys(&app, "index=by_age&from=0").await,
+        json!(["user/2", "user/1", "user/3"])
+    );
+    assert_eq!(keys(&app, "index=by_age&to=0").await, json!(["user/4"]));
+    assert_eq!(keys(&app, "index=by_age&eq=%2231%22").await, json!([]));
+
+    let changed = json!({"email": "bobby@example.com", "age": 10});
+    send(&app, "PUT", "/v1/kv/user/2", &changed.to_string()).await;
+    send(&app, "DELETE", "/v1/kv/user/3", "").await;
+    assert_eq!(
+        keys(&app, "index=by_email&eq=bob@example.com").await,
+        json!([])
+    );
+    assert_eq!(
+        keys(&app, "index=by_email&eq=bobby@example.com").await,
+        json!(["user/2"])
+    );
+    assert_eq!(
+        keys(&app, "index=by_age&from=0&limit=2").await,
+        json!(["user/2", "user/1"])
+    );
+    assert_eq!(keys(&app, "index=by_age&eq=45").await, json!([]));
+
+    let (_, body) = send(&app, "GET", "/query?index=by_age&eq=10&documents=true", "").await;
+    assert_eq!(
+        body["documents"],
+        json!([{"key": "user/2", "value": changed}])
+    );
+}
+
+#[tokio::test]
+async fn test_new_index_covers_existing_values() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir, |db| {
+        db.put(b"post/1", br#"{"tags": ["rust", "db"]}"#).unwrap();
+        db.put(b"post/2", br#"{"tags": ["db"]}"#).unwrap();
+        db.put(b"note", b"not json").unwrap();
+    });
+    let app = routes::router(state);
+
+    assert_eq!(
+        keys(&app, "index=by_tag&eq=db").await,
+        json!(["post/1", "post/2"])
+    );
+    assert_eq!(keys(&app, "index=by_tag&eq=rust").await, json!(["post/1"]));
+}
+
+#[tokio::test]
+async fn test_query_errors() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir, |_| {}));
+
+    let (status, _) = send(&app, "GET", "/query?index=missing&eq=1", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, "GET", "/query?index=by_age&eq=1&from=0", "").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_changed_or_unfinished_indexes_are_rebuilt() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let open = |pointer: &str, stale: bool| {
+        let mut db = common::open_db_with_column_families(&temp_dir);
+        db.put(b"user/1", br#"{"email": "ann@example.com", "age": 30}"#)
+            .unwrap();
+        if stale {
+            // A backfill interrupted before it finished.
+            let column_family = db.cf_handle("index.by_field").unwrap();
+            db.delete_cf(column_family, b"\0built").unwrap();
+            db.put_cf(column_family, b"\x05gone\0user/9", b"user/9")
+                .unwrap();
+        }
+        let config: IndexConfig = serde_json::from_value(json!({
+            "indexes": [{"name": "by_field", "pointer": pointer}]
+        }))
+        .unwrap();
+        let indexes = Indexes::new(config);
+        indexes
+            .prepare(&mut db, &Encryption::disabled())
+            .expect("Failed to build indexes");
+        let mut state = AppState::new(Arc::new(db));
+        state.indexes = Arc::new(indexes);
+        routes::router(state)
+    };
+
+    let app = open("/email", false);
+    assert_eq!(
+        keys(&app, "index=by_field&eq=ann@example.com").await,
+        json!(["user/1"])
+    );
+    drop(app);
+
+    let app = open("/age", false);
+    assert_eq!(keys(&app, "index=by_field&eq=30").await, json!(["user/1"]));
+    assert_eq!(
+        keys(&app, "index=by_field&eq=ann@example.com").await,
+        json!([])
+    );
+    drop(app);
+
+    let app = open("/age", true);
+    assert_eq!(keys(&app, "index=by_field&from=0").await, json!(["user/1"]));
+    assert_eq!(keys(&app, "index=by_field&eq=gone").await, json!([]));
+}
+
+#[test]
+fn test_indexing_encrypted_values_must_be_accepted() {
+    let encryption = Encryption::new(KeyFile {
+        active_key: "k1".to_string(),
+        keys: HashMap::from([("k1".to_string(), STANDARD.encode([1u8; 32]))]),
+    })
+    .unwrap();
+    let indexes = |config: Value| Indexes::new(serde_json::from_value(config).unwrap());
+    let definitions = json!([{"name": "by_email", "pointer": "/email"}]);
+
+    let refused = indexes(json!({"indexes": definitions}));
+    assert!(refused.check_encryption(&encryption).is_err());
+    assert!(refused.check_encryption(&Encryption::disabled()).is_ok());
+    let accepted = indexes(json!({"indexes": definitions, "plaintext_terms": true}));
+    assert!(accepted.check_encryption(&encryption).is_ok());
+}
@@ -0,0 +1,149 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, StatusCode},
+    Router,
+};
+use common::create_test_state;
+use h_rocksdb::{
+    api::{handlers::VALUE_SIZE_HEADER, routes},
+    auth::{acl::AccessControl, audit::AuditLog, AuthConfig, Authenticator},
+};
+use std::{fs, sync::Arc};
+use tempfile::TempDir;
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    api_key: Option<&str>,
+    body: &str,
+) -> (StatusCode, HeaderMap, String) {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(api_key) = api_key {
+        request = request.header("x-api-key", api_key);
+    }
+    let request = request.body(Body::from(body.to_string())).unwrap();
+    let (status, headers, body) = common::respond(app, request).await;
+    (status, headers, String::from_utf8(body).unwrap())
+}
+
+#[tokio::test]
+async fn test_resource_routes_share_storage_with_legacy_routes() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let (status, _, _) = send(&app, "PUT", "/v1/kv/team%2Fkey%201", None, "value1").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, body) = send(&app, "GET", "/v1/kv/team%2Fkey%201", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "\"value1\"");
+
+    let (status, _, body) = send(&app, "POST", "/get?key=team/key%201", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "\"value1\"", "Path keys are percent-decoded");
+
+    let (status, headers, body) = send(&app, "HEAD", "/v1/kv/team/key%201", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[VALUE_SIZE_HEADER], "6");
+    assert!(body.is_empty());
+
+    let (status, _, body) = send(&app, "DELETE", "/v1/kv/team/key%201", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["deleted"], true);
+
+    let (status, _, _) = send(&app, "HEAD", "/v1/kv/team/key%201", None, "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, _) = send(&app, "GET", "/v1/kv/team/key%201", None, "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, _, body) = send(&app, "DELETE", "/v1/kv/team/key%201", None, "").await;
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["deleted"], false);
+}
+
+#[tokio::test]
+async fn test_resource_routes_are_authorized_and_audited_by_key() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let acl_path = temp_dir.path().join("acl.json");
+    fs::write(
+        &acl_path,
+        r#"{"rules": [
+            {"principal": "team-a", "prefix": "team-a/", "permissions": ["read", "write"]},
+            {"principal": "*", "prefix": "public/", "permissions": ["read"]},
+            {"principal": "ops", "permissions": ["admin"]}
+        ]}"#,
+    )
+    .unwrap();
+    let config: AuthConfig = serde_json::from_str(
+        r#"{"api_keys": [
+            {"principal": "team-a", "key": "key-a"},
+            {"principal": "ops", "key": "key-ops"}
+        ]}"#,
+    )
+    .unwrap();
+    let mut state = create_test_state(&temp_dir);
+    state.auth = Arc::new(Authenticator::new(config));
+    state.acl = Arc::new(AccessControl::load(&acl_path).unwrap());
+    state.audit = Arc::new(AuditLog::open(&temp_dir.path().join("audit.log")).unwrap());
+    let app = routes::router(state);
+
+    let (status, _, _) = send(&app, "PUT", "/v1/kv/team-a%2Fkey1", Some("key-a"), "v").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send(&app, "PUT", "/v1/kv/public/key1", Some("key-a"), "v").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = send(&app, "DELETE", "/v1/kv/public/key1", Some("key-a"), "").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = send(&app, "HEAD", "/v1/kv/public/key1", Some("key-a"), "").await;
+    assert_eq!(
+        status,
+        StatusCode::NOT_FOUND,
+        "Reads of public keys are allowed"
+    );
+
+    let (_, _, body) = send(&app, "GET", "/admin/audit", Some("key-ops"), "").await;
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let records = json["records"].as_array().unwrap();
+    let put = records
+        .iter()
+        .find(|record| record["outcome"] == "succeeded")
+        .unwrap();
+    assert_eq!(put["route"], "/v1/kv/*key");
+    assert_eq!(put["key"], "team-a/key1");
+}
+
+#[tokio::test]
+async fn test_every_key_route_rejects_reserved_keys() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir);
+    let app = routes::router(state.clone());
+    let (status, _, _) = send(&app, "PUT", "/admin/schemas?prefix=doc/", None, "{}").await;
+    assert_eq!(status, StatusCode::OK);
+    let schema = state.rocksdb.get(b"\0schema\0doc/").unwrap();
+    assert!(schema.is_some());
+
+    let routes = [
+        ("POST", "/put?key=%00schema%00doc/"),
+        ("POST", "/patch?key=%00schema%00doc/"),
+        ("POST", "/get?key=%00schema%00doc/"),
+        ("GET", "/meta?key=%00schema%00doc/"),
+        ("GET", "/history?key=%00schema%00doc/"),
+        ("GET", "/blob?key=%00blob%00x"),
+        ("PUT", "/blob?key=%00blob%00x"),
+        ("GET", "/v1/kv/%00schema%00doc/"),
+        ("HEAD", "/v1/kv/%00schema%00doc/"),
+        ("PUT", "/v1/kv/%00schema%00doc/"),
+        ("PATCH", "/v1/kv/%00schema%00doc/"),
+        ("DELETE", "/v1/kv/%00schema%00doc/"),
+        ("GET", "/v1/hash/%00hash%00h"),
+        ("POST", "/v1/set/%00set%00s"),
+        ("GET", "/v1/zset/%00zmem%00z"),
+    ];
+    for (method, uri) in routes {
+        let (status, _, _) = send(&app, method, uri, None, "{\"type\": \"string\"}").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{} {}", method, uri);
+    }
+    assert_eq!(state.rocksdb.get(b"\0schema\0doc/").unwrap(), schema);
+}
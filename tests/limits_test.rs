@@ -0,0 +1,176 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use h_rocksdb::{
+    api::routes,
+    limits::{bucket::TokenBucket, Limits, LimitsConfig},
+    AppState,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+fn create_test_state(limits: &str) -> (AppState, TempDir) {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let config: LimitsConfig = serde_json::from_str(limits).unwrap();
+    let mut state = common::create_test_state(&temp_dir);
+    state.limits = Arc::new(Limits::new(config));
+    (state, temp_dir)
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, String, String) {
+    let request = common::request(method, uri, body.to_string());
+    let (status, headers, body) = common::respond(app, request).await;
+    let retry_after = headers
+        .get("retry-after")
+        .map(|value| value.to_str().unwrap().to_string())
+        .unwrap_or_default();
+    (status, retry_after, String::from_utf8(body).unwrap())
+}
+
+#[tokio::test]
+async fn test_rate_limit_returns_429_per_route() {
+    let (state, _temp_dir) = create_test_state(
+        r#"{"rate_limits": [{"route": "/get", "requests_per_second": 0.5, "burst": 2}]}"#,
+    );
+    let app = routes::router(state);
+
+    for _ in 0..2 {
+        let (status, _, _) = send(&app, "POST", "/get?key=key1", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+    let (status, retry_after, body) = send(&app, "POST", "/get?key=key1", "").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(retry_after, "2");
+    assert!(body.contains("rate_limit"));
+
+    let (status, _, _) = send(&app, "POST", "/put?key=key1", "value1").await;
+    assert_eq!(status, StatusCode::OK, "Other routes have their own bucket");
+
+    let (status, _, metrics) = send(&app, "GET", "/metrics", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(metrics.contains(
+        r#"rocksdb_http_throttled_total{client="anonymous",route="/get",reason="rate_limit"} 1"#
+    ));
+}
+
+#[tokio::test]
+async fn test_quota_rejects_writes_over_limit() {
+    let (state, _temp_dir) =
+        create_test_state(r#"{"quotas": [{"prefix": "team-a/", "max_bytes": 10}]}"#);
+    let app = routes::router(state);
+
+    let (status, _, _) = send(&app, "POST", "/put?key=team-a/key1", "123456").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, retry_after, body) = send(&app, "POST", "/put?key=team-a/key2", "123456").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(retry_after, "10");
+    assert!(body.contains("quota"));
+
+    let (status, _, _) = send(&app, "POST", "/put?key=team-b/key1", "123456").await;
+    assert_eq!(status, StatusCode::OK, "Other namespaces are unaffected");
+}
+
+#[tokio::test]
+async fn test_quota_usage_is_remeasured_from_live_data() {
+    let (state, _temp_dir) =
+        create_test_state(r#"{"quotas": [{"prefix": "team-a/", "max_bytes": 1000}]}"#);
+    let (limits, db, encryption) = (
+        state.limits.clone(),
+        state.rocksdb.clone(),
+        state.encryption.clone(),
+    );
+    let app = routes::router(state);
+    let value = "x".repeat(600);
+
+    let (status, _, _) = send(&app, "POST", "/put?key=team-a/key1", &value).await;
+    assert_eq!(status, StatusCode::OK);
+    // Still in the memtable, yet measured.
+    limits.measure_all(&db, &encryption);
+    let (status, _, _) = send(&app, "POST", "/put?key=team-a/key2", &value).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    let (status, _, _) = send(&app, "DELETE", "/v1/kv/team-a/key1", "").await;
+    assert_eq!(status, StatusCode::OK);
+    limits.measure_all(&db, &encryption);
+    let (status, _, _) = send(&app, "POST", "/put?key=team-a/key2", &value).await;
+    assert_eq!(status, StatusCode::OK, "Deleted bytes are taken back");
+}
+
+#[test]
+fn test_token_bucket_refills() {
+    let start = Instant::now();
+    let mut bucket = TokenBucket::full(1.0, start);
+    assert!(bucket.try_take(2.0, 1.0, start).is_ok());
+
+    let wait = bucket.try_take(2.0, 1.0, start).unwrap_err();
+    assert_eq!(wait, Duration::from_millis(500));
+
+    assert!(bucket.try_take(2.0, 1.0, start + wait).is_ok());
+}
+
+#[tokio::test]
+async fn test_quota_covers_every_write_path() {
+    let (state, _temp_dir) =
+        create_test_state(r#"{"quotas": [{"prefix": "team-a", "max_bytes": 100}]}"#);
+    let app = routes::router(state);
+    let document = format!(r#"{{"a": "{}"}}"#, "x".repeat(40));
+    let (status, _, _) = send(&app, "POST", "/put?key=team-a-doc", &document).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // A small patch still counts the whole document it produces.
+    let request = Request::builder()
+        .method("PATCH")
+        .uri("/v1/kv/team-a-doc")
+        .header("content-type", "application/merge-patch+json")
+        .body(Body::from(r#"{"b": 1}"#))
+        .unwrap();
+    let (status, _, _) = common::respond(&app, request).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    let fields = format!(r#"{{"field": "{}"}}"#, "x".repeat(60));
+    let members = format!(r#"["{}"]"#, "x".repeat(60));
+    let scores = format!(r#"{{"{}": 1}}"#, "x".repeat(60));
+    let points = format!("[{}]", [r#"{"value": 1}"#; 10].join(","));
+    let line = format!(
+        r#"{{"column_family": "default", "key": "dGVhbS1hLXg=", "value": "{}"}}"#,
+        "eHh4".repeat(30)
+    );
+    let writes = [
+        ("/v1/hash/team-a-h", fields.as_str()),
+        ("/v1/set/team-a-s", members.as_str()),
+        ("/v1/zset/team-a-z", scores.as_str()),
+        ("/queue/team-a-q/messages", members.as_str()),
+        ("/ts/team-a-ts/points", points.as_str()),
+        ("/admin/restore", line.as_str()),
+    ];
+    for (uri, body) in writes {
+        let (status, _, body) = send(&app, "POST", uri, body).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "{}: {}", uri, body);
+    }
+    let (status, _, _) = send(&app, "POST", "/v1/hash/team-b-h", &fields).await;
+    assert_eq!(status, StatusCode::OK, "Other namespaces are unaffected");
+    let (status, _, _) = send(&app, "POST", "/get?key=team-a-x", "").await;
+    assert_eq!(
+        status,
+        StatusCode::NOT_FOUND,
+        "A refused restore writes nothing"
+    );
+}
+
+#[test]
+fn test_idle_token_buckets_are_full() {
+    let start = Instant::now();
+    let mut bucket = TokenBucket::full(2.0, start);
+    assert!(bucket.is_full(1.0, 2.0, start));
+    assert!(bucket.try_take(1.0, 2.0, start).is_ok());
+    assert!(!bucket.is_full(1.0, 2.0, start));
+    assert!(bucket.is_full(1.0, 2.0, start + Duration::from_secs(1)));
+}
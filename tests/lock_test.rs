@@ -0,0 +1,185 @@
+mod common;
+
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::{send, send_text};
+use h_rocksdb::{
+    api::routes,
+    storage::{
+        encryption::{Encryption, KeyFile},
+        lock::{Locks, LOCK_COLUMN_FAMILY},
+        wal,
+    },
+    AppState,
+};
+use serde_json::Value;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tempfile::TempDir;
+
+/// Opens the DB, creating the locks column family on first use.
+fn create_test_state(temp_dir: &TempDir) -> AppState {
+    let mut db = common::open_db_with_column_families(temp_dir);
+    Locks::default()
+        .prepare(&mut db)
+        .expect("Failed to create locks column family");
+    AppState::new(Arc::new(db))
+}
+
+#[tokio::test]
+async fn test_locks_exclude_other_owners() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let (status, lease) = send(&app, "POST", "/lock/cron/acquire?owner=a", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(lease["owner"], "a");
+    assert_eq!(lease["fence"], 1);
+    let token = lease["token"].as_str().unwrap();
+
+    let (status, _) = send(&app, "POST", "/lock/cron/acquire?owner=b", "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(&app, "POST", "/lock/cron/release?token=wrong", "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (_, status) = send(&app, "GET", "/lock/cron", "").await;
+    assert_eq!(status["held"], true);
+    assert_eq!(status["owner"], "a");
+    assert!(status.get("token").is_none());
+
+    let (status, renewed) = send(
+        &app,
+        "POST",
+        &format!("/lock/cron/renew?token={token}&ttl=60"),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(renewed["fence"], 1);
+    assert!(renewed["expires_ms"].as_u64() > lease["expires_ms"].as_u64());
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("/lock/cron/release?token={token}"),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "POST", &format!("/lock/cron/renew?token={token}"), "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, lease) = send(&app, "POST", "/lock/cron/acquire?owner=b", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(lease["fence"], 2);
+}
+
+#[tokio::test]
+async fn test_expired_leases_can_be_taken_over() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let (status, _) = send(&app, "POST", "/lock/cron/acquire?ttl=0", "").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, stale) = send(&app, "POST", "/lock/cron/acquire?owner=a&ttl=1", "").await;
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let (_, status) = send(&app, "GET", "/lock/cron", "").await;
+    assert_eq!(status["held"], false);
+    assert_eq!(status["fence"], 1);
+
+    let (status, lease) = send(&app, "POST", "/lock/cron/acquire?owner=b", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(lease["fence"], 2);
+
+    let token = stale["token"].as_str().unwrap();
+    let (status, _) = send(&app, "POST", &format!("/lock/cron/renew?token={token}"), "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("/lock/cron/release?token={token}"),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_locks_survive_restarts() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let token = {
+        let app = routes::router(create_test_state(&temp_dir));
+        let (_, lease) = send(&app, "POST", "/lock/cron/acquire?owner=a", "").await;
+        lease["token"].as_str().unwrap().to_string()
+    };
+
+    let app = routes::router(create_test_state(&temp_dir));
+    let (status, _) = send(&app, "POST", "/lock/cron/acquire?owner=b", "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("/lock/cron/release?token={token}"),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, lease) = send(&app, "POST", "/lock/cron/acquire?owner=b", "").await;
+    assert_eq!(lease["fence"], 2);
+}
+
+#[tokio::test]
+async fn test_lock_values_are_sealed() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mut state = create_test_state(&temp_dir);
+    let key_file = KeyFile {
+        active_key: "k1".to_string(),
+        keys: HashMap::from([("k1".to_string(), STANDARD.encode([7u8; 32]))]),
+    };
+    state.encryption = Arc::new(Encryption::new(key_file).unwrap());
+    let app = routes::router(state.clone());
+
+    let (status, lease) = send(&app, "POST", "/lock/cron/acquire?owner=worker-7", "").await;
+    assert_eq!(status, StatusCode::OK);
+    let column_family = state.rocksdb.cf_handle(LOCK_COLUMN_FAMILY).unwrap();
+    let stored = state
+        .rocksdb
+        .get_cf(column_family, b"cron")
+        .unwrap()
+        .unwrap();
+    let token = lease["token"].as_str().unwrap();
+    let leaked = |text: &str| stored.windows(text.len()).any(|w| w == text.as_bytes());
+    assert!(!leaked("worker-7") && !leaked(token));
+
+    let (_, status) = send(&app, "GET", "/lock/cron", "").await;
+    assert_eq!(status["owner"], "worker-7");
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("/lock/cron/release?token={token}"),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_lease_tokens_stay_out_of_the_change_feed() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir);
+    let locks = wal::column_family_id(state.rocksdb.cf_handle(LOCK_COLUMN_FAMILY).unwrap());
+    let app = routes::router(state);
+
+    let (_, lease) = send(&app, "POST", "/lock/cron/acquire?owner=a", "").await;
+    let token = lease["token"].as_str().unwrap();
+    let (status, changes) = send_text(&app, "GET", "/changes?since=0&internal=true", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!changes.contains(token));
+    let changes: Value = serde_json::from_str(&changes).unwrap();
+    let records = changes["records"].as_array().unwrap();
+    assert!(records
+        .iter()
+        .all(|record| record["column_family_id"] != locks));
+}
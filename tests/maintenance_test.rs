@@ -0,0 +1,134 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use h_rocksdb::{
+    api::routes,
+    storage::mode::{AccessMode, DbView},
+    AppState,
+};
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tempfile::TempDir;
+
+fn create_test_state(temp_dir: &TempDir) -> AppState {
+    AppState::new(Arc::new(common::open_db_with_extra_column_family(temp_dir)))
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    common::send(app, method, uri, &body.to_string()).await
+}
+
+#[tokio::test]
+async fn test_compact_and_flush_column_families() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+    for i in 0..10 {
+        send(&app, "PUT", &format!("/v1/kv/key{i}"), json!(i)).await;
+    }
+
+    let (status, flushed) = send(&app, "POST", "/admin/flush", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(flushed["column_families"], json!(["default", "extra"]));
+    let (status, compacted) = send(
+        &app,
+        "POST",
+        "/admin/compact?column_family=default&from=key2&to=key5",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(compacted["column_families"], json!(["default"]));
+    let (status, _) = send(&app, "POST", "/admin/compact", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    for uri in [
+        "/admin/compact?from=00ff&to=ff00&key_encoding=hex",
+        "/admin/compact?from=AP8%3D&to=%2F%2F8%3D&key_encoding=base64",
+    ] {
+        let (status, _) = send(&app, "POST", uri, Value::Null).await;
+        assert_eq!(status, StatusCode::OK, "{}", uri);
+    }
+    for uri in [
+        "/admin/compact?from=0f0&key_encoding=hex",
+        "/admin/compact?to=%C3%A9%C3%A9&key_encoding=hex",
+        "/admin/compact?from=!!&key_encoding=base64",
+    ] {
+        let (status, _) = send(&app, "POST", uri, Value::Null).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+    let (status, _) = send(&app, "POST", "/admin/wal/sync", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app, "POST", "/admin/flush?column_family=nope", Value::Null).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, files) = send(&app, "GET", "/admin/live-files", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    for file in files.as_array().unwrap() {
+        assert_eq!(file["column_family"], "default");
+    }
+    let (status, value) = send(&app, "GET", "/v1/kv/key3", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(value, "3");
+}
+
+#[tokio::test]
+async fn test_set_options_and_read_properties() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let (status, options) = send(
+        &app,
+        "PUT",
+        "/admin/options?column_family=extra",
+        json!({"disable_auto_compactions": true, "write_buffer_size": 8388608}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(options["disable_auto_compactions"], "true");
+    assert_eq!(options["write_buffer_size"], "8388608");
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/admin/options",
+        json!({"write_buffer_size": [1]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, "PUT", "/admin/options", json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, property) = send(
+        &app,
+        "GET",
+        "/admin/properties/rocksdb.estimate-num-keys?column_family=extra",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(property["name"], "rocksdb.estimate-num-keys");
+    assert!(property["value"].as_str().unwrap().parse::<u64>().is_ok());
+    let (status, _) = send(&app, "GET", "/admin/properties/leveldb.stats", Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_maintenance_needs_write_access() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mut state = create_test_state(&temp_dir);
+    state.view = Arc::new(DbView::new(AccessMode::ReadOnly, Duration::MAX));
+    let app = routes::router(state);
+
+    for uri in ["/admin/compact", "/admin/flush", "/admin/wal/sync"] {
+        let (status, _) = send(&app, "POST", uri, Value::Null).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED, "{}", uri);
+    }
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/admin/options",
+        json!({"disable_auto_compactions": true}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    let (status, _) = send(&app, "GET", "/admin/properties/rocksdb.stats", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+}
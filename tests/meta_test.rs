@@ -0,0 +1,137 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::create_test_state;
+use h_rocksdb::api::{handlers::TAGS_HEADER, routes};
+use serde_json::Value;
+use tempfile::TempDir;
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> (StatusCode, Vec<u8>) {
+    let mut request = Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let request = request.body(Body::from(body.to_string())).unwrap();
+    let (status, _, body) = common::respond(app, request).await;
+    (status, body)
+}
+
+async fn meta(app: &Router, key: &str) -> (StatusCode, Value) {
+    let (status, body) = send(app, "GET", &format!("/meta?key={}", key), &[], "").await;
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_meta_tracks_versions_and_timestamps() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let headers = [
+        ("content-type", "application/json"),
+        (TAGS_HEADER, "red, blue"),
+    ];
+    send(&app, "POST", "/put?key=key1", &headers, "{\"a\":1}").await;
+    let (status, first) = meta(&app, "key1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first["version"], 1);
+    assert_eq!(first["size"], 7);
+    assert_eq!(first["chunked"], false);
+    assert_eq!(first["content_type"], "application/json");
+    assert_eq!(first["modified_by"], "anonymous");
+    assert_eq!(first["tags"], serde_json::json!(["red", "blue"]));
+    assert_eq!(first["created_ms"], first["modified_ms"]);
+    assert!(
+        first.get("value").is_none(),
+        "The value body is not returned"
+    );
+
+    send(&app, "PUT", "/v1/kv/key1", &[], "value2").await;
+    let (_, second) = meta(&app, "key1").await;
+    assert_eq!(second["version"], 2);
+    assert_eq!(second["created_ms"], first["created_ms"]);
+    assert!(second["modified_ms"].as_u64() >= first["modified_ms"].as_u64());
+    assert_eq!(second["tags"], serde_json::json!([]));
+    assert_ne!(second["sha256"], first["sha256"]);
+
+    let (status, _) = meta(&app, "missing").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_blob_keeps_content_type_and_version() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    send(&app, "PUT", "/v1/kv/image", &[], "placeholder").await;
+    let headers = [("content-type", "image/png"), (TAGS_HEADER, "avatar")];
+    send(&app, "PUT", "/blob?key=image", &headers, "png bytes").await;
+
+    let (_, described) = meta(&app, "image").await;
+    assert_eq!(described["version"], 2);
+    assert_eq!(described["chunked"], true);
+    assert_eq!(described["size"], 9);
+    assert_eq!(described["tags"], serde_json::json!(["avatar"]));
+
+    let request = Request::get("/blob?key=image").body(Body::empty()).unwrap();
+    let (_, headers, _) = common::respond(&app, request).await;
+    assert_eq!(headers[header::CONTENT_TYPE], "image/png");
+}
+
+#[tokio::test]
+async fn test_meta_of_value_written_without_header() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir);
+    state.rocksdb.put(b"legacy", b"old").unwrap();
+    let app = routes::router(state);
+
+    let (status, described) = meta(&app, "legacy").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(described["version"], 1);
+    assert_eq!(described["size"], 3);
+    assert!(described["created_ms"].is_null());
+
+    send(&app, "POST", "/put?key=legacy", &[], "new").await;
+    let (_, described) = meta(&app, "legacy").await;
+    assert_eq!(described["version"], 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_writers_take_distinct_versions() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let writers: Vec<_> = (0..32)
+        .map(|i| {
+            let app = app.clone();
+            let key = ["shared", "other"][i % 2];
+            tokio::spawn(async move {
+                send(
+                    &app,
+                    "POST",
+                    &format!("/put?key={key}"),
+                    &[],
+                    &i.to_string(),
+                )
+                .await
+            })
+        })
+        .collect();
+    for writer in writers {
+        assert_eq!(writer.await.unwrap().0, StatusCode::OK);
+    }
+    for key in ["shared", "other"] {
+        let (_, described) = send(&app, "GET", &format!("/meta?key={key}"), &[], "").await;
+        let described: Value = serde_json::from_slice(&described).unwrap();
+        assert_eq!(described["version"], 16, "{}", key);
+    }
+}
@@ -0,0 +1,98 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use h_rocksdb::{
+    api::routes,
+    storage::mode::{self, AccessMode, DbView},
+    AppState,
+};
+use rocksdb::Options;
+use std::{sync::Arc, time::Duration};
+use tempfile::TempDir;
+use tower::util::ServiceExt;
+
+fn create_read_only_state() -> (AppState, TempDir) {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let path = temp_dir.path().join("rocks.db");
+
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    {
+        let db =
+            mode::open(&opts, &path, &AccessMode::ReadWrite).expect("Failed to open test database");
+        db.put(b"key1", b"value1").unwrap();
+    }
+
+    let db = mode::open(&opts, &path, &AccessMode::ReadOnly)
+        .expect("Failed to open test database read-only");
+    let mut state = AppState::new(Arc::new(db));
+    state.view = Arc::new(DbView::new(AccessMode::ReadOnly, Duration::MAX));
+    (state, temp_dir)
+}
+
+#[tokio::test]
+async fn test_read_only_mode_rejects_writes() {
+    let (state, _temp_dir) = create_read_only_state();
+    let app = routes::router(state);
+
+    let put_request = Request::builder()
+        .method("POST")
+        .uri("/put?key=key2")
+        .body(Body::from("value2"))
+        .unwrap();
+    let put_response = app.clone().oneshot(put_request).await.unwrap();
+    assert_eq!(put_response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let body = to_bytes(put_response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body_str = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body_str.contains("read_only"));
+
+    let get_request = Request::builder()
+        .method("POST")
+        .uri("/get?key=key1")
+        .body(Body::empty())
+        .unwrap();
+    let get_response = app.oneshot(get_request).await.unwrap();
+    assert_eq!(get_response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_ready_reports_mode() {
+    let (state, _temp_dir) = create_read_only_state();
+    let app = routes::router(state);
+
+    let request = Request::builder()
+        .uri("/ready")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["ready"], true);
+    assert_eq!(json["mode"], "read_only");
+}
+
+#[test]
+fn test_secondary_view_becomes_stale() {
+    let view = DbView::new(
+        AccessMode::Secondary {
+            secondary_path: "unused".into(),
+        },
+        Duration::from_millis(10),
+    );
+    assert!(view.readiness().ready, "A fresh view should be ready");
+
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(!view.readiness().ready, "An old view should be stale");
+
+    view.record_refresh();
+    assert!(
+        view.readiness().ready,
+        "Catching up should refresh the view"
+    );
+}
@@ -0,0 +1,231 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::send;
+use h_rocksdb::{
+    api::routes,
+    storage::{
+        encryption::{Encryption, KeyFile},
+        queue::{Queues, QUEUE_COLUMN_FAMILY},
+    },
+    AppState,
+};
+use serde_json::Value;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Opens the DB, creating the queues column family on first use.
+fn create_test_state(temp_dir: &TempDir, max_attempts: u32) -> AppState {
+    let mut db = common::open_db_with_column_families(temp_dir);
+    let queues = Queues::new(max_attempts);
+    queues
+        .prepare(&mut db, &Encryption::disabled())
+        .expect("Failed to prepare queues");
+    let mut state = AppState::new(Arc::new(db));
+    state.queues = Arc::new(queues);
+    state
+}
+
+async fn dequeue(app: &Router, uri: &str) -> Vec<Value> {
+    let (status, body) = send(app, "POST", uri, "").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body.as_array().unwrap().clone()
+}
+
+fn answer(message: &Value, verb: &str) -> String {
+    format!(
+        "/queue/jobs/messages/{}/{}?receipt={}",
+        message["id"],
+        verb,
+        message["receipt"].as_str().unwrap()
+    )
+}
+
+#[tokio::test]
+async fn test_messages_are_delivered_in_order_and_acked() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir, 5));
+
+    for body in ["first", "second", "third"] {
+        let (status, _) = send(&app, "POST", "/queue/jobs/messages", body).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    send(&app, "POST", "/queue/jobs/messages?delay=3600", "later").await;
+
+    let delivered = dequeue(&app, "/queue/jobs/dequeue?max=2").await;
+    let bodies: Vec<&str> = delivered
+        .iter()
+        .map(|m| m["body"].as_str().unwrap())
+        .collect();
+    assert_eq!(bodies, ["first", "second"]);
+    assert_eq!(delivered[0]["attempts"], 1);
+
+    let (_, status) = send(&app, "GET", "/queue/jobs", "").await;
+    assert_eq!(status["waiting"], 2);
+    assert_eq!(status["in_flight"], 2);
+
+    let (status, _) = send(&app, "POST", &answer(&delivered[0], "ack"), "").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "POST", &answer(&delivered[0], "ack"), "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let stale = format!(
+        "/queue/jobs/messages/{}/ack?receipt=nope",
+        delivered[1]["id"]
+    );
+    let (status, _) = send(&app, "POST", &stale, "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let delivered = dequeue(&app, "/queue/jobs/dequeue?max=10").await;
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0]["body"], "third");
+}
+
+#[tokio::test]
+async fn test_failed_messages_are_dead_lettered() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir, 2));
+
+    send(&app, "POST", "/queue/jobs/messages", "timed out").await;
+
+    // A zero visibility timeout makes an unacked message due again at once.
+    let first = dequeue(&app, "/queue/jobs/dequeue?visibility_timeout=0").await;
+    assert_eq!(first[0]["body"], "timed out");
+    let again = dequeue(&app, "/queue/jobs/dequeue?visibility_timeout=0").await;
+    assert_eq!(again[0]["body"], "timed out");
+    assert_eq!(again[0]["attempts"], 2);
+    let (status, _) = send(&app, "POST", &answer(&first[0], "nack"), "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Due messages are delivered in the order they became due.
+    send(&app, "POST", "/queue/jobs/messages", "rejected").await;
+
+    let rejected = dequeue(&app, "/queue/jobs/dequeue").await;
+    assert_eq!(rejected[0]["body"], "rejected");
+    let (_, nacked) = send(&app, "POST", &answer(&rejected[0], "nack"), "").await;
+    assert_eq!(nacked["dead_lettered"], false);
+    let rejected = dequeue(&app, "/queue/jobs/dequeue").await;
+    assert_eq!(rejected[0]["attempts"], 2);
+    let (_, nacked) = send(&app, "POST", &answer(&rejected[0], "nack"), "").await;
+    assert_eq!(nacked["dead_lettered"], true);
+
+    assert!(dequeue(&app, "/queue/jobs/dequeue").await.is_empty());
+    let (_, dead) = send(&app, "GET", "/queue/jobs/dead-letters", "").await;
+    let bodies: Vec<&str> = dead
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["body"].as_str().unwrap())
+        .collect();
+    assert_eq!(bodies, ["timed out", "rejected"]);
+
+    let (_, metrics) = common::send_text(&app, "GET", "/metrics", "").await;
+    assert!(metrics.contains("rocksdb_queue_messages{queue=\"jobs\",state=\"dead_letter\"} 2"));
+    assert!(metrics.contains("rocksdb_queue_messages{queue=\"jobs\",state=\"waiting\"} 0"));
+}
+
+#[tokio::test]
+async fn test_queues_survive_restarts() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    {
+        let app = routes::router(create_test_state(&temp_dir, 5));
+        send(&app, "POST", "/queue/jobs/messages", "one").await;
+        send(&app, "POST", "/queue/jobs/messages", "two").await;
+        dequeue(&app, "/queue/jobs/dequeue").await;
+    }
+
+    let state = create_test_state(&temp_dir, 5);
+    assert!(state.rocksdb.cf_handle(QUEUE_COLUMN_FAMILY).is_some());
+    let app = routes::router(state);
+    let (_, status) = send(&app, "GET", "/queue/jobs", "").await;
+    assert_eq!(status["waiting"], 1);
+    assert_eq!(status["in_flight"], 1);
+
+    let (_, message) = send(&app, "POST", "/queue/jobs/messages", "three").await;
+    assert_eq!(message["id"], 3);
+    let delivered = dequeue(&app, "/queue/jobs/dequeue").await;
+    assert_eq!(delivered[0]["body"], "two");
+}
+
+#[tokio::test]
+async fn test_due_messages_skip_delayed_ones_and_depths_are_counted() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir, 5));
+
+    send(&app, "POST", "/queue/jobs/messages?delay=3600", "later").await;
+    send(&app, "POST", "/queue/jobs/messages", "now").await;
+    send(&app, "POST", "/queue/other/messages", "elsewhere").await;
+    let delivered = dequeue(&app, "/queue/jobs/dequeue?max=10").await;
+    let bodies: Vec<&str> = delivered
+        .iter()
+        .map(|m| m["body"].as_str().unwrap())
+        .collect();
+    assert_eq!(bodies, ["now"]);
+    let (_, nacked) = send(&app, "POST", &answer(&delivered[0], "nack"), "").await;
+    assert_eq!(nacked["dead_lettered"], false);
+    let again = dequeue(&app, "/queue/jobs/dequeue").await;
+    assert_eq!(again[0]["body"], "now");
+    assert_eq!(again[0]["attempts"], 2);
+
+    let (_, metrics) = common::send_text(&app, "GET", "/metrics", "").await;
+    assert!(metrics.contains("rocksdb_queue_messages{queue=\"jobs\",state=\"waiting\"} 1"));
+    assert!(metrics.contains("rocksdb_queue_messages{queue=\"jobs\",state=\"in_flight\"} 1"));
+    assert!(metrics.contains("rocksdb_queue_messages{queue=\"other\",state=\"waiting\"} 1"));
+
+    send(&app, "POST", &answer(&again[0], "ack"), "").await;
+    let (_, status) = send(&app, "GET", "/queue/jobs", "").await;
+    assert_eq!(status["waiting"], 1);
+    assert_eq!(status["in_flight"], 0);
+}
+
+#[tokio::test]
+async fn test_dequeue_max_is_clamped() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir, 3);
+    for i in 0..1001 {
+        state
+            .queues
+            .enqueue(&state.rocksdb, &state.encryption, "jobs", i.to_string(), 0)
+            .unwrap();
+    }
+    let app = routes::router(state);
+
+    let messages = dequeue(&app, "/queue/jobs/dequeue?max=100000").await;
+    assert_eq!(messages.len(), 1000);
+}
+
+#[test]
+fn test_messages_are_readable_after_key_rotation() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir, 3);
+    let keyring = |active: &str, ids: &[&str]| {
+        Encryption::new(KeyFile {
+            active_key: active.to_string(),
+            keys: ids
+                .iter()
+                .map(|id| (id.to_string(), STANDARD.encode([id.as_bytes()[1]; 32])))
+                .collect(),
+        })
+        .unwrap()
+    };
+    let old = keyring("k1", &["k1"]);
+    state
+        .queues
+        .enqueue(&state.rocksdb, &old, "jobs", "hello".to_string(), 0)
+        .unwrap();
+
+    let rotated = keyring("k2", &["k1", "k2"]);
+    assert!(rotated.start_reencrypt());
+    rotated.reencrypt(&state.rocksdb);
+    let status = rotated.status();
+    assert_eq!(status.error, None);
+    assert_eq!(status.failed, 0);
+
+    let new = keyring("k2", &["k2"]);
+    let messages = state
+        .queues
+        .dequeue(&state.rocksdb, &new, "jobs", 30_000, 10)
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].body, "hello");
+}
@@ -0,0 +1,182 @@
+mod common;
+
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::send_text;
+use h_rocksdb::{
+    api::routes,
+    replication::{follower, Replication, WalBatch, WalFrame},
+    storage::checkpoint,
+    AppState,
+};
+use rocksdb::WriteBatch;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn create_test_state(follower: bool) -> (AppState, TempDir) {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mut state = common::create_test_state(&temp_dir);
+    if follower {
+        state.replication = Arc::new(Replication::follower("http://127.0.0.1:1".to_string()));
+    }
+    (state, temp_dir)
+}
+
+#[tokio::test]
+async fn test_follower_rejects_writes_until_promoted() {
+    let (state, _temp_dir) = create_test_state(true);
+    let app = routes::router(state);
+
+    let (status, body) = send_text(&app, "POST", "/put?key=key1", "value1").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(body.contains("follower"));
+
+    let (status, _) = send_text(&app, "POST", "/get?key=key1", "").await;
+    assert_eq!(
+        status,
+        StatusCode::NOT_FOUND,
+        "Followers should serve reads"
+    );
+
+    let (status, _) = send_text(&app, "POST", "/admin/promote", "").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send_text(&app, "POST", "/put?key=key1", "value1").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_replication_status_reports_role() {
+    let (state, _temp_dir) = create_test_state(true);
+    let app = routes::router(state);
+
+    let (status, body) = send_text(&app, "GET", "/replication/status", "").await;
+    assert_eq!(status, StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["role"], "follower");
+    assert_eq!(json["primary_url"], "http://127.0.0.1:1");
+    assert_eq!(json["applied_sequence"], 0);
+}
+
+#[tokio::test]
+async fn test_checkpoint_lifecycle() {
+    let (state, _temp_dir) = create_test_state(false);
+    state.rocksdb.put(b"key1", b"value1").unwrap();
+    let app = routes::router(state);
+
+    let (status, body) = send_text(&app, "POST", "/replication/checkpoints", "").await;
+    assert_eq!(status, StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let id = json["id"].as_str().unwrap().to_string();
+    let file = json["files"][0]["name"].as_str().unwrap().to_string();
+
+    let (status, _) = send_text(
+        &app,
+        "GET",
+        &format!("/replication/checkpoints/{}/{}", id, file),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send_text(
+        &app,
+        "GET",
+        &format!("/replication/checkpoints/{}/..", id),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send_text(
+        &app,
+        "DELETE",
+        &format!("/replication/checkpoints/{}", id),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_follower_lag_is_exported_as_metrics() {
+    let (state, _temp_dir) = create_test_state(true);
+    let app = routes::router(state.clone());
+
+    let (_, metrics) = send_text(&app, "GET", "/metrics", "").await;
+    assert!(!metrics.contains("rocksdb_replication_lag_sequences"));
+
+    state.rocksdb.put(b"key1", b"value1").unwrap();
+    state
+        .replication
+        .record_contact(state.rocksdb.latest_sequence_number() + 3);
+    let (status, metrics) = send_text(&app, "GET", "/metrics", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        metrics.contains("\nrocksdb_replication_lag_sequences 3\n"),
+        "{}",
+        metrics
+    );
+    assert!(metrics.contains("\nrocksdb_replication_last_contact_seconds "));
+
+    let (primary, _primary_dir) = create_test_state(false);
+    let (_, metrics) = send_text(&routes::router(primary), "GET", "/metrics", "").await;
+    assert!(!metrics.contains("rocksdb_replication_"));
+}
+
+#[tokio::test]
+async fn test_checkpoints_left_behind_are_swept() {
+    let (state, _temp_dir) = create_test_state(false);
+    state.rocksdb.put(b"key1", b"value1").unwrap();
+    let app = routes::router(state.clone());
+    assert_eq!(checkpoint::sweep(&state.rocksdb).unwrap(), 0);
+
+    let (_, body) = send_text(&app, "POST", "/replication/checkpoints", "").await;
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let id = json["id"].as_str().unwrap();
+    let file = json["files"][0]["name"].as_str().unwrap();
+
+    assert_eq!(checkpoint::sweep(&state.rocksdb).unwrap(), 1);
+    let (status, _) = send_text(
+        &app,
+        "GET",
+        &format!("/replication/checkpoints/{}/{}", id, file),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(checkpoint::checkpoints_dir(&state.rocksdb).exists());
+}
+
+#[test]
+fn test_follower_refuses_batches_for_column_families_it_lacks() {
+    let (state, _temp_dir) = create_test_state(true);
+    let mut batch = WriteBatch::default();
+    batch.put(b"key1", b"value1");
+    let frame = |column_families: &[&str]| WalFrame {
+        primary_sequence: 1,
+        batches: vec![WalBatch {
+            seq: 1,
+            data: STANDARD.encode(batch.data()),
+        }],
+        column_families: column_families
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    };
+
+    let error = follower::apply(
+        &state.rocksdb,
+        &state.replication,
+        &frame(&["default", "idx_by_owner"]),
+    )
+    .unwrap_err();
+    assert!(error.contains("idx_by_owner"));
+    assert_eq!(state.rocksdb.get(b"key1").unwrap(), None);
+
+    assert!(follower::apply(&state.rocksdb, &state.replication, &frame(&["default"])).unwrap());
+    assert_eq!(
+        state.rocksdb.get(b"key1").unwrap(),
+        Some(b"value1".to_vec())
+    );
+}
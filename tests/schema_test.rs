@@ -0,0 +1,353 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use common::create_test_state;
+use h_rocksdb::{
+    api::routes,
+    storage::{
+        encryption::Encryption,
+        queue::Queues,
+        schema::{Schemas, Scope},
+        timeseries::TimeSeries,
+    },
+    AppState,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    content_type: &str,
+    body: &str,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", content_type)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let (status, _, body) = common::respond(app, request).await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// A state whose DB has the queue and time series column families.
+fn create_state_with_column_families(temp_dir: &TempDir) -> AppState {
+    let mut db = common::open_db(temp_dir);
+    Queues::new(3)
+        .prepare(&mut db, &Encryption::disabled())
+        .expect("Failed to prepare queues");
+    TimeSeries::default()
+        .prepare(&mut db)
+        .expect("Failed to create time series column family");
+    AppState::new(Arc::new(db))
+}
+
+fn user_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["email"],
+        "properties": {
+            "email": {"type": "string"},
+            "age": {"type": "integer", "minimum": 0}
+        }
+    })
+}
+
+#[tokio::test]
+async fn test_writes_are_validated_against_the_schema() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/admin/schemas?prefix=user/",
+        "application/json",
+        r#"{"type": 5}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/admin/schemas?prefix=user/",
+        "application/json",
+        &user_schema().to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/v1/kv/user/1",
+        "application/json",
+        r#"{"email": "ann@example.com", "age": 31}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(
+        &app,
+        "PUT",
+        "/v1/kv/user/2",
+        "application/json",
+        r#"{"age": -1}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let mut paths: Vec<&str> = body["violations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|violation| violation["path"].as_str().unwrap())
+        .collect();
+    paths.sort();
+    assert_eq!(paths, ["", "/age"]);
+    assert_eq!(body["violations"][0]["prefix"], "user/");
+
+    let (status, _) = send(&app, "PUT", "/v1/kv/user/3", "text/plain", "not json").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, "PUT", "/v1/kv/other", "text/plain", "not json").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_patches_and_nested_prefixes_are_validated() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    send(
+        &app,
+        "PUT",
+        "/admin/schemas?prefix=user/",
+        "application/json",
+        &user_schema().to_string(),
+    )
+    .await;
+    send(
+        &app,
+        "PUT",
+        "/admin/schemas?prefix=user/admin/",
+        "application/json",
+        r#"{"required": ["role"]}"#,
+    )
+    .await;
+
+    let (status, body) = send(
+        &app,
+        "PUT",
+        "/v1/kv/user/admin/1",
+        "application/json",
+        r#"{"role": "owner"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["violations"][0]["prefix"], "user/");
+
+    send(
+        &app,
+        "PUT",
+        "/v1/kv/user/admin/1",
+        "application/json",
+        r#"{"role": "owner", "email": "ann@example.com"}"#,
+    )
+    .await;
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        "/v1/kv/user/admin/1",
+        "application/merge-patch+json",
+        r#"{"role": null, "age": 2.5}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["violations"].as_array().unwrap().len(), 2);
+
+    let (_, value) = send(&app, "GET", "/v1/kv/user/admin/1", "", "").await;
+    let stored: Value = serde_json::from_str(value.as_str().unwrap()).unwrap();
+    assert_eq!(stored, json!({"role": "owner", "email": "ann@example.com"}));
+}
+
+#[tokio::test]
+async fn test_schemas_persist_and_detach() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir);
+    let app = routes::router(state.clone());
+
+    send(
+        &app,
+        "PUT",
+        "/admin/schemas?prefix=user/",
+        "application/json",
+        &user_schema().to_string(),
+    )
+    .await;
+    let (status, body) = send(&app, "GET", "/admin/schemas", "", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!([{"prefix": "user/", "schema": user_schema()}]));
+
+    let reloaded = Schemas::load(&state.rocksdb, &Encryption::disabled()).unwrap();
+    assert_eq!(
+        reloaded.list(),
+        vec![(Scope::Prefix("user/".to_string()), user_schema())]
+    );
+
+    let (status, _) = send(&app, "DELETE", "/admin/schemas?prefix=user/", "", "").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "DELETE", "/admin/schemas?prefix=user/", "", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, "PUT", "/v1/kv/user/1", "application/json", "{}").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let reloaded = Schemas::load(&state.rocksdb, &Encryption::disabled()).unwrap();
+    assert!(reloaded.list().is_empty());
+}
+
+#[tokio::test]
+async fn test_queue_and_time_series_writes_are_validated() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_state_with_column_families(&temp_dir);
+    let app = routes::router(state.clone());
+
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/admin/schemas?column_family=locks",
+        "application/json",
+        "{}",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/admin/schemas?prefix=user/&column_family=queues",
+        "application/json",
+        "{}",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = send(
+        &app,
+        "PUT",
+        "/admin/schemas?column_family=queues",
+        "application/json",
+        r#"{"type": "object", "required": ["job"]}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["column_family"], "queues");
+    send(
+        &app,
+        "PUT",
+        "/admin/schemas?column_family=timeseries",
+        "application/json",
+        r#"{"properties": {"value": {"minimum": 0}}}"#,
+    )
+    .await;
+
+    let (status, _) = send(&app, "POST", "/queue/jobs/messages", "", r#"{"job": 1}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "POST", "/queue/jobs/messages", "", "not json").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = send(&app, "POST", "/queue/jobs/messages", "", "{}").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["violations"][0]["column_family"], "queues");
+    assert!(body["violations"][0].get("prefix").is_none());
+
+    let (status, body) = send(
+        &app,
+        "POST",
+        "/ts/cpu/points",
+        "application/json",
+        r#"[{"timestamp": 1000, "value": 1.5}, {"timestamp": 2000, "value": -1}]"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["violations"][0]["path"], "/1/value");
+    let (_, points) = send(&app, "GET", "/ts/cpu", "", "").await;
+    assert_eq!(points, json!([]));
+
+    let reloaded = Schemas::load(&state.rocksdb, &Encryption::disabled()).unwrap();
+    assert!(reloaded.covers_column_family("queues"));
+    let (status, _) = send(
+        &app,
+        "DELETE",
+        "/admin/schemas?column_family=queues",
+        "",
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "POST", "/queue/jobs/messages", "", "not json").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_structures_are_validated_against_their_prefix() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    send(
+        &app,
+        "PUT",
+        "/admin/schemas?prefix=profile/",
+        "application/json",
+        r#"{"required": ["email"], "maxProperties": 2}"#,
+    )
+    .await;
+    send(
+        &app,
+        "PUT",
+        "/admin/schemas?prefix=tags/",
+        "application/json",
+        r#"{"type": "array", "maxItems": 2}"#,
+    )
+    .await;
+    send(
+        &app,
+        "PUT",
+        "/admin/schemas?prefix=scores/",
+        "application/json",
+        r#"{"additionalProperties": {"maximum": 100}}"#,
+    )
+    .await;
+
+    let (status, body) = send(&app, "POST", "/v1/hash/profile/1", "", r#"{"name": "ann"}"#).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["violations"][0]["prefix"], "profile/");
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/v1/hash/profile/1",
+        "",
+        r#"{"name": "ann", "email": "ann@example.com"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    // The schema applies to the hash as a whole, fields already set included.
+    let (status, _) = send(&app, "POST", "/v1/hash/profile/1", "", r#"{"age": "31"}"#).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, _) = send(&app, "POST", "/v1/set/tags/1", "", r#"["a", "b"]"#).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "POST", "/v1/set/tags/1", "", r#"["c"]"#).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (_, members) = send(&app, "GET", "/v1/set/tags/1", "", "").await;
+    assert_eq!(members, json!(["a", "b"]));
+
+    let (status, _) = send(&app, "POST", "/v1/zset/scores/1", "", r#"{"ann": 90}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = send(&app, "POST", "/v1/zset/scores/1", "", r#"{"bob": 120}"#).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["violations"][0]["path"], "/bob");
+}
@@ -0,0 +1,184 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{create_test_state, send};
+use h_rocksdb::api::routes;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_hashes() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let (status, body) = send(
+        &app,
+        "POST",
+        "/v1/hash/user/1",
+        r#"{"name": "Ann", "city": "Oslo"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["added"], 2);
+    let (_, body) = send(
+        &app,
+        "POST",
+        "/v1/hash/user/1",
+        r#"{"city": "Bergen", "zip": "5003"}"#,
+    )
+    .await;
+    assert_eq!(body["added"], 1);
+    send(&app, "POST", "/v1/hash/user/10", r#"{"name": "Bob"}"#).await;
+
+    let (_, fields) = send(&app, "GET", "/v1/hash/user/1", "").await;
+    assert_eq!(
+        fields,
+        json!({"name": "Ann", "city": "Bergen", "zip": "5003"})
+    );
+    let (status, value) = send(&app, "GET", "/v1/hash/user/1?field=city", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(value, "Bergen");
+    let (status, _) = send(&app, "GET", "/v1/hash/user/1?field=age", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, fields) = send(&app, "GET", "/v1/hash/missing", "").await;
+    assert_eq!(fields, json!({}));
+
+    let (status, _) = send(&app, "POST", "/v1/hash/user/1", r#"{"age": 31}"#).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, "GET", "/v1/kv/user/1", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_sets() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let (_, body) = send(&app, "POST", "/v1/set/tags", r#"["red", "blue", "red"]"#).await;
+    assert_eq!(body["added"], 2);
+    let (_, body) = send(&app, "POST", "/v1/set/tags", r#"["green", "blue"]"#).await;
+    assert_eq!(body["added"], 1);
+
+    let (status, members) = send(&app, "GET", "/v1/set/tags", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(members, json!(["blue", "green", "red"]));
+    let (_, members) = send(&app, "GET", "/v1/set/other", "").await;
+    assert_eq!(members, json!([]));
+}
+
+#[tokio::test]
+async fn test_sorted_sets() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    let (_, body) = send(
+        &app,
+        "POST",
+        "/v1/zset/scores",
+        r#"{"ann": 31, "bob": -2.5, "cat": 100, "dan": 0}"#,
+    )
+    .await;
+    assert_eq!(body["added"], 4);
+    let (_, body) = send(&app, "POST", "/v1/zset/scores", r#"{"cat": 7, "eve": 7}"#).await;
+    assert_eq!(body["added"], 1);
+
+    let members = |range: Value| -> Vec<String> {
+        range
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["member"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let (_, range) = send(&app, "GET", "/v1/zset/scores", "").await;
+    assert_eq!(members(range.clone()), ["bob", "dan", "cat", "eve", "ann"]);
+    assert_eq!(range[0]["score"], -2.5);
+
+    let (_, range) = send(&app, "GET", "/v1/zset/scores?min=0&max=7", "").await;
+    assert_eq!(members(range), ["dan", "cat", "eve"]);
+    let (_, range) = send(&app, "GET", "/v1/zset/scores?min=-10&limit=2", "").await;
+    assert_eq!(members(range), ["bob", "dan"]);
+    let (_, range) = send(&app, "GET", "/v1/zset/scores?min=50", "").await;
+    assert_eq!(members(range), Vec::<String>::new());
+}
+
+#[tokio::test]
+async fn test_hashes_and_sets_are_read_in_pages() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    send(
+        &app,
+        "POST",
+        "/v1/hash/h",
+        r#"{"a": "1", "b": "2", "c": "3"}"#,
+    )
+    .await;
+    let (_, fields) = send(&app, "GET", "/v1/hash/h?limit=2", "").await;
+    assert_eq!(fields, json!({"a": "1", "b": "2"}));
+    let (_, fields) = send(&app, "GET", "/v1/hash/h?after=b&limit=2", "").await;
+    assert_eq!(fields, json!({"c": "3"}));
+
+    send(&app, "POST", "/v1/set/s", r#"["x", "y", "z"]"#).await;
+    let (_, members) = send(&app, "GET", "/v1/set/s?limit=1", "").await;
+    assert_eq!(members, json!(["x"]));
+    let (_, members) = send(&app, "GET", "/v1/set/s?after=x", "").await;
+    assert_eq!(members, json!(["y", "z"]));
+}
+
+#[tokio::test]
+async fn test_fields_members_and_structures_are_removed() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    send(&app, "POST", "/v1/hash/h", r#"{"a": "1", "b": "2"}"#).await;
+    let (status, body) = send(&app, "DELETE", "/v1/hash/h", r#"["a", "missing"]"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["removed"], 1);
+    let (_, fields) = send(&app, "GET", "/v1/hash/h", "").await;
+    assert_eq!(fields, json!({"b": "2"}));
+
+    send(&app, "POST", "/v1/set/s", r#"["x", "y"]"#).await;
+    let (_, body) = send(&app, "DELETE", "/v1/set/s", r#"["y"]"#).await;
+    assert_eq!(body["removed"], 1);
+    let (_, members) = send(&app, "GET", "/v1/set/s", "").await;
+    assert_eq!(members, json!(["x"]));
+
+    send(&app, "POST", "/v1/zset/z", r#"{"ann": 1, "bob": 2}"#).await;
+    let (_, body) = send(&app, "DELETE", "/v1/zset/z", r#"["ann"]"#).await;
+    assert_eq!(body["removed"], 1);
+    let (_, range) = send(&app, "GET", "/v1/zset/z", "").await;
+    assert_eq!(range, json!([{"member": "bob", "score": 2.0}]));
+    let (status, _) = send(&app, "DELETE", "/v1/zset/z", "not json").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, body) = send(&app, "DELETE", "/v1/zset/z", "").await;
+    assert_eq!(body["removed"], 1);
+    let (_, range) = send(&app, "GET", "/v1/zset/z", "").await;
+    assert_eq!(range, json!([]));
+    let (_, fields) = send(&app, "GET", "/v1/hash/h", "").await;
+    assert_eq!(fields, json!({"b": "2"}));
+}
+
+#[tokio::test]
+async fn test_deleting_a_key_deletes_its_structures() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir));
+
+    send(&app, "POST", "/v1/hash/k", r#"{"a": "1"}"#).await;
+    send(&app, "POST", "/v1/set/k", r#"["x"]"#).await;
+    send(&app, "POST", "/v1/set/k2", r#"["y"]"#).await;
+
+    let (status, body) = send(&app, "DELETE", "/v1/kv/k", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["deleted"], true);
+    let (_, fields) = send(&app, "GET", "/v1/hash/k", "").await;
+    assert_eq!(fields, json!({}));
+    let (_, members) = send(&app, "GET", "/v1/set/k", "").await;
+    assert_eq!(members, json!([]));
+    let (_, members) = send(&app, "GET", "/v1/set/k2", "").await;
+    assert_eq!(members, json!(["y"]));
+
+    let (_, body) = send(&app, "DELETE", "/v1/kv/k", "").await;
+    assert_eq!(body["deleted"], false);
+}
@@ -0,0 +1,203 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use h_rocksdb::{api::routes, storage::timeseries::TimeSeries, AppState};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Opens the DB, creating the time series column family on first use.
+fn create_test_state(temp_dir: &TempDir, timeseries: TimeSeries) -> AppState {
+    let mut db = common::open_db_with_column_families(temp_dir);
+    timeseries
+        .prepare(&mut db)
+        .expect("Failed to create time series column family");
+    let mut state = AppState::new(Arc::new(db));
+    state.timeseries = Arc::new(timeseries);
+    state
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    common::send(app, method, uri, &body.to_string()).await
+}
+
+async fn read(app: &Router, uri: &str) -> Value {
+    let (status, points) = send(app, "GET", uri, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    points
+}
+
+#[tokio::test]
+async fn test_append_and_read_points() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir, TimeSeries::default()));
+
+    let (status, appended) = send(
+        &app,
+        "POST",
+        "/ts/cpu/points",
+        json!([
+            {"timestamp": 3000, "value": 3.5},
+            {"timestamp": 1000, "value": 1.0},
+            {"timestamp": 2000, "value": 2.0},
+        ]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(appended["points"], 3);
+    send(
+        &app,
+        "POST",
+        "/ts/cpu/points",
+        json!([{"timestamp": 2000, "value": 20.0}]),
+    )
+    .await;
+    send(
+        &app,
+        "POST",
+        "/ts/cpu2/points",
+        json!([{"timestamp": 1500, "value": 9.0}]),
+    )
+    .await;
+
+    assert_eq!(
+        read(&app, "/ts/cpu").await,
+        json!([
+            {"timestamp": 1000, "value": 1.0},
+            {"timestamp": 2000, "value": 20.0},
+            {"timestamp": 3000, "value": 3.5},
+        ])
+    );
+    assert_eq!(
+        read(&app, "/ts/cpu?from=1500&to=3000&limit=1").await,
+        json!([{"timestamp": 2000, "value": 20.0}])
+    );
+    assert_eq!(read(&app, "/ts/missing").await, json!([]));
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/ts/cpu/points",
+        json!([{"timestamp": 4000, "value": "high"}]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, "GET", "/ts/cpu?step=0", Value::Null).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_aggregate_over_buckets() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let app = routes::router(create_test_state(&temp_dir, TimeSeries::default()));
+
+    let points: Vec<Value> = (0..6)
+        .map(|i| json!({"timestamp": 10_000 + i * 500, "value": i}))
+        .collect();
+    send(&app, "POST", "/ts/load/points", Value::Array(points)).await;
+
+    let values = |points: Value| -> Vec<f64> {
+        points
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|point| point["value"].as_f64().unwrap())
+            .collect()
+    };
+    let buckets = read(&app, "/ts/load?step=1000").await;
+    assert_eq!(buckets[0]["timestamp"], 10_000);
+    assert_eq!(buckets[2]["timestamp"], 12_000);
+    assert_eq!(values(buckets), [0.5, 2.5, 4.5]);
+    assert_eq!(
+        values(read(&app, "/ts/load?step=1000&agg=min").await),
+        [0.0, 2.0, 4.0]
+    );
+    assert_eq!(
+        values(read(&app, "/ts/load?step=1000&agg=max").await),
+        [1.0, 3.0, 5.0]
+    );
+    assert_eq!(
+        values(read(&app, "/ts/load?step=2000&agg=sum").await),
+        [6.0, 9.0]
+    );
+    assert_eq!(
+        values(read(&app, "/ts/load?step=60000&agg=count&from=10500").await),
+        [5.0]
+    );
+}
+
+#[tokio::test]
+async fn test_downsampling_keeps_series_bounded() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let state = create_test_state(&temp_dir, TimeSeries::new(1000, Some(5000), Some(8000)));
+    let app = routes::router(state.clone());
+
+    let points: Vec<Value> = (0..10)
+        .map(|i| json!({"timestamp": 100 + i * 250, "value": i}))
+        .collect();
+    send(&app, "POST", "/ts/temp/points", Value::Array(points)).await;
+
+    // Points before 2000 outlive the raw retention at 7000.
+    let downsampled = state
+        .timeseries
+        .downsample(&state.rocksdb, &state.encryption, 7000)
+        .unwrap();
+    assert_eq!(downsampled, 8);
+    let points = read(&app, "/ts/temp?agg=count").await;
+    assert_eq!(
+        points,
+        json!([
+            {"timestamp": 0, "value": 4.0},
+            {"timestamp": 1000, "value": 4.0},
+            {"timestamp": 2100, "value": 1.0},
+            {"timestamp": 2350, "value": 1.0},
+        ])
+    );
+    assert_eq!(
+        read(&app, "/ts/temp?step=1000&to=1999").await,
+        json!([
+            {"timestamp": 0, "value": 1.5},
+            {"timestamp": 1000, "value": 5.5},
+        ])
+    );
+
+    // Late points behind the horizon are read as part of their bucket, and
+    // a retried write replaces them rather than counting twice.
+    for _ in 0..2 {
+        let (_, appended) = send(
+            &app,
+            "POST",
+            "/ts/temp/points",
+            json!([{"timestamp": 1999, "value": 10.0}]),
+        )
+        .await;
+        assert_eq!(appended["downsampled"], 1);
+    }
+    assert_eq!(
+        read(&app, "/ts/temp?agg=max&to=1999").await,
+        json!([
+            {"timestamp": 0, "value": 3.0},
+            {"timestamp": 1000, "value": 10.0},
+        ])
+    );
+    assert_eq!(
+        read(&app, "/ts/temp?agg=count&step=2000").await,
+        json!([
+            {"timestamp": 0, "value": 9.0},
+            {"timestamp": 2000, "value": 2.0},
+        ])
+    );
+    assert_eq!(
+        read(&app, "/ts/temp?agg=count&step=1000&limit=1").await,
+        json!([{"timestamp": 0, "value": 4.0}])
+    );
+
+    // Buckets before 1000 outlive the bucket retention at 9000.
+    state
+        .timeseries
+        .downsample(&state.rocksdb, &state.encryption, 9000)
+        .unwrap();
+    let points = read(&app, "/ts/temp?agg=count").await;
+    assert_eq!(points[0], json!({"timestamp": 1000, "value": 5.0}));
+    assert_eq!(points.as_array().unwrap().len(), 2);
+}
@@ -0,0 +1,185 @@
+use axum::{routing::get, Extension, Router};
+use h_rocksdb::server::tls::{self, ClientAuth, ClientIdentity, ReloadingConfig, TlsSettings};
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, RootCertStore,
+};
+use std::{
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tempfile::TempDir;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::TlsConnector;
+
+/// Certificates under `tests/fixtures/tls`: a CA, and a server (`localhost`,
+/// `127.0.0.1`) and a client (`CN=client`) certificate signed by it.
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/tls")
+        .join(name)
+}
+
+fn certs(name: &str) -> Vec<CertificateDer<'static>> {
+    let mut reader = io::BufReader::new(fs::File::open(fixture(name)).unwrap());
+    rustls_pemfile::certs(&mut reader)
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+fn key(name: &str) -> PrivateKeyDer<'static> {
+    let mut reader = io::BufReader::new(fs::File::open(fixture(name)).unwrap());
+    rustls_pemfile::private_key(&mut reader).unwrap().unwrap()
+}
+
+fn mtls_settings(handshake_timeout: Duration) -> TlsSettings {
+    TlsSettings {
+        cert_path: fixture("server.crt"),
+        key_path: fixture("server.key"),
+        client_ca_path: Some(fixture("ca.crt")),
+        client_auth: ClientAuth::Required,
+        handshake_timeout,
+    }
+}
+
+async fn whoami(identity: Option<Extension<ClientIdentity>>) -> String {
+    identity.map_or_else(
+        || "anonymous".to_string(),
+        |Extension(identity)| identity.subject,
+    )
+}
+
+/// Serves `/whoami` over TLS on an ephemeral port.
+async fn start(settings: TlsSettings) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = Arc::new(ReloadingConfig::load(settings).expect("Failed to load TLS config"));
+    let app = Router::new().route("/whoami", get(whoami));
+    tokio::spawn(tls::serve(listener, app, config));
+    addr
+}
+
+/// A client trusting the test CA, presenting the client certificate if
+/// `with_certificate`.
+fn client_config(with_certificate: bool) -> Arc<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    for cert in certs("ca.crt") {
+        roots.add(cert).unwrap();
+    }
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+    let config = match with_certificate {
+        true => builder
+            .with_client_auth_cert(certs("client.crt"), key("client.key"))
+            .unwrap(),
+        false => builder.with_no_client_auth(),
+    };
+    Arc::new(config)
+}
+
+/// Requests `/whoami` over TLS and reads until the server closes the
+/// connection. The error that ended the exchange if nothing was read.
+async fn request_whoami(addr: SocketAddr, config: Arc<ClientConfig>) -> io::Result<String> {
+    let tcp = TcpStream::connect(addr).await?;
+    let server_name = ServerName::try_from("localhost").unwrap();
+    let mut stream = TlsConnector::from(config).connect(server_name, tcp).await?;
+    stream
+        .write_all(b"GET /whoami HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        .await?;
+    let mut response = Vec::new();
+    let mut buffer = [0; 1024];
+    loop {
+        match stream.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(read) => response.extend_from_slice(&buffer[..read]),
+            Err(e) if response.is_empty() => return Err(e),
+            Err(_) => break,
+        }
+    }
+    Ok(String::from_utf8_lossy(&response).into_owned())
+}
+
+#[test]
+fn test_load_rejects_missing_certificate() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let settings = TlsSettings {
+        cert_path: temp_dir.path().join("server.crt"),
+        key_path: temp_dir.path().join("server.key"),
+        client_ca_path: None,
+        client_auth: ClientAuth::Required,
+        handshake_timeout: Duration::from_secs(10),
+    };
+
+    let result = ReloadingConfig::load(settings);
+    assert!(result.is_err(), "Loading should fail without a certificate");
+}
+
+#[test]
+fn test_load_rejects_empty_certificate() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let cert_path = temp_dir.path().join("server.crt");
+    let key_path = temp_dir.path().join("server.key");
+    std::fs::write(&cert_path, "").unwrap();
+    std::fs::write(&key_path, "").unwrap();
+
+    let settings = TlsSettings {
+        cert_path,
+        key_path,
+        client_ca_path: None,
+        client_auth: ClientAuth::Required,
+        handshake_timeout: Duration::from_secs(10),
+    };
+    match ReloadingConfig::load(settings) {
+        Ok(_) => panic!("Loading should fail with an empty certificate file"),
+        Err(message) => assert!(message.contains("no certificates found")),
+    }
+}
+
+#[tokio::test]
+async fn test_handshake_identifies_client_certificates() {
+    let addr = start(mtls_settings(Duration::from_secs(10))).await;
+
+    let response = request_whoami(addr, client_config(true))
+        .await
+        .expect("Handshake with a client certificate should succeed");
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.ends_with("CN=client"), "{}", response);
+}
+
+#[tokio::test]
+async fn test_required_client_auth_rejects_clients_without_certificate() {
+    let addr = start(mtls_settings(Duration::from_secs(10))).await;
+
+    // Under TLS 1.3 the client finishes its side of the handshake before the
+    // server refuses it, so the refusal shows when reading the response.
+    if let Ok(response) = request_whoami(addr, client_config(false)).await {
+        assert!(
+            !response.starts_with("HTTP/1.1 200"),
+            "A client without a certificate was served: {}",
+            response
+        );
+    }
+
+    let mut settings = mtls_settings(Duration::from_secs(10));
+    settings.client_auth = ClientAuth::Optional;
+    let addr = start(settings).await;
+    let response = request_whoami(addr, client_config(false)).await.unwrap();
+    assert!(response.ends_with("anonymous"), "{}", response);
+}
+
+#[tokio::test]
+async fn test_stalled_handshakes_are_dropped() {
+    let addr = start(mtls_settings(Duration::from_millis(200))).await;
+
+    let mut tcp = TcpStream::connect(addr).await.unwrap();
+    let mut buffer = [0; 16];
+    let read = tokio::time::timeout(Duration::from_secs(5), tcp.read(&mut buffer))
+        .await
+        .expect("The server should close a connection that never handshakes");
+    assert_eq!(read.unwrap_or(0), 0);
+}